@@ -1,155 +1,558 @@
 // src/ast_parser.rs
+use crate::cfg;
+use crate::ignore;
+use crate::modules::qualify;
 use crate::types::{
-    FileASTData, FunctionCallVisitor, LiteralVisitor, SourceLocation, TypedLiteral,
+    CallSite, EntityPosition, EnumVariant, FileASTData, GenericParameter, GlobalItem, ItemGenerics, StructField,
+    FnParam, FnQualifiers, FnSignature, OuterAttributes, FunctionCallVisitor, LifetimeUsage, LifetimeVisitor,
+    LiteralVisitor, NestedFnVisitor, PanicPoint, PanicVisitor, PropagationVisitor, SourceLocation, TypedLiteral,
+    UnsafeRegion, UnsafeVisitor,
 };
-use proc_macro2::Span;
+use proc_macro2::{Delimiter, Spacing, Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
-use syn::ExprMacro;
+use syn::spanned::Spanned;
+use syn::parse::ParseStream;
+use syn::punctuated::Punctuated;
 use syn::{
     parse_file,
     visit::{self, Visit},
-    Expr, ExprCall, ExprField, ExprMethodCall, File, Item, ItemFn, ItemImpl, Lit, Member, PatMacro,
+    token, Attribute, Block, Expr, ExprCall, ExprClosure, ExprField, ExprMethodCall, ExprPath, ExprUnsafe, Field,
+    Fields, File, FnArg, ForeignItem, GenericParam, Generics, Ident, ImplItem, Item, ItemEnum, ItemFn, ItemForeignMod,
+    ItemImpl, ItemMacro, ItemTrait, Lifetime, Lit, Local, Macro, Member, Meta, Pat, PatMacro, PatType, ReturnType,
+    Signature, Stmt, Token, TraitItem, Type, TypeInfer, TypeReference, UseTree, Visibility, WherePredicate,
 };
-// Extract the module name from a Rust file
-pub fn extract_module_name(file_path: &str) -> String {
-    // Try to parse the file to extract the module name
-    if let Ok(content) = fs::read_to_string(file_path) {
-        if let Ok(file) = parse_file(&content) {
-            for item in file.items {
-                if let Item::Mod(module) = item {
-                    return module.ident.to_string();
-                }
-            }
-        }
-    }
+// Read the source of a Rust file
+pub fn read_source_file(file_path: &str) -> Result<String, String> {
+    eprintln!("Reading file: {}", file_path);
 
-    // If we can't find a module declaration, use the directory name
-    let path = Path::new(file_path);
-    if let Some(parent) = path.parent() {
-        if let Some(dir_name) = parent.file_name() {
-            if let Some(name) = dir_name.to_str() {
-                return name.to_string();
+    match fs::read_to_string(file_path) {
+        Ok(content) => Ok(content),
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                eprintln!("File does not exist at this commit: {}", file_path);
+                return Err(format!("File not found: {}", e));
             }
+            Err(format!("File couldn't be read: {}", e))
         }
     }
-
-    // Last resort: use "unknown"
-    "unknown".to_string()
 }
 
-// Extract AST data from a Rust file
-pub fn extract_file_ast(file_path: &str) -> Result<FileASTData, String> {
-    println!("Reading file: {}", file_path);
+// Extract AST data from the source of a Rust file, keying every entity by its qualified name
+// when the file's module path is known
+pub fn parse_file_ast(
+    file_path: &str,
+    file_content: String,
+    module_path: Option<&str>,
+) -> Result<FileASTData, String> {
+    eprintln!("File size: {} bytes", file_content.len());
 
-    // Read file content
-    let file_content = match fs::read_to_string(file_path) {
-        Ok(content) => content,
+    // Parse file to AST
+    let mut ast_data = match parse_file(&file_content) {
+        Ok(file) => {
+            // Initialize AST data
+            let mut ast_data = FileASTData::new(file_path.to_string(), file_content);
+
+            // Process all items in the file, without what the ignore rules leave out
+            let file = ignore::strip_attributes(file);
+            process_file_items(&file, &mut ast_data);
+            ast_data
+        }
         Err(e) => {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                println!("File does not exist at this commit: {}", file_path);
-                return Err(format!("File not found: {}", e));
+            // Keep the items that still parse, so one syntax error doesn't make the whole file look deleted
+            let error = format!("Parsing error: {}", e);
+            let (file, skipped_lines) = recover_items(&file_content);
+            if file.items.is_empty() {
+                return Err(error);
             }
-            return Err(format!("File couldn't be read: {}", e));
+            eprintln!(
+                "{} in {}, recovered {} items and skipped lines {}",
+                error,
+                file_path,
+                file.items.len(),
+                skipped_lines.join(", ")
+            );
+
+            let mut ast_data = FileASTData::new(file_path.to_string(), file_content);
+            process_file_items(&ignore::strip_attributes(file), &mut ast_data);
+            ast_data.parse_error = Some(format!("{}; skipped lines {}", error, skipped_lines.join(", ")));
+            ast_data
         }
     };
+    qualify_keys(&mut ast_data, module_path);
+    ignore::drop_ignored_names(&mut ast_data);
+    hash_entities(&mut ast_data);
+    record_positions(&mut ast_data);
+    mark_test_entities(&mut ast_data);
 
-    println!("File size: {} bytes", file_content.len());
+    Ok(ast_data)
+}
 
-    // Parse file to AST
-    let file = match parse_file(&file_content) {
-        Ok(ast) => ast,
-        Err(e) => return Err(format!("Parsing error: {}", e)),
+// Most consecutive chunks joined while looking for an item that parses, e.g. when a line in
+// the first column sits inside a raw string
+const MAX_JOINED_CHUNKS: usize = 8;
+
+// Parse the top-level items of a file with syntax errors chunk by chunk, splitting it at the
+// lines that start an item in the first column (with the attributes and comments above them).
+// Returns a file of the items and inner attributes that parse, and the 1-based line ranges skipped.
+fn recover_items(content: &str) -> (File, Vec<String>) {
+    // Byte offset of each line start, plus the end of the file
+    let mut line_offsets: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(index, _)| index + 1))
+        .collect();
+    if line_offsets.last() != Some(&content.len()) {
+        line_offsets.push(content.len());
+    }
+    let line_count = line_offsets.len() - 1;
+
+    let mut chunk_starts = vec![0];
+    let mut after_attributes = false;
+    for (line_index, line) in content.lines().enumerate() {
+        let starts_item = line
+            .chars()
+            .next()
+            .is_some_and(|c| !c.is_whitespace() && !matches!(c, '}' | ')' | ']'));
+        if !starts_item {
+            continue;
+        }
+        if !after_attributes && line_index > 0 {
+            chunk_starts.push(line_index);
+        }
+        after_attributes = ["#[", "#!", "//", "/*"].iter().any(|prefix| line.starts_with(prefix));
+    }
+    chunk_starts.push(line_count);
+
+    // Pad each chunk with the lines before it, so spans keep their place in the file
+    let parse_lines = |start: usize, end: usize| {
+        let source = format!("{}{}", "\n".repeat(start), &content[line_offsets[start]..line_offsets[end]]);
+        parse_file(&source).ok()
     };
 
-    // Initialize AST data
-    let mut ast_data = FileASTData::new(file_path.to_string(), file_content);
+    let mut recovered = File {
+        shebang: None,
+        attrs: Vec::new(),
+        items: Vec::new(),
+    };
+    let mut skipped: Vec<(usize, usize)> = Vec::new();
+    let mut chunk = 0;
+    while chunk + 1 < chunk_starts.len() {
+        let start = chunk_starts[chunk];
+        let last_end = (chunk + MAX_JOINED_CHUNKS).min(chunk_starts.len() - 1);
+        let parsed = (chunk + 1..=last_end)
+            .find_map(|end| parse_lines(start, chunk_starts[end]).map(|file| (end, file)));
 
-    // Process all items in the file
-    process_file_items(&file, &mut ast_data);
+        match parsed {
+            Some((end, file)) => {
+                recovered.attrs.extend(file.attrs);
+                recovered.items.extend(file.items);
+                chunk = end;
+            }
+            None => {
+                let end = chunk_starts[chunk + 1];
+                if content[line_offsets[start]..line_offsets[end]].trim().is_empty() {
+                    chunk += 1;
+                    continue;
+                }
+                // Extend the skipped range the previous chunk ended, if any
+                match skipped.last_mut() {
+                    Some(range) if range.1 == start => range.1 = end,
+                    _ => skipped.push((start, end)),
+                }
+                chunk += 1;
+            }
+        }
+    }
 
-    Ok(ast_data)
+    let skipped_lines = skipped.iter().map(|(start, end)| format!("{}-{}", start + 1, end)).collect();
+    (recovered, skipped_lines)
 }
 
-// Process all items in a Rust file
+// Process the inner attributes and all items of a Rust file
 fn process_file_items(file: &File, ast_data: &mut FileASTData) {
-    for item in &file.items {
+    ast_data.inner_attributes = inner_attributes(&file.attrs);
+    process_items(&file.items, "", &[], ast_data);
+}
+
+// Inner attributes whose lists name independent features or lints
+const LIST_INNER_ATTRIBUTES: &[&str] = &["feature", "allow", "warn", "deny", "forbid", "expect"];
+
+// Render the inner attributes of a file, splitting `#![feature(..)]` and lint attributes into one
+// entry per feature or lint, e.g. `#![deny(unsafe_code)]`, so changing one doesn't change the rest
+fn inner_attributes(attrs: &[Attribute]) -> Vec<String> {
+    let mut rendered = Vec::new();
+    for attr in attrs {
+        let entries = match &attr.meta {
+            Meta::List(list) if LIST_INNER_ATTRIBUTES.iter().any(|name| list.path.is_ident(name)) => list
+                .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .ok()
+                .filter(|nested| nested.iter().all(|meta| matches!(meta, Meta::Path(_))))
+                .map(|nested| {
+                    let path = format_compact(&list.path);
+                    nested.iter().map(|meta| format!("#![{}({})]", path, format_compact(meta))).collect()
+                }),
+            _ => None,
+        };
+        for entry in entries.unwrap_or_else(|| vec![format_compact(attr)]) {
+            if !rendered.contains(&entry) {
+                rendered.push(entry);
+            }
+        }
+    }
+    rendered
+}
+
+// Process the items of a file or inline module, naming each one after its module path
+// prefix (e.g. `foo::bar::` for items inside `mod foo { mod bar { ... } }`) and the cfg
+// predicates gating it, including those of the enclosing modules
+fn process_items(items: &[Item], module_prefix: &str, module_cfg: &[Meta], ast_data: &mut FileASTData) {
+    for item in items {
+        let Some(item_cfg) = cfg::active_predicates(module_cfg, item_attrs(item)) else {
+            continue;
+        };
+        let item_cfg = item_cfg.as_slice();
+
         match item {
             Item::Fn(func) => {
                 // Regular function
-                let func_name = func.sig.ident.to_string();
+                let func_path = format!("{}{}", module_prefix, func.sig.ident);
+                let func_name = cfg::keyed(func_path.clone(), item_cfg);
                 ast_data.functions.insert(func_name.clone(), func.clone());
-                println!(
+                eprintln!(
                     "Extracted function {} from {}",
                     func_name, ast_data.file_path
                 );
+                process_nested_functions(&func_path, &func.block, item_cfg, ast_data);
             }
             Item::Impl(impl_block) => {
                 // Methods inside impl blocks
-                process_impl_block(impl_block, ast_data);
+                process_impl_block(impl_block, module_prefix, item_cfg, ast_data);
             }
             Item::Trait(trait_def) => {
                 // Trait definition (interface in Rust)
-                let trait_name = trait_def.ident.to_string();
+                let trait_name = cfg::keyed(format!("{}{}", module_prefix, trait_def.ident), item_cfg);
                 ast_data
                     .interfaces
                     .insert(trait_name.clone(), trait_def.clone());
-                println!("Extracted trait {} from {}", trait_name, ast_data.file_path);
+                eprintln!("Extracted trait {} from {}", trait_name, ast_data.file_path);
+
+                let trait_path = format!("{}{}", module_prefix, trait_def.ident);
+                for trait_item in &trait_def.items {
+                    let (ident, attrs) = match trait_item {
+                        TraitItem::Const(item) => (&item.ident, &item.attrs),
+                        TraitItem::Type(item) => (&item.ident, &item.attrs),
+                        TraitItem::Fn(item) => (&item.sig.ident, &item.attrs),
+                        _ => continue,
+                    };
+                    let Some(cfg) = cfg::active_predicates(item_cfg, attrs) else {
+                        continue;
+                    };
+
+                    match trait_item {
+                        // Associated consts and types, keyed like methods as `Trait.NAME`
+                        TraitItem::Const(_) | TraitItem::Type(_) => {
+                            let full_name = cfg::keyed(format!("{}.{}", trait_path, ident), &cfg);
+                            let is_const = matches!(trait_item, TraitItem::Const(_));
+                            insert_associated_item(full_name, trait_item, is_const, ast_data);
+                        }
+                        // Methods as `Trait::method`, so a change to one default body is pinpointed
+                        TraitItem::Fn(method) => {
+                            let method_path = format!("{}::{}", trait_path, ident);
+                            let method_name = cfg::keyed(method_path.clone(), &cfg);
+                            ast_data.trait_methods.insert(method_name.clone(), method.clone());
+                            eprintln!("Extracted trait method {} from {}", method_name, ast_data.file_path);
+                            if let Some(body) = &method.default {
+                                process_nested_functions(&method_path, body, &cfg, ast_data);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
             }
             Item::Struct(struct_def) => {
                 // Struct definition
-                let struct_name = struct_def.ident.to_string();
+                let struct_name = cfg::keyed(format!("{}{}", module_prefix, struct_def.ident), item_cfg);
                 ast_data
                     .types
                     .insert(struct_name.clone(), Item::Struct(struct_def.clone()));
-                println!(
+                ast_data
+                    .struct_fields
+                    .insert(struct_name.clone(), extract_fields(&struct_def.fields));
+                eprintln!(
                     "Extracted struct {} from {}",
                     struct_name, ast_data.file_path
                 );
             }
             Item::Enum(enum_def) => {
                 // Enum definition
-                let enum_name = enum_def.ident.to_string();
+                let enum_name = cfg::keyed(format!("{}{}", module_prefix, enum_def.ident), item_cfg);
                 ast_data
                     .types
                     .insert(enum_name.clone(), Item::Enum(enum_def.clone()));
-                println!("Extracted enum {} from {}", enum_name, ast_data.file_path);
+                ast_data
+                    .enum_variants
+                    .insert(enum_name.clone(), extract_variants(enum_def));
+                eprintln!("Extracted enum {} from {}", enum_name, ast_data.file_path);
             }
             Item::Type(type_alias) => {
                 // Type alias
-                let type_name = type_alias.ident.to_string();
+                let type_name = cfg::keyed(format!("{}{}", module_prefix, type_alias.ident), item_cfg);
                 ast_data
                     .types
                     .insert(type_name.clone(), Item::Type(type_alias.clone()));
-                println!(
+                eprintln!(
                     "Extracted type alias {} from {}",
                     type_name, ast_data.file_path
                 );
             }
+            Item::Union(union_def) => {
+                // Union definition
+                let union_name = cfg::keyed(format!("{}{}", module_prefix, union_def.ident), item_cfg);
+                ast_data
+                    .types
+                    .insert(union_name.clone(), Item::Union(union_def.clone()));
+                ast_data
+                    .struct_fields
+                    .insert(union_name.clone(), extract_fields(&union_def.fields.named));
+                eprintln!("Extracted union {} from {}", union_name, ast_data.file_path);
+            }
+            Item::ForeignMod(foreign_mod) => {
+                // Functions, statics and types of extern blocks
+                process_foreign_mod(foreign_mod, module_prefix, item_cfg, ast_data);
+            }
+            Item::Const(const_def) => {
+                // Constant
+                let const_name = cfg::keyed(format!("{}{}", module_prefix, const_def.ident), item_cfg);
+                ast_data
+                    .constants
+                    .insert(const_name.clone(), Item::Const(const_def.clone()));
+                eprintln!("Extracted const {} from {}", const_name, ast_data.file_path);
+            }
+            Item::Static(static_def) => {
+                // Static, mutable or not
+                let static_name = cfg::keyed(format!("{}{}", module_prefix, static_def.ident), item_cfg);
+                ast_data
+                    .constants
+                    .insert(static_name.clone(), Item::Static(static_def.clone()));
+                if let Some(global) = static_global(static_def) {
+                    ast_data.globals.insert(static_name.clone(), global);
+                }
+                eprintln!("Extracted static {} from {}", static_name, ast_data.file_path);
+            }
+            Item::Macro(macro_def) => {
+                // Only macro_rules! definitions are named; other invocations are ignored unless
+                // they declare global state
+                if let Some(ident) = &macro_def.ident {
+                    let macro_name = cfg::keyed(format!("{}{}", module_prefix, ident), item_cfg);
+                    ast_data.macros.insert(macro_name.clone(), macro_def.clone());
+                    eprintln!("Extracted macro {} from {}", macro_name, ast_data.file_path);
+                } else {
+                    for (ident, global) in macro_globals(&macro_def.mac) {
+                        let global_name = cfg::keyed(format!("{}{}", module_prefix, ident), item_cfg);
+                        eprintln!("Extracted {} global {} from {}", global.kind, global_name, ast_data.file_path);
+                        ast_data.globals.insert(global_name, global);
+                    }
+                }
+            }
+            Item::Use(use_item) => {
+                // Each imported path is tracked on its own, keeping re-exports apart
+                let visibility = match use_item.vis {
+                    syn::Visibility::Inherited => String::new(),
+                    ref vis => format!("{} ", format_node(vis)),
+                };
+                let root = if use_item.leading_colon.is_some() { "::" } else { "" };
+                // Imports of nested modules name their module, e.g. `tests: super::*`
+                let scope = match module_prefix.strip_suffix("::") {
+                    Some(module_path) => format!("{}: ", module_path),
+                    None => String::new(),
+                };
+                let mut imports = Vec::new();
+                flatten_use_tree(&use_item.tree, root.to_string(), &mut imports);
+                for import in imports {
+                    // Only a plain `pub use` adds to the public API; `pub(crate)` and the like don't
+                    if matches!(use_item.vis, syn::Visibility::Public(_)) {
+                        let reexport = cfg::keyed(format!("{}{}", scope, import), item_cfg);
+                        if !ast_data.reexports.contains(&reexport) {
+                            ast_data.reexports.push(reexport);
+                        }
+                    }
+                    let import = cfg::keyed(format!("{}{}{}", scope, visibility, import), item_cfg);
+                    if !ast_data.imports.contains(&import) {
+                        ast_data.imports.push(import);
+                    }
+                }
+            }
+            Item::Mod(module) => {
+                // Inline modules are walked with their name added to the prefix;
+                // out-of-line `mod x;` declarations have no items here
+                if let Some((_, module_items)) = &module.content {
+                    let nested_prefix = format!("{}{}::", module_prefix, module.ident);
+                    process_items(module_items, &nested_prefix, item_cfg, ast_data);
+                }
+            }
             _ => {} // Ignore other items
         }
     }
 }
 
-// Process methods inside impl blocks
-fn process_impl_block(impl_block: &ItemImpl, ast_data: &mut FileASTData) {
-    // Get the type name for this impl block
-    let type_name = match &*impl_block.self_ty {
-        syn::Type::Path(type_path) => {
-            if let Some(segment) = type_path.path.segments.last() {
-                segment.ident.to_string()
-            } else {
-                return; // Can't determine type name
+// Outer attributes of an item
+fn item_attrs(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Const(item) => &item.attrs,
+        Item::Enum(item) => &item.attrs,
+        Item::ExternCrate(item) => &item.attrs,
+        Item::Fn(item) => &item.attrs,
+        Item::ForeignMod(item) => &item.attrs,
+        Item::Impl(item) => &item.attrs,
+        Item::Macro(item) => &item.attrs,
+        Item::Mod(item) => &item.attrs,
+        Item::Static(item) => &item.attrs,
+        Item::Struct(item) => &item.attrs,
+        Item::Trait(item) => &item.attrs,
+        Item::TraitAlias(item) => &item.attrs,
+        Item::Type(item) => &item.attrs,
+        Item::Union(item) => &item.attrs,
+        Item::Use(item) => &item.attrs,
+        _ => &[],
+    }
+}
+
+// Process the items of an extern block. Foreign functions become bodiless functions carrying
+// the block's ABI; statics and types keep an extern block around them so their code reads as written.
+fn process_foreign_mod(
+    foreign_mod: &ItemForeignMod,
+    module_prefix: &str,
+    block_cfg: &[Meta],
+    ast_data: &mut FileASTData,
+) {
+    // Wrap a single foreign item in a copy of its extern block
+    let wrap = |item: ForeignItem| {
+        Item::ForeignMod(ItemForeignMod {
+            items: vec![item],
+            ..foreign_mod.clone()
+        })
+    };
+
+    for item in &foreign_mod.items {
+        let attrs = match item {
+            ForeignItem::Fn(item) => &item.attrs,
+            ForeignItem::Static(item) => &item.attrs,
+            ForeignItem::Type(item) => &item.attrs,
+            _ => continue, // Macros in extern blocks aren't named
+        };
+        let Some(item_cfg) = cfg::active_predicates(block_cfg, attrs) else {
+            continue;
+        };
+
+        match item {
+            ForeignItem::Fn(foreign_fn) => {
+                let func_name = cfg::keyed(format!("{}{}", module_prefix, foreign_fn.sig.ident), &item_cfg);
+                let mut sig = foreign_fn.sig.clone();
+                sig.abi = sig.abi.or_else(|| Some(foreign_mod.abi.clone()));
+                let fn_item = ItemFn {
+                    attrs: foreign_fn.attrs.clone(),
+                    vis: foreign_fn.vis.clone(),
+                    sig,
+                    block: Box::new(Block {
+                        brace_token: token::Brace(foreign_fn.semi_token.span),
+                        stmts: Vec::new(),
+                    }),
+                };
+                ast_data.functions.insert(func_name.clone(), fn_item);
+                eprintln!("Extracted foreign function {} from {}", func_name, ast_data.file_path);
+            }
+            ForeignItem::Static(foreign_static) => {
+                let static_name = cfg::keyed(format!("{}{}", module_prefix, foreign_static.ident), &item_cfg);
+                ast_data
+                    .constants
+                    .insert(static_name.clone(), wrap(ForeignItem::Static(foreign_static.clone())));
+                eprintln!("Extracted foreign static {} from {}", static_name, ast_data.file_path);
+            }
+            ForeignItem::Type(foreign_type) => {
+                let type_name = cfg::keyed(format!("{}{}", module_prefix, foreign_type.ident), &item_cfg);
+                ast_data
+                    .types
+                    .insert(type_name.clone(), wrap(ForeignItem::Type(foreign_type.clone())));
+                eprintln!("Extracted foreign type {} from {}", type_name, ast_data.file_path);
             }
+            _ => {}
+        }
+    }
+}
+
+// Expand a use tree into one path per imported name, e.g. `a::{b, c as d}` into
+// `a::b` and `a::c as d`
+fn flatten_use_tree(tree: &UseTree, path: String, imports: &mut Vec<String>) {
+    match tree {
+        UseTree::Path(use_path) => {
+            flatten_use_tree(&use_path.tree, format!("{}{}::", path, use_path.ident), imports);
+        }
+        UseTree::Name(use_name) => imports.push(format!("{}{}", path, use_name.ident)),
+        UseTree::Rename(use_rename) => {
+            imports.push(format!("{}{} as {}", path, use_rename.ident, use_rename.rename));
+        }
+        UseTree::Glob(_) => imports.push(format!("{}*", path)),
+        UseTree::Group(use_group) => {
+            for item in &use_group.items {
+                flatten_use_tree(item, path.clone(), imports);
+            }
+        }
+    }
+}
+
+// Process methods inside impl blocks
+fn process_impl_block(impl_block: &ItemImpl, module_prefix: &str, impl_cfg: &[Meta], ast_data: &mut FileASTData) {
+    // Name the impl after its full self type, generic arguments and references included,
+    // so impls on `Foo<u8>`, `Foo<String>` and `&Foo` are told apart, followed by the bounds
+    // on its generics, so `impl<T: Send> Foo<T>` and `impl<T: Clone> Foo<T>` are too. Bounds
+    // are sorted, so `impl<T: Send> Foo<T>` and `impl<T> Foo<T> where T: Send` share a name.
+    let mut bounds = item_generics(&impl_block.generics).bounds;
+    bounds.sort();
+    let type_name = if bounds.is_empty() {
+        format_compact(&*impl_block.self_ty)
+    } else {
+        format!("{} where {}", format_compact(&*impl_block.self_ty), bounds.join(", "))
+    };
+
+    // Trait impls are keyed as `<Trait for Type>::item` and recorded as a whole, so
+    // adding or removing one is reported; inherent impls are keyed as `Type.item`. The trait
+    // keeps its path as written, so `fmt::Write` and `io::Write` impls don't collide.
+    let (owner, separator) = match &impl_block.trait_ {
+        Some((negative, trait_path, _)) => {
+            let trait_name = format_compact(trait_path);
+            let negation = if negative.is_some() { "!" } else { "" };
+            let owner = format!("{}<{}{} for {}>", module_prefix, negation, trait_name, type_name);
+            let impl_name = cfg::keyed(owner.clone(), impl_cfg);
+            ast_data.trait_impls.insert(impl_name.clone(), impl_block.clone());
+            eprintln!("Extracted trait impl {} from {}", impl_name, ast_data.file_path);
+            (owner, "::")
         }
-        _ => return, // Can't determine type name
+        // A where clause is wrapped in angle brackets, like a trait impl, to keep it apart from the item name
+        None if bounds.is_empty() => (format!("{}{}", module_prefix, type_name), "."),
+        None => (format!("{}<{}>", module_prefix, type_name), "."),
     };
 
     // Process all items inside the impl block
     for item in &impl_block.items {
-        if let syn::ImplItem::Fn(method) = item {
-            let method_name = method.sig.ident.to_string();
-            let full_name = format!("{}.{}", type_name, method_name);
+        let (ident, attrs) = match item {
+            ImplItem::Const(item) => (&item.ident, &item.attrs),
+            ImplItem::Type(item) => (&item.ident, &item.attrs),
+            ImplItem::Fn(item) => (&item.sig.ident, &item.attrs),
+            _ => continue,
+        };
+        let Some(item_cfg) = cfg::active_predicates(impl_cfg, attrs) else {
+            continue;
+        };
+        let full_path = format!("{}{}{}", owner, separator, ident);
+        let full_name = cfg::keyed(full_path.clone(), &item_cfg);
+
+        match item {
+            ImplItem::Const(item) => insert_associated_item(full_name.clone(), item, true, ast_data),
+            ImplItem::Type(item) => insert_associated_item(full_name.clone(), item, false, ast_data),
+            _ => {}
+        }
+
+        if let ImplItem::Fn(method) = item {
 
             // Convert impl method to a standalone function
             let fn_item = ItemFn {
@@ -162,55 +565,823 @@ fn process_impl_block(impl_block: &ItemImpl, ast_data: &mut FileASTData) {
             ast_data
                 .methods
                 .insert(full_name.clone(), (impl_block.clone(), fn_item));
-            println!("Extracted method {} from {}", full_name, ast_data.file_path);
+            eprintln!("Extracted method {} from {}", full_name, ast_data.file_path);
+            process_nested_functions(&full_path, &method.block, &item_cfg, ast_data);
         }
     }
 }
 
-// Extract source code from the original content
-// pub fn get_source_code(span: Span, file_content: &str) -> String {
-//     let start = span.start();
-//     let end = span.end();
+// Extract the functions and block-bodied closures bound with `let` inside a function body as
+// functions named after their parent, e.g. `outer::helper` and `outer::{closure#handler}`
+fn process_nested_functions(parent_path: &str, block: &Block, parent_cfg: &[Meta], ast_data: &mut FileASTData) {
+    let mut visitor = NestedFnVisitor {
+        functions: Vec::new(),
+        closures: Vec::new(),
+    };
+    visitor.visit_block(block);
 
-//     // Safety check to avoid out-of-bounds access
-//     let content_bytes = file_content.as_bytes();
-//     if start.line == 0 || end.line == 0 ||
-//        start.line > file_content.lines().count() ||
-//        end.line > file_content.lines().count() {
-//         return String::new();
-//     }
+    for func in visitor.functions {
+        let Some(func_cfg) = cfg::active_predicates(parent_cfg, &func.attrs) else {
+            continue;
+        };
+        let func_path = format!("{}::{}", parent_path, func.sig.ident);
+        let func_name = cfg::keyed(func_path.clone(), &func_cfg);
+        process_nested_functions(&func_path, &func.block, &func_cfg, ast_data);
+        ast_data.functions.insert(func_name.clone(), func);
+        eprintln!("Extracted nested function {} from {}", func_name, ast_data.file_path);
+    }
+
+    for (ident, closure) in visitor.closures {
+        let closure_name = cfg::keyed(format!("{}::{{closure#{}}}", parent_path, ident), parent_cfg);
+        ast_data.functions.insert(closure_name.clone(), closure_as_fn(&ident, &closure));
+        eprintln!("Extracted closure {} from {}", closure_name, ast_data.file_path);
+    }
+}
 
-//     // Find the byte offsets
-//     let mut start_offset = 0;
-//     let mut end_offset = 0;
-//     let mut current_line = 1;
-//     let mut current_column = 1;
+// View a closure as a function named after the binding it's assigned to, so it can be diffed
+// like one. Untyped parameters get the inferred type `_`.
+fn closure_as_fn(ident: &Ident, closure: &ExprClosure) -> ItemFn {
+    let span = closure.or1_token.span;
+    let inputs = closure
+        .inputs
+        .iter()
+        .map(|input| match input {
+            Pat::Type(typed) => FnArg::Typed(typed.clone()),
+            pat => FnArg::Typed(PatType {
+                attrs: Vec::new(),
+                pat: Box::new(pat.clone()),
+                colon_token: Token![:](span),
+                ty: Box::new(Type::Infer(TypeInfer {
+                    underscore_token: Token![_](span),
+                })),
+            }),
+        })
+        .collect();
+    let block = match &*closure.body {
+        Expr::Block(body) => body.block.clone(),
+        body => Block {
+            brace_token: token::Brace(span),
+            stmts: vec![Stmt::Expr(body.clone(), None)],
+        },
+    };
 
-//     for (i, byte) in content_bytes.iter().enumerate() {
-//         if current_line == start.line && current_column == start.column {
-//             start_offset = i;
-//         }
+    ItemFn {
+        attrs: closure.attrs.clone(),
+        vis: Visibility::Inherited,
+        sig: Signature {
+            constness: closure.constness,
+            asyncness: closure.asyncness,
+            unsafety: None,
+            abi: None,
+            fn_token: Token![fn](span),
+            ident: ident.clone(),
+            generics: Generics::default(),
+            paren_token: token::Paren(span),
+            inputs,
+            variadic: None,
+            output: closure.output.clone(),
+        },
+        block: Box::new(block),
+    }
+}
 
-//         if current_line == end.line && current_column == end.column {
-//             end_offset = i;
-//             break;
-//         }
+// Record an associated const or type of an impl block or trait under its full name
+fn insert_associated_item(full_name: String, item: &impl ToTokens, is_const: bool, ast_data: &mut FileASTData) {
+    let (map, kind) = if is_const {
+        (&mut ast_data.associated_consts, "associated const")
+    } else {
+        (&mut ast_data.associated_types, "associated type")
+    };
+    map.insert(full_name.clone(), item.to_token_stream());
+    eprintln!("Extracted {} {} from {}", kind, full_name, ast_data.file_path);
+}
 
-//         // Update line and column counters
-//         if *byte == b'\n' {
-//             current_line += 1;
-//             current_column = 1;
-//         } else {
-//             current_column += 1;
-//         }
-//     }
+// Type names of statics that hold lazily initialized or interior-mutable state
+const LAZY_TYPES: &[&str] = &["Lazy", "LazyLock", "LazyCell"];
+const ONCE_TYPES: &[&str] = &["OnceCell", "OnceLock"];
+const INTERIOR_MUTABLE_TYPES: &[&str] = &["Mutex", "RwLock", "RefCell", "Cell", "UnsafeCell"];
 
-//     if start_offset < end_offset && end_offset <= content_bytes.len() {
-//         String::from_utf8_lossy(&content_bytes[start_offset..end_offset]).to_string()
-//     } else {
-//         String::new()
-//     }
-// }
+// A static as global state, when it's `static mut` or of a lazy, once or interior-mutable
+// type like `Lazy<..>`, `OnceLock<..>`, `Mutex<..>` or an atomic
+fn static_global(static_def: &syn::ItemStatic) -> Option<GlobalItem> {
+    let type_name = match &*static_def.ty {
+        Type::Path(type_path) => type_path.path.segments.last().map(|segment| segment.ident.to_string()),
+        _ => None,
+    }
+    .unwrap_or_default();
+
+    let kind = if matches!(static_def.mutability, syn::StaticMutability::Mut(_)) {
+        "static_mut"
+    } else if LAZY_TYPES.contains(&type_name.as_str()) {
+        "lazy"
+    } else if ONCE_TYPES.contains(&type_name.as_str()) {
+        "once"
+    } else if INTERIOR_MUTABLE_TYPES.contains(&type_name.as_str()) || type_name.starts_with("Atomic") {
+        "interior_mutable"
+    } else {
+        return None;
+    };
+
+    Some(GlobalItem {
+        kind: kind.to_string(),
+        ty: format_compact(&static_def.ty),
+        initializer: format_compact(&static_def.expr),
+    })
+}
+
+// The statics declared by a `thread_local!` or `lazy_static!` invocation, or none for other
+// macros and invocations that don't parse
+fn macro_globals(mac: &Macro) -> Vec<(Ident, GlobalItem)> {
+    let kind = match mac.path.segments.last().map(|segment| segment.ident.to_string()).as_deref() {
+        Some("thread_local") => "thread_local",
+        Some("lazy_static") => "lazy_static",
+        _ => return Vec::new(),
+    };
+
+    // Each declaration reads `#[attrs] vis static [ref] NAME: Type = init;`
+    let declarations = mac.parse_body_with(|input: ParseStream| {
+        let mut declarations = Vec::new();
+        while !input.is_empty() {
+            input.call(Attribute::parse_outer)?;
+            input.parse::<Visibility>()?;
+            input.parse::<Token![static]>()?;
+            input.parse::<Option<Token![ref]>>()?;
+            let ident: Ident = input.parse()?;
+            input.parse::<Token![:]>()?;
+            let ty: Type = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let initializer: Expr = input.parse()?;
+            if !input.is_empty() {
+                input.parse::<Token![;]>()?;
+            }
+
+            declarations.push((
+                ident,
+                GlobalItem {
+                    kind: kind.to_string(),
+                    ty: format_compact(&ty),
+                    initializer: format_compact(&initializer),
+                },
+            ));
+        }
+        Ok(declarations)
+    });
+    declarations.unwrap_or_default()
+}
+
+// Structured form of the fields of a struct, union or enum variant
+pub fn extract_fields<'a>(fields: impl IntoIterator<Item = &'a Field>) -> Vec<StructField> {
+    fields
+        .into_iter()
+        .enumerate()
+        .map(|(index, field)| StructField {
+            name: match &field.ident {
+                Some(ident) => ident.to_string(),
+                None => index.to_string(),
+            },
+            ty: format_compact(&field.ty),
+            visibility: visibility_name(&field.vis),
+            attributes: field
+                .attrs
+                .iter()
+                .filter(|attr| !attr.path().is_ident("doc"))
+                .map(format_compact)
+                .collect(),
+        })
+        .collect()
+}
+
+// Structured form of the variants of an enum
+pub fn extract_variants(enum_def: &ItemEnum) -> Vec<EnumVariant> {
+    enum_def
+        .variants
+        .iter()
+        .map(|variant| EnumVariant {
+            name: variant.ident.to_string(),
+            shape: match &variant.fields {
+                Fields::Unit => "unit",
+                Fields::Unnamed(_) => "tuple",
+                Fields::Named(_) => "struct",
+            }
+            .to_string(),
+            fields: extract_fields(&variant.fields),
+            discriminant: variant.discriminant.as_ref().map(|(_, expr)| format_compact(expr)),
+            attributes: variant
+                .attrs
+                .iter()
+                .filter(|attr| !attr.path().is_ident("doc"))
+                .map(format_compact)
+                .collect(),
+        })
+        .collect()
+}
+
+// Visibility as reported in changes, e.g. `pub(crate)`, or `private` without one
+pub fn visibility_name(vis: &Visibility) -> String {
+    match vis {
+        Visibility::Inherited => "private".to_string(),
+        vis => format_compact(vis),
+    }
+}
+
+// Qualifiers of a function signature
+pub fn fn_qualifiers(sig: &Signature) -> FnQualifiers {
+    FnQualifiers {
+        is_async: sig.asyncness.is_some(),
+        is_const: sig.constness.is_some(),
+        is_unsafe: sig.unsafety.is_some(),
+        abi: sig.abi.as_ref().map(|abi| match &abi.name {
+            Some(name) => name.value(),
+            None => "C".to_string(),
+        }),
+    }
+}
+
+// Structured form of a function signature
+pub fn fn_signature(sig: &Signature) -> FnSignature {
+    let params = sig
+        .inputs
+        .iter()
+        .map(|input| match input {
+            // The mutability of `&mut self` belongs to the reference, that of `mut self` to the binding
+            FnArg::Receiver(receiver) => FnParam {
+                pattern: match (&receiver.reference, &receiver.mutability) {
+                    (None, Some(_)) => "mut self",
+                    _ => "self",
+                }
+                .to_string(),
+                ty: format_compact(&*receiver.ty),
+            },
+            FnArg::Typed(typed) => FnParam {
+                pattern: format_compact(&*typed.pat),
+                ty: format_compact(&*typed.ty),
+            },
+        })
+        .collect();
+
+    FnSignature {
+        name: sig.ident.to_string(),
+        generics: sig.generics.params.iter().map(format_compact).collect(),
+        where_predicates: sig
+            .generics
+            .where_clause
+            .iter()
+            .flat_map(|where_clause| where_clause.predicates.iter().map(format_compact))
+            .collect(),
+        params,
+        variadic: sig.variadic.is_some(),
+        return_type: match &sig.output {
+            ReturnType::Default => None,
+            ReturnType::Type(_, ty) => Some(format_compact(&**ty)),
+        },
+        qualifiers: fn_qualifiers(sig),
+    }
+}
+
+// Structured form of an item's generics, with inline bounds and where-clause predicates
+// split into one `Bounded: Bound` entry per bound, so moving a bound between them isn't a change
+pub fn item_generics(generics: &Generics) -> ItemGenerics {
+    let mut params = Vec::new();
+    let mut bounds = Vec::new();
+    let mut add_bound = |bound: String| {
+        if !bounds.contains(&bound) {
+            bounds.push(bound);
+        }
+    };
+
+    for param in &generics.params {
+        match param {
+            GenericParam::Lifetime(lifetime) => {
+                let name = format_compact(&lifetime.lifetime);
+                for bound in &lifetime.bounds {
+                    add_bound(format!("{}: {}", name, format_compact(bound)));
+                }
+                params.push(GenericParameter {
+                    kind: "lifetime".to_string(),
+                    name,
+                    ty: None,
+                    default: None,
+                });
+            }
+            GenericParam::Type(type_param) => {
+                let name = type_param.ident.to_string();
+                for bound in &type_param.bounds {
+                    add_bound(format!("{}: {}", name, format_compact(bound)));
+                }
+                params.push(GenericParameter {
+                    kind: "type".to_string(),
+                    name,
+                    ty: None,
+                    default: type_param.default.as_ref().map(format_compact),
+                });
+            }
+            GenericParam::Const(const_param) => params.push(GenericParameter {
+                kind: "const".to_string(),
+                name: const_param.ident.to_string(),
+                ty: Some(format_compact(&const_param.ty)),
+                default: const_param.default.as_ref().map(format_compact),
+            }),
+        }
+    }
+
+    for predicate in generics.where_clause.iter().flat_map(|where_clause| &where_clause.predicates) {
+        match predicate {
+            WherePredicate::Lifetime(lifetime) => {
+                let name = format_compact(&lifetime.lifetime);
+                for bound in &lifetime.bounds {
+                    add_bound(format!("{}: {}", name, format_compact(bound)));
+                }
+            }
+            WherePredicate::Type(type_predicate) => {
+                // Higher-ranked lifetimes stay with the bounded type, as in `for<'a> F: Fn(&'a str)`
+                let bounded_ty = format_compact(&type_predicate.bounded_ty);
+                let bounded = match &type_predicate.lifetimes {
+                    Some(lifetimes) => format!("{} {}", format_compact(lifetimes), bounded_ty),
+                    None => bounded_ty,
+                };
+                for bound in &type_predicate.bounds {
+                    add_bound(format!("{}: {}", bounded, format_compact(bound)));
+                }
+            }
+            predicate => add_bound(format_compact(predicate)),
+        }
+    }
+
+    ItemGenerics { params, bounds }
+}
+
+// Structured form of a trait's generics, with its supertraits as bounds on `Self`
+pub fn trait_generics(item: &ItemTrait) -> ItemGenerics {
+    let mut generics = item_generics(&item.generics);
+    for supertrait in &item.supertraits {
+        let bound = format!("Self: {}", format_compact(supertrait));
+        if !generics.bounds.contains(&bound) {
+            generics.bounds.push(bound);
+        }
+    }
+    generics
+}
+
+// Lifetimes of a function signature, from its parameter and return types
+pub fn fn_lifetimes(sig: &Signature) -> LifetimeUsage {
+    let mut visitor = LifetimeVisitor { lifetimes: Vec::new() };
+    for input in &sig.inputs {
+        match input {
+            FnArg::Receiver(receiver) => visitor.visit_type(&receiver.ty),
+            FnArg::Typed(typed) => visitor.visit_type(&typed.ty),
+        }
+    }
+    visitor.visit_return_type(&sig.output);
+
+    LifetimeUsage {
+        params: lifetime_params(&sig.generics),
+        positions: visitor.lifetimes,
+    }
+}
+
+// Lifetimes of a struct, enum, union or type alias, from its field or aliased types
+pub fn type_lifetimes(item: &Item) -> Option<LifetimeUsage> {
+    let mut visitor = LifetimeVisitor { lifetimes: Vec::new() };
+    let generics = match item {
+        Item::Struct(item) => {
+            visitor.visit_fields(&item.fields);
+            &item.generics
+        }
+        Item::Enum(item) => {
+            for variant in &item.variants {
+                visitor.visit_fields(&variant.fields);
+            }
+            &item.generics
+        }
+        Item::Union(item) => {
+            visitor.visit_fields_named(&item.fields);
+            &item.generics
+        }
+        Item::Type(item) => {
+            visitor.visit_type(&item.ty);
+            &item.generics
+        }
+        _ => return None,
+    };
+
+    Some(LifetimeUsage {
+        params: lifetime_params(generics),
+        positions: visitor.lifetimes,
+    })
+}
+
+// Names of the lifetime parameters of an item
+fn lifetime_params(generics: &Generics) -> Vec<String> {
+    generics.lifetimes().map(|param| format_compact(&param.lifetime)).collect()
+}
+
+// Split a macro_rules! definition into its (matcher, transcriber) arms, or None when
+// the body isn't a plain list of `matcher => transcriber` rules
+pub fn extract_macro_arms(macro_def: &ItemMacro) -> Option<Vec<(String, String)>> {
+    let mut arms = Vec::new();
+    let mut tokens = macro_def.mac.tokens.clone().into_iter().peekable();
+
+    while let Some(matcher) = tokens.next() {
+        let TokenTree::Group(matcher) = matcher else {
+            return None;
+        };
+        match (tokens.next(), tokens.next()) {
+            (Some(TokenTree::Punct(eq)), Some(TokenTree::Punct(gt))) if eq.as_char() == '=' && gt.as_char() == '>' => {}
+            _ => return None,
+        }
+        let Some(TokenTree::Group(transcriber)) = tokens.next() else {
+            return None;
+        };
+        if matcher.delimiter() == Delimiter::None || transcriber.delimiter() == Delimiter::None {
+            return None;
+        }
+        arms.push((matcher.to_string(), transcriber.to_string()));
+
+        // Rules are separated by semicolons, with an optional one after the last
+        if let Some(TokenTree::Punct(semi)) = tokens.peek() {
+            if semi.as_char() == ';' {
+                tokens.next();
+                continue;
+            }
+        }
+        if tokens.peek().is_some() {
+            return None;
+        }
+    }
+
+    Some(arms)
+}
+
+// Source text of a node as written in the file it was parsed from, with its comments and
+// formatting, or its format_node rendering when the text at its span doesn't hold exactly
+// its tokens, as for nodes assembled during extraction like closures viewed as functions
+pub fn get_source_code(node: &impl ToTokens, file_content: &str) -> String {
+    let tokens = node.to_token_stream();
+    let rendered = tokens.to_string();
+    let span = node.span();
+    let start = span.start();
+    let end = span.end();
+
+    let start_offset = byte_offset(file_content, start.line, start.column);
+    let end_offset = byte_offset(file_content, end.line, end.column);
+    let text = match (start_offset, end_offset) {
+        (Some(start_offset), Some(end_offset)) if start_offset < end_offset => &file_content[start_offset..end_offset],
+        _ => return rendered,
+    };
+    match text.parse::<TokenStream>() {
+        Ok(text_tokens) if same_tokens(&text_tokens, &tokens) => text.to_string(),
+        _ => rendered,
+    }
+}
+
+// Prefix every entity key with the file's module path, e.g. `client::new` in `src/api.rs` becomes
+// `crate::api::client::new`, so same-named items of different files stay apart in every map.
+// Both sides of a file share one module path, so their keys still line up.
+fn qualify_keys(ast_data: &mut FileASTData, module_path: Option<&str>) {
+    let Some(module_path) = module_path else {
+        return;
+    };
+    fn requalify<V>(entities: &mut HashMap<String, V>, module_path: &str) {
+        *entities = entities.drain().map(|(name, entity)| (qualify(Some(module_path), &name), entity)).collect();
+    }
+    requalify(&mut ast_data.functions, module_path);
+    requalify(&mut ast_data.types, module_path);
+    requalify(&mut ast_data.struct_fields, module_path);
+    requalify(&mut ast_data.enum_variants, module_path);
+    requalify(&mut ast_data.globals, module_path);
+    requalify(&mut ast_data.interfaces, module_path);
+    requalify(&mut ast_data.methods, module_path);
+    requalify(&mut ast_data.trait_methods, module_path);
+    requalify(&mut ast_data.trait_impls, module_path);
+    requalify(&mut ast_data.constants, module_path);
+    requalify(&mut ast_data.macros, module_path);
+    requalify(&mut ast_data.associated_consts, module_path);
+    requalify(&mut ast_data.associated_types, module_path);
+    ast_data.module_path = Some(module_path.to_string());
+}
+
+// FNV-1a parameters, which keep content hashes stable across runs, platforms and toolchains
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// Record the content hash of every extracted entity, by the kind names the differ uses
+fn hash_entities(ast_data: &mut FileASTData) {
+    fn hash_all<'a, T: ToTokens + 'a>(entities: impl Iterator<Item = (&'a String, &'a T)>) -> HashMap<String, String> {
+        entities.map(|(name, node)| (name.clone(), content_hash(node))).collect()
+    }
+
+    let content_hashes = [
+        ("function", hash_all(ast_data.functions.iter())),
+        ("type", hash_all(ast_data.types.iter())),
+        ("interface", hash_all(ast_data.interfaces.iter())),
+        ("method", hash_all(ast_data.methods.iter().map(|(name, (_, method))| (name, method)))),
+        ("trait_method", hash_all(ast_data.trait_methods.iter())),
+        ("trait_impl", hash_all(ast_data.trait_impls.iter())),
+        ("constant", hash_all(ast_data.constants.iter())),
+        ("macro", hash_all(ast_data.macros.iter())),
+        ("associated_const", hash_all(ast_data.associated_consts.iter())),
+        ("associated_type", hash_all(ast_data.associated_types.iter())),
+    ];
+    ast_data.content_hashes = content_hashes.into_iter().collect();
+}
+
+// Record where each extracted entity starts, numbering them all in source order
+fn record_positions(ast_data: &mut FileASTData) {
+    fn starts_of<'a, T: ToTokens + 'a>(
+        kind: &'static str,
+        entities: impl Iterator<Item = (&'a String, &'a T)>,
+    ) -> Vec<((usize, usize), &'static str, String)> {
+        entities
+            .map(|(name, node)| {
+                let start = node.span().start();
+                ((start.line, start.column), kind, name.clone())
+            })
+            .collect()
+    }
+
+    let mut starts: Vec<_> = [
+        starts_of("function", ast_data.functions.iter()),
+        starts_of("type", ast_data.types.iter()),
+        starts_of("interface", ast_data.interfaces.iter()),
+        starts_of("method", ast_data.methods.iter().map(|(name, (_, method))| (name, method))),
+        starts_of("trait_method", ast_data.trait_methods.iter()),
+        starts_of("trait_impl", ast_data.trait_impls.iter()),
+        starts_of("constant", ast_data.constants.iter()),
+        starts_of("macro", ast_data.macros.iter()),
+        starts_of("associated_const", ast_data.associated_consts.iter()),
+        starts_of("associated_type", ast_data.associated_types.iter()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    starts.sort();
+
+    let mut positions: HashMap<&'static str, HashMap<String, EntityPosition>> = HashMap::new();
+    for (index, ((line, _), kind, name)) in starts.into_iter().enumerate() {
+        positions.entry(kind).or_default().insert(name, EntityPosition { index, line });
+    }
+    ast_data.positions = positions;
+}
+
+// Record the entities that are test code: functions marked `#[test]` or `#[tokio::test]` and
+// everything inside them, and every item gated by `#[cfg(test)]`
+fn mark_test_entities(ast_data: &mut FileASTData) {
+    let test_functions: HashSet<&str> = ast_data
+        .functions
+        .iter()
+        .filter(|(_, func)| is_test_function(func))
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let inside_test_function =
+        |name: &str| name.match_indices("::").any(|(index, _)| test_functions.contains(&name[..index]));
+
+    let mut test_entities: HashMap<&'static str, HashSet<String>> = HashMap::new();
+    for (kind, hashes) in &ast_data.content_hashes {
+        for name in hashes.keys() {
+            let is_test = cfg::gated_by_test(name)
+                || (*kind == "function" && (test_functions.contains(name.as_str()) || inside_test_function(name)));
+            if is_test {
+                test_entities.entry(*kind).or_default().insert(name.clone());
+            }
+        }
+    }
+    ast_data.test_entities = test_entities;
+}
+
+// Whether a function carries a test attribute, such as `#[test]`, `#[tokio::test]` or
+// `#[async_std::test]`
+fn is_test_function(func: &ItemFn) -> bool {
+    func.attrs.iter().any(|attr| {
+        attr.path()
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "test")
+    })
+}
+
+// Count the tokens of some code, with the delimiters of each group as tokens of their own, so
+// code can be compared regardless of spacing and layout
+pub fn token_counts(tokens: TokenStream) -> HashMap<String, usize> {
+    fn count(tokens: TokenStream, counts: &mut HashMap<String, usize>) {
+        for token in tokens {
+            match token {
+                TokenTree::Group(group) => {
+                    let delimiters = match group.delimiter() {
+                        Delimiter::Parenthesis => Some(("(", ")")),
+                        Delimiter::Brace => Some(("{", "}")),
+                        Delimiter::Bracket => Some(("[", "]")),
+                        Delimiter::None => None,
+                    };
+                    if let Some((open, close)) = delimiters {
+                        *counts.entry(open.to_string()).or_default() += 1;
+                        *counts.entry(close.to_string()).or_default() += 1;
+                    }
+                    count(group.stream(), counts);
+                }
+                token => *counts.entry(token.to_string()).or_default() += 1,
+            }
+        }
+    }
+
+    let mut counts = HashMap::new();
+    count(tokens, &mut counts);
+    counts
+}
+
+// Similarity of two pieces of code as a percentage: the tokens they share out of all the tokens
+// either has, counting repeated tokens
+pub fn token_similarity(old: &HashMap<String, usize>, new: &HashMap<String, usize>) -> u32 {
+    let shared: usize = old
+        .iter()
+        .map(|(token, count)| (*count).min(new.get(token).copied().unwrap_or(0)))
+        .sum();
+    let total = old.values().sum::<usize>() + new.values().sum::<usize>() - shared;
+    if total == 0 {
+        return 100;
+    }
+    (shared * 100 / total) as u32
+}
+
+// Largest token diff worked out in full, as the product of the lengths of the differing middles;
+// bigger ones fall back to comparing token counts
+const MAX_TOKEN_DIFF_CELLS: usize = 16_000_000;
+
+// The tokens of some code in order, with the delimiters of each group as tokens of their own
+pub fn token_sequence(tokens: TokenStream) -> Vec<String> {
+    fn flatten(tokens: TokenStream, sequence: &mut Vec<String>) {
+        for token in tokens {
+            match token {
+                TokenTree::Group(group) => {
+                    let delimiters = match group.delimiter() {
+                        Delimiter::Parenthesis => Some(("(", ")")),
+                        Delimiter::Brace => Some(("{", "}")),
+                        Delimiter::Bracket => Some(("[", "]")),
+                        Delimiter::None => None,
+                    };
+                    match delimiters {
+                        Some((open, close)) => {
+                            sequence.push(open.to_string());
+                            flatten(group.stream(), sequence);
+                            sequence.push(close.to_string());
+                        }
+                        None => flatten(group.stream(), sequence),
+                    }
+                }
+                token => sequence.push(token.to_string()),
+            }
+        }
+    }
+
+    let mut sequence = Vec::new();
+    flatten(tokens, &mut sequence);
+    sequence
+}
+
+// Similarity of two token sequences as a percentage, from a token-level diff: twice the tokens
+// kept in order out of the tokens both sides have
+pub fn sequence_similarity(old: &[String], new: &[String]) -> u32 {
+    let total = old.len() + new.len();
+    if total == 0 {
+        return 100;
+    }
+
+    // The common prefix and suffix are kept as they are
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let suffix = old_rest.iter().rev().zip(new_rest.iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old_middle, new_middle) = (&old_rest[..old_rest.len() - suffix], &new_rest[..new_rest.len() - suffix]);
+
+    let kept_middle = if old_middle.len().saturating_mul(new_middle.len()) > MAX_TOKEN_DIFF_CELLS {
+        fn counts(tokens: &[String]) -> HashMap<&String, usize> {
+            let mut counts = HashMap::new();
+            for token in tokens {
+                *counts.entry(token).or_default() += 1;
+            }
+            counts
+        }
+        let new_counts = counts(new_middle);
+        counts(old_middle)
+            .iter()
+            .map(|(token, count)| (*count).min(new_counts.get(token).copied().unwrap_or(0)))
+            .sum()
+    } else {
+        longest_common_subsequence(old_middle, new_middle)
+    };
+
+    ((prefix + suffix + kept_middle) * 200 / total) as u32
+}
+
+// Length of the longest common subsequence of two token sequences, one row at a time
+fn longest_common_subsequence(old: &[String], new: &[String]) -> usize {
+    let mut previous = vec![0; new.len() + 1];
+    let mut current = vec![0; new.len() + 1];
+    for old_token in old {
+        for (index, new_token) in new.iter().enumerate() {
+            current[index + 1] = if old_token == new_token {
+                previous[index] + 1
+            } else {
+                previous[index + 1].max(current[index])
+            };
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[new.len()]
+}
+
+// Stable hash of a node's tokens as 16 hex digits, ignoring spans and how punctuation is
+// joined, so equal code hashes the same wherever it sits in a file
+pub fn content_hash(node: &impl ToTokens) -> String {
+    let mut hash = FNV_OFFSET_BASIS;
+    hash_tokens(node.to_token_stream(), &mut hash);
+    format!("{:016x}", hash)
+}
+
+// Stable hash of a text as 16 hex digits
+pub fn text_hash(text: &str) -> String {
+    let hash = text
+        .bytes()
+        .fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME));
+    format!("{:016x}", hash)
+}
+
+// Feed tokens into an FNV-1a hash, tagging each kind of token so that adjacent tokens
+// can't run together
+fn hash_tokens(tokens: TokenStream, hash: &mut u64) {
+    fn write(hash: &mut u64, bytes: &[u8]) {
+        for byte in bytes {
+            *hash ^= u64::from(*byte);
+            *hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    for token in tokens {
+        match token {
+            TokenTree::Group(group) => {
+                let (open, close): (&[u8], &[u8]) = match group.delimiter() {
+                    Delimiter::Parenthesis => (b"(", b")"),
+                    Delimiter::Brace => (b"{", b"}"),
+                    Delimiter::Bracket => (b"[", b"]"),
+                    Delimiter::None => (b"\x01", b"\x02"),
+                };
+                write(hash, open);
+                hash_tokens(group.stream(), hash);
+                write(hash, close);
+            }
+            TokenTree::Ident(ident) => {
+                write(hash, b"i");
+                write(hash, ident.to_string().as_bytes());
+                write(hash, b"\xff");
+            }
+            TokenTree::Punct(punct) => {
+                write(hash, b"p");
+                write(hash, punct.as_char().to_string().as_bytes());
+            }
+            TokenTree::Literal(literal) => {
+                write(hash, b"l");
+                write(hash, literal.to_string().as_bytes());
+                write(hash, b"\xff");
+            }
+        }
+    }
+}
+
+// Whether two token streams hold the same tokens, ignoring how punctuation is joined
+fn same_tokens(a: &TokenStream, b: &TokenStream) -> bool {
+    let mut b = b.clone().into_iter();
+    for a in a.clone() {
+        let same = match (a, b.next()) {
+            (TokenTree::Group(a), Some(TokenTree::Group(b))) => {
+                a.delimiter() == b.delimiter() && same_tokens(&a.stream(), &b.stream())
+            }
+            (TokenTree::Punct(a), Some(TokenTree::Punct(b))) => a.as_char() == b.as_char(),
+            (TokenTree::Ident(a), Some(TokenTree::Ident(b))) => a == b,
+            (TokenTree::Literal(a), Some(TokenTree::Literal(b))) => a.to_string() == b.to_string(),
+            _ => false,
+        };
+        if !same {
+            return false;
+        }
+    }
+    b.next().is_none()
+}
+
+// Byte offset in a file of a span location's 1-based line and 0-based column, which
+// counts characters rather than bytes
+fn byte_offset(file_content: &str, line: usize, column: usize) -> Option<usize> {
+    let line_start = match line {
+        0 => return None,
+        1 => 0,
+        _ => file_content.match_indices('\n').nth(line - 2)?.0 + 1,
+    };
+    let line_text = file_content[line_start..].split('\n').next().unwrap_or("");
+
+    match line_text.char_indices().nth(column) {
+        Some((offset, _)) => Some(line_start + offset),
+        // A span may end just past the last character of its line
+        None if line_text.chars().count() == column => Some(line_start + line_text.len()),
+        None => None,
+    }
+}
 
 // Get source location from a span
 pub fn get_source_location(span: Span, filename: &str) -> SourceLocation {
@@ -226,19 +1397,34 @@ pub fn get_source_location(span: Span, filename: &str) -> SourceLocation {
     }
 }
 
-// Extract function calls from a function
-pub fn extract_function_calls(func: &ItemFn) -> Vec<String> {
-    let mut visitor = FunctionCallVisitor { calls: Vec::new() };
+// Extract function calls from a function, along with the argument count of each path and
+// method call and the declared type of the receivers it can find
+pub fn extract_function_calls(func: &ItemFn) -> FunctionCallVisitor {
+    let mut visitor = FunctionCallVisitor {
+        calls: Vec::new(),
+        call_sites: Vec::new(),
+        bindings: HashMap::new(),
+    };
 
+    // Parameters are in scope throughout; typed `let` bindings are added as they're reached
+    for input in &func.sig.inputs {
+        match input {
+            FnArg::Receiver(receiver) => {
+                visitor.bindings.insert("self".to_string(), format_compact(&receiver.ty));
+            }
+            FnArg::Typed(pat_type) => visitor.bind(pat_type),
+        }
+    }
     visitor.visit_item_fn(func);
 
-    visitor.calls
+    visitor
 }
 
-// Extract literals from a function
-pub fn extract_literals(func: &ItemFn) -> Vec<TypedLiteral> {
+// Extract literals from a function in a file
+pub fn extract_literals(func: &ItemFn, file_path: &str) -> Vec<TypedLiteral> {
     let mut visitor = LiteralVisitor {
         literals: Vec::new(),
+        file_path: file_path.to_string(),
     };
 
     visitor.visit_item_fn(func);
@@ -246,6 +1432,64 @@ pub fn extract_literals(func: &ItemFn) -> Vec<TypedLiteral> {
     visitor.literals
 }
 
+// Extract the await points and `?` error propagations from a function, each named by the
+// expression it applies to, e.g. `client.send(request)`
+pub fn extract_propagation_points(func: &ItemFn) -> PropagationVisitor {
+    let mut visitor = PropagationVisitor {
+        awaits: Vec::new(),
+        error_propagations: Vec::new(),
+    };
+
+    visitor.visit_item_fn(func);
+
+    visitor
+}
+
+// Extract the expressions of a function of a file that can panic, each with its code and location
+pub fn extract_panic_points(func: &ItemFn, ast: &FileASTData) -> Vec<PanicPoint> {
+    let mut visitor = PanicVisitor {
+        points: Vec::new(),
+        file_path: &ast.file_path,
+    };
+
+    visitor.visit_item_fn(func);
+
+    visitor.points
+}
+
+// Extract the unsafe blocks of a function of a file and the calls made in unsafe context:
+// inside those blocks, or anywhere in the body of an `unsafe fn`. Calls to unsafe functions
+// can only be made there, so these are all the unsafe call sites.
+pub fn extract_unsafe_regions<'a>(func: &ItemFn, ast: &'a FileASTData) -> UnsafeVisitor<'a> {
+    let mut visitor = UnsafeVisitor {
+        blocks: Vec::new(),
+        calls: Vec::new(),
+        file_path: &ast.file_path,
+        file_content: &ast.file_content,
+    };
+
+    if func.sig.unsafety.is_some() {
+        visitor.calls = calls_in_block(&func.block);
+    }
+    visitor.visit_item_fn(func);
+
+    visitor.calls = remove_duplicates(visitor.calls);
+    visitor
+}
+
+// Calls made in a block
+fn calls_in_block(block: &Block) -> Vec<String> {
+    let mut visitor = FunctionCallVisitor {
+        calls: Vec::new(),
+        call_sites: Vec::new(),
+        bindings: HashMap::new(),
+    };
+
+    visitor.visit_block(block);
+
+    visitor.calls
+}
+
 // Remove duplicates from a vector of strings
 pub fn remove_duplicates(strings: Vec<String>) -> Vec<String> {
     let mut seen = std::collections::HashSet::new();
@@ -261,21 +1505,201 @@ pub fn remove_duplicates(strings: Vec<String>) -> Vec<String> {
     result
 }
 
+// Format an AST node compactly for use in a name, e.g. `From<Vec<u8>>` rather than
+// `From < Vec < u8 > >`: spaces are only kept between words, after commas, semicolons and
+// a lone `:`, and around a lone `=` or `+`
+pub fn format_compact(node: &impl ToTokens) -> String {
+    let mut compact = String::new();
+    write_compact(node.to_token_stream(), &mut compact);
+    compact
+}
+
+// Append tokens to a compact rendering, see format_compact
+fn write_compact(tokens: TokenStream, compact: &mut String) {
+    // A lifetime's quote starts a word but doesn't end one
+    let ends_word = |c: char| c.is_alphanumeric() || c == '_' || c == '"';
+    let starts_word = |c: char| ends_word(c) || c == '\'';
+
+    for token in tokens {
+        let text = match &token {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                let opens_block = group.delimiter() == Delimiter::Brace && !compact.is_empty();
+                if compact.ends_with([',', ';', '=']) || opens_block {
+                    compact.push(' ');
+                }
+                compact.push_str(open);
+                write_compact(group.stream(), compact);
+                compact.push_str(close);
+                continue;
+            }
+            TokenTree::Punct(punct) if punct.as_char() == '=' && punct.spacing() == Spacing::Alone => {
+                if !compact.ends_with(['=', '!', '<', '>']) {
+                    compact.push_str(" = ");
+                    continue;
+                }
+                "=".to_string()
+            }
+            // Bounds read `T: Clone + Send`, while `::` paths stay tight
+            TokenTree::Punct(punct) if punct.as_char() == ':' && punct.spacing() == Spacing::Alone => {
+                if !compact.ends_with(':') {
+                    compact.push_str(": ");
+                    continue;
+                }
+                ":".to_string()
+            }
+            TokenTree::Punct(punct) if punct.as_char() == '+' && punct.spacing() == Spacing::Alone => {
+                compact.push_str(" + ");
+                continue;
+            }
+            token => token.to_string(),
+        };
+
+        let after_word = compact.ends_with(ends_word);
+        let after_separator = compact.ends_with([',', ';']);
+        if (after_word && text.starts_with(starts_word)) || (after_separator && !text.starts_with([',', ';'])) {
+            compact.push(' ');
+        }
+        compact.push_str(&text);
+    }
+}
+
+// Split the outer attributes off the front of formatted code: doc comments as their text,
+// other attributes in compact form, and the code that follows them formatted like format_node
+pub fn split_outer_attributes(code: &str) -> Option<OuterAttributes> {
+    let tokens: Vec<TokenTree> = code.parse::<TokenStream>().ok()?.into_iter().collect();
+
+    let mut docs = Vec::new();
+    let mut attributes = Vec::new();
+    let mut rest = tokens.as_slice();
+    while let [TokenTree::Punct(pound), TokenTree::Group(group), remaining @ ..] = rest {
+        if pound.as_char() != '#' || group.delimiter() != Delimiter::Bracket {
+            break;
+        }
+        match doc_text(group.stream()) {
+            Some(line) => docs.push(line),
+            None => attributes.push(format_compact(&TokenStream::from_iter(rest[..2].iter().cloned()))),
+        }
+        rest = remaining;
+    }
+
+    // `pub` may be restricted, as in `pub(crate)` or `pub(in crate::a)`
+    let visibility = match rest {
+        [TokenTree::Ident(ident), TokenTree::Group(group), ..]
+            if ident == "pub" && group.delimiter() == Delimiter::Parenthesis =>
+        {
+            format_compact(&TokenStream::from_iter(rest[..2].iter().cloned()))
+        }
+        [TokenTree::Ident(ident), ..] if ident == "pub" => "pub".to_string(),
+        _ => "private".to_string(),
+    };
+
+    Some(OuterAttributes {
+        docs: docs.join("\n"),
+        attributes,
+        visibility,
+        code: rest.iter().cloned().collect::<TokenStream>().to_string(),
+    })
+}
+
+// Text of a `doc = "..."` attribute body, which is what `///` comments become
+fn doc_text(attribute: TokenStream) -> Option<String> {
+    let tokens: Vec<TokenTree> = attribute.into_iter().collect();
+    match tokens.as_slice() {
+        [TokenTree::Ident(ident), TokenTree::Punct(eq), TokenTree::Literal(literal)]
+            if ident == "doc" && eq.as_char() == '=' =>
+        {
+            syn::parse2::<syn::LitStr>(TokenTree::Literal(literal.clone()).into())
+                .ok()
+                .map(|text| text.value())
+        }
+        _ => None,
+    }
+}
+
+// Macros from std whose arguments are comma-separated expressions, including format strings
+const EXPRESSION_MACROS: &[&str] = &[
+    "print", "println", "eprint", "eprintln", "format", "format_args", "write", "writeln", "panic", "assert",
+    "assert_eq", "assert_ne", "debug_assert", "debug_assert_eq", "debug_assert_ne", "dbg", "todo", "unimplemented",
+    "unreachable", "vec",
+];
+
+// Expressions in the arguments of a well-known macro, so the calls and literals inside
+// `println!`, `assert_eq!`, `vec![]` or `matches!` are analyzed like any other. Other
+// macros, and invocations that don't parse, have none.
+pub fn macro_expressions(mac: &Macro) -> Vec<Expr> {
+    let Some(name) = mac.path.segments.last().map(|segment| segment.ident.to_string()) else {
+        return Vec::new();
+    };
+    let list = |input: ParseStream| {
+        Punctuated::<Expr, Token![,]>::parse_terminated(input).map(|args| args.into_iter().collect())
+    };
+
+    let parsed = match name.as_str() {
+        // `vec![value; count]` as well as a list of elements
+        "vec" => mac
+            .parse_body_with(|input: ParseStream| {
+                let value: Expr = input.parse()?;
+                input.parse::<Token![;]>()?;
+                Ok(vec![value, input.parse()?])
+            })
+            .or_else(|_| mac.parse_body_with(list)),
+        // The pattern of `matches!(value, pattern if guard)` holds no calls
+        "matches" => mac.parse_body_with(|input: ParseStream| {
+            let mut exprs = vec![input.parse::<Expr>()?];
+            input.parse::<Token![,]>()?;
+            Pat::parse_multi_with_leading_vert(input)?;
+            if input.parse::<Option<Token![if]>>()?.is_some() {
+                exprs.push(input.parse()?);
+            }
+            input.parse::<Option<Token![,]>>()?;
+            Ok(exprs)
+        }),
+        name if EXPRESSION_MACROS.contains(&name) => mac.parse_body_with(list),
+        _ => return Vec::new(),
+    };
+    parsed.unwrap_or_default()
+}
+
 // Format an AST node as a string
 pub fn format_node<T: syn::parse::Parse + quote::ToTokens>(node: &T) -> String {
     // Since node already implements ToTokens, we can directly use quote! on it
     quote::quote!(#node).to_string()
 }
 
+// Implementation for the nested function visitor
+impl<'ast> Visit<'ast> for NestedFnVisitor {
+    // Items inside a body aren't walked; nested functions are processed on their own
+    fn visit_item(&mut self, item: &'ast Item) {
+        if let Item::Fn(func) = item {
+            self.functions.push(func.clone());
+        }
+    }
+
+    // Only closures with a block body are significant enough to extract
+    fn visit_local(&mut self, local: &'ast Local) {
+        if let (Pat::Ident(binding), Some(init)) = (&local.pat, &local.init) {
+            if let Expr::Closure(closure) = &*init.expr {
+                if matches!(&*closure.body, Expr::Block(_)) {
+                    self.closures.push((binding.ident.clone(), closure.clone()));
+                }
+            }
+        }
+        visit::visit_local(self, local);
+    }
+}
+
 // Implementation for the literal visitor
 impl<'ast> Visit<'ast> for LiteralVisitor {
     fn visit_expr_struct(&mut self, expr: &'ast syn::ExprStruct) {
         if let Some(path_segment) = expr.path.segments.last() {
             let struct_name = path_segment.ident.to_string();
-            self.literals.push(TypedLiteral {
-                type_name: format!("CompositeLit:{}", struct_name),
-                value: "struct-literal".to_string(),
-            });
+            self.push_literal(format!("CompositeLit:{}", struct_name), "struct-literal".to_string(), expr.span());
         }
 
         for field in &expr.fields {
@@ -284,10 +1708,7 @@ impl<'ast> Visit<'ast> for LiteralVisitor {
     }
 
     fn visit_expr_array(&mut self, expr: &'ast syn::ExprArray) {
-        self.literals.push(TypedLiteral {
-            type_name: "CompositeLit:Array".to_string(),
-            value: "array-literal".to_string(),
-        });
+        self.push_literal("CompositeLit:Array".to_string(), "array-literal".to_string(), expr.span());
 
         for elem in &expr.elems {
             visit::visit_expr(self, elem);
@@ -295,10 +1716,7 @@ impl<'ast> Visit<'ast> for LiteralVisitor {
     }
 
     fn visit_expr_tuple(&mut self, expr: &'ast syn::ExprTuple) {
-        self.literals.push(TypedLiteral {
-            type_name: "CompositeLit:Tuple".to_string(),
-            value: "tuple-literal".to_string(),
-        });
+        self.push_literal("CompositeLit:Tuple".to_string(), "tuple-literal".to_string(), expr.span());
 
         for elem in &expr.elems {
             visit::visit_expr(self, elem);
@@ -307,58 +1725,31 @@ impl<'ast> Visit<'ast> for LiteralVisitor {
     fn visit_lit(&mut self, lit: &'ast syn::Lit) {
         match lit {
             syn::Lit::Str(lit_str) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "STRING".to_string(),
-                    value: lit_str.value(),
-                });
+                self.push_lit("STRING".to_string(), lit_str.value(), lit);
             }
             syn::Lit::Int(lit_int) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "INT".to_string(),
-                    value: lit_int.base10_digits().to_string(),
-                });
+                self.push_lit("INT".to_string(), lit_int.base10_digits().to_string(), lit);
             }
             syn::Lit::Float(lit_float) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "FLOAT".to_string(),
-                    value: lit_float.base10_digits().to_string(),
-                });
+                self.push_lit("FLOAT".to_string(), lit_float.base10_digits().to_string(), lit);
             }
             syn::Lit::Bool(lit_bool) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "BOOL".to_string(),
-                    value: lit_bool.value.to_string(),
-                });
+                self.push_lit("BOOL".to_string(), lit_bool.value.to_string(), lit);
             }
             syn::Lit::Char(lit_char) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "CHAR".to_string(),
-                    value: lit_char.value().to_string(),
-                });
+                self.push_lit("CHAR".to_string(), lit_char.value().to_string(), lit);
             }
             syn::Lit::Byte(lit_byte) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "BYTE".to_string(),
-                    value: lit_byte.value().to_string(),
-                });
+                self.push_lit("BYTE".to_string(), lit_byte.value().to_string(), lit);
             }
             syn::Lit::CStr(lit_byte) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "BYTE".to_string(),
-                    value: format!("{:?}", lit_byte.value()),
-                });
+                self.push_lit("BYTE".to_string(), format!("{:?}", lit_byte.value()), lit);
             }
             syn::Lit::ByteStr(lit_byte) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "BYTE_STR".to_string(),
-                    value: format!("{:?}", lit_byte.value()),
-                });
+                self.push_lit("BYTE_STR".to_string(), format!("{:?}", lit_byte.value()), lit);
             }
             syn::Lit::Verbatim(lit_byte) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "VERBATIM".to_string(),
-                    value: format!("{:?}", lit_byte),
-                });
+                self.push_lit("VERBATIM".to_string(), format!("{:?}", lit_byte), lit);
             }
             _ => {}
         }
@@ -366,31 +1757,156 @@ impl<'ast> Visit<'ast> for LiteralVisitor {
         // Continue visiting children
         visit::visit_lit(self, lit);
     }
+
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        for expr in macro_expressions(mac) {
+            self.visit_expr(&expr);
+        }
+    }
 }
+// Implementation for the await and `?` visitor
+impl<'ast> Visit<'ast> for PropagationVisitor {
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        match expr {
+            Expr::Await(expr_await) => self.awaits.push(format_compact(&expr_await.base)),
+            Expr::Try(expr_try) => self.error_propagations.push(format_compact(&expr_try.expr)),
+            _ => {}
+        }
+        visit::visit_expr(self, expr);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        for expr in macro_expressions(mac) {
+            self.visit_expr(&expr);
+        }
+    }
+}
+
+// Macros that panic when reached
+const PANIC_MACROS: &[&str] = &["panic", "todo", "unimplemented", "unreachable"];
+
+// Methods that panic on `None` or `Err`
+const PANIC_METHODS: &[&str] = &["unwrap", "expect", "unwrap_err", "expect_err"];
+
+impl PanicVisitor<'_> {
+    fn push_point(&mut self, kind: &str, node: &impl ToTokens) {
+        self.points.push(PanicPoint {
+            kind: kind.to_string(),
+            code: format_compact(node),
+            location: get_source_location(node.span(), self.file_path),
+        });
+    }
+}
+
+// Implementation for the panic visitor
+impl<'ast> Visit<'ast> for PanicVisitor<'_> {
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        match expr {
+            Expr::MethodCall(method_call) => {
+                let method = method_call.method.to_string();
+                if PANIC_METHODS.contains(&method.as_str()) {
+                    self.push_point(method.split('_').next().unwrap_or(&method), method_call);
+                }
+            }
+            Expr::Index(index) => {
+                // Slicing the whole of something, as in `v[..]`, can't go out of bounds
+                let full_range =
+                    matches!(&*index.index, Expr::Range(range) if range.start.is_none() && range.end.is_none());
+                if !full_range {
+                    self.push_point("index", index);
+                }
+            }
+            _ => {}
+        }
+        visit::visit_expr(self, expr);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        if let Some(name) = mac.path.segments.last().map(|segment| segment.ident.to_string()) {
+            if PANIC_MACROS.contains(&name.as_str()) {
+                self.push_point(&name, mac);
+            }
+        }
+        for expr in macro_expressions(mac) {
+            self.visit_expr(&expr);
+        }
+    }
+}
+
+// Implementation for the lifetime visitor
+impl<'ast> Visit<'ast> for LifetimeVisitor {
+    fn visit_type_reference(&mut self, reference: &'ast TypeReference) {
+        if reference.lifetime.is_none() {
+            self.lifetimes.push("'_".to_string());
+        }
+        visit::visit_type_reference(self, reference);
+    }
+
+    fn visit_lifetime(&mut self, lifetime: &'ast Lifetime) {
+        self.lifetimes.push(format_compact(lifetime));
+    }
+}
+
+// Implementation for the unsafe visitor
+impl<'ast> Visit<'ast> for UnsafeVisitor<'_> {
+    fn visit_expr_unsafe(&mut self, expr: &'ast ExprUnsafe) {
+        self.blocks.push(UnsafeRegion {
+            code: get_source_code(expr, self.file_content),
+            hash: content_hash(expr),
+            location: get_source_location(expr.span(), self.file_path),
+        });
+        self.calls.extend(calls_in_block(&expr.block));
+        visit::visit_expr_unsafe(self, expr);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        for expr in macro_expressions(mac) {
+            self.visit_expr(&expr);
+        }
+    }
+}
+
 impl<'ast> Visit<'ast> for FunctionCallVisitor {
     fn visit_expr(&mut self, expr: &'ast Expr) {
         match expr {
             Expr::Call(expr_call) => self.process_call(expr_call),
             Expr::MethodCall(method_call) => self.process_method_call(method_call),
-            Expr::Macro(expr_macro) => self.process_macro(expr_macro),
             _ => visit::visit_expr(self, expr),
         }
     }
+
+    // Macros in expression and statement position alike
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        self.process_macro(mac);
+    }
+
+    fn visit_local(&mut self, local: &'ast Local) {
+        if let Pat::Type(pat_type) = &local.pat {
+            self.bind(pat_type);
+        }
+        visit::visit_local(self, local);
+    }
 }
 
 impl FunctionCallVisitor {
+    // Remember the declared type of a binding like `client: &Client`
+    fn bind(&mut self, pat_type: &PatType) {
+        if let Pat::Ident(binding) = &*pat_type.pat {
+            self.bindings.insert(binding.ident.to_string(), format_compact(&pat_type.ty));
+        }
+    }
+
     fn process_call(&mut self, call: &ExprCall) {
         match &*call.func {
             Expr::Path(expr_path) => {
-                let path = &expr_path.path;
-                if let Some(last_segment) = path.segments.last() {
-                    let full_path = path
-                        .segments
-                        .iter()
-                        .map(|seg| seg.ident.to_string())
-                        .collect::<Vec<_>>()
-                        .join("::");
-                    self.calls.push(full_path);
+                if !expr_path.path.segments.is_empty() {
+                    let name = call_path(expr_path);
+                    self.calls.push(name.clone());
+                    self.call_sites.push(CallSite {
+                        name,
+                        arg_count: call.args.len(),
+                        receiver_type: None,
+                    });
                 }
             }
             Expr::Field(expr_field) => {
@@ -433,6 +1949,23 @@ impl FunctionCallVisitor {
             _ => self.calls.push(format!("expr.{}", method_name)),
         }
 
+        // Only a receiver named by a typed binding has a known type
+        let receiver_type = match &*call.receiver {
+            Expr::Path(base_path) => base_path
+                .path
+                .get_ident()
+                .and_then(|ident| self.bindings.get(&ident.to_string()))
+                .cloned(),
+            _ => None,
+        };
+        if let Some(name) = self.calls.last().cloned() {
+            self.call_sites.push(CallSite {
+                name,
+                arg_count: call.args.len(),
+                receiver_type,
+            });
+        }
+
         // Visit receiver and arguments recursively
         visit::visit_expr(self, &call.receiver);
         for arg in &call.args {
@@ -440,12 +1973,15 @@ impl FunctionCallVisitor {
         }
     }
 
-    fn process_macro(&mut self, mac: &ExprMacro) {
-        if let Some(macro_name) = mac.mac.path.segments.last() {
+    fn process_macro(&mut self, mac: &Macro) {
+        if let Some(macro_name) = mac.path.segments.last() {
             self.calls.push(format!("macro!{}", macro_name.ident));
         }
 
-        // We don't need to visit the tokens inside the macro
+        // Only the arguments of well-known macros are parsed and visited
+        for expr in macro_expressions(mac) {
+            self.visit_expr(&expr);
+        }
     }
 
     fn process_field_call(&mut self, expr_field: &ExprField) {
@@ -473,6 +2009,25 @@ impl FunctionCallVisitor {
     }
 }
 
+// Name of a called path, e.g. `Vec::new`. Fully-qualified calls keep their self type and
+// trait, as in `<T as Display>::fmt` or `<[u8]>::len`, so they're tracked like any other.
+fn call_path(expr_path: &ExprPath) -> String {
+    let segments: Vec<String> = expr_path.path.segments.iter().map(|seg| seg.ident.to_string()).collect();
+
+    match &expr_path.qself {
+        Some(qself) => {
+            let self_type = format_compact(&qself.ty);
+            let (trait_path, rest) = segments.split_at(qself.position.min(segments.len()));
+            if trait_path.is_empty() {
+                format!("<{}>::{}", self_type, rest.join("::"))
+            } else {
+                format!("<{} as {}>::{}", self_type, trait_path.join("::"), rest.join("::"))
+            }
+        }
+        None => segments.join("::"),
+    }
+}
+
 // impl<'ast> Visit<'ast> for LiteralVisitor {
 //     fn visit_expr(&mut self, expr: &'ast Expr) {
 //         match expr {
@@ -487,63 +2042,132 @@ impl FunctionCallVisitor {
 // }
 
 impl LiteralVisitor {
+    // Record a literal along with where it sits in the file
+    fn push_literal(&mut self, type_name: String, value: String, span: Span) {
+        let location = get_source_location(span, &self.file_path);
+        self.literals.push(TypedLiteral {
+            type_name,
+            value,
+            suffix: String::new(),
+            raw: false,
+            location,
+        });
+    }
+
+    // Record a literal token, keeping its type suffix (`1u8`, `2.0f32`) and whether it's a
+    // raw string (`r"..."`, `br#"..."#`), which change its type or meaning but not its value
+    fn push_lit(&mut self, type_name: String, value: String, lit: &Lit) {
+        self.push_literal(type_name, value, lit.span());
+        if let Some(literal) = self.literals.last_mut() {
+            literal.suffix = lit.suffix().to_string();
+            literal.raw = matches!(lit, Lit::Str(_) | Lit::ByteStr(_) | Lit::CStr(_))
+                && lit.to_token_stream().to_string().trim_start_matches(['b', 'c']).starts_with('r');
+        }
+    }
+
     fn process_lit(&mut self, lit: &Lit) {
         match lit {
             Lit::Str(lit_str) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "STRING".to_string(),
-                    value: lit_str.value(),
-                });
+                self.push_lit("STRING".to_string(), lit_str.value(), lit);
             }
             Lit::ByteStr(lit_byte_str) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "BYTE_STR".to_string(),
-                    value: format!("b\"{}\"", String::from_utf8_lossy(&lit_byte_str.value())),
-                });
+                let value = format!("b\"{}\"", String::from_utf8_lossy(&lit_byte_str.value()));
+                self.push_lit("BYTE_STR".to_string(), value, lit);
             }
             Lit::Byte(lit_byte) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "BYTE".to_string(),
-                    value: lit_byte.value().to_string(),
-                });
+                self.push_lit("BYTE".to_string(), lit_byte.value().to_string(), lit);
             }
             Lit::Char(lit_char) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "CHAR".to_string(),
-                    value: lit_char.value().to_string(),
-                });
+                self.push_lit("CHAR".to_string(), lit_char.value().to_string(), lit);
             }
             Lit::Int(lit_int) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "INT".to_string(),
-                    value: lit_int.base10_digits().to_string(),
-                });
+                self.push_lit("INT".to_string(), lit_int.base10_digits().to_string(), lit);
             }
             Lit::Float(lit_float) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "FLOAT".to_string(),
-                    value: lit_float.base10_digits().to_string(),
-                });
+                self.push_lit("FLOAT".to_string(), lit_float.base10_digits().to_string(), lit);
             }
             Lit::Bool(lit_bool) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "BOOL".to_string(),
-                    value: lit_bool.value.to_string(),
-                });
+                self.push_lit("BOOL".to_string(), lit_bool.value.to_string(), lit);
             }
             Lit::Verbatim(lit_verbatim) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "VERBATIM".to_string(),
-                    value: lit_verbatim.to_string(),
-                });
+                self.push_lit("VERBATIM".to_string(), lit_verbatim.to_string(), lit);
             }
             Lit::CStr(lit_cstr) => {
-                self.literals.push(TypedLiteral {
-                    type_name: "CSTR".to_string(),
-                    value: lit_cstr.value().into_string().unwrap(),
-                });
+                self.push_lit("CSTR".to_string(), lit_cstr.value().into_string().unwrap(), lit);
             }
             _ => todo!(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Source text get_source_code returns for each top-level item of a file
+    fn item_sources(file_content: &str) -> Vec<String> {
+        let file = syn::parse_file(file_content).expect("test source parses");
+        file.items.iter().map(|item| get_source_code(item, file_content)).collect()
+    }
+
+    #[test]
+    fn byte_offset_on_first_and_last_line() {
+        let content = "fn a() {}\nfn b() {}";
+        assert_eq!(byte_offset(content, 1, 0), Some(0));
+        assert_eq!(byte_offset(content, 1, 3), Some(3));
+        assert_eq!(byte_offset(content, 2, 0), Some(10));
+        // Just past the last character of the file
+        assert_eq!(byte_offset(content, 2, 9), Some(content.len()));
+    }
+
+    #[test]
+    fn byte_offset_out_of_range() {
+        let content = "fn a() {}\nfn b() {}";
+        assert_eq!(byte_offset(content, 0, 0), None);
+        assert_eq!(byte_offset(content, 3, 0), None);
+        assert_eq!(byte_offset(content, 1, 10), None);
+    }
+
+    #[test]
+    fn byte_offset_counts_characters_not_bytes() {
+        let content = "// é\nfn ñ() {}";
+        // `é` is two bytes but one column
+        assert_eq!(byte_offset(content, 1, 4), Some(5));
+        assert_eq!(byte_offset(content, 2, 3), Some(9));
+        assert_eq!(byte_offset(content, 2, 4), Some(11));
+    }
+
+    #[test]
+    fn source_code_with_multi_byte_characters() {
+        let content = "// größe\nfn größe() -> &'static str { \"日本語\" }\nfn ünder() {}\n";
+        assert_eq!(
+            item_sources(content),
+            vec!["fn größe() -> &'static str { \"日本語\" }", "fn ünder() {}"]
+        );
+    }
+
+    #[test]
+    fn source_code_with_crlf_line_endings() {
+        let content = "fn a() {}\r\nfn b() {\r\n    1\r\n}\r\n";
+        assert_eq!(item_sources(content), vec!["fn a() {}", "fn b() {\r\n    1\r\n}"]);
+    }
+
+    #[test]
+    fn source_code_with_tabs() {
+        let content = "\tfn a() {}\n\t\tfn b() {\n\t\t\t1\n\t\t}";
+        assert_eq!(item_sources(content), vec!["fn a() {}", "fn b() {\n\t\t\t1\n\t\t}"]);
+    }
+
+    #[test]
+    fn source_code_on_first_and_last_line() {
+        let content = "fn first() {}\nstruct Middle;\nfn last() { 1 }";
+        assert_eq!(item_sources(content), vec!["fn first() {}", "struct Middle;", "fn last() { 1 }"]);
+    }
+
+    #[test]
+    fn source_code_falls_back_to_rendered_tokens_on_mismatch() {
+        let file = syn::parse_file("fn a() {}").expect("test source parses");
+        // The span no longer points at the same tokens in different contents
+        assert_eq!(get_source_code(&file.items[0], "struct X;"), "fn a () { }");
+    }
+}
@@ -0,0 +1,138 @@
+// src/attributes.rs
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::filters::glob_match;
+use crate::git_ops::read_file_at;
+
+// AttributeRule is one pattern line of a .gitattributes file
+struct AttributeRule {
+    // Directory holding the .gitattributes file, empty for the repository root
+    dir: String,
+    pattern: String,
+    generated: Option<bool>,
+    binary: Option<bool>,
+    // Whether the file is stored through the Git LFS filter
+    lfs: Option<bool>,
+}
+
+impl AttributeRule {
+    // Patterns with a slash are anchored to the rule's directory; others match the file name at any depth
+    fn matches(&self, file: &str) -> bool {
+        let relative = if self.dir.is_empty() {
+            file
+        } else {
+            match file.strip_prefix(self.dir.as_str()).and_then(|rest| rest.strip_prefix('/')) {
+                Some(relative) => relative,
+                None => return false,
+            }
+        };
+
+        if self.pattern.contains('/') {
+            glob_match(self.pattern.trim_start_matches('/'), relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            glob_match(&self.pattern, name)
+        }
+    }
+}
+
+// GitAttributes decides which files are generated or binary and stay out of the analysis
+pub struct GitAttributes {
+    rules: Vec<AttributeRule>,
+}
+
+impl GitAttributes {
+    // Load the .gitattributes files at a ref that apply to the given files, followed by the
+    // user-configured generated-file patterns so those take precedence
+    pub fn load(reference: &str, files: &[String], generated_patterns: &[String], local_path: &str) -> Self {
+        // Parent directories sort before their children, so deeper files override shallower ones
+        let dirs: BTreeSet<&str> = files
+            .iter()
+            .flat_map(|file| Path::new(file).ancestors().skip(1))
+            .filter_map(|dir| dir.to_str())
+            .chain([""])
+            .collect();
+
+        let mut rules = Vec::new();
+        for dir in dirs {
+            let attributes_path = Path::new(dir).join(".gitattributes");
+            // Most directories have no .gitattributes file
+            if let Ok(content) = read_file_at(reference, attributes_path.to_str().unwrap_or(""), local_path) {
+                rules.extend(parse_rules(dir, &content));
+            }
+        }
+
+        rules.extend(generated_patterns.iter().map(|pattern| AttributeRule {
+            dir: String::new(),
+            pattern: pattern.clone(),
+            generated: Some(true),
+            binary: None,
+            lfs: None,
+        }));
+
+        GitAttributes { rules }
+    }
+
+    // Why a file is left out of the analysis, if it is; the last matching rule wins per attribute
+    pub fn skip_reason(&self, file: &str) -> Option<&'static str> {
+        let mut generated = false;
+        let mut binary = false;
+        for rule in self.rules.iter().filter(|rule| rule.matches(file)) {
+            generated = rule.generated.unwrap_or(generated);
+            binary = rule.binary.unwrap_or(binary);
+        }
+
+        if generated {
+            Some("generated")
+        } else if binary {
+            Some("binary")
+        } else {
+            None
+        }
+    }
+
+    // Whether a file is stored as a Git LFS pointer, going by its `filter` attribute
+    pub fn is_lfs(&self, file: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(file))
+            .fold(false, |lfs, rule| rule.lfs.unwrap_or(lfs))
+    }
+}
+
+// Parse the generated, binary and LFS filter attributes out of a .gitattributes file
+fn parse_rules(dir: &str, content: &str) -> Vec<AttributeRule> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut tokens = line.split_whitespace();
+            // Comments, macro definitions and directory patterns never match a file
+            let pattern = tokens
+                .next()
+                .filter(|p| !p.starts_with('#') && !p.starts_with("[attr]") && !p.ends_with('/'))?;
+
+            let mut rule = AttributeRule {
+                dir: dir.to_string(),
+                pattern: pattern.to_string(),
+                generated: None,
+                binary: None,
+                lfs: None,
+            };
+            for attribute in tokens {
+                match attribute {
+                    "linguist-generated" | "linguist-generated=true" => rule.generated = Some(true),
+                    "-linguist-generated" | "!linguist-generated" | "linguist-generated=false" => {
+                        rule.generated = Some(false)
+                    }
+                    "binary" | "-diff" => rule.binary = Some(true),
+                    "diff" | "!diff" => rule.binary = Some(false),
+                    "-filter" | "!filter" => rule.lfs = Some(false),
+                    filter if filter.starts_with("filter=") => rule.lfs = Some(filter == "filter=lfs"),
+                    _ => {}
+                }
+            }
+            Some(rule)
+        })
+        .collect()
+}
@@ -0,0 +1,116 @@
+// src/blame.rs
+use proc_macro2::Span;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+use syn::spanned::Spanned;
+
+use crate::content::load_file_contents;
+use crate::differ::parse_or_empty;
+use crate::git_ops::{get_blame, BlameHunk};
+use crate::types::{AnalysisContext, DetailedChanges, EntityBlame, FileASTData};
+
+// Attach the commit that last touched each added or modified function and method,
+// so reviews can be routed to whoever wrote the code
+pub fn annotate_changes(all_changes: &mut [DetailedChanges], ctx: &AnalysisContext) {
+    let files: Vec<String> = all_changes
+        .iter()
+        .filter(|changes| has_blamed_entities(changes))
+        .map(|changes| changes.module_name.clone())
+        .collect();
+    if files.is_empty() {
+        return;
+    }
+    eprintln!("Blaming changed functions in {} files", files.len());
+
+    // Line ranges come from the sources at the current commit
+    let no_skips = HashMap::new();
+    let contents = match load_file_contents(
+        ctx.content_source,
+        ctx.lfs,
+        ctx.current_commit,
+        &files,
+        ctx.local_repo_path,
+        &no_skips,
+    ) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Error loading files at {} for blame: {}", ctx.current_commit, e);
+            ctx.errors.record("blame", None, Some(ctx.current_commit), &e);
+            return;
+        }
+    };
+
+    all_changes
+        .par_iter_mut()
+        .filter(|changes| has_blamed_entities(changes))
+        .for_each(|changes| {
+            let file = &changes.module_name;
+            let hunks = match get_blame(ctx.current_commit, file, ctx.local_repo_path) {
+                Ok(hunks) => hunks,
+                Err(e) => {
+                    eprintln!("Warning: {}", e);
+                    ctx.errors.record("blame", Some(file), Some(ctx.current_commit), &e);
+                    return;
+                }
+            };
+
+            let full_path = Path::new(ctx.local_repo_path).join(file);
+            let ast = parse_or_empty(
+                file,
+                full_path.to_str().unwrap_or(""),
+                changes.module_path.as_deref(),
+                contents.get(file),
+                ctx.current_commit,
+                ctx.errors,
+            );
+            changes.blame = blame_entities(changes, &ast, &hunks);
+        });
+}
+
+// Only added and modified functions and methods exist at the current commit
+fn has_blamed_entities(changes: &DetailedChanges) -> bool {
+    !changes.added_functions.is_empty()
+        || !changes.modified_functions.is_empty()
+        || !changes.added_methods.is_empty()
+        || !changes.modified_methods.is_empty()
+}
+
+// Blame the added and modified functions and methods of one file, in report order
+fn blame_entities(changes: &DetailedChanges, ast: &FileASTData, hunks: &[BlameHunk]) -> Vec<EntityBlame> {
+    let functions = changes
+        .added_functions
+        .iter()
+        .chain(&changes.modified_functions)
+        .map(|entry| (&entry[0], ast.functions.get(&entry[0]).map(|func| func.span())));
+    let methods = changes
+        .added_methods
+        .iter()
+        .chain(&changes.modified_methods)
+        .map(|entry| (&entry[0], ast.methods.get(&entry[0]).map(|(_, method)| method.span())));
+
+    functions
+        .chain(methods)
+        .filter_map(|(name, span)| blame_entity(name, span?, hunks))
+        .collect()
+}
+
+// Pick the most recent commit among the blame hunks overlapping an entity's lines
+fn blame_entity(name: &str, span: Span, hunks: &[BlameHunk]) -> Option<EntityBlame> {
+    let start_line = span.start().line;
+    let end_line = span.end().line;
+
+    let hunk = hunks
+        .iter()
+        .filter(|hunk| hunk.start_line <= end_line && hunk.start_line + hunk.line_count > start_line)
+        .max_by_key(|hunk| hunk.time)?;
+
+    Some(EntityBlame {
+        name: name.to_string(),
+        start_line,
+        end_line,
+        commit: hunk.commit.clone(),
+        author: hunk.author.clone(),
+        author_email: hunk.author_email.clone(),
+    })
+}
@@ -0,0 +1,117 @@
+// src/cfg.rs
+use std::sync::OnceLock;
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Expr, Lit, Meta, Token};
+
+use crate::ast_parser::format_compact;
+
+static FEATURES: OnceLock<Vec<String>> = OnceLock::new();
+
+// Evaluate `feature = "..."` predicates against this feature set for the rest of the process
+pub fn set_features(features: Vec<String>) {
+    if FEATURES.set(features).is_err() {
+        eprintln!("Warning: Cfg features already configured");
+    }
+}
+
+// Combine the predicates gating an enclosing item with an item's own `#[cfg(..)]` predicates,
+// or None when the configured features rule the item out. Predicates the features decide are
+// dropped; the others stay and become part of the item's key.
+pub fn active_predicates(inherited: &[Meta], attrs: &[Attribute]) -> Option<Vec<Meta>> {
+    let own = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("cfg"))
+        .filter_map(|attr| attr.parse_args::<Meta>().ok());
+
+    let mut active = Vec::new();
+    for predicate in inherited.iter().cloned().chain(own) {
+        match FEATURES.get().and_then(|features| evaluate(&predicate, features)) {
+            Some(true) => {}
+            Some(false) => return None,
+            None => active.push(predicate),
+        }
+    }
+    Some(active)
+}
+
+// Key an entity by its name and the predicates still gating it, e.g. `imp #[cfg(unix)]`, so
+// variants of the same item behind different cfgs don't overwrite each other
+pub fn keyed(name: String, predicates: &[Meta]) -> String {
+    match predicates {
+        [] => name,
+        [predicate] => format!("{} #[cfg({})]", name, format_compact(predicate)),
+        _ => {
+            let predicates: Vec<String> = predicates.iter().map(format_compact).collect();
+            format!("{} #[cfg(all({}))]", name, predicates.join(", "))
+        }
+    }
+}
+
+// Whether an entity key names the symbol, either bare or as one of its cfg-gated variants
+pub fn names(key: &str, symbol: &str) -> bool {
+    key.strip_prefix(symbol)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(" #[cfg("))
+}
+
+// Whether an entity key carries a `test` predicate, alone or within all(), as the items of
+// `#[cfg(test)]` modules do
+pub fn gated_by_test(key: &str) -> bool {
+    let Some(predicate) = key
+        .split_once(" #[cfg(")
+        .and_then(|(_, rest)| rest.strip_suffix(")]"))
+        .and_then(|predicate| syn::parse_str::<Meta>(predicate).ok())
+    else {
+        return false;
+    };
+
+    let is_test = |meta: &Meta| matches!(meta, Meta::Path(path) if path.is_ident("test"));
+    match &predicate {
+        Meta::List(list) if list.path.is_ident("all") => list
+            .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+            .is_ok_and(|nested| nested.iter().any(is_test)),
+        predicate => is_test(predicate),
+    }
+}
+
+// Evaluate a cfg predicate against the enabled features. Only features are known, so
+// predicates on anything else (test, unix, target_os, ...) are undecided unless all(), any()
+// or not() can be settled from the features alone.
+fn evaluate(predicate: &Meta, features: &[String]) -> Option<bool> {
+    match predicate {
+        Meta::NameValue(name_value) if name_value.path.is_ident("feature") => match &name_value.value {
+            Expr::Lit(expr) => match &expr.lit {
+                Lit::Str(feature) => Some(features.contains(&feature.value())),
+                _ => None,
+            },
+            _ => None,
+        },
+        Meta::List(list) => {
+            let nested = list
+                .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .ok()?;
+            let values: Vec<Option<bool>> = nested.iter().map(|meta| evaluate(meta, features)).collect();
+
+            if list.path.is_ident("all") {
+                if values.contains(&Some(false)) {
+                    Some(false)
+                } else {
+                    values.iter().all(|value| *value == Some(true)).then_some(true)
+                }
+            } else if list.path.is_ident("any") {
+                if values.contains(&Some(true)) {
+                    Some(true)
+                } else {
+                    values.iter().all(|value| *value == Some(false)).then_some(false)
+                }
+            } else if list.path.is_ident("not") {
+                match values.as_slice() {
+                    [value] => value.map(|value| !value),
+                    _ => None,
+                }
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,288 @@
+// src/changeset.rs
+use std::collections::HashMap;
+
+use crate::attributes::GitAttributes;
+use crate::content::{ContentSource, LfsMode};
+use crate::filters;
+use crate::git_ops;
+use crate::modules::is_build_script;
+use crate::types::{AnalysisContext, BuildScriptChange, ErrorLog, PathChanges, SkippedFile};
+
+// DetectOptions selects which changed files are analyzed
+#[derive(Debug, Clone, Copy)]
+pub struct DetectOptions<'a> {
+    pub scopes: &'a [String],
+    pub generated_patterns: &'a [String],
+    pub find_copies: bool,
+    pub lfs: LfsMode,
+}
+
+// ChangeSet holds the Rust files changed between two refs and how each one changed
+#[derive(Debug, Clone, Default)]
+pub struct ChangeSet {
+    pub rust_files: Vec<String>,
+    pub new_file_map: HashMap<String, bool>,
+    pub deleted_file_map: HashMap<String, bool>,
+    pub renamed_file_map: HashMap<String, String>,
+    pub copied_file_map: HashMap<String, String>,
+    // Generated and binary files that changed but aren't analyzed
+    pub skipped_files: Vec<SkippedFile>,
+    // Mode changes and case-only renames of any file inside the scopes
+    pub path_changes: PathChanges,
+    // Changed Cargo.toml manifests inside the scopes
+    pub manifest_files: Vec<String>,
+}
+
+impl ChangeSet {
+    // Build the analysis context for this change set
+    pub fn context<'a>(
+        &'a self,
+        local_repo_path: &'a str,
+        branch_name: &'a str,
+        current_commit: &'a str,
+        content_source: ContentSource,
+        lfs: LfsMode,
+        errors: &'a ErrorLog,
+    ) -> AnalysisContext<'a> {
+        AnalysisContext {
+            local_repo_path,
+            branch_name,
+            current_commit,
+            new_file_map: &self.new_file_map,
+            deleted_file_map: &self.deleted_file_map,
+            renamed_file_map: &self.renamed_file_map,
+            copied_file_map: &self.copied_file_map,
+            content_source,
+            lfs,
+            errors,
+        }
+    }
+
+    // Paths whose contents are read on either side: the changed files plus rename and copy sources
+    pub fn paths_to_read(&self) -> Vec<String> {
+        let mut paths = self.rust_files.clone();
+        paths.extend(self.renamed_file_map.values().cloned());
+        paths.extend(self.copied_file_map.values().cloned());
+        paths
+    }
+
+    // Describe how a file changed, with the source path for renames and copies
+    pub fn describe(&self, file: &str) -> String {
+        match self.renamed_file_map.get(file).or_else(|| self.copied_file_map.get(file)) {
+            Some(old_path) => format!("{}\t{}\t{}", self.status(file), old_path, file),
+            None => format!("{}\t{}", self.status(file), file),
+        }
+    }
+
+    // How a file changed: `added`, `modified`, `deleted`, `renamed` or `copied`
+    pub fn status(&self, file: &str) -> &'static str {
+        if self.renamed_file_map.contains_key(file) {
+            "renamed"
+        } else if self.copied_file_map.contains_key(file) {
+            "copied"
+        } else if self.new_file_map.contains_key(file) {
+            "added"
+        } else if self.deleted_file_map.contains_key(file) {
+            "deleted"
+        } else {
+            "modified"
+        }
+    }
+
+    // Changed build scripts, which can alter generated code and cfgs without any other file changing
+    pub fn build_script_changes(&self) -> Vec<BuildScriptChange> {
+        self.rust_files
+            .iter()
+            .filter(|file| is_build_script(file))
+            .map(|file| BuildScriptChange {
+                file: file.clone(),
+                status: self.status(file).to_string(),
+            })
+            .collect()
+    }
+}
+
+// Detect the Rust files inside the scopes that changed between two refs.
+// Only failing to list the changed files is fatal; the other lookups are recorded and skipped.
+pub fn detect_changes(
+    old_ref: &str,
+    new_ref: &str,
+    local_repo_path: &str,
+    options: &DetectOptions,
+    errors: &ErrorLog,
+) -> Result<ChangeSet, String> {
+    let scopes = options.scopes;
+
+    // Get changed files between commits
+    let changed_files = git_ops::get_changed_files_between(old_ref, new_ref, local_repo_path)?;
+
+    // Get lists of new and deleted files
+    let new_files = match git_ops::get_new_files(old_ref, new_ref, local_repo_path) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Warning: Error getting new files: {}", e);
+            errors.record("git_diff", None, Some(new_ref), &e);
+            vec![]
+        }
+    };
+
+    let deleted_files = match git_ops::get_deleted_files(old_ref, new_ref, local_repo_path) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Warning: Error getting deleted files: {}", e);
+            errors.record("git_diff", None, Some(new_ref), &e);
+            vec![]
+        }
+    };
+
+    let similar_files = match git_ops::get_similar_files(old_ref, new_ref, options.find_copies, local_repo_path) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Warning: Error getting renamed files: {}", e);
+            errors.record("git_diff", None, Some(new_ref), &e);
+            vec![]
+        }
+    };
+
+    let mode_changes = match git_ops::get_mode_changes(old_ref, new_ref, local_repo_path) {
+        Ok(changes) => changes,
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            errors.record("git_diff", None, Some(new_ref), &e);
+            vec![]
+        }
+    };
+
+    let path_changes = PathChanges {
+        mode_changes: mode_changes
+            .into_iter()
+            .filter(|change| filters::in_scope(&change.file, scopes))
+            .collect(),
+        case_renames: git_ops::find_case_renames(&deleted_files, &new_files)
+            .into_iter()
+            .filter(|rename| filters::in_scope(&rename.new_path, scopes))
+            .collect(),
+    };
+    for change in &path_changes.mode_changes {
+        eprintln!("Mode change detected: {} {} -> {}", change.file, change.old_mode, change.new_mode);
+    }
+    for rename in &path_changes.case_renames {
+        eprintln!("Case-only rename detected: {} -> {}", rename.old_path, rename.new_path);
+    }
+
+    // Create maps for quick lookup
+    let mut changes = ChangeSet {
+        path_changes,
+        ..ChangeSet::default()
+    };
+
+    for file in &new_files {
+        changes.new_file_map.insert(file.clone(), true);
+        eprintln!("New file detected: {}", file);
+    }
+
+    for file in &deleted_files {
+        changes.deleted_file_map.insert(file.clone(), true);
+        eprintln!("Deleted file detected: {}", file);
+    }
+
+    eprintln!("Modified files: {:?}", changed_files);
+
+    changes.manifest_files = changed_files
+        .iter()
+        .filter(|file| file.rsplit('/').next() == Some("Cargo.toml"))
+        .filter(|file| filters::in_scope(file, scopes))
+        .cloned()
+        .collect();
+
+    // Filter only Rust files inside the requested scopes; build scripts are always analyzed
+    changes.rust_files = changed_files
+        .into_iter()
+        .filter(|file| file.ends_with(".rs"))
+        .filter(|file| filters::in_scope(file, scopes) || is_build_script(file))
+        .collect();
+    skip_unanalyzed_files(old_ref, new_ref, &mut changes, options, local_repo_path, errors);
+
+    (changes.renamed_file_map, changes.copied_file_map) = filters::pair_similar_files(
+        &mut changes.rust_files,
+        &similar_files,
+        &mut changes.new_file_map,
+        &mut changes.deleted_file_map,
+    );
+    for (new_path, old_path) in &changes.renamed_file_map {
+        eprintln!("Renamed file detected: {} -> {}", old_path, new_path);
+    }
+    for (new_path, old_path) in &changes.copied_file_map {
+        eprintln!("Copied file detected: {} -> {}", old_path, new_path);
+    }
+
+    Ok(changes)
+}
+
+// Set aside generated and binary files, which would only produce parse errors, and with
+// `--lfs skip` files stored as Git LFS pointers on either side, whose pointer text would
+// otherwise be parsed as the file and reported as deleting all of its code.
+// Deleted files are judged by the attributes they had at the old ref. Only the attributes
+// are read, so no file contents are fetched before the blobs are prefetched.
+fn skip_unanalyzed_files(
+    old_ref: &str,
+    new_ref: &str,
+    changes: &mut ChangeSet,
+    options: &DetectOptions,
+    local_repo_path: &str,
+    errors: &ErrorLog,
+) {
+    let skip_lfs = options.lfs == LfsMode::Skip;
+    let binary_files = match git_ops::get_binary_files(old_ref, new_ref, &changes.rust_files, local_repo_path) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            errors.record("git_diff", None, Some(new_ref), &e);
+            vec![]
+        }
+    };
+
+    // The LFS check needs the old attributes of every file that existed at the old ref
+    let old_files: Vec<String> = changes
+        .rust_files
+        .iter()
+        .filter(|file| {
+            changes.deleted_file_map.contains_key(*file) || (skip_lfs && !changes.new_file_map.contains_key(*file))
+        })
+        .cloned()
+        .collect();
+    let generated_patterns = options.generated_patterns;
+    let old_attributes = GitAttributes::load(old_ref, &old_files, generated_patterns, local_repo_path);
+    let new_attributes = GitAttributes::load(new_ref, &changes.rust_files, generated_patterns, local_repo_path);
+
+    let mut skipped_files = Vec::new();
+    changes.rust_files.retain(|file| {
+        let attributes = if changes.deleted_file_map.contains_key(file) {
+            &old_attributes
+        } else {
+            &new_attributes
+        };
+        let reason = attributes
+            .skip_reason(file)
+            .filter(|_| !is_build_script(file))
+            .or_else(|| binary_files.contains(file).then_some("binary"))
+            .or_else(|| {
+                let is_lfs = (!changes.new_file_map.contains_key(file) && old_attributes.is_lfs(file))
+                    || (!changes.deleted_file_map.contains_key(file) && new_attributes.is_lfs(file));
+                (skip_lfs && is_lfs).then_some("lfs")
+            });
+
+        match reason {
+            Some(reason) => {
+                eprintln!("Skipping {} file {}", reason, file);
+                skipped_files.push(SkippedFile {
+                    file: file.clone(),
+                    reason: reason.to_string(),
+                });
+                false
+            }
+            None => true,
+        }
+    });
+    changes.skipped_files = skipped_files;
+}
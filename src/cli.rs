@@ -0,0 +1,372 @@
+// src/cli.rs
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::time::Duration;
+
+use crate::changeset::DetectOptions;
+use crate::content::{ContentSource, DirtyTreePolicy, LfsMode};
+use crate::git_ops::{BackendKind, CloneOptions, GitCommand};
+use crate::normalize::{CosmeticMode, NormalizeStep};
+use crate::policy::ChangeCategory;
+use crate::scoring::RiskKind;
+
+// Command line interface: a full diff run by default, or one of the subcommands
+#[derive(Parser, Debug)]
+#[command(
+    name = "rust-ast-differ",
+    version,
+    about = "Analyze AST differences between Git commits for Rust code",
+    long_about = "Analyze AST differences between Git commits for Rust code.\n\n\
+        Clones (or reuses) a repository, finds the Rust files changed between a base \
+        branch and a commit, and reports added, modified and deleted functions, types, \
+        traits and methods as JSON, together with granular call and literal changes \
+        inside modified functions.",
+    after_help = "Examples:\n  \
+        rust-ast-differ https://github.com/org/repo.git ./repo main 1a2b3c4 ./out\n  \
+        rust-ast-differ --stdout --fail-on deleted-functions $URL ./repo main HEAD\n  \
+        rust-ast-differ query --symbol Client.send --from main --to HEAD --repo-url $URL --repo ./repo\n  \
+        rust-ast-differ completions bash > /etc/bash_completion.d/rust-ast-differ",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub diff: Option<DiffArgs>,
+}
+
+// Subcommands of the differ
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check whether a single symbol changed between two refs
+    Query(Box<QueryArgs>),
+
+    /// Compare two sides of a merge against their common ancestor and report the entities both changed
+    MergeDiff(Box<MergeDiffArgs>),
+
+    /// Generate a shell completion script and print it to stdout
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+// Arguments for a full diff run. clap leaves the group of a struct with flattened fields empty,
+// so the positional arguments are added to it for `Option<DiffArgs>` to see them.
+#[derive(Args, Debug)]
+#[group(args = ["repo_url", "local_repo_path", "branch_name", "current_commit"])]
+pub struct DiffArgs {
+    /// URL of the Git repository
+    #[arg(env = "CADA_REPO_URL")]
+    pub repo_url: String,
+
+    /// Path where to clone/use the repository
+    #[arg(env = "CADA_LOCAL_REPO_PATH")]
+    pub local_repo_path: String,
+
+    /// Base branch to compare against
+    #[arg(env = "CADA_BRANCH")]
+    pub branch_name: String,
+
+    /// Target commit to analyze
+    #[arg(env = "CADA_COMMIT")]
+    pub current_commit: String,
+
+    /// Directory to store output files
+    #[arg(env = "CADA_OUTPUT_PATH", default_value = "./")]
+    pub output_path: String,
+
+    #[command(flatten)]
+    pub options: RepoOptions,
+
+    /// Compare against the common ancestor of the two refs instead of the base ref itself
+    #[arg(long, env = "CADA_MERGE_BASE")]
+    pub merge_base: bool,
+
+    /// Also report the changes of each commit in the range against its first parent
+    #[arg(long, env = "CADA_PER_COMMIT")]
+    pub per_commit: bool,
+
+    /// Compare the current commit against its Nth parent instead of the base ref
+    #[arg(long, env = "CADA_PARENT", value_name = "N", conflicts_with = "merge_base")]
+    pub parent: Option<NonZeroUsize>,
+
+    /// Also report the changes of the current commit against each of its parents
+    #[arg(long, env = "CADA_ALL_PARENTS")]
+    pub all_parents: bool,
+
+    /// Attach the commit and author that last touched each added or modified function and method
+    #[arg(long, env = "CADA_BLAME")]
+    pub blame: bool,
+
+    /// Write a single consolidated JSON document to stdout instead of output files
+    #[arg(long, env = "CADA_STDOUT")]
+    pub stdout: bool,
+
+    /// Exit with status 2 when any of these change categories are present (comma-separated)
+    #[arg(long, env = "CADA_FAIL_ON", value_delimiter = ',', value_name = "CATEGORIES")]
+    pub fail_on: Vec<ChangeCategory>,
+
+    /// Restrict the diff to files under this repository-relative path (repeatable)
+    #[arg(long = "scope", env = "CADA_SCOPE", value_delimiter = ',', value_name = "PATH")]
+    pub scopes: Vec<String>,
+
+    /// Directory recording analyzed files so interrupted runs can resume
+    #[arg(long, env = "CADA_STATE_DIR", value_name = "DIR")]
+    pub state_dir: Option<String>,
+
+    /// Print the Rust files that would be analyzed with their status, then exit
+    #[arg(long, env = "CADA_DRY_RUN")]
+    pub dry_run: bool,
+
+    /// Number of files to parse and diff concurrently (defaults to available cores)
+    #[arg(long, short = 'j', env = "CADA_JOBS", value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+// Arguments for the query subcommand
+#[derive(Args, Debug)]
+pub struct QueryArgs {
+    /// Symbol to look up, e.g. `my_fn`, `MyStruct` or `MyStruct.my_method`
+    #[arg(long)]
+    pub symbol: String,
+
+    /// Old side of the comparison
+    #[arg(long)]
+    pub from: String,
+
+    /// New side of the comparison
+    #[arg(long)]
+    pub to: String,
+
+    /// Restrict the lookup to these repository-relative files (repeatable)
+    #[arg(long = "file", value_name = "PATH")]
+    pub files: Vec<String>,
+
+    /// Compare against the common ancestor of the two refs instead of the base ref itself
+    #[arg(long, env = "CADA_MERGE_BASE")]
+    pub merge_base: bool,
+
+    #[command(flatten)]
+    pub repo: RepoArgs,
+}
+
+// Arguments for the merge-diff subcommand
+#[derive(Args, Debug)]
+pub struct MergeDiffArgs {
+    /// Common ancestor both sides are compared against
+    #[arg(long)]
+    pub base: String,
+
+    /// Our side of the merge
+    #[arg(long)]
+    pub ours: String,
+
+    /// Their side of the merge
+    #[arg(long)]
+    pub theirs: String,
+
+    /// Restrict the comparison to these repository-relative files (repeatable)
+    #[arg(long = "file", value_name = "PATH")]
+    pub files: Vec<String>,
+
+    #[command(flatten)]
+    pub repo: RepoArgs,
+}
+
+// Repository the subcommands work on, with the options they share with a full diff run
+#[derive(Args, Debug)]
+pub struct RepoArgs {
+    /// URL of the Git repository
+    #[arg(long, env = "CADA_REPO_URL")]
+    pub repo_url: String,
+
+    /// Path where to clone/use the repository
+    #[arg(long = "repo", env = "CADA_LOCAL_REPO_PATH")]
+    pub local_repo_path: String,
+
+    #[command(flatten)]
+    pub options: RepoOptions,
+}
+
+// Git, content and analysis options shared by a full diff run and the subcommands
+#[derive(Args, Debug)]
+pub struct RepoOptions {
+    /// Access token used to authenticate HTTPS clones and fetches
+    #[arg(long, env = "CADA_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+
+    /// Name of the remote the repository URL is cloned and fetched as
+    #[arg(long, env = "CADA_REMOTE", default_value = "origin")]
+    pub remote: String,
+
+    /// Reuse the local repository even when none of its remotes points at the repository URL
+    #[arg(long, env = "CADA_ALLOW_MISMATCHED_REMOTE")]
+    pub allow_mismatched_remote: bool,
+
+    /// Analyze the repository already at the local path without cloning, fetching or changing remotes
+    #[arg(long, visible_alias = "no-clone", env = "CADA_OFFLINE")]
+    pub offline: bool,
+
+    /// How git operations are performed
+    #[arg(long, env = "CADA_GIT_BACKEND", value_enum, default_value_t = BackendKind::Auto)]
+    pub git_backend: BackendKind,
+
+    /// Path of the git executable to run instead of the one on PATH
+    #[arg(long, env = "CADA_GIT_PATH", value_name = "PATH")]
+    pub git_path: Option<String>,
+
+    /// Extra git config passed to every git command, such as http.proxy=http://proxy:3128
+    #[arg(long, env = "CADA_GIT_CONFIG", value_delimiter = ',', value_name = "KEY=VALUE", value_parser = parse_git_config)]
+    pub git_config: Vec<String>,
+
+    /// Run git with a minimal environment instead of inheriting this process's
+    #[arg(long, env = "CADA_SCRUB_GIT_ENV")]
+    pub scrub_git_env: bool,
+
+    /// Where file contents are read from: git objects, or a checkout of each ref
+    #[arg(long, env = "CADA_CONTENT_SOURCE", value_enum, default_value_t = ContentSource::Objects)]
+    pub content_source: ContentSource,
+
+    /// Stash local modifications before checking out refs and reapply them afterwards
+    #[arg(long, env = "CADA_STASH", conflicts_with = "force")]
+    pub stash: bool,
+
+    /// Discard local modifications before checking out refs
+    #[arg(long, env = "CADA_FORCE")]
+    pub force: bool,
+
+    /// Clone without a working tree; existing bare repositories are detected automatically
+    #[arg(long, env = "CADA_BARE")]
+    pub bare: bool,
+
+    /// Clone only the most recent N commits of each branch, deepening if the refs aren't reached
+    #[arg(long, env = "CADA_CLONE_DEPTH", value_name = "N")]
+    pub clone_depth: Option<NonZeroU32>,
+
+    /// Borrow objects from a local repository, such as a CI cache, when cloning
+    #[arg(long = "reference", env = "CADA_REFERENCE", value_name = "PATH")]
+    pub reference_repo: Option<String>,
+
+    /// Partial clone filter such as blob:none; only the blobs of changed files are downloaded
+    #[arg(long, env = "CADA_FILTER", value_name = "SPEC")]
+    pub filter: Option<String>,
+
+    /// Extra attempts for clones and fetches that fail, e.g. on a flaky network
+    #[arg(long, env = "CADA_RETRIES", value_name = "N", default_value_t = 3)]
+    pub retries: u32,
+
+    /// Delay before the first retry in milliseconds, doubled after every failed attempt
+    #[arg(long, env = "CADA_RETRY_DELAY_MS", value_name = "MS", default_value_t = 1000)]
+    pub retry_delay_ms: u64,
+
+    /// Materialize only the directories holding changed Rust files when checking out refs
+    #[arg(long, env = "CADA_SPARSE")]
+    pub sparse: bool,
+
+    /// Detect files created by copying an existing file and diff them against the copy source
+    #[arg(long, env = "CADA_FIND_COPIES")]
+    pub find_copies: bool,
+
+    /// Treat files matching this gitattributes-style pattern as generated and skip them (repeatable)
+    #[arg(long = "generated", env = "CADA_GENERATED", value_delimiter = ',', value_name = "PATTERN")]
+    pub generated_patterns: Vec<String>,
+
+    /// What to do with Rust files stored as Git LFS pointers
+    #[arg(long, env = "CADA_LFS", value_enum, default_value_t = LfsMode::Skip)]
+    pub lfs: LfsMode,
+
+    /// Compile-time features to evaluate `#[cfg(feature = "...")]` predicates against, dropping
+    /// items the features rule out (predicates stay in entity keys when not given)
+    #[arg(long, env = "CADA_FEATURES", value_delimiter = ',', value_name = "FEATURES")]
+    pub features: Option<Vec<String>>,
+
+    /// Normalizations applied to both sides of modified entities to find cosmetic changes (comma-separated)
+    #[arg(long, env = "CADA_NORMALIZE", value_enum, value_delimiter = ',', value_name = "STEPS")]
+    pub normalize: Vec<NormalizeStep>,
+
+    /// What to do with changes that are cosmetic under --normalize
+    #[arg(long, env = "CADA_COSMETIC", value_enum, default_value_t = CosmeticMode::Label)]
+    pub cosmetic: CosmeticMode,
+
+    /// Weight of a kind of change in the risk scores, such as deleted-public=20 (comma-separated)
+    #[arg(long = "risk-weight", env = "CADA_RISK_WEIGHTS", value_delimiter = ',', value_name = "KIND=WEIGHT", value_parser = parse_risk_weight)]
+    pub risk_weights: Vec<(RiskKind, u32)>,
+
+    /// Configuration file with the rules for what to ignore (defaults to ./cada.toml when present)
+    #[arg(long, env = "CADA_CONFIG", value_name = "FILE")]
+    pub config: Option<String>,
+}
+
+impl DiffArgs {
+    pub fn detect_options(&self) -> DetectOptions<'_> {
+        self.options.detect_options(&self.scopes)
+    }
+}
+
+impl RepoOptions {
+    // Scopes are the --scope paths of a diff run, or the requested files of a subcommand
+    pub fn detect_options<'a>(&'a self, scopes: &'a [String]) -> DetectOptions<'a> {
+        DetectOptions {
+            scopes,
+            generated_patterns: &self.generated_patterns,
+            find_copies: self.find_copies,
+            lfs: self.lfs,
+        }
+    }
+
+    pub fn dirty_tree_policy(&self) -> DirtyTreePolicy {
+        if self.stash {
+            DirtyTreePolicy::Stash
+        } else if self.force {
+            DirtyTreePolicy::Force
+        } else {
+            DirtyTreePolicy::Refuse
+        }
+    }
+
+    pub fn git_command(&self) -> GitCommand {
+        GitCommand {
+            program: self.git_path.clone(),
+            config: self.git_config.clone(),
+            scrub_env: self.scrub_git_env,
+            no_lazy_fetch: self.offline,
+        }
+    }
+
+    pub fn clone_options(&self) -> CloneOptions {
+        CloneOptions {
+            remote: self.remote.clone(),
+            token: self.token.clone(),
+            allow_mismatched_remote: self.allow_mismatched_remote,
+            offline: self.offline,
+            bare: self.bare,
+            depth: self.clone_depth.map(|d| d.get()),
+            reference_repo: self.reference_repo.clone(),
+            filter: self.filter.clone(),
+            sparse: self.sparse,
+            retries: self.retries,
+            retry_delay: Duration::from_millis(self.retry_delay_ms),
+        }
+    }
+}
+
+// Accept a risk weight given as `kind=weight`, such as `literal=0`, with the kind spelled as
+// on the command line or as in reports
+fn parse_risk_weight(setting: &str) -> Result<(RiskKind, u32), String> {
+    let (kind, weight) = setting.split_once('=').ok_or("expected kind=weight, such as deleted-public=20")?;
+    let kind = <RiskKind as ValueEnum>::from_str(&kind.replace('_', "-"), true)?;
+    let weight = weight.parse().map_err(|e| format!("invalid weight {}: {}", weight, e))?;
+    Ok((kind, weight))
+}
+
+// Accept a git config setting given as `section.key=value`
+fn parse_git_config(setting: &str) -> Result<String, String> {
+    match setting.split_once('=') {
+        Some((key, _)) if key.contains('.') => Ok(setting.to_string()),
+        _ => Err("expected section.key=value, such as http.proxy=http://proxy:3128".to_string()),
+    }
+}
@@ -0,0 +1,33 @@
+// src/config.rs
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+use crate::ignore::{self, IgnoreRules};
+
+// File the configuration is read from when --config isn't given, if it exists
+const DEFAULT_CONFIG_FILE: &str = "cada.toml";
+
+// Config is the contents of `cada.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // What to leave out of the comparison
+    pub ignore: IgnoreRules,
+}
+
+// Read the configuration file and apply it for the rest of the process. An explicitly given file
+// must exist; without one, `cada.toml` in the working directory is read when present.
+pub fn configure(path: Option<&str>) -> Result<(), String> {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(DEFAULT_CONFIG_FILE).is_file() => DEFAULT_CONFIG_FILE,
+        None => return Ok(()),
+    };
+    let content = fs::read_to_string(path).map_err(|e| format!("Failed to read config {}: {}", path, e))?;
+    let config: Config = toml::from_str(&content).map_err(|e| format!("Invalid config {}: {}", path, e))?;
+    eprintln!("Using config {}", path);
+
+    ignore::configure(config.ignore);
+    Ok(())
+}
@@ -0,0 +1,226 @@
+// src/content.rs
+use clap::ValueEnum;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::ast_parser::read_source_file;
+use crate::filters::parent_dirs;
+use crate::git_ops::{
+    checkout_commit, discard_changes, get_dirty_files, is_bare_repo, lfs_smudge, read_file_at, set_sparse_dirs,
+    GitError, HeadGuard, StashGuard, TempWorktree,
+};
+use crate::types::AnalysisContext;
+
+// ContentSource selects how file contents at a ref are obtained
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ContentSource {
+    // Read blobs straight from the object database, leaving the working tree untouched
+    #[default]
+    Objects,
+    // Check out each ref and read the files from the working tree
+    Checkout,
+    // Check out each ref into a temporary worktree, leaving the main working tree untouched
+    Worktree,
+}
+
+// LfsMode selects what happens to Rust files stored as Git LFS pointers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LfsMode {
+    // Leave them out of the analysis, recorded as skipped
+    #[default]
+    Skip,
+    // Replace each pointer with the real contents through `git lfs smudge`
+    Smudge,
+}
+
+// First line of every Git LFS pointer file
+const LFS_POINTER_VERSION: &str = "version https://git-lfs.github.com/spec/v1";
+
+// Pointer files are a few lines long; anything bigger is real source
+const MAX_LFS_POINTER_SIZE: usize = 1024;
+
+// Check whether file contents are a Git LFS pointer rather than the file itself
+pub fn is_lfs_pointer(content: &str) -> bool {
+    content.len() <= MAX_LFS_POINTER_SIZE
+        && content.starts_with(LFS_POINTER_VERSION)
+        && content.lines().any(|line| line.starts_with("oid sha256:"))
+}
+
+// Sources of the loaded files, or the reason a file couldn't be read
+pub type FileContents = HashMap<String, Result<String, String>>;
+
+// Check that the repository can serve contents the requested way
+pub fn ensure_supported(source: ContentSource, local_repo_path: &str) -> Result<(), String> {
+    if source == ContentSource::Checkout && is_bare_repo(local_repo_path)? {
+        return Err(format!(
+            "{} is a bare repository; use --content-source objects to analyze it",
+            local_repo_path
+        ));
+    }
+
+    Ok(())
+}
+
+// DirtyTreePolicy decides what happens to local modifications before refs are checked out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DirtyTreePolicy {
+    // Stop with an error listing the modified files
+    #[default]
+    Refuse,
+    // Stash the modifications and reapply them afterwards
+    Stash,
+    // Discard the modifications
+    Force,
+}
+
+// CheckoutGuard undoes a checkout-based analysis when dropped: HEAD is restored
+// first, then stashed modifications are reapplied on top of it
+pub struct CheckoutGuard {
+    _head: Option<HeadGuard>,
+    _stash: Option<StashGuard>,
+}
+
+// Prepare the working tree for checking out refs, returning a guard that restores it
+pub fn prepare_checkout(
+    source: ContentSource,
+    policy: DirtyTreePolicy,
+    local_repo_path: &str,
+) -> Result<Option<CheckoutGuard>, GitError> {
+    if source != ContentSource::Checkout {
+        return Ok(None);
+    }
+
+    let dirty_files = get_dirty_files(local_repo_path)?;
+    let stash = if dirty_files.is_empty() {
+        None
+    } else {
+        match policy {
+            DirtyTreePolicy::Refuse => {
+                return Err(GitError::DirtyTree {
+                    path: local_repo_path.to_string(),
+                    files: dirty_files,
+                });
+            }
+            DirtyTreePolicy::Stash => Some(StashGuard::push(local_repo_path)?),
+            DirtyTreePolicy::Force => {
+                discard_changes(local_repo_path)?;
+                eprintln!("Discarded local modifications in {} files", dirty_files.len());
+                None
+            }
+        }
+    };
+
+    // Record HEAD once the tree is clean so it's restored before the stash is reapplied
+    let head = match HeadGuard::record(local_repo_path) {
+        Ok(guard) => Some(guard),
+        Err(e) => {
+            eprintln!("Warning: {}; HEAD won't be restored", e);
+            None
+        }
+    };
+
+    Ok(Some(CheckoutGuard {
+        _head: head,
+        _stash: stash,
+    }))
+}
+
+// Narrow a sparse working tree to the files that will be checked out and read
+pub fn prepare_sparse_checkout(rust_files: &[String], sparse: bool, ctx: &AnalysisContext) {
+    // Object reads never touch the working tree
+    if !sparse || ctx.content_source != ContentSource::Checkout {
+        return;
+    }
+
+    // Renamed and copied files are read from their source path at the old ref
+    let mut files = rust_files.to_vec();
+    files.extend(rust_files.iter().map(|file| ctx.old_path(file).clone()));
+
+    if let Err(e) = set_sparse_dirs(&parent_dirs(&files), ctx.local_repo_path) {
+        eprintln!("Warning: {}; checking out the full tree", e);
+    }
+}
+
+// Load the sources of the selected files as they are at a ref
+pub fn load_file_contents(
+    source: ContentSource,
+    lfs: LfsMode,
+    reference: &str,
+    rust_files: &[String],
+    local_repo_path: &str,
+    skip_map: &HashMap<String, bool>,
+) -> Result<FileContents, String> {
+    let contents = read_file_contents(source, reference, rust_files, local_repo_path, skip_map)?;
+
+    // A checkout with git-lfs installed has already replaced the pointers
+    if lfs == LfsMode::Smudge {
+        Ok(smudge_lfs_pointers(contents, local_repo_path))
+    } else {
+        Ok(contents)
+    }
+}
+
+// Read the selected files at a ref from the configured source
+fn read_file_contents(
+    source: ContentSource,
+    reference: &str,
+    rust_files: &[String],
+    local_repo_path: &str,
+    skip_map: &HashMap<String, bool>,
+) -> Result<FileContents, String> {
+    let files: Vec<&String> = rust_files
+        .iter()
+        .filter(|file| !skip_map.contains_key(*file))
+        .collect();
+
+    match source {
+        ContentSource::Objects => {
+            eprintln!("Reading {} files at {} from git objects", files.len(), reference);
+            Ok(files
+                .par_iter()
+                .map(|file| {
+                    let content = read_file_at(reference, file, local_repo_path).map_err(String::from);
+                    ((*file).clone(), content)
+                })
+                .collect())
+        }
+        ContentSource::Checkout => {
+            checkout_commit(reference, local_repo_path)?;
+            eprintln!("Successfully checked out {}", reference);
+            Ok(read_from_dir(&files, Path::new(local_repo_path)))
+        }
+        ContentSource::Worktree => {
+            // The worktree is removed once the files are read
+            let worktree = TempWorktree::add(reference, local_repo_path)?;
+            Ok(read_from_dir(&files, worktree.path()))
+        }
+    }
+}
+
+// Replace the LFS pointers among the contents with the files they point to
+fn smudge_lfs_pointers(contents: FileContents, local_repo_path: &str) -> FileContents {
+    contents
+        .into_par_iter()
+        .map(|(file, content)| {
+            let content = match content {
+                Ok(pointer) if is_lfs_pointer(&pointer) => {
+                    lfs_smudge(&pointer, &file, local_repo_path).map_err(String::from)
+                }
+                other => other,
+            };
+            (file, content)
+        })
+        .collect()
+}
+
+// Read the files from a checked out tree
+fn read_from_dir(files: &[&String], dir: &Path) -> FileContents {
+    files
+        .iter()
+        .map(|file| {
+            let full_path = dir.join(file);
+            ((*file).clone(), read_source_file(full_path.to_str().unwrap_or("")))
+        })
+        .collect()
+}
@@ -0,0 +1,121 @@
+// src/deprecation.rs
+use syn::parse::Parser;
+use syn::{Attribute, Expr, ExprLit, Lit, LitStr, Meta};
+
+use crate::modules::{full_name, short_name};
+use crate::semver::is_public;
+use crate::types::{DeprecationChange, DeprecationReportEntry, DetailedChanges, FileASTData};
+
+// Find the public entities, and the public fields and variants of public types, that gained or
+// lost `#[deprecated]` or whose `since` or `note` changed. Runs once the attribute changes are known.
+pub fn find_deprecations(
+    changes: &DetailedChanges,
+    old_ast: &FileASTData,
+    new_ast: &FileASTData,
+) -> Vec<DeprecationChange> {
+    let public = |kind: &str, name: &str| is_public(old_ast, kind, name) || is_public(new_ast, kind, name);
+    let mut deprecations = Vec::new();
+
+    for change in changes.attribute_changes.iter().filter(|change| public(&change.kind, &change.name)) {
+        deprecations.extend(deprecation_change(
+            &change.kind,
+            &change.name,
+            &change.removed_attributes,
+            &change.added_attributes,
+        ));
+    }
+
+    for field_changes in changes.field_changes.iter().filter(|changes| public("type", &changes.name)) {
+        for field in &field_changes.modified_fields {
+            if field.old_field.visibility != "pub" && field.new_field.visibility != "pub" {
+                continue;
+            }
+            let name = format!("{}.{}", field_changes.name, field.name);
+            let (old, new) = (&field.old_field.attributes, &field.new_field.attributes);
+            deprecations.extend(deprecation_change("field", &name, old, new));
+        }
+    }
+
+    // Variants are as public as their enum
+    for variant_changes in changes.variant_changes.iter().filter(|changes| public("type", &changes.name)) {
+        for variant in &variant_changes.modified_variants {
+            let name = format!("{}::{}", variant_changes.name, variant.name);
+            let (old, new) = (&variant.old_variant.attributes, &variant.new_variant.attributes);
+            deprecations.extend(deprecation_change("variant", &name, old, new));
+        }
+    }
+    deprecations
+}
+
+// How the deprecation among two lists of attributes changed, if it did
+fn deprecation_change(
+    kind: &str,
+    name: &str,
+    old_attributes: &[String],
+    new_attributes: &[String],
+) -> Option<DeprecationChange> {
+    let old = old_attributes.iter().find_map(|attribute| deprecation(attribute));
+    let new = new_attributes.iter().find_map(|attribute| deprecation(attribute));
+    let (change, (since, note)) = match (old, new) {
+        (None, Some(new)) => ("deprecated", new),
+        (Some(old), None) => ("undeprecated", old),
+        (Some(old), Some(new)) if old != new => ("changed", new),
+        _ => return None,
+    };
+    Some(DeprecationChange {
+        kind: kind.to_string(),
+        name: name.to_string(),
+        change: change.to_string(),
+        since,
+        note,
+    })
+}
+
+// The `since` and `note` of a `#[deprecated]` attribute, or None for any other attribute.
+// `#[deprecated = ".."]` gives just the note.
+fn deprecation(text: &str) -> Option<(Option<String>, Option<String>)> {
+    let attributes = Attribute::parse_outer.parse_str(text).ok()?;
+    let attribute = attributes.into_iter().find(|attribute| attribute.path().is_ident("deprecated"))?;
+
+    match &attribute.meta {
+        Meta::Path(_) => Some((None, None)),
+        Meta::NameValue(meta) => match &meta.value {
+            Expr::Lit(ExprLit { lit: Lit::Str(note), .. }) => Some((None, Some(note.value()))),
+            _ => Some((None, None)),
+        },
+        Meta::List(_) => {
+            let (mut since, mut note) = (None, None);
+            let parsed = attribute.parse_nested_meta(|meta| {
+                let value = meta.value()?.parse::<LitStr>()?.value();
+                if meta.path.is_ident("since") {
+                    since = Some(value);
+                } else if meta.path.is_ident("note") {
+                    note = Some(value);
+                }
+                Ok(())
+            });
+            if let Err(e) = parsed {
+                eprintln!("Warning: Could not parse {}: {}", text, e);
+            }
+            Some((since, note))
+        }
+    }
+}
+
+// Gather the deprecation changes of all files, for release notes and downstream consumers
+pub fn build_deprecation_report(all_changes: &[DetailedChanges]) -> Vec<DeprecationReportEntry> {
+    all_changes
+        .iter()
+        .flat_map(|c| {
+            c.deprecations.iter().map(|change| DeprecationReportEntry {
+                module: c.module_name.clone(),
+                qualified_name: change.name.clone(),
+                full_name: full_name(c.crate_name.as_deref(), &change.name),
+                change: DeprecationChange {
+                    name: short_name(c.module_path.as_deref(), &change.name).to_string(),
+                    ..change.clone()
+                },
+            })
+        })
+        .collect()
+}
@@ -1,22 +1,61 @@
 // src/differ.rs
-use std::collections::HashMap;
+use rayon::prelude::*;
+use proc_macro2::TokenStream;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::path::Path;
-use syn::{Item, ItemFn, ItemTrait,ItemImpl};
-
-use crate::ast_parser::{extract_file_ast, extract_module_name, format_node};
-use crate::git_ops::{checkout_branch, checkout_commit};
-use crate::types::{DetailedChanges, FileASTData};
+use quote::ToTokens;
+use syn::{ImplItem, Item, ItemFn, ItemTrait,ItemImpl, ItemMacro, Signature, TraitItem, TraitItemFn};
+
+use crate::ast_parser::{
+    extract_macro_arms, fn_lifetimes, fn_qualifiers, fn_signature, format_compact, format_node, get_source_code,
+    item_generics, parse_file_ast, sequence_similarity, split_outer_attributes, token_counts, token_sequence,
+    token_similarity, trait_generics, type_lifetimes,
+};
+use crate::cfg;
+use crate::content::{load_file_contents, FileContents};
+use crate::deprecation;
+use crate::error_surface;
+use crate::modules::{is_test_file, resolve_module_paths, short_name};
+use crate::normalize;
+use crate::scoring;
+use crate::semver;
+use crate::statements;
+use crate::types::{
+    AnalysisContext, AttributeChange, CrossFileMove, DetailedChanges, DocChange, EntityHash, EntitySimilarity,
+    EnumVariant, ErrorLog, FieldChanges, FileASTData, FnParam, FnQualifiers, FnSignature, GenericsChange,
+    GlobalChange, ItemGenerics, LifetimeChange, LifetimeUsage, MacroArmChanges, MatcherChange, ModifiedCode,
+    ModifiedField, ModifiedVariant, MovedEntity, ParamChange, QualifierChange, RenamedEntity, SignatureChange,
+    StructField, TestChange, TraitItemChanges, ValueChange, VariantChanges, VisibilityChange,
+};
 
 // Compare ASTs to find differences
 pub fn compare_asts(
     old_ast: &FileASTData,
     new_ast: &FileASTData,
-    package_name: &str,
+    module_path: Option<&str>,
     file_path: &str,
     is_new_file: bool,
     is_removed_file: bool,
 ) -> DetailedChanges {
     let mut changes = DetailedChanges::new(file_path.to_string());
+    changes.module_path = module_path.map(|path| path.to_string());
+
+    // Inner attributes of a crate root apply to the whole crate
+    if module_path == Some("crate") {
+        changes.added_crate_attributes = new_ast
+            .inner_attributes
+            .iter()
+            .filter(|attr| !old_ast.inner_attributes.contains(attr))
+            .cloned()
+            .collect();
+        changes.removed_crate_attributes = old_ast
+            .inner_attributes
+            .iter()
+            .filter(|attr| !new_ast.inner_attributes.contains(attr))
+            .cloned()
+            .collect();
+    }
 
     // Handle special cases for new or removed files
     if is_new_file {
@@ -45,6 +84,44 @@ pub fn compare_asts(
             changes.added_methods.push(vec![name.clone(), code]);
         }
 
+        // Extract all trait methods from the new AST
+        for (name, method) in &new_ast.trait_methods {
+            changes.added_trait_methods.push(vec![name.clone(), format_node(method)]);
+        }
+
+        // Extract all constants and statics from the new AST
+        for (name, const_item) in &new_ast.constants {
+            let code = format_node(const_item);
+            changes.added_constants.push(vec![name.clone(), code]);
+        }
+
+        // Extract all macro definitions from the new AST
+        for (name, macro_def) in &new_ast.macros {
+            let code = format_node(macro_def);
+            changes.added_macros.push(vec![name.clone(), code]);
+        }
+
+        // Extract all associated consts and types from the new AST
+        for (name, item) in &new_ast.associated_consts {
+            changes.added_associated_consts.push(vec![name.clone(), format_node(item)]);
+        }
+        for (name, item) in &new_ast.associated_types {
+            changes.added_associated_types.push(vec![name.clone(), format_node(item)]);
+        }
+
+        // Extract all trait impls from the new AST
+        for (name, impl_block) in &new_ast.trait_impls {
+            changes.added_trait_impls.push(vec![name.clone(), format_node(impl_block)]);
+        }
+
+        changes.added_imports = new_ast.imports.clone();
+        changes.added_reexports = new_ast.reexports.clone();
+        changes.global_changes = find_global_changes(old_ast, new_ast);
+
+        order_entities(&mut changes, old_ast, new_ast);
+        annotate_entities(&mut changes, old_ast, new_ast);
+        changes.semver_changes = semver::classify_semver_changes(&changes, old_ast, new_ast);
+        scoring::score_changes(&mut changes, old_ast, new_ast);
         return changes;
     }
 
@@ -74,33 +151,996 @@ pub fn compare_asts(
             changes.deleted_methods.push(vec![name.clone(), code]);
         }
 
+        // Extract all trait methods from the old AST
+        for (name, method) in &old_ast.trait_methods {
+            changes.deleted_trait_methods.push(vec![name.clone(), format_node(method)]);
+        }
+
+        // Extract all constants and statics from the old AST
+        for (name, const_item) in &old_ast.constants {
+            let code = format_node(const_item);
+            changes.deleted_constants.push(vec![name.clone(), code]);
+        }
+
+        // Extract all macro definitions from the old AST
+        for (name, macro_def) in &old_ast.macros {
+            let code = format_node(macro_def);
+            changes.deleted_macros.push(vec![name.clone(), code]);
+        }
+
+        // Extract all associated consts and types from the old AST
+        for (name, item) in &old_ast.associated_consts {
+            changes.deleted_associated_consts.push(vec![name.clone(), format_node(item)]);
+        }
+        for (name, item) in &old_ast.associated_types {
+            changes.deleted_associated_types.push(vec![name.clone(), format_node(item)]);
+        }
+
+        // Extract all trait impls from the old AST
+        for (name, impl_block) in &old_ast.trait_impls {
+            changes.deleted_trait_impls.push(vec![name.clone(), format_node(impl_block)]);
+        }
+
+        changes.removed_imports = old_ast.imports.clone();
+        changes.removed_reexports = old_ast.reexports.clone();
+        changes.global_changes = find_global_changes(old_ast, new_ast);
+
+        order_entities(&mut changes, old_ast, new_ast);
+        annotate_entities(&mut changes, old_ast, new_ast);
+        changes.semver_changes = semver::classify_semver_changes(&changes, old_ast, new_ast);
+        scoring::score_changes(&mut changes, old_ast, new_ast);
         return changes;
     }
 
     // Normal case - file exists in both versions
+    // Entities whose content hashes match are unchanged, without rendering either side
+    let same_hash = |kind: &'static str| {
+        move |name: &str| old_ast.content_hash(kind, name) == new_ast.content_hash(kind, name)
+    };
+
     // Compare functions
     changes.added_functions = find_added_func_elements(&old_ast.functions, &new_ast.functions);
-    changes.modified_functions = find_modified_func_elements(&old_ast.functions, &new_ast.functions);
+    changes.modified_functions =
+        find_modified_func_elements(&old_ast.functions, &new_ast.functions, same_hash("function"));
     changes.deleted_functions = find_deleted_func_elements(&old_ast.functions, &new_ast.functions);
 
     // Compare types
     changes.added_types = find_added_type_elements(&old_ast.types, &new_ast.types);
-    changes.modified_types = find_modified_type_elements(&old_ast.types, &new_ast.types);
+    changes.modified_types = find_modified_type_elements(&old_ast.types, &new_ast.types, same_hash("type"));
     changes.deleted_types = find_deleted_type_elements(&old_ast.types, &new_ast.types);
 
     // Compare interfaces
     changes.added_interfaces = find_added_trait_elements(&old_ast.interfaces, &new_ast.interfaces);
-    changes.modified_interfaces = find_modified_trait_elements(&old_ast.interfaces, &new_ast.interfaces);
+    changes.modified_interfaces =
+        find_modified_trait_elements(&old_ast.interfaces, &new_ast.interfaces, same_hash("interface"));
     changes.deleted_interfaces = find_deleted_trait_elements(&old_ast.interfaces, &new_ast.interfaces);
 
     // Compare methods
     changes.added_methods = find_added_method_elements(&old_ast.methods, &new_ast.methods);
-    changes.modified_methods = find_modified_method_elements(&old_ast.methods, &new_ast.methods);
+    changes.modified_methods = find_modified_method_elements(&old_ast.methods, &new_ast.methods, same_hash("method"));
     changes.deleted_methods = find_deleted_method_elements(&old_ast.methods, &new_ast.methods);
 
+    // Compare trait methods, with or without default bodies
+    let (old_trait_methods, new_trait_methods) = (&old_ast.trait_methods, &new_ast.trait_methods);
+    changes.added_trait_methods = find_added_trait_method_elements(old_trait_methods, new_trait_methods);
+    changes.modified_trait_methods =
+        find_modified_trait_method_elements(old_trait_methods, new_trait_methods, same_hash("trait_method"));
+    changes.deleted_trait_methods = find_deleted_trait_method_elements(old_trait_methods, new_trait_methods);
+
+    // Compare constants and statics, which are items like types
+    changes.added_constants = find_added_type_elements(&old_ast.constants, &new_ast.constants);
+    changes.modified_constants =
+        find_modified_type_elements(&old_ast.constants, &new_ast.constants, same_hash("constant"));
+    changes.deleted_constants = find_deleted_type_elements(&old_ast.constants, &new_ast.constants);
+
+    // Compare the fields of modified structs and unions
+    changes.field_changes = changes
+        .modified_types
+        .iter()
+        .filter_map(|entry| {
+            let old_fields = old_ast.struct_fields.get(&entry[0])?;
+            let new_fields = new_ast.struct_fields.get(&entry[0])?;
+            find_field_changes(&entry[0], old_fields, new_fields)
+        })
+        .collect();
+
+    // Compare the variants of modified enums
+    changes.variant_changes = changes
+        .modified_types
+        .iter()
+        .filter_map(|entry| {
+            let old_variants = old_ast.enum_variants.get(&entry[0])?;
+            let new_variants = new_ast.enum_variants.get(&entry[0])?;
+            find_variant_changes(&entry[0], old_variants, new_variants)
+        })
+        .collect();
+
+    // Compare global state, including statics declared through macros
+    changes.global_changes = find_global_changes(old_ast, new_ast);
+
+    // Compare macro definitions, down to their arms when both sides can be split
+    changes.added_macros = find_added_macro_elements(&old_ast.macros, &new_ast.macros);
+    changes.modified_macros = find_modified_macro_elements(&old_ast.macros, &new_ast.macros, same_hash("macro"));
+    changes.deleted_macros = find_deleted_macro_elements(&old_ast.macros, &new_ast.macros);
+    changes.macro_arm_changes = changes
+        .modified_macros
+        .iter()
+        .filter_map(|entry| find_macro_arm_changes(&entry[0], &old_ast.macros[&entry[0]], &new_ast.macros[&entry[0]]))
+        .collect();
+
+    // Compare associated consts and types of impls and traits
+    let (old_consts, new_consts) = (&old_ast.associated_consts, &new_ast.associated_consts);
+    changes.added_associated_consts = find_added_associated_elements(old_consts, new_consts);
+    changes.modified_associated_consts =
+        find_modified_associated_elements(old_consts, new_consts, same_hash("associated_const"));
+    changes.deleted_associated_consts = find_deleted_associated_elements(old_consts, new_consts);
+    let (old_types, new_types) = (&old_ast.associated_types, &new_ast.associated_types);
+    changes.added_associated_types = find_added_associated_elements(old_types, new_types);
+    changes.modified_associated_types =
+        find_modified_associated_elements(old_types, new_types, same_hash("associated_type"));
+    changes.deleted_associated_types = find_deleted_associated_elements(old_types, new_types);
+
+    // Compare trait impls; changes inside one show up as method and associated item changes
+    changes.added_trait_impls = find_added_impl_elements(&old_ast.trait_impls, &new_ast.trait_impls);
+    changes.deleted_trait_impls = find_deleted_impl_elements(&old_ast.trait_impls, &new_ast.trait_impls);
+
+    // Compare imports, where swapping which path a name comes from shows up as one of each
+    changes.added_imports = new_ast
+        .imports
+        .iter()
+        .filter(|import| !old_ast.imports.contains(import))
+        .cloned()
+        .collect();
+    changes.removed_imports = old_ast
+        .imports
+        .iter()
+        .filter(|import| !new_ast.imports.contains(import))
+        .cloned()
+        .collect();
+
+    // Compare re-exports, where narrowing a `pub use` removes its path from the API surface
+    changes.added_reexports = new_ast
+        .reexports
+        .iter()
+        .filter(|reexport| !old_ast.reexports.contains(reexport))
+        .cloned()
+        .collect();
+    changes.removed_reexports = old_ast
+        .reexports
+        .iter()
+        .filter(|reexport| !new_ast.reexports.contains(reexport))
+        .cloned()
+        .collect();
+
+    changes.renamed_entities = find_renamed_entities(&mut changes, old_ast, new_ast);
+    changes.moved_entities = find_moved_entities(old_ast, new_ast);
+
+    order_entities(&mut changes, old_ast, new_ast);
+    changes.qualifier_changes = find_qualifier_changes(&changes, old_ast, new_ast);
+    changes.signature_changes = find_signature_changes(&changes, old_ast, new_ast);
+    changes.generics_changes = find_generics_changes(&changes, old_ast, new_ast);
+    changes.lifetime_changes = find_lifetime_changes(&changes, old_ast, new_ast);
+    classify_attribute_changes(&mut changes);
+    changes.deprecations = deprecation::find_deprecations(&changes, old_ast, new_ast);
+    changes.error_surface_changes = error_surface::find_error_surface_changes(&changes, old_ast, new_ast);
+    normalize::classify_cosmetic_changes(&mut changes);
+    changes.trait_item_changes = find_trait_item_changes(&changes);
+    classify_modified_fns(&mut changes, old_ast, new_ast);
+    changes.similarities = find_similarities(&changes);
+    changes.value_changes = find_value_changes(&changes, old_ast, new_ast);
+    changes.statement_changes = statements::find_statement_changes(&changes, old_ast, new_ast);
+    annotate_entities(&mut changes, old_ast, new_ast);
+    changes.semver_changes = semver::classify_semver_changes(&changes, old_ast, new_ast);
+    scoring::score_changes(&mut changes, old_ast, new_ast);
+
     changes
 }
 
+// Swap the rendered code of each entry for its source text, once the comparisons and
+// classifications that work on the rendered code are done, and record the content hashes
+// of the changed entities and which of them are test code
+fn annotate_entities(changes: &mut DetailedChanges, old_ast: &FileASTData, new_ast: &FileASTData) {
+    let mut entity_hashes = Vec::new();
+    let mut test_changes = Vec::new();
+    let test_file = is_test_file(&changes.module_name);
+    for_each_entity_list(changes, |kind, added, modified, deleted| {
+        for entry in added.iter().chain(modified.iter()).chain(deleted.iter()) {
+            entity_hashes.push(EntityHash {
+                kind: kind.to_string(),
+                name: entry[0].clone(),
+                old_hash: old_ast.content_hash(kind, &entry[0]).cloned(),
+                new_hash: new_ast.content_hash(kind, &entry[0]).cloned(),
+            });
+            if test_file || old_ast.is_test(kind, &entry[0]) || new_ast.is_test(kind, &entry[0]) {
+                test_changes.push(TestChange {
+                    kind: kind.to_string(),
+                    name: entry[0].clone(),
+                });
+            }
+        }
+
+        for entry in added.iter_mut() {
+            if let Some(code) = entity_source_code(new_ast, kind, &entry[0]) {
+                entry[1] = code;
+            }
+        }
+        for entry in modified.iter_mut() {
+            if let Some(code) = entity_source_code(old_ast, kind, &entry[0]) {
+                entry[1] = code;
+            }
+            if let Some(code) = entity_source_code(new_ast, kind, &entry[0]) {
+                entry[2] = code;
+            }
+        }
+        for entry in deleted.iter_mut() {
+            if let Some(code) = entity_source_code(old_ast, kind, &entry[0]) {
+                entry[1] = code;
+            }
+        }
+    });
+    // Import changes aren't entities, so a file with any is never test-only
+    changes.test_only = !test_changes.is_empty()
+        && test_changes.len() == entity_hashes.len()
+        && changes.added_imports.is_empty()
+        && changes.removed_imports.is_empty();
+    changes.entity_hashes = entity_hashes;
+    changes.test_changes = test_changes;
+}
+
+// Sort the added and modified entries of each kind in their order in the new source, and the
+// deleted ones in their order in the old source
+fn order_entities(changes: &mut DetailedChanges, old_ast: &FileASTData, new_ast: &FileASTData) {
+    for_each_entity_list(changes, |kind, added, modified, deleted| {
+        // Entities without a known position go last, by name
+        let order = |ast: &FileASTData, entry: &Vec<String>| {
+            let index = ast.position(kind, &entry[0]).map_or(usize::MAX, |position| position.index);
+            (index, entry[0].clone())
+        };
+        added.sort_by_cached_key(|entry| order(new_ast, entry));
+        modified.sort_by_cached_key(|entry| order(new_ast, entry));
+        deleted.sort_by_cached_key(|entry| order(old_ast, entry));
+    });
+}
+
+// Least share of tokens, in percent, that a deleted and an added entity of the same kind need in
+// common to count as one renamed entity
+const RENAME_SIMILARITY: u32 = 80;
+
+// Pair up deleted and added entities of the same kind whose code is nearly the same, most similar
+// pairs first, and take them out of the deleted and added lists as renamed entities
+fn find_renamed_entities(
+    changes: &mut DetailedChanges,
+    old_ast: &FileASTData,
+    new_ast: &FileASTData,
+) -> Vec<RenamedEntity> {
+    let mut renamed = Vec::new();
+    for_each_entity_list(changes, |kind, added, _, deleted| {
+        // Trait impls are named after what they implement, so they can't be renamed
+        if kind == "trait_impl" || added.is_empty() || deleted.is_empty() {
+            return;
+        }
+        let counts = |entries: &Vec<Vec<String>>| -> Vec<HashMap<String, usize>> {
+            entries
+                .iter()
+                .map(|entry| token_counts(entry[1].parse().unwrap_or_default()))
+                .collect()
+        };
+        let (deleted_counts, added_counts) = (counts(deleted), counts(added));
+
+        let mut candidates = Vec::new();
+        for (d, old_counts) in deleted_counts.iter().enumerate() {
+            for (a, new_counts) in added_counts.iter().enumerate() {
+                let similarity = token_similarity(old_counts, new_counts);
+                if similarity >= RENAME_SIMILARITY {
+                    candidates.push((similarity, d, a));
+                }
+            }
+        }
+        candidates.sort_by_key(|&(similarity, d, a)| (Reverse(similarity), deleted[d][0].clone(), added[a][0].clone()));
+
+        let (mut renamed_deleted, mut renamed_added) = (HashSet::new(), HashSet::new());
+        for (similarity, d, a) in candidates {
+            if renamed_deleted.contains(&d) || renamed_added.contains(&a) {
+                continue;
+            }
+            renamed_deleted.insert(d);
+            renamed_added.insert(a);
+            let (old_name, new_name) = (&deleted[d][0], &added[a][0]);
+            renamed.push(RenamedEntity {
+                kind: kind.to_string(),
+                old_name: old_name.clone(),
+                new_name: new_name.clone(),
+                similarity,
+                old_code: entity_source_code(old_ast, kind, old_name).unwrap_or_else(|| deleted[d][1].clone()),
+                new_code: entity_source_code(new_ast, kind, new_name).unwrap_or_else(|| added[a][1].clone()),
+            });
+        }
+
+        remove_entries(deleted, &renamed_deleted);
+        remove_entries(added, &renamed_added);
+    });
+
+    renamed.sort_by_key(|entity| {
+        let position = new_ast.position(&entity.kind, &entity.new_name);
+        (position.map_or(usize::MAX, |position| position.index), entity.new_name.clone())
+    });
+    renamed
+}
+
+// Remove the entries at the given indices from a list
+fn remove_entries(entries: &mut Vec<Vec<String>>, indices: &HashSet<usize>) {
+    *entries = std::mem::take(entries)
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !indices.contains(index))
+        .map(|(_, entry)| entry)
+        .collect();
+}
+
+// Find the entities on both sides that moved relative to the others, namely those left out of
+// the longest run of common entities that kept their order
+fn find_moved_entities(old_ast: &FileASTData, new_ast: &FileASTData) -> Vec<MovedEntity> {
+    let mut common: Vec<_> = old_ast
+        .positions
+        .iter()
+        .flat_map(|(kind, positions)| {
+            positions
+                .iter()
+                .filter_map(move |(name, old)| Some((*kind, name, *old, new_ast.position(kind, name)?)))
+        })
+        .collect();
+    common.sort_by_key(|(_, _, old, _)| old.index);
+    let new_indices: Vec<usize> = common.iter().map(|(_, _, _, new)| new.index).collect();
+
+    let mut moved: Vec<MovedEntity> = common
+        .into_iter()
+        .zip(in_order(&new_indices))
+        .filter(|(_, in_order)| !in_order)
+        .map(|((kind, name, old_position, new_position), _)| MovedEntity {
+            kind: kind.to_string(),
+            name: name.clone(),
+            old_position,
+            new_position,
+        })
+        .collect();
+
+    // Items inside a moved entity, like the methods of a trait or the functions nested in a
+    // function, moved along with it
+    let moved_names: HashSet<String> = moved.iter().map(|entity| entity.name.clone()).collect();
+    moved.retain(|entity| {
+        let name = entity.name.split(" #[cfg(").next().unwrap_or(&entity.name);
+        let owners = [name.rsplit_once("::"), name.rsplit_once('.')];
+        !owners
+            .into_iter()
+            .flatten()
+            .any(|(owner, _)| moved_names.iter().any(|moved_name| cfg::names(moved_name, owner)))
+    });
+    moved.sort_by_key(|entity| entity.new_position.index);
+    moved
+}
+
+// Whether each index is part of the longest increasing run of the indices, which is what kept
+// its order when the rest moved around it
+fn in_order(indices: &[usize]) -> Vec<bool> {
+    // The last index of the best run of each length so far, and the index before each one in its run
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; indices.len()];
+    for (i, index) in indices.iter().enumerate() {
+        let length = tails.partition_point(|&tail| indices[tail] < *index);
+        if length > 0 {
+            previous[i] = Some(tails[length - 1]);
+        }
+        if length == tails.len() {
+            tails.push(i);
+        } else {
+            tails[length] = i;
+        }
+    }
+    let mut in_order = vec![false; indices.len()];
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        in_order[i] = true;
+        next = previous[i];
+    }
+    in_order
+}
+
+// Call a function with the kind and the added, modified and deleted entry lists of each kind
+// of entity, where trait impls have no modified list
+fn for_each_entity_list(
+    changes: &mut DetailedChanges,
+    mut f: impl FnMut(&'static str, &mut Vec<Vec<String>>, &mut Vec<Vec<String>>, &mut Vec<Vec<String>>),
+) {
+    let categories = [
+        ("function", &mut changes.added_functions, &mut changes.modified_functions, &mut changes.deleted_functions),
+        ("type", &mut changes.added_types, &mut changes.modified_types, &mut changes.deleted_types),
+        ("interface", &mut changes.added_interfaces, &mut changes.modified_interfaces, &mut changes.deleted_interfaces),
+        ("method", &mut changes.added_methods, &mut changes.modified_methods, &mut changes.deleted_methods),
+        (
+            "trait_method",
+            &mut changes.added_trait_methods,
+            &mut changes.modified_trait_methods,
+            &mut changes.deleted_trait_methods,
+        ),
+        ("constant", &mut changes.added_constants, &mut changes.modified_constants, &mut changes.deleted_constants),
+        ("macro", &mut changes.added_macros, &mut changes.modified_macros, &mut changes.deleted_macros),
+        (
+            "associated_const",
+            &mut changes.added_associated_consts,
+            &mut changes.modified_associated_consts,
+            &mut changes.deleted_associated_consts,
+        ),
+        (
+            "associated_type",
+            &mut changes.added_associated_types,
+            &mut changes.modified_associated_types,
+            &mut changes.deleted_associated_types,
+        ),
+        ("trait_impl", &mut changes.added_trait_impls, &mut Vec::new(), &mut changes.deleted_trait_impls),
+    ];
+    for (kind, added, modified, deleted) in categories {
+        f(kind, added, modified, deleted);
+    }
+}
+
+// Source text of a named entity of one kind
+pub fn entity_source_code(ast: &FileASTData, kind: &str, name: &str) -> Option<String> {
+    let content = &ast.file_content;
+    match kind {
+        "function" => ast.functions.get(name).map(|node| get_source_code(node, content)),
+        "type" => ast.types.get(name).map(|node| get_source_code(node, content)),
+        "interface" => ast.interfaces.get(name).map(|node| get_source_code(node, content)),
+        "method" => ast.methods.get(name).map(|(_, node)| get_source_code(node, content)),
+        "trait_method" => ast.trait_methods.get(name).map(|node| get_source_code(node, content)),
+        "constant" => ast.constants.get(name).map(|node| get_source_code(node, content)),
+        "macro" => ast.macros.get(name).map(|node| get_source_code(node, content)),
+        "associated_const" => ast.associated_consts.get(name).map(|node| get_source_code(node, content)),
+        "associated_type" => ast.associated_types.get(name).map(|node| get_source_code(node, content)),
+        "trait_impl" => ast.trait_impls.get(name).map(|node| get_source_code(node, content)),
+        _ => None,
+    }
+}
+
+// Signature and body of a function, where trait methods without a default have no body
+type FnParts<'a> = (&'a Signature, &'a dyn ToTokens);
+
+// Pair up the old and new declarations of modified functions, methods and trait methods,
+// with their kind
+fn modified_fns<'a>(
+    changes: &'a DetailedChanges,
+    old_ast: &'a FileASTData,
+    new_ast: &'a FileASTData,
+) -> impl Iterator<Item = (&'static str, &'a String, FnParts<'a>, FnParts<'a>)> {
+    let fn_parts = |func: &'a ItemFn| -> FnParts<'a> { (&func.sig, &func.block) };
+    let trait_method_parts = |method: &'a TraitItemFn| -> FnParts<'a> { (&method.sig, &method.default) };
+
+    let functions = changes.modified_functions.iter().map(move |entry| {
+        let name = &entry[0];
+        ("function", name, fn_parts(&old_ast.functions[name]), fn_parts(&new_ast.functions[name]))
+    });
+    let methods = changes.modified_methods.iter().map(move |entry| {
+        let name = &entry[0];
+        ("method", name, fn_parts(&old_ast.methods[name].1), fn_parts(&new_ast.methods[name].1))
+    });
+    let trait_methods = changes.modified_trait_methods.iter().map(move |entry| {
+        let name = &entry[0];
+        let old = trait_method_parts(&old_ast.trait_methods[name]);
+        ("trait_method", name, old, trait_method_parts(&new_ast.trait_methods[name]))
+    });
+    functions.chain(methods).chain(trait_methods)
+}
+
+// Append the change kind to each modified function, method and trait method entry: `signature`
+// when only its signature or visibility changed, `body` when only its body did, or `both`
+fn classify_modified_fns(changes: &mut DetailedChanges, old_ast: &FileASTData, new_ast: &FileASTData) {
+    let change_kinds: Vec<&str> = modified_fns(changes, old_ast, new_ast)
+        .map(|(kind, name, (_, old_body), (_, new_body))| {
+            let body_changed = old_body.to_token_stream().to_string() != new_body.to_token_stream().to_string();
+            // Entries left in the modified lists changed in their code, so one with the same body
+            // changed in its signature, even where the structured signature misses it
+            let signature_changed = !body_changed
+                || changes.signature_changes.iter().any(|change| change.kind == kind && &change.name == name)
+                || changes.visibility_changes.iter().any(|change| change.kind == kind && &change.name == name);
+
+            match (signature_changed, body_changed) {
+                (true, true) => "both",
+                (true, false) => "signature",
+                _ => "body",
+            }
+        })
+        .collect();
+
+    let entries = changes
+        .modified_functions
+        .iter_mut()
+        .chain(changes.modified_methods.iter_mut())
+        .chain(changes.modified_trait_methods.iter_mut());
+    for (entry, change_kind) in entries.zip(change_kinds) {
+        entry.push(change_kind.to_string());
+    }
+}
+
+// Score how similar the old and new code of each modified entity are, token by token
+fn find_similarities(changes: &DetailedChanges) -> Vec<EntitySimilarity> {
+    let categories = [
+        ("function", &changes.modified_functions),
+        ("type", &changes.modified_types),
+        ("interface", &changes.modified_interfaces),
+        ("method", &changes.modified_methods),
+        ("trait_method", &changes.modified_trait_methods),
+        ("constant", &changes.modified_constants),
+        ("macro", &changes.modified_macros),
+        ("associated_const", &changes.modified_associated_consts),
+        ("associated_type", &changes.modified_associated_types),
+    ];
+
+    let sequence = |code: &str| code.parse::<TokenStream>().map(token_sequence).unwrap_or_default();
+    categories
+        .into_iter()
+        .flat_map(|(kind, modified)| {
+            modified.iter().map(move |entry| EntitySimilarity {
+                kind: kind.to_string(),
+                name: entry[0].clone(),
+                similarity: sequence_similarity(&sequence(&entry[1]), &sequence(&entry[2])),
+            })
+        })
+        .collect()
+}
+
+// Compare the initializers of modified consts, statics and associated consts, and the
+// discriminants of modified enum variants, keeping those whose value changed
+fn find_value_changes(changes: &DetailedChanges, old_ast: &FileASTData, new_ast: &FileASTData) -> Vec<ValueChange> {
+    let mut value_changes = Vec::new();
+    let mut push = |kind: &str, name: &str, old_value: Option<String>, new_value: Option<String>| {
+        if old_value != new_value {
+            value_changes.push(ValueChange {
+                kind: kind.to_string(),
+                name: name.to_string(),
+                old_value,
+                new_value,
+            });
+        }
+    };
+
+    let constant_value = |ast: &FileASTData, name: &str| match ast.constants.get(name)? {
+        Item::Const(const_def) => Some(format_compact(&*const_def.expr)),
+        Item::Static(static_def) => Some(format_compact(&*static_def.expr)),
+        _ => None,
+    };
+    for entry in &changes.modified_constants {
+        if let (Some(old_value), Some(new_value)) =
+            (constant_value(old_ast, &entry[0]), constant_value(new_ast, &entry[0]))
+        {
+            push("constant", &entry[0], Some(old_value), Some(new_value));
+        }
+    }
+
+    // Consts of impls always have a value, those of traits only a default
+    let associated_value = |ast: &FileASTData, name: &str| {
+        let tokens = ast.associated_consts.get(name)?.clone();
+        match syn::parse2::<ImplItem>(tokens.clone()) {
+            Ok(ImplItem::Const(const_def)) => Some(Some(format_compact(&const_def.expr))),
+            _ => match syn::parse2::<TraitItem>(tokens).ok()? {
+                TraitItem::Const(const_def) => Some(const_def.default.map(|(_, expr)| format_compact(&expr))),
+                _ => None,
+            },
+        }
+    };
+    for entry in &changes.modified_associated_consts {
+        if let (Some(old_value), Some(new_value)) =
+            (associated_value(old_ast, &entry[0]), associated_value(new_ast, &entry[0]))
+        {
+            push("associated_const", &entry[0], old_value, new_value);
+        }
+    }
+
+    for enum_changes in &changes.variant_changes {
+        for variant in &enum_changes.modified_variants {
+            let name = format!("{}::{}", enum_changes.name, variant.name);
+            let (old_value, new_value) = (&variant.old_variant.discriminant, &variant.new_variant.discriminant);
+            push("discriminant", &name, old_value.clone(), new_value.clone());
+        }
+    }
+
+    value_changes
+}
+
+// Compare the signatures of modified functions, methods and trait methods
+fn find_signature_changes(
+    changes: &DetailedChanges,
+    old_ast: &FileASTData,
+    new_ast: &FileASTData,
+) -> Vec<SignatureChange> {
+    modified_fns(changes, old_ast, new_ast)
+        .filter_map(|(kind, name, (old_sig, old_body), (new_sig, new_body))| {
+            let old_signature = fn_signature(old_sig);
+            let new_signature = fn_signature(new_sig);
+            if old_signature == new_signature {
+                return None;
+            }
+            Some(SignatureChange {
+                kind: kind.to_string(),
+                name: name.clone(),
+                param_changes: find_param_changes(&old_signature, &new_signature),
+                old_signature,
+                new_signature,
+                body_changed: old_body.to_token_stream().to_string() != new_body.to_token_stream().to_string(),
+            })
+        })
+        .collect()
+}
+
+// Break a signature change down into parameters added, removed, renamed, retyped or
+// reordered, and a changed return type. Parameters are matched by name first, ignoring `mut`
+// bindings, which callers don't see; the ones left that keep their position and type were renamed.
+fn find_param_changes(old: &FnSignature, new: &FnSignature) -> Vec<ParamChange> {
+    let name = |param: &FnParam| param.pattern.strip_prefix("mut ").unwrap_or(&param.pattern).to_string();
+    let mut pairs = Vec::new();
+    let mut new_matched = vec![false; new.params.len()];
+    let mut old_matched = vec![false; old.params.len()];
+    for (old_index, param) in old.params.iter().enumerate() {
+        let found = (0..new.params.len()).find(|&i| !new_matched[i] && name(&new.params[i]) == name(param));
+        if let Some(new_index) = found {
+            pairs.push((old_index, new_index));
+            old_matched[old_index] = true;
+            new_matched[new_index] = true;
+        }
+    }
+    for index in 0..old.params.len().min(new.params.len()) {
+        if !old_matched[index] && !new_matched[index] && old.params[index].ty == new.params[index].ty {
+            pairs.push((index, index));
+            old_matched[index] = true;
+            new_matched[index] = true;
+        }
+    }
+    pairs.sort();
+
+    let change = |change: &str, old_index: Option<usize>, new_index: Option<usize>| {
+        let (old_param, new_param) = (old_index.map(|i| &old.params[i]), new_index.map(|i| &new.params[i]));
+        ParamChange {
+            change: change.to_string(),
+            old_name: old_param.map(|param| param.pattern.clone()),
+            new_name: new_param.map(|param| param.pattern.clone()),
+            old_type: old_param.map(|param| param.ty.clone()),
+            new_type: new_param.map(|param| param.ty.clone()),
+            old_position: old_index,
+            new_position: new_index,
+        }
+    };
+
+    let mut param_changes = Vec::new();
+    let new_indices: Vec<usize> = pairs.iter().map(|(_, new_index)| *new_index).collect();
+    for (&(old_index, new_index), in_order) in pairs.iter().zip(in_order(&new_indices)) {
+        let (old_param, new_param) = (&old.params[old_index], &new.params[new_index]);
+        for (kind, changed) in [
+            ("renamed", name(old_param) != name(new_param)),
+            ("retyped", old_param.ty != new_param.ty),
+            ("reordered", !in_order),
+        ] {
+            if changed {
+                param_changes.push(change(kind, Some(old_index), Some(new_index)));
+            }
+        }
+    }
+    for index in (0..old.params.len()).filter(|&index| !old_matched[index]) {
+        param_changes.push(change("removed", Some(index), None));
+    }
+    for index in (0..new.params.len()).filter(|&index| !new_matched[index]) {
+        param_changes.push(change("added", None, Some(index)));
+    }
+    if old.return_type != new.return_type {
+        param_changes.push(ParamChange {
+            change: "return_type".to_string(),
+            old_name: None,
+            new_name: None,
+            old_type: old.return_type.clone(),
+            new_type: new.return_type.clone(),
+            old_position: None,
+            new_position: None,
+        });
+    }
+    param_changes
+}
+
+// Compare the generic parameters and bounds of modified functions, methods, trait methods,
+// types and traits
+fn find_generics_changes(
+    changes: &DetailedChanges,
+    old_ast: &FileASTData,
+    new_ast: &FileASTData,
+) -> Vec<GenericsChange> {
+    let fns = modified_fns(changes, old_ast, new_ast).map(|(kind, name, (old_sig, _), (new_sig, _))| {
+        (kind, name, item_generics(&old_sig.generics), item_generics(&new_sig.generics))
+    });
+    let types = changes.modified_types.iter().filter_map(|entry| {
+        let name = &entry[0];
+        let old = item_generics(type_generics(old_ast.types.get(name)?)?);
+        Some(("type", name, old, item_generics(type_generics(new_ast.types.get(name)?)?)))
+    });
+    let interfaces = changes.modified_interfaces.iter().filter_map(|entry| {
+        let name = &entry[0];
+        let old = trait_generics(old_ast.interfaces.get(name)?);
+        Some(("interface", name, old, trait_generics(new_ast.interfaces.get(name)?)))
+    });
+
+    let trait_impls = common_trait_impls(old_ast, new_ast).into_iter().map(|name| {
+        let (old, new) = (&old_ast.trait_impls[name].generics, &new_ast.trait_impls[name].generics);
+        ("trait_impl", name, item_generics(old), item_generics(new))
+    });
+
+    let mut generics_changes: Vec<GenericsChange> = fns
+        .chain(types)
+        .chain(interfaces)
+        .chain(trait_impls)
+        .filter(|(_, _, old, new)| old.params != new.params || !same_bounds(old, new))
+        .map(|(kind, name, old, new)| generics_change(kind, name, old, new))
+        .collect();
+
+    // Inherent impls aren't entities of their own, so those of methods on both sides are
+    // compared, each under the type it's on
+    let mut method_names: Vec<&String> = old_ast
+        .methods
+        .iter()
+        .filter(|(name, (impl_block, _))| impl_block.trait_.is_none() && new_ast.methods.contains_key(*name))
+        .map(|(name, _)| name)
+        .collect();
+    method_names.sort();
+    for name in method_names {
+        let old = item_generics(&old_ast.methods[name].0.generics);
+        let new = item_generics(&new_ast.methods[name].0.generics);
+        let key = name.split(" #[cfg(").next().unwrap_or(name);
+        let Some((owner, _)) = key.rsplit_once('.') else {
+            continue;
+        };
+        let seen = generics_changes.iter().any(|change| {
+            change.kind == "impl" && change.name == owner && change.old_generics == old && change.new_generics == new
+        });
+        if !seen && (old.params != new.params || !same_bounds(&old, &new)) {
+            generics_changes.push(generics_change("impl", owner, old, new));
+        }
+    }
+    generics_changes
+}
+
+// Generics of a struct, enum, union or type alias
+fn type_generics(item: &Item) -> Option<&syn::Generics> {
+    match item {
+        Item::Struct(item) => Some(&item.generics),
+        Item::Enum(item) => Some(&item.generics),
+        Item::Union(item) => Some(&item.generics),
+        Item::Type(item) => Some(&item.generics),
+        _ => None,
+    }
+}
+
+// Whether two sides have the same bounds, in whatever order they were written
+fn same_bounds(old: &ItemGenerics, new: &ItemGenerics) -> bool {
+    old.bounds.len() == new.bounds.len() && old.bounds.iter().all(|bound| new.bounds.contains(bound))
+}
+
+// Record the parameters and bounds only one side of a modified item's generics has
+fn generics_change(kind: &str, name: &str, old: ItemGenerics, new: ItemGenerics) -> GenericsChange {
+    GenericsChange {
+        kind: kind.to_string(),
+        name: name.to_string(),
+        added_params: new.params.iter().filter(|param| !old.params.contains(param)).cloned().collect(),
+        removed_params: old.params.iter().filter(|param| !new.params.contains(param)).cloned().collect(),
+        added_bounds: new.bounds.iter().filter(|bound| !old.bounds.contains(bound)).cloned().collect(),
+        removed_bounds: old.bounds.iter().filter(|bound| !new.bounds.contains(bound)).cloned().collect(),
+        old_generics: old,
+        new_generics: new,
+    }
+}
+
+// Compare the lifetimes of modified functions, methods, trait methods and types
+fn find_lifetime_changes(
+    changes: &DetailedChanges,
+    old_ast: &FileASTData,
+    new_ast: &FileASTData,
+) -> Vec<LifetimeChange> {
+    let fns = modified_fns(changes, old_ast, new_ast)
+        .map(|(kind, name, (old_sig, _), (new_sig, _))| (kind, name, fn_lifetimes(old_sig), fn_lifetimes(new_sig)));
+    let types = changes.modified_types.iter().filter_map(|entry| {
+        let name = &entry[0];
+        let old = type_lifetimes(old_ast.types.get(name)?)?;
+        Some(("type", name, old, type_lifetimes(new_ast.types.get(name)?)?))
+    });
+
+    fns.chain(types)
+        .filter(|(_, _, old, new)| old != new)
+        .map(|(kind, name, old, new)| LifetimeChange {
+            kind: kind.to_string(),
+            name: name.clone(),
+            changes: lifetime_change_kinds(&old, &new),
+            old_lifetimes: old,
+            new_lifetimes: new,
+        })
+        .collect()
+}
+
+// Kinds of change between two lifetime usages. Positions are compared pairwise when both sides
+// have the same number of them; otherwise references or lifetime arguments were added or removed.
+fn lifetime_change_kinds(old: &LifetimeUsage, new: &LifetimeUsage) -> Vec<String> {
+    let mut kinds = Vec::new();
+    let mut push = |kind: &str| {
+        if !kinds.iter().any(|existing| existing == kind) {
+            kinds.push(kind.to_string());
+        }
+    };
+
+    if new.params.iter().any(|param| !old.params.contains(param)) {
+        push("added_param");
+    }
+    if old.params.iter().any(|param| !new.params.contains(param)) {
+        push("removed_param");
+    }
+
+    if old.positions.len() != new.positions.len() {
+        push(if old.positions.len() < new.positions.len() { "added_positions" } else { "removed_positions" });
+        return kinds;
+    }
+    for (was, is) in old.positions.iter().zip(&new.positions) {
+        match (was.as_str(), is.as_str()) {
+            (was, is) if was == is => {}
+            ("'_", _) => push("elided_to_explicit"),
+            (_, "'_") => push("explicit_to_elided"),
+            (_, "'static") => push("became_static"),
+            ("'static", _) => push("no_longer_static"),
+            _ => push("changed_lifetime"),
+        }
+    }
+    kinds
+}
+
+// Compare the qualifiers of modified functions, methods and trait methods
+fn find_qualifier_changes(
+    changes: &DetailedChanges,
+    old_ast: &FileASTData,
+    new_ast: &FileASTData,
+) -> Vec<QualifierChange> {
+    let mut qualifier_changes: Vec<QualifierChange> = modified_fns(changes, old_ast, new_ast)
+        .filter_map(|(kind, name, (old_sig, _), (new_sig, _))| {
+            let old = fn_qualifiers(old_sig);
+            let new = fn_qualifiers(new_sig);
+            if old == new {
+                return None;
+            }
+
+            let mut qualifier_changes = Vec::new();
+            for (qualifier, was, is) in [
+                ("async", old.is_async, new.is_async),
+                ("const", old.is_const, new.is_const),
+                ("unsafe", old.is_unsafe, new.is_unsafe),
+                ("extern", old.abi.is_some(), new.abi.is_some()),
+            ] {
+                match (was, is) {
+                    (false, true) => qualifier_changes.push(format!("became_{}", qualifier)),
+                    (true, false) => qualifier_changes.push(format!("no_longer_{}", qualifier)),
+                    _ => {}
+                }
+            }
+            if old.abi.is_some() && new.abi.is_some() && old.abi != new.abi {
+                qualifier_changes.push("changed_abi".to_string());
+            }
+
+            Some(QualifierChange {
+                kind: kind.to_string(),
+                name: name.clone(),
+                changes: qualifier_changes,
+                old_qualifiers: old,
+                new_qualifiers: new,
+            })
+        })
+        .collect();
+
+    // Traits and trait impls only have `unsafe`, which changes what implementing them promises
+    let traits = changes.modified_interfaces.iter().filter_map(|entry| {
+        let (old, new) = (old_ast.interfaces.get(&entry[0])?, new_ast.interfaces.get(&entry[0])?);
+        Some(("interface", &entry[0], old.unsafety.is_some(), new.unsafety.is_some()))
+    });
+    let trait_impls = common_trait_impls(old_ast, new_ast).into_iter().map(|name| {
+        let (old, new) = (&old_ast.trait_impls[name], &new_ast.trait_impls[name]);
+        ("trait_impl", name, old.unsafety.is_some(), new.unsafety.is_some())
+    });
+    for (kind, name, was_unsafe, is_unsafe) in traits.chain(trait_impls) {
+        if was_unsafe == is_unsafe {
+            continue;
+        }
+        let qualifiers = |is_unsafe| FnQualifiers {
+            is_async: false,
+            is_const: false,
+            is_unsafe,
+            abi: None,
+        };
+        qualifier_changes.push(QualifierChange {
+            kind: kind.to_string(),
+            name: name.clone(),
+            changes: vec![if is_unsafe { "became_unsafe" } else { "no_longer_unsafe" }.to_string()],
+            old_qualifiers: qualifiers(was_unsafe),
+            new_qualifiers: qualifiers(is_unsafe),
+        });
+    }
+    qualifier_changes
+}
+
+// Names of the trait impls on both sides, in order
+fn common_trait_impls<'a>(old_ast: &'a FileASTData, new_ast: &FileASTData) -> Vec<&'a String> {
+    let mut names: Vec<&String> =
+        old_ast.trait_impls.keys().filter(|name| new_ast.trait_impls.contains_key(*name)).collect();
+    names.sort();
+    names
+}
+
+// Record how the attributes, docs and visibility of each modified entity changed, moving
+// entities where only attributes or docs changed out of the modified lists
+fn classify_attribute_changes(changes: &mut DetailedChanges) {
+    let mut attribute_changes = Vec::new();
+    let mut doc_only_changes = Vec::new();
+    let mut visibility_changes = Vec::new();
+    let categories = [
+        ("function", &mut changes.modified_functions),
+        ("type", &mut changes.modified_types),
+        ("interface", &mut changes.modified_interfaces),
+        ("method", &mut changes.modified_methods),
+        ("trait_method", &mut changes.modified_trait_methods),
+        ("constant", &mut changes.modified_constants),
+        ("macro", &mut changes.modified_macros),
+        ("associated_const", &mut changes.modified_associated_consts),
+        ("associated_type", &mut changes.modified_associated_types),
+    ];
+
+    for (kind, modified) in categories {
+        modified.retain(|entry| {
+            let (Some(old), Some(new)) = (split_outer_attributes(&entry[1]), split_outer_attributes(&entry[2])) else {
+                return true;
+            };
+            let code_changed = old.code != new.code;
+
+            if old.visibility != new.visibility {
+                visibility_changes.push(VisibilityChange {
+                    kind: kind.to_string(),
+                    name: entry[0].clone(),
+                    old_visibility: old.visibility.clone(),
+                    new_visibility: new.visibility.clone(),
+                });
+            }
+
+            if old.attributes != new.attributes {
+                attribute_changes.push(AttributeChange {
+                    kind: kind.to_string(),
+                    name: entry[0].clone(),
+                    added_attributes: new
+                        .attributes
+                        .iter()
+                        .filter(|attribute| !old.attributes.contains(attribute))
+                        .cloned()
+                        .collect(),
+                    removed_attributes: old
+                        .attributes
+                        .iter()
+                        .filter(|attribute| !new.attributes.contains(attribute))
+                        .cloned()
+                        .collect(),
+                    attributes_only: !code_changed,
+                });
+            } else if old.docs != new.docs && !code_changed {
+                doc_only_changes.push(DocChange {
+                    kind: kind.to_string(),
+                    name: entry[0].clone(),
+                    old_docs: old.docs,
+                    new_docs: new.docs,
+                });
+            }
+
+            code_changed
+        });
+    }
+
+    changes.attribute_changes = attribute_changes;
+    changes.doc_only_changes = doc_only_changes;
+    changes.visibility_changes = visibility_changes;
+}
+
 // Find elements present in new but not in old (for functions)
 fn find_added_func_elements(
     old_map: &HashMap<String, ItemFn>,
@@ -122,16 +1162,14 @@ fn find_added_func_elements(
 fn find_modified_func_elements(
     old_map: &HashMap<String, ItemFn>,
     new_map: &HashMap<String, ItemFn>,
+    same_content: impl Fn(&str) -> bool,
 ) -> Vec<Vec<String>> {
     let mut modified = Vec::new();
 
     for (name, old_node) in old_map {
         if let Some(new_node) = new_map.get(name) {
-            let old_code = format_node(old_node);
-            let new_code = format_node(new_node);
-            
-            if old_code != new_code {
-                modified.push(vec![name.clone(), old_code, new_code]);
+            if !same_content(name) {
+                modified.push(vec![name.clone(), format_node(old_node), format_node(new_node)]);
             }
         }
     }
@@ -156,7 +1194,7 @@ fn find_deleted_func_elements(
     deleted
 }
 
-// Find elements present in new but not in old (for types)
+// Find elements present in new but not in old (for types, constants and statics)
 fn find_added_type_elements(
     old_map: &HashMap<String, Item>,
     new_map: &HashMap<String, Item>,
@@ -173,20 +1211,18 @@ fn find_added_type_elements(
     added
 }
 
-// Find elements present in both but with different code (for types)
+// Find elements present in both but with different code (for types, constants and statics)
 fn find_modified_type_elements(
     old_map: &HashMap<String, Item>,
     new_map: &HashMap<String, Item>,
+    same_content: impl Fn(&str) -> bool,
 ) -> Vec<Vec<String>> {
     let mut modified = Vec::new();
 
     for (name, old_node) in old_map {
         if let Some(new_node) = new_map.get(name) {
-            let old_code = format_node(old_node);
-            let new_code = format_node(new_node);
-            
-            if old_code != new_code {
-                modified.push(vec![name.clone(), old_code, new_code]);
+            if !same_content(name) {
+                modified.push(vec![name.clone(), format_node(old_node), format_node(new_node)]);
             }
         }
     }
@@ -194,7 +1230,7 @@ fn find_modified_type_elements(
     modified
 }
 
-// Find elements present in old but not in new (for types)
+// Find elements present in old but not in new (for types, constants and statics)
 fn find_deleted_type_elements(
     old_map: &HashMap<String, Item>,
     new_map: &HashMap<String, Item>,
@@ -232,16 +1268,14 @@ fn find_added_trait_elements(
 fn find_modified_trait_elements(
     old_map: &HashMap<String, ItemTrait>,
     new_map: &HashMap<String, ItemTrait>,
+    same_content: impl Fn(&str) -> bool,
 ) -> Vec<Vec<String>> {
     let mut modified = Vec::new();
 
     for (name, old_node) in old_map {
         if let Some(new_node) = new_map.get(name) {
-            let old_code = format_node(old_node);
-            let new_code = format_node(new_node);
-            
-            if old_code != new_code {
-                modified.push(vec![name.clone(), old_code, new_code]);
+            if !same_content(name) {
+                modified.push(vec![name.clone(), format_node(old_node), format_node(new_node)]);
             }
         }
     }
@@ -287,16 +1321,14 @@ fn find_added_method_elements(
 fn find_modified_method_elements(
     old_map: &HashMap<String, (ItemImpl, ItemFn)>,
     new_map: &HashMap<String, (ItemImpl, ItemFn)>,
+    same_content: impl Fn(&str) -> bool,
 ) -> Vec<Vec<String>> {
     let mut modified = Vec::new();
 
     for (name, (_, old_node)) in old_map {
         if let Some((_, new_node)) = new_map.get(name) {
-            let old_code = format_node(old_node);
-            let new_code = format_node(new_node);
-            
-            if old_code != new_code {
-                modified.push(vec![name.clone(), old_code, new_code]);
+            if !same_content(name) {
+                modified.push(vec![name.clone(), format_node(old_node), format_node(new_node)]);
             }
         }
     }
@@ -321,135 +1353,645 @@ fn find_deleted_method_elements(
     deleted
 }
 
-// Process all Rust files with minimized Git checkouts
-pub fn process_rust_files(
-    rust_files: &[String],
-    local_repo_path: &str,
-    branch_name: &str,
-    current_commit: &str,
-    new_file_map: &HashMap<String, bool>,
-    deleted_file_map: &HashMap<String, bool>,
-) -> Vec<DetailedChanges> {
-    let mut all_changes = Vec::new();
-
-    // Maps to store ASTs from both commits
-    let mut branch_asts = HashMap::new();
-    let mut current_asts = HashMap::new();
-
-    // Step 1: Checkout branch commit and extract ASTs for all files
-    if let Err(e) = checkout_branch(branch_name, local_repo_path) {
-        println!("Error checking out branch {}: {}", branch_name, e);
-        return all_changes;
-    }
-    println!("Successfully checked out branch {}", branch_name);
-
-    // Process all files in the branch commit (except new files)
-    for go_file in rust_files {
-        if !new_file_map.contains_key(go_file) {
-            let full_path = Path::new(local_repo_path).join(go_file);
-            match extract_file_ast(full_path.to_str().unwrap_or("")) {
-                Ok(ast) => {
-                    branch_asts.insert(go_file.clone(), ast);
-                },
-                Err(e) => {
-                    println!("Error parsing AST for {} in branch: {}", go_file, e);
-                    // Create an empty AST if we couldn't parse the file
-                    branch_asts.insert(go_file.clone(), FileASTData::empty(go_file.clone()));
-                }
+// Find elements present in new but not in old (for trait impls)
+fn find_added_impl_elements(
+    old_map: &HashMap<String, ItemImpl>,
+    new_map: &HashMap<String, ItemImpl>,
+) -> Vec<Vec<String>> {
+    let mut added = Vec::new();
+
+    for (name, new_node) in new_map {
+        if !old_map.contains_key(name) {
+            let code = format_node(new_node);
+            added.push(vec![name.clone(), code]);
+        }
+    }
+
+    added
+}
+
+// Find elements present in old but not in new (for trait impls)
+fn find_deleted_impl_elements(
+    old_map: &HashMap<String, ItemImpl>,
+    new_map: &HashMap<String, ItemImpl>,
+) -> Vec<Vec<String>> {
+    let mut deleted = Vec::new();
+
+    for (name, old_node) in old_map {
+        if !new_map.contains_key(name) {
+            let code = format_node(old_node);
+            deleted.push(vec![name.clone(), code]);
+        }
+    }
+
+    deleted
+}
+
+// Find elements present in new but not in old (for trait methods)
+fn find_added_trait_method_elements(
+    old_map: &HashMap<String, TraitItemFn>,
+    new_map: &HashMap<String, TraitItemFn>,
+) -> Vec<Vec<String>> {
+    let mut added = Vec::new();
+
+    for (name, new_node) in new_map {
+        if !old_map.contains_key(name) {
+            let code = format_node(new_node);
+            added.push(vec![name.clone(), code]);
+        }
+    }
+
+    added
+}
+
+// Find elements present in both but with different code (for trait methods)
+fn find_modified_trait_method_elements(
+    old_map: &HashMap<String, TraitItemFn>,
+    new_map: &HashMap<String, TraitItemFn>,
+    same_content: impl Fn(&str) -> bool,
+) -> Vec<Vec<String>> {
+    let mut modified = Vec::new();
+
+    for (name, old_node) in old_map {
+        if let Some(new_node) = new_map.get(name) {
+            if !same_content(name) {
+                modified.push(vec![name.clone(), format_node(old_node), format_node(new_node)]);
             }
         }
     }
 
-    // Step 2: Checkout current commit and extract ASTs for all files
-    if let Err(e) = checkout_commit(current_commit, local_repo_path) {
-        println!("Error checking out commit {}: {}", current_commit, e);
-        
-        // Try alternative checkout method
-        if let Err(e) = checkout_commit(&format!("{}^{{commit}}", current_commit), local_repo_path) {
-            println!("Error checking out commit using alternative method: {}", e);
-            return all_changes;
-        }
-        
-        println!("Successfully checked out commit using alternative method.");
-    } else {
-        println!("Successfully checked out commit {}", current_commit);
-    }
-
-    // Process all files in the current commit (except deleted files)
-    for go_file in rust_files {
-        if !deleted_file_map.contains_key(go_file) {
-            let full_path = Path::new(local_repo_path).join(go_file);
-            match extract_file_ast(full_path.to_str().unwrap_or("")) {
-                Ok(ast) => {
-                    current_asts.insert(go_file.clone(), ast);
-                },
-                Err(e) => {
-                    println!("Error parsing AST for {} in current commit: {}", go_file, e);
-                    // Create an empty AST if we couldn't parse the file
-                    current_asts.insert(go_file.clone(), FileASTData::empty(go_file.clone()));
-                }
+    modified
+}
+
+// Find elements present in old but not in new (for trait methods)
+fn find_deleted_trait_method_elements(
+    old_map: &HashMap<String, TraitItemFn>,
+    new_map: &HashMap<String, TraitItemFn>,
+) -> Vec<Vec<String>> {
+    let mut deleted = Vec::new();
+
+    for (name, old_node) in old_map {
+        if !new_map.contains_key(name) {
+            let code = format_node(old_node);
+            deleted.push(vec![name.clone(), code]);
+        }
+    }
+
+    deleted
+}
+
+// Find elements present in new but not in old (for macros)
+fn find_added_macro_elements(
+    old_map: &HashMap<String, ItemMacro>,
+    new_map: &HashMap<String, ItemMacro>,
+) -> Vec<Vec<String>> {
+    let mut added = Vec::new();
+
+    for (name, new_node) in new_map {
+        if !old_map.contains_key(name) {
+            let code = format_node(new_node);
+            added.push(vec![name.clone(), code]);
+        }
+    }
+
+    added
+}
+
+// Find elements present in both but with different code (for macros)
+fn find_modified_macro_elements(
+    old_map: &HashMap<String, ItemMacro>,
+    new_map: &HashMap<String, ItemMacro>,
+    same_content: impl Fn(&str) -> bool,
+) -> Vec<Vec<String>> {
+    let mut modified = Vec::new();
+
+    for (name, old_node) in old_map {
+        if let Some(new_node) = new_map.get(name) {
+            if !same_content(name) {
+                modified.push(vec![name.clone(), format_node(old_node), format_node(new_node)]);
             }
         }
     }
 
-    // Step 3: Compare all ASTs and collect changes
-    for go_file in rust_files {
-        // Extract package name for the module name
-        let package_name;
-        
-        if new_file_map.contains_key(go_file) {
-            // For new files, extract package from current commit's AST
-            let full_path = Path::new(local_repo_path).join(go_file);
-            package_name = extract_module_name(full_path.to_str().unwrap_or(""));
-        } else if deleted_file_map.contains_key(go_file) {
-            // For deleted files, we can't reliably get package name from the file
-            // Use directory name as fallback
-            package_name = Path::new(go_file)
-                .parent()
-                .and_then(|p| p.file_name())
-                .and_then(|name| name.to_str())
-                .unwrap_or("unknown")
-                .to_string();
-        } else {
-            // For modified files, use current commit's package name
-            let full_path = Path::new(local_repo_path).join(go_file);
-            package_name = extract_module_name(full_path.to_str().unwrap_or(""));
-        }
-
-        // Initialize old and new ASTs
-        let old_ast;
-        let new_ast;
-
-        if new_file_map.contains_key(go_file) {
-            // For new files: empty old AST, new AST from current commit
-            old_ast = FileASTData::empty(go_file.clone());
-            new_ast = current_asts.get(go_file).cloned().unwrap_or_else(|| FileASTData::empty(go_file.clone()));
-            println!("File {} is new", go_file);
-        } else if deleted_file_map.contains_key(go_file) {
-            // For deleted files: old AST from branch, empty new AST
-            old_ast = branch_asts.get(go_file).cloned().unwrap_or_else(|| FileASTData::empty(go_file.clone()));
-            new_ast = FileASTData::empty(go_file.clone());
-            println!("File {} has been deleted", go_file);
-        } else {
-            // For modified files: both ASTs
-            old_ast = branch_asts.get(go_file).cloned().unwrap_or_else(|| FileASTData::empty(go_file.clone()));
-            new_ast = current_asts.get(go_file).cloned().unwrap_or_else(|| FileASTData::empty(go_file.clone()));
-        }
-
-        // Compare ASTs and collect changes
-        let changes = compare_asts(
-            &old_ast,
-            &new_ast,
-            &package_name,
-            go_file,
-            new_file_map.contains_key(go_file),
-            deleted_file_map.contains_key(go_file),
-        );
-        
-        if changes.has_changes() {
-            all_changes.push(changes);
+    modified
+}
+
+// Find elements present in old but not in new (for macros)
+fn find_deleted_macro_elements(
+    old_map: &HashMap<String, ItemMacro>,
+    new_map: &HashMap<String, ItemMacro>,
+) -> Vec<Vec<String>> {
+    let mut deleted = Vec::new();
+
+    for (name, old_node) in old_map {
+        if !new_map.contains_key(name) {
+            let code = format_node(old_node);
+            deleted.push(vec![name.clone(), code]);
+        }
+    }
+
+    deleted
+}
+
+// Find elements present in new but not in old (for associated consts and types)
+fn find_added_associated_elements(
+    old_map: &HashMap<String, TokenStream>,
+    new_map: &HashMap<String, TokenStream>,
+) -> Vec<Vec<String>> {
+    let mut added = Vec::new();
+
+    for (name, new_node) in new_map {
+        if !old_map.contains_key(name) {
+            let code = format_node(new_node);
+            added.push(vec![name.clone(), code]);
         }
     }
 
+    added
+}
+
+// Find elements present in both but with different code (for associated consts and types)
+fn find_modified_associated_elements(
+    old_map: &HashMap<String, TokenStream>,
+    new_map: &HashMap<String, TokenStream>,
+    same_content: impl Fn(&str) -> bool,
+) -> Vec<Vec<String>> {
+    let mut modified = Vec::new();
+
+    for (name, old_node) in old_map {
+        if let Some(new_node) = new_map.get(name) {
+            if !same_content(name) {
+                modified.push(vec![name.clone(), format_node(old_node), format_node(new_node)]);
+            }
+        }
+    }
+
+    modified
+}
+
+// Find elements present in old but not in new (for associated consts and types)
+fn find_deleted_associated_elements(
+    old_map: &HashMap<String, TokenStream>,
+    new_map: &HashMap<String, TokenStream>,
+) -> Vec<Vec<String>> {
+    let mut deleted = Vec::new();
+
+    for (name, old_node) in old_map {
+        if !new_map.contains_key(name) {
+            let code = format_node(old_node);
+            deleted.push(vec![name.clone(), code]);
+        }
+    }
+
+    deleted
+}
+
+// Compare the arms of a modified macro by matcher, in the order they appear. An arm removed and
+// one added with the same transcriber are one arm whose matcher changed.
+fn find_macro_arm_changes(name: &str, old_macro: &ItemMacro, new_macro: &ItemMacro) -> Option<MacroArmChanges> {
+    let old_arms = extract_macro_arms(old_macro)?;
+    let new_arms = extract_macro_arms(new_macro)?;
+    let format_arm = |(matcher, transcriber): &(String, String)| format!("{} => {}", matcher, transcriber);
+
+    let mut changes = MacroArmChanges {
+        name: name.to_string(),
+        added_arms: Vec::new(),
+        modified_arms: Vec::new(),
+        removed_arms: Vec::new(),
+        modified_matchers: Vec::new(),
+        reordered: false,
+    };
+
+    let mut added = Vec::new();
+    for arm in &new_arms {
+        match old_arms.iter().find(|(matcher, _)| *matcher == arm.0) {
+            Some((_, old_transcriber)) if *old_transcriber != arm.1 => changes.modified_arms.push(ModifiedCode {
+                name: arm.0.clone(),
+                old_code: old_transcriber.clone(),
+                new_code: arm.1.clone(),
+            }),
+            Some(_) => {}
+            None => added.push(arm),
+        }
+    }
+    for arm in &old_arms {
+        if new_arms.iter().any(|(matcher, _)| *matcher == arm.0) {
+            continue;
+        }
+        match added.iter().position(|(_, transcriber)| *transcriber == arm.1) {
+            Some(index) => changes.modified_matchers.push(MatcherChange {
+                old_matcher: arm.0.clone(),
+                new_matcher: added.remove(index).0.clone(),
+                transcriber: arm.1.clone(),
+            }),
+            None => changes.removed_arms.push(format_arm(arm)),
+        }
+    }
+    changes.added_arms = added.into_iter().map(format_arm).collect();
+
+    // The first arm that matches is the one expanded, so the order of the common arms matters
+    let common = |arms: &[(String, String)], others: &[(String, String)]| -> Vec<String> {
+        arms.iter()
+            .filter(|(matcher, _)| others.iter().any(|(other, _)| other == matcher))
+            .map(|(matcher, _)| matcher.clone())
+            .collect()
+    };
+    changes.reordered = common(&old_arms, &new_arms) != common(&new_arms, &old_arms);
+
+    // Attribute-only changes leave every arm as it was
+    let arms_changed = !changes.added_arms.is_empty() || !changes.modified_arms.is_empty();
+    if !arms_changed && changes.removed_arms.is_empty() && changes.modified_matchers.is_empty() && !changes.reordered {
+        return None;
+    }
+
+    Some(changes)
+}
+
+// Compare the fields of a modified struct or union by name, in the order they appear
+fn find_field_changes(name: &str, old_fields: &[StructField], new_fields: &[StructField]) -> Option<FieldChanges> {
+    let mut changes = FieldChanges {
+        name: name.to_string(),
+        added_fields: Vec::new(),
+        modified_fields: Vec::new(),
+        removed_fields: Vec::new(),
+    };
+
+    for field in new_fields {
+        match old_fields.iter().find(|old_field| old_field.name == field.name) {
+            Some(old_field) if old_field != field => changes.modified_fields.push(ModifiedField {
+                name: field.name.clone(),
+                old_field: old_field.clone(),
+                new_field: field.clone(),
+            }),
+            Some(_) => {}
+            None => changes.added_fields.push(field.clone()),
+        }
+    }
+    for field in old_fields {
+        if !new_fields.iter().any(|new_field| new_field.name == field.name) {
+            changes.removed_fields.push(field.clone());
+        }
+    }
+
+    // Changes elsewhere in the type leave every field as it was
+    if changes.added_fields.is_empty() && changes.modified_fields.is_empty() && changes.removed_fields.is_empty() {
+        return None;
+    }
+
+    Some(changes)
+}
+
+// Find the globals added, removed, or changed in type, kind or initializer, by name
+fn find_global_changes(old_ast: &FileASTData, new_ast: &FileASTData) -> Vec<GlobalChange> {
+    let mut names: Vec<&String> = old_ast.globals.keys().chain(new_ast.globals.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let old_global = old_ast.globals.get(name);
+            let new_global = new_ast.globals.get(name);
+            (old_global != new_global).then(|| GlobalChange {
+                name: name.clone(),
+                old_global: old_global.cloned(),
+                new_global: new_global.cloned(),
+            })
+        })
+        .collect()
+}
+
+// Compare the variants of a modified enum by name, in the order they appear
+fn find_variant_changes(
+    name: &str,
+    old_variants: &[EnumVariant],
+    new_variants: &[EnumVariant],
+) -> Option<VariantChanges> {
+    let mut changes = VariantChanges {
+        name: name.to_string(),
+        added_variants: Vec::new(),
+        modified_variants: Vec::new(),
+        removed_variants: Vec::new(),
+    };
+
+    for variant in new_variants {
+        match old_variants.iter().find(|old_variant| old_variant.name == variant.name) {
+            Some(old_variant) if old_variant != variant => changes.modified_variants.push(ModifiedVariant {
+                name: variant.name.clone(),
+                old_variant: old_variant.clone(),
+                new_variant: variant.clone(),
+            }),
+            Some(_) => {}
+            None => changes.added_variants.push(variant.clone()),
+        }
+    }
+    for variant in old_variants {
+        if !new_variants.iter().any(|new_variant| new_variant.name == variant.name) {
+            changes.removed_variants.push(variant.clone());
+        }
+    }
+
+    // Changes elsewhere in the enum leave every variant as it was
+    let unchanged = changes.added_variants.is_empty() && changes.modified_variants.is_empty();
+    if unchanged && changes.removed_variants.is_empty() {
+        return None;
+    }
+
+    Some(changes)
+}
+
+// Break each modified trait down into the changed entries under its path, i.e. its methods
+// `Trait::method` and associated items `Trait.NAME`. Traits whose change lies outside their
+// items, such as in supertraits or generics, have no breakdown.
+fn find_trait_item_changes(changes: &DetailedChanges) -> Vec<TraitItemChanges> {
+    changes
+        .modified_interfaces
+        .iter()
+        .filter_map(|entry| {
+            let trait_path = entry[0].split(" #[cfg(").next().unwrap_or(&entry[0]);
+            let (method_prefix, item_prefix) = (format!("{}::", trait_path), format!("{}.", trait_path));
+            let items = |entries: &[Vec<String>], prefix: &str| -> Vec<String> {
+                entries
+                    .iter()
+                    .filter_map(|entry| entry[0].strip_prefix(prefix))
+                    .map(str::to_string)
+                    .collect()
+            };
+
+            let item_changes = TraitItemChanges {
+                name: entry[0].clone(),
+                added_methods: items(&changes.added_trait_methods, &method_prefix),
+                modified_methods: items(&changes.modified_trait_methods, &method_prefix),
+                removed_methods: items(&changes.deleted_trait_methods, &method_prefix),
+                added_associated_types: items(&changes.added_associated_types, &item_prefix),
+                modified_associated_types: items(&changes.modified_associated_types, &item_prefix),
+                removed_associated_types: items(&changes.deleted_associated_types, &item_prefix),
+                added_associated_consts: items(&changes.added_associated_consts, &item_prefix),
+                modified_associated_consts: items(&changes.modified_associated_consts, &item_prefix),
+                removed_associated_consts: items(&changes.deleted_associated_consts, &item_prefix),
+            };
+            let lists = [
+                &item_changes.added_methods,
+                &item_changes.modified_methods,
+                &item_changes.removed_methods,
+                &item_changes.added_associated_types,
+                &item_changes.modified_associated_types,
+                &item_changes.removed_associated_types,
+                &item_changes.added_associated_consts,
+                &item_changes.modified_associated_consts,
+                &item_changes.removed_associated_consts,
+            ];
+            lists.iter().any(|list| !list.is_empty()).then_some(item_changes)
+        })
+        .collect()
+}
+
+// Parse a file's source, falling back to an empty AST if it can't be read or parsed
+pub fn parse_or_empty(
+    go_file: &str,
+    file_path: &str,
+    module_path: Option<&str>,
+    content: Option<&Result<String, String>>,
+    reference: &str,
+    errors: &ErrorLog,
+) -> FileASTData {
+    let (stage, e) = match content {
+        Some(Ok(source)) => match parse_file_ast(file_path, source.clone(), module_path) {
+            Ok(ast) => {
+                // Entities in the skipped lines are missing from this side
+                if let Some(e) = &ast.parse_error {
+                    errors.record("partial_parse", Some(go_file), Some(reference), e);
+                }
+                return ast;
+            }
+            Err(e) => ("parse", e),
+        },
+        Some(Err(e)) => ("read", e.clone()),
+        None => return FileASTData::empty(file_path.to_string()),
+    };
+
+    eprintln!("Error parsing AST for {} in {}: {}", go_file, reference, e);
+    errors.record(stage, Some(go_file), Some(reference), &e);
+
+    // Create an empty AST if we couldn't parse the file
+    FileASTData::empty(file_path.to_string())
+}
+
+// Load both sides of the selected files, recording a failed load against its ref
+pub fn load_both_sides(rust_files: &[String], ctx: &AnalysisContext) -> Option<(FileContents, FileContents)> {
+    let load = |reference: &str, files: &[String], skip_map: &HashMap<String, bool>| {
+        load_file_contents(ctx.content_source, ctx.lfs, reference, files, ctx.local_repo_path, skip_map)
+            .map_err(|e| {
+                eprintln!("Error loading files at {}: {}", reference, e);
+                ctx.errors.record("checkout", None, Some(reference), &e);
+            })
+            .ok()
+    };
+
+    // Renamed and copied files are read from their source path on the old side
+    let old_paths: Vec<String> = rust_files.iter().map(|file| ctx.old_path(file).clone()).collect();
+
+    // Files that are new have no old side, and deleted files no new side
+    let mut old_contents = load(ctx.branch_name, &old_paths, ctx.new_file_map)?;
+    let new_contents = load(ctx.current_commit, rust_files, ctx.deleted_file_map)?;
+
+    // Key the old contents of renamed files by their new path; a copy source
+    // keeps its own entry since it's also analyzed as itself
+    for (new_path, old_path) in ctx.renamed_file_map {
+        if let Some(content) = old_contents.remove(old_path) {
+            old_contents.insert(new_path.clone(), content);
+        }
+    }
+    for (new_path, old_path) in ctx.copied_file_map {
+        if let Some(content) = old_contents.get(old_path).cloned() {
+            old_contents.insert(new_path.clone(), content);
+        }
+    }
+
+    Some((old_contents, new_contents))
+}
+
+// Process all Rust files, reading each side once
+pub fn process_rust_files(rust_files: &[String], ctx: &AnalysisContext) -> Vec<DetailedChanges> {
+    // Step 1: Read all files on both sides
+    let Some((branch_contents, current_contents)) = load_both_sides(rust_files, ctx) else {
+        return Vec::new();
+    };
+
+    // Step 2: Resolve the module path each file has in its crate
+    let module_paths = resolve_module_paths(rust_files, ctx);
+
+    // Step 3: Parse and compare all files in parallel, keeping the input order
+    let mut all_changes: Vec<DetailedChanges> = rust_files
+        .par_iter()
+        .filter_map(|go_file| {
+            let is_new_file = ctx.new_file_map.contains_key(go_file);
+            let is_removed_file = ctx.deleted_file_map.contains_key(go_file);
+            // Parse the old and new ASTs; files missing on one side stay empty
+            let full_path = Path::new(ctx.local_repo_path).join(go_file);
+            let full_path = full_path.to_str().unwrap_or("");
+            let module_path = module_paths.get(go_file).map(|location| location.path.as_str());
+            let old_content = branch_contents.get(go_file);
+            let new_content = current_contents.get(go_file);
+            let old_ast = parse_or_empty(go_file, full_path, module_path, old_content, ctx.branch_name, ctx.errors);
+            let new_ast = parse_or_empty(go_file, full_path, module_path, new_content, ctx.current_commit, ctx.errors);
+
+            if is_new_file {
+                eprintln!("File {} is new", go_file);
+            } else if is_removed_file {
+                eprintln!("File {} has been deleted", go_file);
+            }
+
+            // Compare ASTs and collect changes
+            let mut changes = compare_asts(&old_ast, &new_ast, module_path, go_file, is_new_file, is_removed_file);
+            changes.crate_name = module_paths.get(go_file).and_then(|location| location.crate_name.clone());
+            changes.renamed_from = ctx.renamed_file_map.get(go_file).cloned();
+            changes.copied_from = ctx.copied_file_map.get(go_file).cloned();
+
+            // Renames and copies are reported even when the code itself is unchanged
+            if changes.has_changes() || changes.renamed_from.is_some() || changes.copied_from.is_some() {
+                Some(changes)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // Step 4: Match entities deleted from one file with those added to another, then annotate the
+    // files they moved between again, so their hashes and test code leave the moved entities out
+    for file in find_cross_file_moves(&mut all_changes) {
+        let changes = &mut all_changes[file];
+        let go_file = changes.module_name.clone();
+        let full_path = Path::new(ctx.local_repo_path).join(&go_file);
+        let full_path = full_path.to_str().unwrap_or("");
+        let module_path = changes.module_path.clone();
+        // The first parse already recorded any errors of these files
+        let errors = ErrorLog::new();
+        let parse = |content, reference| {
+            parse_or_empty(&go_file, full_path, module_path.as_deref(), content, reference, &errors)
+        };
+        let old_ast = parse(branch_contents.get(&go_file), ctx.branch_name);
+        let new_ast = parse(current_contents.get(&go_file), ctx.current_commit);
+        annotate_entities(changes, &old_ast, &new_ast);
+    }
     all_changes
-}
\ No newline at end of file
+}
+
+// Least share of tokens, in percent, that an entity deleted from one file and an entity of the
+// same kind added to another need in common to count as moved
+const MOVE_SIMILARITY: u32 = 90;
+
+// Pair up entities deleted from one file with entities of the same kind added to another, most
+// similar first, then those staying in their crate, then those keeping their name before
+// renamed ones, and take them out of the deleted and added lists as moves. Returns the indices
+// of the files that entities moved out of or into.
+fn find_cross_file_moves(all_changes: &mut [DetailedChanges]) -> BTreeSet<usize> {
+    // File index, entry index, name and token counts of the deleted and added entities, by kind
+    type Candidates = Vec<(usize, usize, String, HashMap<String, usize>)>;
+    let mut deleted_entities: HashMap<&'static str, Candidates> = HashMap::new();
+    let mut added_entities: HashMap<&'static str, Candidates> = HashMap::new();
+    for (file, changes) in all_changes.iter_mut().enumerate() {
+        for_each_entity_list(changes, |kind, added, _, deleted| {
+            for (list, entities) in [(deleted, &mut deleted_entities), (added, &mut added_entities)] {
+                for (index, entry) in list.iter().enumerate() {
+                    let counts = token_counts(entry[1].parse().unwrap_or_default());
+                    entities.entry(kind).or_default().push((file, index, entry[0].clone(), counts));
+                }
+            }
+        });
+    }
+
+    let mut moves = Vec::new();
+    let mut moved_entries: HashSet<(&'static str, usize, bool, usize)> = HashSet::new();
+    for (kind, deleted) in &deleted_entities {
+        let Some(added) = added_entities.get(kind) else {
+            continue;
+        };
+        let mut candidates = Vec::new();
+        for (d, (old_file, _, old_name, old_counts)) in deleted.iter().enumerate() {
+            for (a, (new_file, _, new_name, new_counts)) in added.iter().enumerate() {
+                if old_file == new_file {
+                    continue;
+                }
+                let similarity = token_similarity(old_counts, new_counts);
+                if similarity >= MOVE_SIMILARITY {
+                    let (old_changes, new_changes) = (&all_changes[*old_file], &all_changes[*new_file]);
+                    let other_crate = old_changes.crate_name != new_changes.crate_name;
+                    // Keys differ by module path across files, so a kept name compares short names
+                    let renamed = short_name(old_changes.module_path.as_deref(), old_name)
+                        != short_name(new_changes.module_path.as_deref(), new_name);
+                    candidates.push((Reverse(similarity), other_crate, renamed, d, a));
+                }
+            }
+        }
+        candidates.sort();
+
+        let (mut moved_deleted, mut moved_added) = (HashSet::new(), HashSet::new());
+        for (Reverse(similarity), _, _, d, a) in candidates {
+            if moved_deleted.contains(&d) || moved_added.contains(&a) {
+                continue;
+            }
+            moved_deleted.insert(d);
+            moved_added.insert(a);
+            let (old_file, old_index, old_name, _) = &deleted[d];
+            let (new_file, new_index, new_name, _) = &added[a];
+            moved_entries.insert((*kind, *old_file, false, *old_index));
+            moved_entries.insert((*kind, *new_file, true, *new_index));
+            moves.push((*old_file, *new_file, *kind, old_name.clone(), new_name.clone(), similarity));
+        }
+    }
+    moves.sort();
+    let moved_files = moves.iter().flat_map(|(old_file, new_file, ..)| [*old_file, *new_file]).collect();
+
+    for (old_file, new_file, kind, old_name, new_name, similarity) in moves {
+        let entity_move = CrossFileMove {
+            kind: kind.to_string(),
+            old_module: all_changes[old_file].module_name.clone(),
+            old_name,
+            new_module: all_changes[new_file].module_name.clone(),
+            new_name,
+            similarity,
+        };
+        eprintln!(
+            "Matched {} {} in {} with {} in {}",
+            kind, entity_move.old_name, entity_move.old_module, entity_move.new_name, entity_move.new_module
+        );
+
+        // A public entity that moved is still gone from its old path, but not new to the API
+        let (old_name, new_name) = (&entity_move.old_name, &entity_move.new_name);
+        for change in all_changes[old_file].semver_changes.iter_mut() {
+            if change.kind == kind && &change.name == old_name {
+                change.reason = format!("moved to {} in {}", new_name, entity_move.new_module);
+            }
+        }
+        all_changes[new_file]
+            .semver_changes
+            .retain(|change| change.kind != kind || &change.name != new_name);
+
+        // Moving an entity doesn't make it riskier, wherever it went
+        for (file, name) in [(old_file, old_name), (new_file, new_name)] {
+            let changes = &mut all_changes[file];
+            changes.risk_scores.retain(|score| score.kind != kind || &score.name != name);
+            changes.risk_score = changes.risk_scores.iter().map(|score| score.score).sum();
+        }
+
+        all_changes[old_file].moved_out.push(entity_move.clone());
+        all_changes[new_file].moved_in.push(entity_move);
+    }
+    for (file, changes) in all_changes.iter_mut().enumerate() {
+        for_each_entity_list(changes, |kind, added, _, deleted| {
+            let moved = |is_added: bool| -> HashSet<usize> {
+                moved_entries
+                    .iter()
+                    .filter(|(moved_kind, moved_file, moved_added, _)| {
+                        *moved_kind == kind && *moved_file == file && *moved_added == is_added
+                    })
+                    .map(|(_, _, _, index)| *index)
+                    .collect()
+            };
+            remove_entries(deleted, &moved(false));
+            remove_entries(added, &moved(true));
+        });
+    }
+    moved_files
+}
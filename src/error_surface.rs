@@ -0,0 +1,121 @@
+// src/error_surface.rs
+use crate::ast_parser::{format_compact, split_outer_attributes};
+use crate::differ::entity_source_code;
+use crate::modules::{full_name, short_name};
+use crate::semver::{is_non_exhaustive, is_public};
+use crate::types::{DetailedChanges, ErrorSurfaceChange, ErrorSurfaceReportEntry, FileASTData};
+
+// Find the error enums among the modified enums whose variants were added, removed or changed
+// payload. An enum is an error type by its name, e.g. `ParseError` or `IoErr`, by deriving `Error`
+// as with thiserror, or by implementing `std::error::Error` in the same file.
+pub fn find_error_surface_changes(
+    changes: &DetailedChanges,
+    old_ast: &FileASTData,
+    new_ast: &FileASTData,
+) -> Vec<ErrorSurfaceChange> {
+    let mut error_changes = Vec::new();
+    for variants in &changes.variant_changes {
+        let detected_by = error_markers(&variants.name, old_ast, new_ast);
+        if detected_by.is_empty() {
+            continue;
+        }
+
+        let changed_variants: Vec<String> = variants
+            .modified_variants
+            .iter()
+            .filter(|variant| {
+                let (old, new) = (&variant.old_variant, &variant.new_variant);
+                old.shape != new.shape || old.fields != new.fields
+            })
+            .map(|variant| variant.name.clone())
+            .collect();
+        let added_variants: Vec<String> = variants.added_variants.iter().map(|v| v.name.clone()).collect();
+        let removed_variants: Vec<String> = variants.removed_variants.iter().map(|v| v.name.clone()).collect();
+        if added_variants.is_empty() && removed_variants.is_empty() && changed_variants.is_empty() {
+            continue;
+        }
+
+        let public = is_public(old_ast, "type", &variants.name) && is_public(new_ast, "type", &variants.name);
+        let non_exhaustive = is_non_exhaustive(old_ast, "type", &variants.name);
+        // A new variant only breaks matches that didn't need a wildcard arm
+        let breaking = public
+            && (!removed_variants.is_empty()
+                || !changed_variants.is_empty()
+                || (!added_variants.is_empty() && !non_exhaustive));
+
+        error_changes.push(ErrorSurfaceChange {
+            name: variants.name.clone(),
+            detected_by,
+            public,
+            non_exhaustive,
+            added_variants,
+            removed_variants,
+            changed_variants,
+            breaking,
+        });
+    }
+    error_changes
+}
+
+// What marks an enum as an error type on either side, empty when nothing does
+fn error_markers(name: &str, old_ast: &FileASTData, new_ast: &FileASTData) -> Vec<String> {
+    let ident = name.rsplit("::").next().unwrap_or(name);
+    let mut markers = Vec::new();
+    if ident.ends_with("Error") || ident.ends_with("Err") {
+        markers.push("name".to_string());
+    }
+    if [old_ast, new_ast].iter().any(|ast| derives_error(ast, name)) {
+        markers.push("derive".to_string());
+    }
+    if [old_ast, new_ast].iter().any(|ast| implements_error(ast, ident)) {
+        markers.push("error_impl".to_string());
+    }
+    markers
+}
+
+// Whether a type derives `Error` under any path, e.g. `thiserror::Error`
+fn derives_error(ast: &FileASTData, name: &str) -> bool {
+    let Some(code) = entity_source_code(ast, "type", name).and_then(|code| split_outer_attributes(&code)) else {
+        return false;
+    };
+    code.attributes
+        .iter()
+        .filter_map(|attr| attr.strip_prefix("#[derive(")?.strip_suffix(")]"))
+        .flat_map(|list| list.split(','))
+        .any(|derive| is_error_trait(derive.trim()))
+}
+
+// Whether the file has an `impl Error for` the type, with `Error` spelled as std's or core's
+fn implements_error(ast: &FileASTData, ident: &str) -> bool {
+    ast.trait_impls.values().any(|impl_block| {
+        let Some((None, trait_path, _)) = &impl_block.trait_ else {
+            return false;
+        };
+        let self_type = format_compact(&*impl_block.self_ty);
+        let self_ident = self_type.split('<').next().unwrap_or(&self_type).rsplit("::").next().unwrap_or_default();
+        self_ident == ident && is_error_trait(&format_compact(trait_path))
+    })
+}
+
+// Whether a path names the standard `Error` trait, not another one such as `serde::de::Error`
+fn is_error_trait(path: &str) -> bool {
+    path == "Error" || path.ends_with("error::Error")
+}
+
+// Gather the error surface changes of all files
+pub fn build_error_surface_report(all_changes: &[DetailedChanges]) -> Vec<ErrorSurfaceReportEntry> {
+    all_changes
+        .iter()
+        .flat_map(|c| {
+            c.error_surface_changes.iter().map(|change| ErrorSurfaceReportEntry {
+                module: c.module_name.clone(),
+                qualified_name: change.name.clone(),
+                full_name: full_name(c.crate_name.as_deref(), &change.name),
+                change: ErrorSurfaceChange {
+                    name: short_name(c.module_path.as_deref(), &change.name).to_string(),
+                    ..change.clone()
+                },
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,88 @@
+// src/filters.rs
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::git_ops::SimilarFile;
+
+// Check whether a repository-relative file lies inside one of the scopes.
+// An empty scope list means the whole repository is in scope.
+pub fn in_scope(file: &str, scopes: &[String]) -> bool {
+    if scopes.is_empty() {
+        return true;
+    }
+
+    scopes.iter().any(|scope| {
+        let scope = scope.trim_start_matches("./");
+        scope.is_empty() || Path::new(file).starts_with(scope)
+    })
+}
+
+// Directories that hold the given files, for a cone-mode sparse checkout.
+// Files at the repository root need no entry since cone mode always includes them.
+pub fn parent_dirs(files: &[String]) -> Vec<String> {
+    let dirs: BTreeSet<String> = files
+        .iter()
+        .filter_map(|file| Path::new(file).parent())
+        .filter_map(|dir| dir.to_str())
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| dir.to_string())
+        .collect();
+
+    dirs.into_iter().collect()
+}
+
+// Pair renamed and copied Rust files with the old-ref file they came from, so they're
+// compared against that file instead of as a delete plus an add or an empty AST.
+// Returns the source path of each renamed and each copied file, keyed by its new path.
+pub fn pair_similar_files(
+    rust_files: &mut Vec<String>,
+    similar_files: &[SimilarFile],
+    new_file_map: &mut HashMap<String, bool>,
+    deleted_file_map: &mut HashMap<String, bool>,
+) -> (HashMap<String, String>, HashMap<String, String>) {
+    let mut renamed_file_map = HashMap::new();
+    let mut copied_file_map = HashMap::new();
+
+    for similar in similar_files {
+        if !similar.old_path.ends_with(".rs") || !rust_files.contains(&similar.new_path) {
+            continue;
+        }
+
+        new_file_map.remove(&similar.new_path);
+        if similar.copied {
+            copied_file_map.insert(similar.new_path.clone(), similar.old_path.clone());
+        } else {
+            deleted_file_map.remove(&similar.old_path);
+            renamed_file_map.insert(similar.new_path.clone(), similar.old_path.clone());
+        }
+    }
+
+    // The source of a rename is gone; the source of a copy is analyzed on its own
+    rust_files.retain(|file| !renamed_file_map.values().any(|old_path| old_path == file));
+    (renamed_file_map, copied_file_map)
+}
+
+// Match a path against a gitignore-style glob: `*` and `?` stay within a path
+// component, `**` spans directories and `**/` may also match no directory at all
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    glob_match_bytes(pattern.as_bytes(), path.as_bytes())
+}
+
+fn glob_match_bytes(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            if let [b'/', after @ ..] = rest {
+                if glob_match_bytes(after, path) {
+                    return true;
+                }
+            }
+            (0..=path.len()).any(|i| glob_match_bytes(rest, &path[i..]))
+        }
+        [b'*', rest @ ..] => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != b'/')
+            .any(|i| glob_match_bytes(rest, &path[i..])),
+        [b'?', rest @ ..] => matches!(path, [c, tail @ ..] if *c != b'/' && glob_match_bytes(rest, tail)),
+        [c, rest @ ..] => matches!(path, [p, tail @ ..] if p == c && glob_match_bytes(rest, tail)),
+    }
+}
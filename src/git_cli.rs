@@ -0,0 +1,570 @@
+// src/git_cli.rs
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::git_ops::{
+    git_command, BlameHunk, CloneOptions, CommitMetadata, DiffFilter, GitBackend, ModeChange, SimilarFile,
+    BARE_FETCH_REFSPEC,
+};
+
+// CliBackend runs every operation through the `git` executable
+pub struct CliBackend;
+
+// Environment variables git still sees when the rest of the environment is scrubbed
+const KEPT_ENV_VARS: &[&str] = &[
+    "PATH",
+    "HOME",
+    "TMPDIR",
+    "LANG",
+    "LC_ALL",
+    "SSL_CERT_FILE",
+    "SSL_CERT_DIR",
+    "SSH_AUTH_SOCK",
+];
+
+// Start a git command with the configured executable, extra config and environment
+fn new_command(args: &[String]) -> Command {
+    let settings = git_command();
+    let mut command = Command::new(settings.program.as_deref().unwrap_or("git"));
+
+    if settings.scrub_env {
+        command.env_clear();
+        for name in KEPT_ENV_VARS {
+            if let Some(value) = std::env::var_os(name) {
+                command.env(name, value);
+            }
+        }
+        // Nobody is around to answer a credential prompt
+        command.env("GIT_TERMINAL_PROMPT", "0");
+    }
+    if settings.no_lazy_fetch {
+        // Honored by git 2.44 and later
+        command.env("GIT_NO_LAZY_FETCH", "1");
+    }
+    for setting in &settings.config {
+        command.arg("-c").arg(setting);
+    }
+
+    command.args(args);
+    command
+}
+
+// Build the git config arguments that authenticate HTTPS requests with a token,
+// passed per command so the token is never written to the repository config
+fn auth_args(token: Option<&str>) -> Vec<String> {
+    match token {
+        Some(token) if !token.is_empty() => {
+            let credentials = STANDARD.encode(format!("x-access-token:{}", token));
+            vec![
+                "-c".to_string(),
+                format!("http.extraHeader=Authorization: Basic {}", credentials),
+            ]
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Run a git command and return its stdout, or stderr as the error
+fn run_git(args: &[String], local_path: Option<&str>, action: &str) -> Result<String, String> {
+    let mut command = new_command(args);
+    if let Some(local_path) = local_path {
+        command.current_dir(local_path);
+    }
+
+    let output = command
+        .output()
+        .map_err(|e| format!("Failed to execute git {} command: {}", action, e))?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// Run a git command with the given stdin and return its stdout, or stderr as the error
+fn run_git_with_input(args: &[String], input: &str, local_path: &str, action: &str) -> Result<String, String> {
+    let mut child = new_command(args)
+        .current_dir(local_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to execute git {} command: {}", action, e))?;
+
+    // Dropping stdin closes it so git sees the end of the input
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input.as_bytes())
+            .map_err(|e| format!("Failed to write to git {} command: {}", action, e))?;
+    }
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to execute git {} command: {}", action, e))?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// Split newline-separated git output into a list of paths
+fn split_lines(output: &str) -> Vec<String> {
+    let files_str = output.trim();
+
+    if files_str.is_empty() {
+        Vec::new()
+    } else {
+        files_str.split('\n').map(|s| s.to_string()).collect()
+    }
+}
+
+fn to_args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+impl GitBackend for CliBackend {
+    fn name(&self) -> &'static str {
+        "git CLI"
+    }
+
+    fn clone_repo(&self, repo_url: &str, local_path: &str, options: &CloneOptions) -> Result<(), String> {
+        let mut args = auth_args(options.token.as_deref());
+        args.extend(to_args(&["clone", "--origin", &options.remote]));
+        if options.bare {
+            args.push("--bare".to_string());
+        } else if options.sparse {
+            // Only files at the repository root are materialized until the cone is set
+            args.push("--sparse".to_string());
+        }
+        if let Some(depth) = options.depth {
+            // --depth implies --single-branch, but the base branch is needed too
+            args.push(format!("--depth={}", depth));
+            args.push("--no-single-branch".to_string());
+        }
+        if let Some(filter) = &options.filter {
+            args.push(format!("--filter={}", filter));
+        }
+        if let Some(reference_repo) = &options.reference_repo {
+            // Objects found in the reference repository are linked through alternates instead of downloaded
+            args.push("--reference".to_string());
+            args.push(reference_repo.clone());
+        }
+        args.extend(to_args(&[repo_url, local_path]));
+        run_git(&args, None, "clone")?;
+
+        if options.bare {
+            let key = format!("remote.{}.fetch", options.remote);
+            let args = to_args(&["config", &key, BARE_FETCH_REFSPEC]);
+            run_git(&args, Some(local_path), "config")?;
+        }
+
+        Ok(())
+    }
+
+    fn is_bare(&self, local_path: &str) -> Result<bool, String> {
+        run_git(&to_args(&["rev-parse", "--is-bare-repository"]), Some(local_path), "rev-parse")
+            .map(|output| output.trim() == "true")
+    }
+
+    fn repository_root(&self, local_path: &str) -> Result<String, String> {
+        // git looks for a repository in the parent directories too
+        let flag = if self.is_bare(local_path)? {
+            "--absolute-git-dir"
+        } else {
+            "--show-toplevel"
+        };
+        run_git(&to_args(&["rev-parse", flag]), Some(local_path), "rev-parse").map(|root| root.trim().to_string())
+    }
+
+    fn set_remote_url(&self, remote: &str, repo_url: &str, local_path: &str) -> Result<(), String> {
+        let command = if self.list_remotes(local_path)?.iter().any(|r| r == remote) {
+            "set-url"
+        } else {
+            "add"
+        };
+        run_git(&to_args(&["remote", command, remote, repo_url]), Some(local_path), "remote").map(|_| ())
+    }
+
+    fn list_remotes(&self, local_path: &str) -> Result<Vec<String>, String> {
+        run_git(&to_args(&["remote"]), Some(local_path), "remote").map(|output| split_lines(&output))
+    }
+
+    fn remote_url(&self, remote: &str, local_path: &str) -> Result<String, String> {
+        run_git(&to_args(&["remote", "get-url", remote]), Some(local_path), "remote").map(|url| url.trim().to_string())
+    }
+
+    fn list_branches(&self, local_path: &str) -> Result<Vec<String>, String> {
+        let output = run_git(&to_args(&["branch", "--all"]), Some(local_path), "branch")?;
+        Ok(split_lines(&output).iter().map(|b| b.trim().to_string()).collect())
+    }
+
+    fn is_shallow(&self, local_path: &str) -> Result<bool, String> {
+        run_git(&to_args(&["rev-parse", "--is-shallow-repository"]), Some(local_path), "rev-parse")
+            .map(|output| output.trim() == "true")
+    }
+
+    fn fetch(
+        &self,
+        local_path: &str,
+        remote: &str,
+        token: Option<&str>,
+        refspecs: &[String],
+        depth: Option<u32>,
+    ) -> Result<(), String> {
+        let mut args = auth_args(token);
+        args.push("fetch".to_string());
+        if let Some(depth) = depth {
+            args.push(format!("--depth={}", depth));
+        }
+        args.push(remote.to_string());
+        args.extend(refspecs.iter().cloned());
+        run_git(&args, Some(local_path), "fetch").map(|_| ())
+    }
+
+    fn diff_files(
+        &self,
+        old_ref: &str,
+        new_ref: &str,
+        filter: Option<DiffFilter>,
+        local_path: &str,
+    ) -> Result<Vec<String>, String> {
+        // Keep renames as a delete plus an add regardless of the user's diff.renames setting
+        let mut args = to_args(&["diff", "--name-only", "--no-renames"]);
+        match filter {
+            Some(DiffFilter::Added) => args.push("--diff-filter=A".to_string()),
+            Some(DiffFilter::Deleted) => args.push("--diff-filter=D".to_string()),
+            None => {}
+        }
+        args.push(old_ref.to_string());
+        args.push(new_ref.to_string());
+
+        run_git(&args, Some(local_path), "diff").map(|output| split_lines(&output))
+    }
+
+    fn binary_files(
+        &self,
+        old_ref: &str,
+        new_ref: &str,
+        files: &[String],
+        local_path: &str,
+    ) -> Result<Vec<String>, String> {
+        // numstat reports "-" for the line counts of binary files
+        let mut args = to_args(&["diff", "--numstat", "-z", "--no-renames", old_ref, new_ref, "--"]);
+        args.extend(files.iter().cloned());
+
+        let output = run_git(&args, Some(local_path), "diff")?;
+        Ok(output
+            .split('\0')
+            .filter_map(|entry| entry.strip_prefix("-\t-\t"))
+            .map(|file| file.to_string())
+            .collect())
+    }
+
+    fn mode_changes(&self, old_ref: &str, new_ref: &str, local_path: &str) -> Result<Vec<ModeChange>, String> {
+        // Raw records are ":<old mode> <new mode> <old sha> <new sha> <status>" followed by the path
+        let args = to_args(&["diff", "--raw", "-z", "--no-renames", old_ref, new_ref]);
+        let output = run_git(&args, Some(local_path), "diff")?;
+
+        let mut changes = Vec::new();
+        let mut fields = output.split('\0');
+        while let (Some(record), Some(file)) = (fields.next(), fields.next()) {
+            let mut parts = record.trim_start_matches(':').split(' ');
+            let (Some(old_mode), Some(new_mode)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            // Added and deleted files have a zero mode on the missing side
+            if old_mode != new_mode && old_mode != "000000" && new_mode != "000000" {
+                changes.push(ModeChange {
+                    file: file.to_string(),
+                    old_mode: old_mode.to_string(),
+                    new_mode: new_mode.to_string(),
+                });
+            }
+        }
+
+        Ok(changes)
+    }
+
+    fn similar_files(
+        &self,
+        old_ref: &str,
+        new_ref: &str,
+        find_copies: bool,
+        local_path: &str,
+    ) -> Result<Vec<SimilarFile>, String> {
+        let mut args = to_args(&["diff", "--name-status", "-z", "--find-renames"]);
+        if find_copies {
+            // Copies are usually made from files that are otherwise unchanged
+            args.push("--find-copies-harder".to_string());
+        }
+        args.extend(to_args(&[old_ref, new_ref]));
+        let output = run_git(&args, Some(local_path), "diff")?;
+
+        // Entries are "<status>\0<path>\0", with a second path for renames and copies
+        let mut fields = output.split('\0');
+        let mut similar = Vec::new();
+        while let Some(status) = fields.next() {
+            let copied = status.starts_with('C');
+            if status.starts_with('R') || copied {
+                if let (Some(old_path), Some(new_path)) = (fields.next(), fields.next()) {
+                    similar.push(SimilarFile {
+                        old_path: old_path.to_string(),
+                        new_path: new_path.to_string(),
+                        copied,
+                    });
+                }
+            } else {
+                fields.next();
+            }
+        }
+
+        Ok(similar)
+    }
+
+    fn read_file(&self, reference: &str, file: &str, local_path: &str) -> Result<String, String> {
+        let object = format!("{}:{}", reference, file);
+        run_git(&to_args(&["cat-file", "blob", &object]), Some(local_path), "cat-file")
+    }
+
+    fn grep_rust_files(&self, reference: &str, word: &str, local_path: &str) -> Result<Vec<String>, String> {
+        let args = to_args(&["grep", "-l", "-z", "-w", "-F", "-e", word, reference, "--", "*.rs"]);
+        let output = new_command(&args)
+            .current_dir(local_path)
+            .output()
+            .map_err(|e| format!("Failed to execute git grep command: {}", e))?;
+
+        // git grep exits with 1 when nothing matches, and prints each match as "<ref>:<path>"
+        match output.status.code() {
+            Some(0) => {
+                let prefix = format!("{}:", reference);
+                Ok(String::from_utf8_lossy(&output.stdout)
+                    .split('\0')
+                    .filter(|entry| !entry.is_empty())
+                    .map(|entry| entry.strip_prefix(&prefix).unwrap_or(entry).to_string())
+                    .collect())
+            }
+            Some(1) if output.stderr.is_empty() => Ok(Vec::new()),
+            _ => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+        }
+    }
+
+    fn lfs_smudge(&self, pointer: &str, file: &str, local_path: &str) -> Result<String, String> {
+        // git-lfs downloads the object if it isn't cached yet
+        let args = to_args(&["lfs", "smudge", "--", file]);
+        run_git_with_input(&args, pointer, local_path, "lfs smudge")
+    }
+
+    fn checkout(&self, reference: &str, local_path: &str) -> Result<(), String> {
+        run_git(&to_args(&["checkout", reference]), Some(local_path), "checkout").map(|_| ())
+    }
+
+    fn add_worktree(&self, reference: &str, worktree_path: &str, local_path: &str) -> Result<(), String> {
+        let args = to_args(&["worktree", "add", "--detach", worktree_path, reference]);
+        run_git(&args, Some(local_path), "worktree").map(|_| ())
+    }
+
+    fn remove_worktree(&self, worktree_path: &str, local_path: &str) -> Result<(), String> {
+        let args = to_args(&["worktree", "remove", "--force", worktree_path]);
+        run_git(&args, Some(local_path), "worktree").map(|_| ())
+    }
+
+    fn set_sparse_dirs(&self, dirs: &[String], local_path: &str) -> Result<(), String> {
+        let mut args = to_args(&["sparse-checkout", "set", "--cone", "--"]);
+        args.extend(dirs.iter().cloned());
+        run_git(&args, Some(local_path), "sparse-checkout").map(|_| ())
+    }
+
+    fn head(&self, local_path: &str) -> Result<String, String> {
+        match run_git(&to_args(&["symbolic-ref", "-q", "--short", "HEAD"]), Some(local_path), "symbolic-ref") {
+            Ok(branch) => Ok(branch.trim().to_string()),
+            Err(_) => self.resolve_commit("HEAD", local_path),
+        }
+    }
+
+    fn dirty_files(&self, local_path: &str) -> Result<Vec<String>, String> {
+        let args = to_args(&["status", "--porcelain", "-z", "--untracked-files=no"]);
+        let output = run_git(&args, Some(local_path), "status")?;
+
+        // Entries are "XY <path>", renames carry the original path as an extra entry
+        let mut files = Vec::new();
+        let mut entries = output.split('\0').filter(|e| !e.is_empty());
+        while let Some(entry) = entries.next() {
+            if let Some(path) = entry.get(3..) {
+                files.push(path.to_string());
+            }
+            if entry.starts_with('R') || entry.starts_with('C') {
+                entries.next();
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn stash_push(&self, local_path: &str) -> Result<(), String> {
+        run_git(&to_args(&["stash", "push", "-m", "rust-ast-differ"]), Some(local_path), "stash").map(|_| ())
+    }
+
+    fn stash_pop(&self, local_path: &str) -> Result<(), String> {
+        run_git(&to_args(&["stash", "pop", "--index"]), Some(local_path), "stash").map(|_| ())
+    }
+
+    fn discard_changes(&self, local_path: &str) -> Result<(), String> {
+        run_git(&to_args(&["reset", "--hard", "-q", "HEAD"]), Some(local_path), "reset").map(|_| ())
+    }
+
+    fn resolve_commit(&self, reference: &str, local_path: &str) -> Result<String, String> {
+        let args = to_args(&["rev-parse", "--verify", &format!("{}^{{commit}}", reference)]);
+        run_git(&args, Some(local_path), "rev-parse").map(|output| output.trim().to_string())
+    }
+
+    fn merge_base(&self, first: &str, second: &str, local_path: &str) -> Result<String, String> {
+        // git exits silently when a shallow or disconnected history has no common ancestor
+        let output = run_git(&to_args(&["merge-base", first, second]), Some(local_path), "merge-base")
+            .map_err(|e| if e.is_empty() { "no common ancestor".to_string() } else { e })?;
+
+        Ok(output.trim().to_string())
+    }
+
+    fn parents(&self, commit: &str, local_path: &str) -> Result<Vec<String>, String> {
+        // The commit itself comes first, followed by its parents
+        let args = to_args(&["rev-list", "--parents", "-n", "1", commit]);
+        let output = run_git(&args, Some(local_path), "rev-list")?;
+        Ok(output.split_whitespace().skip(1).map(|p| p.to_string()).collect())
+    }
+
+    fn commit_metadata(&self, commit: &str, local_path: &str) -> Result<CommitMetadata, String> {
+        // NUL-separated so names and subjects can hold any other character
+        let args = to_args(&["show", "-s", "--format=%H%x00%an%x00%ae%x00%cn%x00%ce%x00%ct%x00%s", commit]);
+        let output = run_git(&args, Some(local_path), "show")?;
+
+        let fields: Vec<&str> = output.trim_end_matches('\n').split('\0').collect();
+        let [sha, author, author_email, committer, committer_email, timestamp, subject] = fields[..] else {
+            return Err(format!("Unexpected commit metadata for {}: {}", commit, output));
+        };
+        Ok(CommitMetadata {
+            sha: sha.to_string(),
+            author: author.to_string(),
+            author_email: author_email.to_string(),
+            committer: committer.to_string(),
+            committer_email: committer_email.to_string(),
+            timestamp: timestamp.parse().unwrap_or(0),
+            subject: subject.to_string(),
+        })
+    }
+
+    fn blame(&self, commit: &str, file: &str, local_path: &str) -> Result<Vec<BlameHunk>, String> {
+        let args = to_args(&["blame", "--porcelain", commit, "--", file]);
+        let output = run_git(&args, Some(local_path), "blame")?;
+
+        // Each group starts with "<sha> <orig line> <final line> <lines>"; the commit's
+        // headers follow only the first time it appears, and every line ends in its content
+        let mut hunks: Vec<BlameHunk> = Vec::new();
+        let mut commits: HashMap<String, BlameHunk> = HashMap::new();
+        let mut current: Option<BlameHunk> = None;
+        for line in output.lines() {
+            if line.starts_with('\t') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(' ').collect();
+            if let [sha, _, final_line, count] = fields[..] {
+                if sha.len() >= 40 && sha.chars().all(|c| c.is_ascii_hexdigit()) {
+                    hunks.extend(current.take());
+                    let mut hunk = commits.get(sha).cloned().unwrap_or_else(|| BlameHunk {
+                        commit: sha.to_string(),
+                        author: String::new(),
+                        author_email: String::new(),
+                        time: 0,
+                        start_line: 0,
+                        line_count: 0,
+                    });
+                    hunk.start_line = final_line.parse().unwrap_or(0);
+                    hunk.line_count = count.parse().unwrap_or(0);
+                    current = Some(hunk);
+                    continue;
+                }
+            }
+
+            let Some(hunk) = current.as_mut() else {
+                continue;
+            };
+            if let Some(author) = line.strip_prefix("author ") {
+                hunk.author = author.to_string();
+            } else if let Some(email) = line.strip_prefix("author-mail ") {
+                hunk.author_email = email.trim_matches(|c| c == '<' || c == '>').to_string();
+            } else if let Some(time) = line.strip_prefix("committer-time ") {
+                hunk.time = time.parse().unwrap_or(0);
+            } else if line.starts_with("filename ") {
+                commits.insert(hunk.commit.clone(), hunk.clone());
+            }
+        }
+        hunks.extend(current);
+
+        Ok(hunks)
+    }
+
+    fn first_parent_commits(&self, old_ref: &str, new_ref: &str, local_path: &str) -> Result<Vec<String>, String> {
+        let range = format!("{}..{}", old_ref, new_ref);
+        let args = to_args(&["rev-list", "--reverse", "--first-parent", &range]);
+        run_git(&args, Some(local_path), "rev-list").map(|output| split_lines(&output))
+    }
+
+    fn blob_ids(&self, commit: &str, files: &[String], local_path: &str) -> Result<HashMap<String, String>, String> {
+        let mut args = to_args(&["ls-tree", "-r", "-z", commit, "--"]);
+        args.extend(files.iter().cloned());
+        let output = run_git(&args, Some(local_path), "ls-tree")?;
+
+        // Each entry is "<mode> <type> <id>\t<path>"
+        let mut blobs = HashMap::new();
+        for entry in output.split('\0') {
+            if let Some((meta, file)) = entry.split_once('\t') {
+                if let Some(id) = meta.split_whitespace().nth(2) {
+                    blobs.insert(file.to_string(), id.to_string());
+                }
+            }
+        }
+
+        Ok(blobs)
+    }
+
+    fn missing_blobs(&self, commit: &str, files: &[String], local_path: &str) -> Result<Vec<String>, String> {
+        // The pathspec limits the walk to the given files; missing objects are printed as "?<id>"
+        let mut args = to_args(&["rev-list", "--objects", "--missing=print", "--no-walk", commit, "--"]);
+        args.extend(files.iter().cloned());
+        let output = run_git(&args, Some(local_path), "rev-list")?;
+
+        Ok(output
+            .lines()
+            .filter_map(|line| line.strip_prefix('?'))
+            .map(|id| id.trim().to_string())
+            .collect())
+    }
+
+    fn fetch_objects(
+        &self,
+        object_ids: &[String],
+        remote: &str,
+        token: Option<&str>,
+        local_path: &str,
+    ) -> Result<(), String> {
+        // The same request git makes when it lazily fetches a single missing object
+        let mut args = auth_args(token);
+        args.extend(to_args(&[
+            "-c",
+            "fetch.negotiationAlgorithm=noop",
+            "fetch",
+            "--no-tags",
+            "--no-write-fetch-head",
+            "--recurse-submodules=no",
+            "--filter=blob:none",
+            remote,
+        ]));
+        args.extend(object_ids.iter().cloned());
+        run_git(&args, Some(local_path), "fetch").map(|_| ())
+    }
+}
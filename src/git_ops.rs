@@ -1,198 +1,1330 @@
 // src/git_ops.rs
-use std::path::Path;
-use std::process::Command;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use crate::git_cli::CliBackend;
+use crate::git_libgit2::Libgit2Backend;
+
+// GitError classifies a failed git operation so callers can choose how to recover
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum GitError {
+    // A git command or libgit2 call failed for a reason not covered below
+    #[error("{0}")]
+    Command(String),
+    // A branch, tag, SHA or revision expression names no commit
+    #[error("{0}")]
+    RefNotFound(String),
+    // The remote rejected the credentials or asked for some
+    #[error("{0}")]
+    Auth(String),
+    // The remote couldn't be reached or the connection dropped
+    #[error("{0}")]
+    Network(String),
+    // The working tree has uncommitted modifications that a checkout would clobber
+    #[error("Working tree at {path} has local modifications in {} files ({})", files.len(), files.join(", "))]
+    DirtyTree { path: String, files: Vec<String> },
+    // The local path isn't a clone of the requested repository
+    #[error("{0}")]
+    WrongRepository(String),
+    // A branch exists on several remotes at different commits
+    #[error("{reference} is ambiguous: {} point at different commits; pass one of them instead", remote_refs.join(", "))]
+    AmbiguousRef { reference: String, remote_refs: Vec<String> },
+}
+
+// Lowercase fragments of git and libgit2 messages for each kind, checked in this order
+const AUTH_ERROR_MARKERS: &[&str] = &[
+    "authentication failed",
+    "authentication required",
+    "authentication replays",
+    "could not read username",
+    "could not read password",
+    "invalid credentials",
+    "permission denied",
+    "returned error: 401",
+    "returned error: 403",
+];
+const NETWORK_ERROR_MARKERS: &[&str] = &[
+    "could not resolve host",
+    "failed to resolve address",
+    "failed to connect",
+    "connection refused",
+    "connection reset",
+    "connection timed out",
+    "operation timed out",
+    "network is unreachable",
+    "unable to access",
+    "the remote end hung up",
+    "early eof",
+];
+const REF_ERROR_MARKERS: &[&str] = &[
+    "unknown revision",
+    "bad revision",
+    "not a valid object name",
+    "needed a single revision",
+    "couldn't find remote ref",
+    "revspec",
+    "invalid reference",
+];
+
+impl GitError {
+    // Classify a backend error message by what went wrong
+    fn classify(message: String) -> Self {
+        let lower = message.to_lowercase();
+        let mentions = |markers: &[&str]| markers.iter().any(|marker| lower.contains(marker));
+
+        if mentions(AUTH_ERROR_MARKERS) {
+            GitError::Auth(message)
+        } else if mentions(NETWORK_ERROR_MARKERS) {
+            GitError::Network(message)
+        } else if mentions(REF_ERROR_MARKERS) {
+            GitError::RefNotFound(message)
+        } else {
+            GitError::Command(message)
+        }
+    }
+
+    // Prefix the message with what was being attempted, keeping the kind
+    pub fn context(self, context: impl Display) -> Self {
+        match self {
+            GitError::Command(e) => GitError::Command(format!("{}: {}", context, e)),
+            GitError::RefNotFound(e) => GitError::RefNotFound(format!("{}: {}", context, e)),
+            GitError::Auth(e) => GitError::Auth(format!("{}: {}", context, e)),
+            GitError::Network(e) => GitError::Network(format!("{}: {}", context, e)),
+            GitError::WrongRepository(e) => GitError::WrongRepository(format!("{}: {}", context, e)),
+            dirty @ GitError::DirtyTree { .. } => dirty,
+            ambiguous @ GitError::AmbiguousRef { .. } => ambiguous,
+        }
+    }
+
+    // Whether running the operation again could succeed; bad credentials and refs stay bad
+    pub fn is_transient(&self) -> bool {
+        matches!(self, GitError::Network(_) | GitError::Command(_))
+    }
+}
+
+// Callers that only report failures keep working with plain messages
+impl From<GitError> for String {
+    fn from(e: GitError) -> Self {
+        e.to_string()
+    }
+}
+
+// DiffFilter restricts a diff to one kind of file change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFilter {
+    Added,
+    Deleted,
+}
+
+// SimilarFile pairs a file at the new ref with the old-ref file it was renamed or copied from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarFile {
+    pub old_path: String,
+    pub new_path: String,
+    pub copied: bool,
+}
+
+// ModeChange records a file whose mode changed between two refs, such as gaining the executable bit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModeChange {
+    pub file: String,
+    // Octal git modes, e.g. 100644 for a regular file and 100755 for an executable
+    pub old_mode: String,
+    pub new_mode: String,
+}
+
+// CaseRename records a file renamed by changing only the case of its path, which
+// case-insensitive filesystems can't represent as two distinct files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaseRename {
+    pub old_path: String,
+    pub new_path: String,
+}
+
+// CommitMetadata describes a compared commit so archived reports say what they were built from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitMetadata {
+    pub sha: String,
+    pub author: String,
+    pub author_email: String,
+    pub committer: String,
+    pub committer_email: String,
+    // Committer time in seconds since the epoch
+    pub timestamp: i64,
+    pub subject: String,
+}
+
+// BlameHunk is a run of lines of a file that were last changed by the same commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameHunk {
+    pub commit: String,
+    pub author: String,
+    pub author_email: String,
+    // Committer time in seconds since the epoch, used to find the most recent hunk
+    pub time: i64,
+    // First line of the hunk, starting at 1
+    pub start_line: usize,
+    pub line_count: usize,
+}
+
+// Bare clones keep remote branches as local branches so refs resolve and fetches update them
+pub const BARE_FETCH_REFSPEC: &str = "+refs/heads/*:refs/heads/*";
+
+// Depth passed to git to fetch the complete history of a shallow clone
+pub const UNSHALLOW_DEPTH: u32 = i32::MAX as u32;
+
+// Depth used to deepen a shallow clone when no clone depth was requested
+const DEFAULT_DEEPEN_DEPTH: u32 = 50;
+
+// Number of times a shallow clone is deepened before fetching the full history
+const MAX_DEEPEN_ATTEMPTS: usize = 4;
+
+// CloneOptions controls how the repository is cloned and fetched
+#[derive(Debug, Clone, Default)]
+pub struct CloneOptions {
+    // Name of the remote the repository URL is cloned from and kept up to date under
+    pub remote: String,
+    // Access token for the repository URL; other remotes use their own credentials
+    pub token: Option<String>,
+    // Reuse an existing clone even when none of its remotes points at the repository URL
+    pub allow_mismatched_remote: bool,
+    // Use the repository already on disk as it is, without cloning, fetching or touching remotes
+    pub offline: bool,
+    pub bare: bool,
+    pub depth: Option<u32>,
+    // Local repository whose objects the clone borrows through git alternates
+    pub reference_repo: Option<String>,
+    // Partial clone filter such as `blob:none`; missing objects are fetched when needed
+    pub filter: Option<String>,
+    pub sparse: bool,
+    // Extra attempts for clones and fetches, waiting `retry_delay` and doubling it after each failure
+    pub retries: u32,
+    pub retry_delay: Duration,
+}
+
+// GitBackend is the set of repository operations the differ relies on
+pub trait GitBackend {
+    fn name(&self) -> &'static str;
+    fn clone_repo(&self, repo_url: &str, local_path: &str, options: &CloneOptions) -> Result<(), String>;
+    fn is_bare(&self, local_path: &str) -> Result<bool, String>;
+    // Working tree root of the repository at the path, or its git directory when bare
+    fn repository_root(&self, local_path: &str) -> Result<String, String>;
+    fn is_shallow(&self, local_path: &str) -> Result<bool, String>;
+    // Point a remote at the URL, adding the remote when the repository doesn't have it yet
+    fn set_remote_url(&self, remote: &str, repo_url: &str, local_path: &str) -> Result<(), String>;
+    fn list_remotes(&self, local_path: &str) -> Result<Vec<String>, String>;
+    fn remote_url(&self, remote: &str, local_path: &str) -> Result<String, String>;
+    fn list_branches(&self, local_path: &str) -> Result<Vec<String>, String>;
+    // Fetch the given refspecs from a remote, or its configured ones when empty, limiting the
+    // history of each ref to `depth` commits when given
+    fn fetch(
+        &self,
+        local_path: &str,
+        remote: &str,
+        token: Option<&str>,
+        refspecs: &[String],
+        depth: Option<u32>,
+    ) -> Result<(), String>;
+    // Files changed between two refs, with renames reported as a delete plus an add
+    fn diff_files(
+        &self,
+        old_ref: &str,
+        new_ref: &str,
+        filter: Option<DiffFilter>,
+        local_path: &str,
+    ) -> Result<Vec<String>, String>;
+    // Files among `files` whose contents git considers binary at either ref
+    fn binary_files(&self, old_ref: &str, new_ref: &str, files: &[String], local_path: &str)
+        -> Result<Vec<String>, String>;
+    // Files present at both refs whose mode differs
+    fn mode_changes(&self, old_ref: &str, new_ref: &str, local_path: &str) -> Result<Vec<ModeChange>, String>;
+    // Files renamed, and optionally copied, between two refs, detected by content similarity
+    fn similar_files(
+        &self,
+        old_ref: &str,
+        new_ref: &str,
+        find_copies: bool,
+        local_path: &str,
+    ) -> Result<Vec<SimilarFile>, String>;
+    // Contents of a file as stored at a ref
+    fn read_file(&self, reference: &str, file: &str, local_path: &str) -> Result<String, String>;
+    // Rust files at a ref whose contents have the word, with no identifier character on either side
+    fn grep_rust_files(&self, reference: &str, word: &str, local_path: &str) -> Result<Vec<String>, String>;
+    // Contents of the file a Git LFS pointer stands for
+    fn lfs_smudge(&self, pointer: &str, file: &str, local_path: &str) -> Result<String, String>;
+    fn checkout(&self, reference: &str, local_path: &str) -> Result<(), String>;
+    // The checked out branch, or the commit SHA when HEAD is detached
+    fn head(&self, local_path: &str) -> Result<String, String>;
+    // Tracked files with uncommitted modifications in the index or working tree
+    fn dirty_files(&self, local_path: &str) -> Result<Vec<String>, String>;
+    fn stash_push(&self, local_path: &str) -> Result<(), String>;
+    fn stash_pop(&self, local_path: &str) -> Result<(), String>;
+    // Reset the index and working tree to HEAD, dropping local modifications
+    fn discard_changes(&self, local_path: &str) -> Result<(), String>;
+    // Check out a ref into a new detached worktree, and remove such a worktree again
+    fn add_worktree(&self, reference: &str, worktree_path: &str, local_path: &str) -> Result<(), String>;
+    fn remove_worktree(&self, worktree_path: &str, local_path: &str) -> Result<(), String>;
+    // Restrict the working tree to the given directories with a cone-mode sparse checkout
+    fn set_sparse_dirs(&self, dirs: &[String], local_path: &str) -> Result<(), String>;
+    fn resolve_commit(&self, reference: &str, local_path: &str) -> Result<String, String>;
+    fn merge_base(&self, first: &str, second: &str, local_path: &str) -> Result<String, String>;
+    // Parents of a commit in order, the first parent first
+    fn parents(&self, commit: &str, local_path: &str) -> Result<Vec<String>, String>;
+    fn commit_metadata(&self, commit: &str, local_path: &str) -> Result<CommitMetadata, String>;
+    // Commits that last changed each line of a file as it is at a commit
+    fn blame(&self, commit: &str, file: &str, local_path: &str) -> Result<Vec<BlameHunk>, String>;
+    // Commits reachable from `new_ref` but not `old_ref` along first parents, oldest first
+    fn first_parent_commits(&self, old_ref: &str, new_ref: &str, local_path: &str) -> Result<Vec<String>, String>;
+    fn blob_ids(&self, commit: &str, files: &[String], local_path: &str) -> Result<HashMap<String, String>, String>;
+    // Ids of the blobs of the given files at a commit that a partial clone hasn't downloaded yet
+    fn missing_blobs(&self, commit: &str, files: &[String], local_path: &str) -> Result<Vec<String>, String>;
+    // Download the given objects from the promisor remote of a partial clone
+    fn fetch_objects(
+        &self,
+        object_ids: &[String],
+        remote: &str,
+        token: Option<&str>,
+        local_path: &str,
+    ) -> Result<(), String>;
+}
+
+// BackendKind selects how git operations are performed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum BackendKind {
+    // libgit2 first, falling back to the git CLI when an operation fails
+    #[default]
+    Auto,
+    Libgit2,
+    Cli,
+}
+
+static BACKEND: OnceLock<BackendKind> = OnceLock::new();
+
+// Select the git backend for the rest of the process
+pub fn set_backend(kind: BackendKind) {
+    if BACKEND.set(kind).is_err() {
+        eprintln!("Warning: Git backend already configured");
+    }
+}
+
+// GitCommand controls how the git executable is run by the CLI backend
+#[derive(Debug, Clone, Default)]
+pub struct GitCommand {
+    // Path of the git executable; the one on PATH when unset
+    pub program: Option<String>,
+    // `key=value` settings passed to every command with -c, such as http.proxy
+    pub config: Vec<String>,
+    // Run git with only a minimal set of environment variables
+    pub scrub_env: bool,
+    // Stop partial clones from downloading missing objects on demand
+    pub no_lazy_fetch: bool,
+}
+
+impl GitCommand {
+    // Value of a setting passed through `config`, the last one winning as it does for git
+    pub fn config_value(&self, key: &str) -> Option<&str> {
+        self.config
+            .iter()
+            .rev()
+            .filter_map(|setting| setting.split_once('='))
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+}
+
+static GIT_COMMAND: OnceLock<GitCommand> = OnceLock::new();
+
+// Configure the git executable for the rest of the process
+pub fn set_git_command(command: GitCommand) {
+    if GIT_COMMAND.set(command).is_err() {
+        eprintln!("Warning: Git command already configured");
+    }
+}
+
+// The configured git executable settings, or the defaults
+pub fn git_command() -> &'static GitCommand {
+    GIT_COMMAND.get_or_init(GitCommand::default)
+}
+
+// Run an operation on the configured backend, falling back to the CLI in auto mode
+fn with_backend<T>(action: &str, op: impl Fn(&dyn GitBackend) -> Result<T, String>) -> Result<T, GitError> {
+    let kind = BACKEND.get().copied().unwrap_or_default();
+    run_on_backend(kind, &Libgit2Backend, &CliBackend, action, op)
+}
+
+// Run an operation on the backend a kind selects. Auto mode falls back from libgit2 to the CLI,
+// except when the ref isn't there, which the CLI wouldn't find either.
+fn run_on_backend<T>(
+    kind: BackendKind,
+    libgit2: &dyn GitBackend,
+    cli: &dyn GitBackend,
+    action: &str,
+    op: impl Fn(&dyn GitBackend) -> Result<T, String>,
+) -> Result<T, GitError> {
+    match kind {
+        BackendKind::Cli => op(cli).map_err(GitError::classify),
+        BackendKind::Libgit2 => op(libgit2).map_err(GitError::classify),
+        BackendKind::Auto => match op(libgit2).map_err(GitError::classify) {
+            Err(GitError::RefNotFound(e)) => Err(GitError::RefNotFound(e)),
+            Err(e) => {
+                eprintln!("{} {} failed ({}), falling back to {}", libgit2.name(), action, e, cli.name());
+                op(cli).map_err(GitError::classify)
+            }
+            result => result,
+        },
+    }
+}
+
+// Run a network operation, retrying transient failures with exponential backoff before
+// surfacing the last error
+fn with_retries<T>(action: &str, options: &CloneOptions, op: impl Fn() -> Result<T, GitError>) -> Result<T, GitError> {
+    retry_with_backoff(action, options, op, thread::sleep)
+}
+
+// with_retries, waiting between attempts with the given sleep
+fn retry_with_backoff<T>(
+    action: &str,
+    options: &CloneOptions,
+    op: impl Fn() -> Result<T, GitError>,
+    sleep: impl Fn(Duration),
+) -> Result<T, GitError> {
+    let mut delay = options.retry_delay;
+    let mut attempt = 0;
+
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(e) if e.is_transient() && attempt < options.retries => {
+                attempt += 1;
+                eprintln!(
+                    "Warning: {} failed ({}), retrying in {:?} (attempt {} of {})",
+                    action,
+                    e,
+                    delay,
+                    attempt,
+                    options.retries
+                );
+                sleep(delay);
+                delay = delay.saturating_mul(2);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Clone a Git repository if it doesn't exist locally, making sure the required refs are present.
+// An existing directory is only reused when it is a clone of the repository.
+pub fn clone_repo(
+    repo_url: &str,
+    required_refs: &[&str],
+    local_path: &str,
+    options: &CloneOptions,
+) -> Result<(), GitError> {
+    if options.offline {
+        return use_local_repo(local_path, required_refs);
+    }
 
-// Clone a Git repository if it doesn't exist locally
-pub fn clone_repo(repo_url: &str, branch_name: &str, local_path: &str) {
     let path = Path::new(local_path);
-    
-    if !path.exists() {
-        println!("Cloning repository {} to {}", repo_url, local_path);
-        
-        let output = Command::new("git")
-            .args(&["clone", repo_url, local_path])
-            .output()
-            .expect("Failed to execute git clone command");
-            
-        if !output.status.success() {
-            eprintln!("Error cloning repository: {}", String::from_utf8_lossy(&output.stderr));
-            std::process::exit(1);
+    // A pre-created empty directory, such as a mount point, is cloned into
+    let is_empty_dir = path.read_dir().is_ok_and(|mut entries| entries.next().is_none());
+
+    if !path.exists() || is_empty_dir {
+        eprintln!("Cloning repository {} to {}", repo_url, local_path);
+
+        let clone = || {
+            with_backend("clone", |b| {
+                let result = b.clone_repo(repo_url, local_path, options);
+                // A failed clone can leave a partial directory behind that blocks the next attempt
+                if result.is_err() && path.exists() {
+                    let _ = std::fs::remove_dir_all(path);
+                }
+                result
+            })
+        };
+
+        match with_retries("clone", options, clone) {
+            Ok(()) => {}
+            Err(GitError::Auth(e)) => {
+                return Err(GitError::Auth(format!(
+                    "Error cloning repository: {}; check the --token for {}",
+                    e, repo_url
+                )));
+            }
+            Err(e) => return Err(e.context("Error cloning repository")),
         }
     } else {
-        println!("Repository already cloned.");
-        
+        verify_existing_repo(repo_url, local_path, options)?;
+        eprintln!("Repository already cloned.");
+
         // Set the remote URL
-        let output_remote = Command::new("git")
-            .args(&["remote", "set-url", "origin", repo_url])
-            .current_dir(local_path)
-            .output()
-            .expect("Failed to set remote URL");
-            
-        if output_remote.status.success() {
-            println!("Successfully set origin remote url");
-        } else {
-            println!("Warning: Failed to set remote URL: {}", String::from_utf8_lossy(&output_remote.stderr));
+        match with_backend("remote set-url", |b| b.set_remote_url(&options.remote, repo_url, local_path)) {
+            Ok(()) => eprintln!("Successfully set {} remote url", options.remote),
+            Err(e) => eprintln!("Warning: Failed to set remote URL: {}", e),
         }
-        
+
         // List all branches for debugging
-        let output_branches = Command::new("git")
-            .args(&["branch", "--all"])
-            .current_dir(local_path)
-            .output()
-            .expect("Failed to list branches");
-            
-        if output_branches.status.success() {
-            println!("Successfully fetched all branches \n{}", String::from_utf8_lossy(&output_branches.stdout));
-        } else {
-            println!("Warning: Failed to list branches: {}", String::from_utf8_lossy(&output_branches.stderr));
+        match with_backend("branch listing", |b| b.list_branches(local_path)) {
+            Ok(branches) => eprintln!("Successfully fetched all branches \n{}", branches.join("\n")),
+            Err(e) => eprintln!("Warning: Failed to list branches: {}", e),
         }
-        
+
         // Fetch the latest changes
-        let output_fetch = Command::new("git")
-            .args(&["fetch"])
-            .current_dir(local_path)
-            .output()
-            .expect("Failed to fetch latest changes");
-            
-        if output_fetch.status.success() {
-            println!("Successfully fetched latest changes. {}", String::from_utf8_lossy(&output_fetch.stdout));
-        } else {
-            println!("Warning: Failed to fetch latest changes: {}", String::from_utf8_lossy(&output_fetch.stderr));
+        match fetch_all_remotes(local_path, options.depth, options) {
+            Ok(()) => eprintln!("Successfully fetched latest changes."),
+            Err(e) => eprintln!("Warning: Failed to fetch latest changes: {}", e),
         }
     }
+
+    fetch_review_refs(required_refs, local_path, options);
+    deepen_until_present(required_refs, local_path, options);
+    Ok(())
 }
 
-// Get a list of files that are new in the current commit compared to the branch
-pub fn get_new_files(branch_name: &str, new_commit: &str, local_path: &str) -> Result<Vec<String>, String> {
-    let output = Command::new("git")
-        .args(&["diff", "--name-only", "--diff-filter=A", branch_name, new_commit])
-        .current_dir(local_path)
-        .output()
-        .map_err(|e| format!("Failed to execute git diff command: {}", e))?;
-        
-    if !output.status.success() {
-        return Err(format!(
-            "Error getting new files between {} and {}: {}",
-            branch_name,
-            new_commit,
-            String::from_utf8_lossy(&output.stderr)
-        ));
-    }
-    
-    let files_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    
-    let files = if files_str.is_empty() {
-        Vec::new()
+// Check that the repository already on disk has the required refs, without any network access
+fn use_local_repo(local_path: &str, required_refs: &[&str]) -> Result<(), GitError> {
+    if !Path::new(local_path).exists() {
+        return Err(GitError::WrongRepository(format!(
+            "{} doesn't exist; offline runs never clone",
+            local_path
+        )));
+    }
+    verify_repository_root(local_path)?;
+
+    let missing: Vec<&str> = required_refs
+        .iter()
+        .copied()
+        .filter(|reference| !is_present(reference, local_path))
+        .collect();
+    if !missing.is_empty() {
+        return Err(GitError::RefNotFound(format!(
+            "{} not found in {}; fetch them first, since offline runs never fetch",
+            missing.join(", "),
+            local_path
+        )));
+    }
+
+    eprintln!("Using local repository {} without fetching", local_path);
+    Ok(())
+}
+
+// Check that an existing directory is the root of a git repository
+fn verify_repository_root(local_path: &str) -> Result<(), GitError> {
+    let root = with_backend("repository check", |b| b.repository_root(local_path))
+        .map_err(|e| GitError::WrongRepository(format!("{} is not a git repository: {}", local_path, e)))?;
+
+    // A plain directory inside another repository would otherwise operate on that repository
+    let canonical = |path: &str| std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    if canonical(&root) != canonical(local_path) {
+        return Err(GitError::WrongRepository(format!(
+            "{} is not a git repository; it is inside the repository at {}",
+            local_path, root
+        )));
+    }
+
+    Ok(())
+}
+
+// Check that an existing directory is the root of a clone of the repository before reusing it
+fn verify_existing_repo(repo_url: &str, local_path: &str, options: &CloneOptions) -> Result<(), GitError> {
+    verify_repository_root(local_path)?;
+
+    let remotes = with_backend("remote listing", |b| b.list_remotes(local_path))?;
+    let urls: Vec<String> = remotes
+        .iter()
+        .filter_map(|remote| with_backend("remote url", |b| b.remote_url(remote, local_path)).ok())
+        .collect();
+    let wanted = normalize_repo_url(repo_url);
+    if urls.iter().any(|url| normalize_repo_url(url) == wanted) {
+        return Ok(());
+    }
+
+    let found = if urls.is_empty() {
+        "it has no remotes".to_string()
     } else {
-        files_str.split('\n').map(|s| s.to_string()).collect()
+        format!("its remotes point at {}", urls.join(", "))
+    };
+    if options.allow_mismatched_remote {
+        eprintln!("Warning: {} is not a clone of {} ({}); reusing it anyway", local_path, repo_url, found);
+        return Ok(());
+    }
+
+    Err(GitError::WrongRepository(format!(
+        "{} is not a clone of {} ({}); use another path or pass --allow-mismatched-remote",
+        local_path, repo_url, found
+    )))
+}
+
+// Reduce a repository URL to host and path, so the SSH, HTTPS and `.git` forms of one
+// repository compare equal; local paths are compared after resolving them
+fn normalize_repo_url(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    let path = url.strip_prefix("file://").unwrap_or(url);
+    if let Ok(path) = std::fs::canonicalize(path) {
+        return path.to_string_lossy().to_string();
+    }
+
+    // `scheme://user@host/path` or the scp-like `user@host:path`
+    let (rest, scp_like) = match url.split_once("://") {
+        Some((_, rest)) => (rest, false),
+        None => (url, true),
     };
-    
-    println!(
+    let rest = rest.split_once('@').map_or(rest, |(_, host_and_path)| host_and_path);
+    let rest = if scp_like { rest.replacen(':', "/", 1) } else { rest.to_string() };
+    rest.to_lowercase()
+}
+
+// Fetch the main remote, then every other remote the repository has so branches that only
+// exist there resolve too. Only a failure to fetch the main remote is returned.
+fn fetch_all_remotes(local_path: &str, depth: Option<u32>, options: &CloneOptions) -> Result<(), GitError> {
+    let fetch = |remote: &str, token: Option<&str>| {
+        with_retries("fetch", options, || with_backend("fetch", |b| b.fetch(local_path, remote, token, &[], depth)))
+    };
+    fetch(&options.remote, options.token.as_deref())?;
+
+    let remotes = match with_backend("remote listing", |b| b.list_remotes(local_path)) {
+        Ok(remotes) => remotes,
+        Err(e) => {
+            eprintln!("Warning: Failed to list remotes: {}", e);
+            return Ok(());
+        }
+    };
+    // The token belongs to the repository URL and is never sent to other remotes
+    for remote in remotes.iter().filter(|remote| **remote != options.remote) {
+        match fetch(remote, None) {
+            Ok(()) => eprintln!("Fetched remote {}", remote),
+            Err(e) => eprintln!("Warning: Failed to fetch remote {}: {}", remote, e),
+        }
+    }
+
+    Ok(())
+}
+
+// Map a pull or merge request ref such as `pull/123` or `merge-requests/7/merge` to the refspec
+// that fetches it from the forge into the remote-tracking ref of the same name
+fn review_refspec(reference: &str, remote: &str) -> Option<String> {
+    let mut parts = reference.splitn(3, '/');
+    let namespace = parts.next().filter(|ns| *ns == "pull" || *ns == "merge-requests")?;
+    let number = parts.next().filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))?;
+    let head = parts.next().unwrap_or("head");
+    if head != "head" && head != "merge" {
+        return None;
+    }
+
+    Some(format!(
+        "+refs/{}/{}/{}:refs/remotes/{}/{}",
+        namespace, number, head, remote, reference
+    ))
+}
+
+// Fetch the pull and merge request refs among the required refs, which plain fetches never bring in
+fn fetch_review_refs(required_refs: &[&str], local_path: &str, options: &CloneOptions) {
+    let refspecs: Vec<String> = required_refs
+        .iter()
+        .filter_map(|r| review_refspec(r, &options.remote))
+        .collect();
+    if refspecs.is_empty() {
+        return;
+    }
+
+    let fetch = || {
+        with_backend("fetch", |b| {
+            b.fetch(local_path, &options.remote, options.token.as_deref(), &refspecs, options.depth)
+        })
+    };
+    match with_retries("fetch", options, fetch) {
+        Ok(()) => eprintln!("Fetched review refs {}", refspecs.join(", ")),
+        Err(e) => eprintln!("Warning: Failed to fetch review refs: {}", e),
+    }
+}
+
+// Check whether a ref already names a commit in the local repository
+fn is_present(reference: &str, local_path: &str) -> bool {
+    resolve_ref(reference, local_path).is_ok()
+}
+
+// Deepen a shallow clone until the required refs resolve, fetching the full history as a last resort
+fn deepen_until_present(required_refs: &[&str], local_path: &str, options: &CloneOptions) {
+    let mut depth = options.depth.unwrap_or(DEFAULT_DEEPEN_DEPTH);
+
+    for attempt in 0..=MAX_DEEPEN_ATTEMPTS {
+        let missing: Vec<&str> = required_refs
+            .iter()
+            .copied()
+            .filter(|reference| !is_present(reference, local_path))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        // Only a shallow clone can be missing commits that a fetch would bring in
+        match with_backend("shallow check", |b| b.is_shallow(local_path)) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                eprintln!("Warning: Failed to check whether the clone is shallow: {}", e);
+                return;
+            }
+        }
+
+        depth = if attempt == MAX_DEEPEN_ATTEMPTS {
+            UNSHALLOW_DEPTH
+        } else {
+            depth.saturating_mul(2)
+        };
+        if depth == UNSHALLOW_DEPTH {
+            eprintln!("Fetching full history to find {}", missing.join(", "));
+        } else {
+            eprintln!("Deepening shallow clone to {} commits to find {}", depth, missing.join(", "));
+        }
+
+        if let Err(e) = fetch_all_remotes(local_path, Some(depth), options) {
+            eprintln!("Warning: Failed to deepen shallow clone: {}", e);
+            return;
+        }
+    }
+}
+
+// Get a list of files that are new in the current commit compared to the branch
+pub fn get_new_files(branch_name: &str, new_commit: &str, local_path: &str) -> Result<Vec<String>, GitError> {
+    let files = with_backend("diff", |b| {
+        b.diff_files(branch_name, new_commit, Some(DiffFilter::Added), local_path)
+    })
+    .map_err(|e| e.context(format!("Error getting new files between {} and {}", branch_name, new_commit)))?;
+
+    eprintln!(
         "Detected {} new files added between {} and {}",
         files.len(),
         branch_name,
         new_commit
     );
-    
+
     Ok(files)
 }
 
 // Get a list of files that were deleted in the current commit compared to the branch
-pub fn get_deleted_files(branch_name: &str, new_commit: &str, local_path: &str) -> Result<Vec<String>, String> {
-    let output = Command::new("git")
-        .args(&["diff", "--name-only", "--diff-filter=D", branch_name, new_commit])
-        .current_dir(local_path)
-        .output()
-        .map_err(|e| format!("Failed to execute git diff command: {}", e))?;
-        
-    if !output.status.success() {
-        return Err(format!(
-            "Error getting deleted files between {} and {}: {}",
-            branch_name,
-            new_commit,
-            String::from_utf8_lossy(&output.stderr)
-        ));
-    }
-    
-    let files_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    
-    let files = if files_str.is_empty() {
-        Vec::new()
-    } else {
-        files_str.split('\n').map(|s| s.to_string()).collect()
-    };
-    
-    println!(
+pub fn get_deleted_files(branch_name: &str, new_commit: &str, local_path: &str) -> Result<Vec<String>, GitError> {
+    let files = with_backend("diff", |b| {
+        b.diff_files(branch_name, new_commit, Some(DiffFilter::Deleted), local_path)
+    })
+    .map_err(|e| e.context(format!("Error getting deleted files between {} and {}", branch_name, new_commit)))?;
+
+    eprintln!(
         "Detected {} files deleted between {} and {}",
         files.len(),
         branch_name,
         new_commit
     );
-    
+
     Ok(files)
 }
 
-// Get a list of files that have changed between the current state and the branch
-pub fn get_changed_files(branch_name: &str, local_path: &str) -> Result<Vec<String>, String> {
-    let output = Command::new("git")
-        .args(&["diff", "--name-only", branch_name])
-        .current_dir(local_path)
-        .output()
-        .map_err(|e| format!("Failed to execute git diff command: {}", e))?;
-        
-    if !output.status.success() {
-        return Err(format!(
-            "Error getting direct diff for {}: {}",
-            branch_name,
-            String::from_utf8_lossy(&output.stderr)
-        ));
-    }
-    
-    let files_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    
-    let files = if files_str.is_empty() {
-        Vec::new()
-    } else {
-        files_str.split('\n').map(|s| s.to_string()).collect()
-    };
-    
+// Get a list of files that have changed between two commits
+pub fn get_changed_files_between(old_commit: &str, new_commit: &str, local_path: &str) -> Result<Vec<String>, GitError> {
+    with_backend("diff", |b| b.diff_files(old_commit, new_commit, None, local_path))
+        .map_err(|e| e.context(format!("Error getting changed files between {} and {}", old_commit, new_commit)))
+}
+
+// Get the files among the given ones that have binary contents at either ref
+pub fn get_binary_files(old_ref: &str, new_ref: &str, files: &[String], local_path: &str) -> Result<Vec<String>, GitError> {
+    // An empty pathspec would diff the whole tree
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    with_backend("diff", |b| b.binary_files(old_ref, new_ref, files, local_path))
+        .map_err(|e| e.context(format!("Error detecting binary files between {} and {}", old_ref, new_ref)))
+}
+
+// Get the files whose mode changed between the branch and the current commit
+pub fn get_mode_changes(branch_name: &str, new_commit: &str, local_path: &str) -> Result<Vec<ModeChange>, GitError> {
+    let changes = with_backend("diff", |b| b.mode_changes(branch_name, new_commit, local_path))
+        .map_err(|e| e.context(format!("Error getting mode changes between {} and {}", branch_name, new_commit)))?;
+
+    eprintln!(
+        "Detected {} file mode changes between {} and {}",
+        changes.len(),
+        branch_name,
+        new_commit
+    );
+
+    Ok(changes)
+}
+
+// Pair deleted and added files whose paths differ only in case. Rename detection
+// can't be relied on here since the contents may have changed as well.
+pub fn find_case_renames(deleted_files: &[String], new_files: &[String]) -> Vec<CaseRename> {
+    let deleted_by_case: HashMap<String, &String> =
+        deleted_files.iter().map(|file| (file.to_lowercase(), file)).collect();
+
+    new_files
+        .iter()
+        .filter_map(|new_path| {
+            deleted_by_case.get(&new_path.to_lowercase()).map(|old_path| CaseRename {
+                old_path: (*old_path).clone(),
+                new_path: new_path.clone(),
+            })
+        })
+        .collect()
+}
+
+// Check whether the repository has no working tree
+pub fn is_bare_repo(local_path: &str) -> Result<bool, GitError> {
+    with_backend("bare check", |b| b.is_bare(local_path))
+        .map_err(|e| e.context(format!("Error inspecting repository {}", local_path)))
+}
+
+// Get the files that were renamed or copied in the current commit compared to the branch
+pub fn get_similar_files(
+    branch_name: &str,
+    new_commit: &str,
+    find_copies: bool,
+    local_path: &str,
+) -> Result<Vec<SimilarFile>, GitError> {
+    let files = with_backend("diff", |b| b.similar_files(branch_name, new_commit, find_copies, local_path))
+        .map_err(|e| e.context(format!("Error getting renamed files between {} and {}", branch_name, new_commit)))?;
+
+    let copies = files.iter().filter(|f| f.copied).count();
+    eprintln!(
+        "Detected {} files renamed and {} copied between {} and {}",
+        files.len() - copies,
+        copies,
+        branch_name,
+        new_commit
+    );
+
     Ok(files)
 }
 
-// Checkout a specific branch
-pub fn checkout_branch(branch_name: &str, local_path: &str) -> Result<(), String> {
-    let output = Command::new("git")
-        .args(&["checkout", branch_name])
-        .current_dir(local_path)
-        .output()
-        .map_err(|e| format!("Failed to execute git checkout command: {}", e))?;
-        
-    if !output.status.success() {
-        return Err(format!(
-            "Failed to checkout branch {}: {}",
-            branch_name,
-            String::from_utf8_lossy(&output.stderr)
-        ));
-    }
-    
+// Resolve a ref to the full SHA of the commit it points at
+pub fn resolve_commit(reference: &str, local_path: &str) -> Result<String, GitError> {
+    with_backend("rev-parse", |b| b.resolve_commit(reference, local_path))
+}
+
+// Resolve a branch, tag, remote-tracking ref, short SHA or revision expression such as
+// `HEAD~3` to the full SHA of the commit it names. Branches that only exist on a remote
+// resolve through their remote-tracking ref, as long as every remote having them agrees.
+pub fn resolve_ref(reference: &str, local_path: &str) -> Result<String, GitError> {
+    let e = match resolve_commit(reference, local_path) {
+        Ok(commit) => return Ok(commit),
+        Err(e) => e,
+    };
+
+    let remotes = with_backend("remote listing", |b| b.list_remotes(local_path)).unwrap_or_default();
+    let matches: Vec<(String, String)> = remotes
+        .iter()
+        .filter_map(|remote| {
+            let remote_ref = format!("{}/{}", remote, reference);
+            resolve_commit(&remote_ref, local_path).ok().map(|commit| (remote_ref, commit))
+        })
+        .collect();
+
+    match matches.as_slice() {
+        [] => Err(GitError::RefNotFound(format!("Unknown revision {}: {}", reference, e))),
+        [(remote_ref, commit), rest @ ..] if rest.iter().all(|(_, other)| other == commit) => {
+            eprintln!("Resolved {} through {}", reference, remote_ref);
+            Ok(commit.clone())
+        }
+        _ => Err(GitError::AmbiguousRef {
+            reference: reference.to_string(),
+            remote_refs: matches.into_iter().map(|(remote_ref, _)| remote_ref).collect(),
+        }),
+    }
+}
+
+// Find the best common ancestor of two refs, as `git merge-base` does
+pub fn get_merge_base(first: &str, second: &str, local_path: &str) -> Result<String, GitError> {
+    let base = with_backend("merge-base", |b| b.merge_base(first, second, local_path))
+        .map_err(|e| e.context(format!("Error finding merge base of {} and {}", first, second)))?;
+
+    eprintln!("Using merge base {} of {} and {}", base, first, second);
+    Ok(base)
+}
+
+// Get the parents of a commit in order; merge commits have more than one
+pub fn get_parents(commit: &str, local_path: &str) -> Result<Vec<String>, GitError> {
+    with_backend("rev-list", |b| b.parents(commit, local_path))
+        .map_err(|e| e.context(format!("Error listing parents of {}", commit)))
+}
+
+// Get the author, committer, time and subject of a commit
+pub fn get_commit_metadata(commit: &str, local_path: &str) -> Result<CommitMetadata, GitError> {
+    with_backend("show", |b| b.commit_metadata(commit, local_path))
+        .map_err(|e| e.context(format!("Error reading metadata of {}", commit)))
+}
+
+// Blame a file as it is at a commit
+pub fn get_blame(commit: &str, file: &str, local_path: &str) -> Result<Vec<BlameHunk>, GitError> {
+    with_backend("blame", |b| b.blame(commit, file, local_path))
+        .map_err(|e| e.context(format!("Error blaming {} at {}", file, commit)))
+}
+
+// List the commits of a range along first parents, oldest first
+pub fn get_range_commits(old_ref: &str, new_ref: &str, local_path: &str) -> Result<Vec<String>, GitError> {
+    with_backend("rev-list", |b| b.first_parent_commits(old_ref, new_ref, local_path))
+        .map_err(|e| e.context(format!("Error listing commits between {} and {}", old_ref, new_ref)))
+}
+
+// Get the blob ids of the given files at a commit; files absent at that commit are omitted
+pub fn get_blob_ids(commit: &str, files: &[String], local_path: &str) -> Result<HashMap<String, String>, GitError> {
+    with_backend("ls-tree", |b| b.blob_ids(commit, files, local_path))
+        .map_err(|e| e.context(format!("Error listing blobs at {}", commit)))
+}
+
+// Download the blobs of the given files at the refs in one batch when a partial clone lacks
+// them, instead of letting each read fetch its own blob
+pub fn prefetch_blobs(refs: &[&str], files: &[String], local_path: &str, options: &CloneOptions) {
+    if files.is_empty() || options.offline {
+        return;
+    }
+
+    let mut missing = Vec::new();
+    for reference in refs {
+        match with_backend("missing object check", |b| b.missing_blobs(reference, files, local_path)) {
+            Ok(object_ids) => missing.extend(object_ids),
+            Err(e) => eprintln!("Warning: Failed to check for missing blobs at {}: {}", reference, e),
+        }
+    }
+    missing.sort();
+    missing.dedup();
+    if missing.is_empty() {
+        return;
+    }
+
+    let fetch = || {
+        with_backend("object fetch", |b| {
+            b.fetch_objects(&missing, &options.remote, options.token.as_deref(), local_path)
+        })
+    };
+    match with_retries("object fetch", options, fetch) {
+        Ok(()) => eprintln!("Fetched {} missing blobs of the partial clone", missing.len()),
+        Err(e) => eprintln!("Warning: Failed to prefetch blobs, reading them one by one: {}", e),
+    }
+}
+
+// Read a file as it is stored at a ref, without touching the working tree
+pub fn read_file_at(reference: &str, file: &str, local_path: &str) -> Result<String, GitError> {
+    with_backend("blob read", |b| b.read_file(reference, file, local_path))
+        .map_err(|e| e.context(format!("Error reading {} at {}", file, reference)))
+}
+
+// List the Rust files at a ref that mention the word as a whole word, without touching the working tree
+pub fn grep_rust_files(reference: &str, word: &str, local_path: &str) -> Result<Vec<String>, GitError> {
+    with_backend("grep", |b| b.grep_rust_files(reference, word, local_path))
+        .map_err(|e| e.context(format!("Error searching for {} at {}", word, reference)))
+}
+
+// Fetch the contents a Git LFS pointer stands for
+pub fn lfs_smudge(pointer: &str, file: &str, local_path: &str) -> Result<String, GitError> {
+    with_backend("lfs smudge", |b| b.lfs_smudge(pointer, file, local_path))
+        .map_err(|e| e.context(format!("Error fetching LFS contents of {}", file)))
+}
+
+// Limit the working tree to the directories that will be read from disk
+pub fn set_sparse_dirs(dirs: &[String], local_path: &str) -> Result<(), GitError> {
+    with_backend("sparse-checkout", |b| b.set_sparse_dirs(dirs, local_path))
+        .map_err(|e| e.context("Error configuring sparse checkout"))?;
+
+    eprintln!("Sparse checkout limited to {} directories", dirs.len());
     Ok(())
 }
 
 // Checkout a specific commit
-pub fn checkout_commit(commit: &str, local_path: &str) -> Result<(), String> {
-    let output = Command::new("git")
-        .args(&["checkout", commit])
-        .current_dir(local_path)
-        .output()
-        .map_err(|e| format!("Failed to execute git checkout command: {}", e))?;
-        
-    if !output.status.success() {
-        return Err(format!(
-            "Failed to checkout commit {}: {}",
-            commit,
-            String::from_utf8_lossy(&output.stderr)
-        ));
-    }
-    
-    Ok(())
-}
\ No newline at end of file
+pub fn checkout_commit(commit: &str, local_path: &str) -> Result<(), GitError> {
+    with_backend("checkout", |b| b.checkout(commit, local_path))
+        .map_err(|e| e.context(format!("Failed to checkout commit {}", commit)))
+}
+
+static WORKTREE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// TempWorktree is a worktree checked out at a ref that is removed when dropped
+pub struct TempWorktree {
+    path: PathBuf,
+    local_path: String,
+}
+
+impl TempWorktree {
+    // Check out a ref into a fresh worktree under the system temp directory
+    pub fn add(reference: &str, local_path: &str) -> Result<Self, GitError> {
+        let name = format!(
+            "cada-worktree-{}-{}",
+            std::process::id(),
+            WORKTREE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(name);
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| GitError::Command("Temporary directory path is not valid UTF-8".to_string()))?;
+
+        with_backend("worktree add", |b| b.add_worktree(reference, path_str, local_path))
+            .map_err(|e| e.context(format!("Failed to create worktree for {}", reference)))?;
+        eprintln!("Checked out {} into worktree {}", reference, path.display());
+
+        Ok(TempWorktree {
+            path,
+            local_path: local_path.to_string(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempWorktree {
+    fn drop(&mut self) {
+        let path = self.path.to_string_lossy();
+        if let Err(e) = with_backend("worktree remove", |b| b.remove_worktree(&path, &self.local_path)) {
+            eprintln!("Warning: Failed to remove worktree {}: {}", path, e);
+        }
+    }
+}
+
+// HeadGuard restores the checked out branch or commit when dropped, including on panic
+pub struct HeadGuard {
+    head: String,
+    local_path: String,
+}
+
+impl HeadGuard {
+    // Record the current HEAD of the repository
+    pub fn record(local_path: &str) -> Result<Self, GitError> {
+        let head = with_backend("HEAD lookup", |b| b.head(local_path))
+            .map_err(|e| e.context(format!("Failed to read HEAD of {}", local_path)))?;
+
+        Ok(HeadGuard {
+            head,
+            local_path: local_path.to_string(),
+        })
+    }
+}
+
+impl Drop for HeadGuard {
+    fn drop(&mut self) {
+        match with_backend("checkout", |b| b.checkout(&self.head, &self.local_path)) {
+            Ok(()) => eprintln!("Restored original HEAD {}", self.head),
+            Err(e) => eprintln!("Warning: Failed to restore original HEAD {}: {}", self.head, e),
+        }
+    }
+}
+
+// List the tracked files with uncommitted modifications
+pub fn get_dirty_files(local_path: &str) -> Result<Vec<String>, GitError> {
+    with_backend("status", |b| b.dirty_files(local_path))
+        .map_err(|e| e.context("Error checking working tree status"))
+}
+
+// Throw away local modifications so refs can be checked out
+pub fn discard_changes(local_path: &str) -> Result<(), GitError> {
+    with_backend("reset", |b| b.discard_changes(local_path))
+        .map_err(|e| e.context("Error discarding local modifications"))
+}
+
+// StashGuard stashes local modifications and reapplies them when dropped
+pub struct StashGuard {
+    local_path: String,
+}
+
+impl StashGuard {
+    pub fn push(local_path: &str) -> Result<Self, GitError> {
+        with_backend("stash", |b| b.stash_push(local_path))
+            .map_err(|e| e.context("Error stashing local modifications"))?;
+        eprintln!("Stashed local modifications");
+
+        Ok(StashGuard {
+            local_path: local_path.to_string(),
+        })
+    }
+}
+
+impl Drop for StashGuard {
+    fn drop(&mut self) {
+        match with_backend("stash pop", |b| b.stash_pop(&self.local_path)) {
+            Ok(()) => eprintln!("Restored stashed local modifications"),
+            Err(e) => eprintln!(
+                "Warning: Failed to restore stashed modifications, they remain in `git stash list`: {}",
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    // FakeBackend answers resolve_commit with a fixed result and counts how often it was asked
+    struct FakeBackend {
+        name: &'static str,
+        result: Result<String, String>,
+        calls: Cell<usize>,
+    }
+
+    impl FakeBackend {
+        fn new(name: &'static str, result: Result<&str, &str>) -> Self {
+            FakeBackend {
+                name,
+                result: result.map(str::to_string).map_err(str::to_string),
+                calls: Cell::new(0),
+            }
+        }
+    }
+
+    impl GitBackend for FakeBackend {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        fn resolve_commit(&self, _reference: &str, _local_path: &str) -> Result<String, String> {
+            self.calls.set(self.calls.get() + 1);
+            self.result.clone()
+        }
+        fn clone_repo(&self, _: &str, _: &str, _: &CloneOptions) -> Result<(), String> {
+            unreachable!()
+        }
+        fn is_bare(&self, _: &str) -> Result<bool, String> {
+            unreachable!()
+        }
+        fn repository_root(&self, _: &str) -> Result<String, String> {
+            unreachable!()
+        }
+        fn is_shallow(&self, _: &str) -> Result<bool, String> {
+            unreachable!()
+        }
+        fn set_remote_url(&self, _: &str, _: &str, _: &str) -> Result<(), String> {
+            unreachable!()
+        }
+        fn list_remotes(&self, _: &str) -> Result<Vec<String>, String> {
+            unreachable!()
+        }
+        fn remote_url(&self, _: &str, _: &str) -> Result<String, String> {
+            unreachable!()
+        }
+        fn list_branches(&self, _: &str) -> Result<Vec<String>, String> {
+            unreachable!()
+        }
+        fn fetch(&self, _: &str, _: &str, _: Option<&str>, _: &[String], _: Option<u32>) -> Result<(), String> {
+            unreachable!()
+        }
+        fn diff_files(&self, _: &str, _: &str, _: Option<DiffFilter>, _: &str) -> Result<Vec<String>, String> {
+            unreachable!()
+        }
+        fn binary_files(&self, _: &str, _: &str, _: &[String], _: &str) -> Result<Vec<String>, String> {
+            unreachable!()
+        }
+        fn mode_changes(&self, _: &str, _: &str, _: &str) -> Result<Vec<ModeChange>, String> {
+            unreachable!()
+        }
+        fn similar_files(&self, _: &str, _: &str, _: bool, _: &str) -> Result<Vec<SimilarFile>, String> {
+            unreachable!()
+        }
+        fn read_file(&self, _: &str, _: &str, _: &str) -> Result<String, String> {
+            unreachable!()
+        }
+        fn grep_rust_files(&self, _: &str, _: &str, _: &str) -> Result<Vec<String>, String> {
+            unreachable!()
+        }
+        fn lfs_smudge(&self, _: &str, _: &str, _: &str) -> Result<String, String> {
+            unreachable!()
+        }
+        fn checkout(&self, _: &str, _: &str) -> Result<(), String> {
+            unreachable!()
+        }
+        fn head(&self, _: &str) -> Result<String, String> {
+            unreachable!()
+        }
+        fn dirty_files(&self, _: &str) -> Result<Vec<String>, String> {
+            unreachable!()
+        }
+        fn stash_push(&self, _: &str) -> Result<(), String> {
+            unreachable!()
+        }
+        fn stash_pop(&self, _: &str) -> Result<(), String> {
+            unreachable!()
+        }
+        fn discard_changes(&self, _: &str) -> Result<(), String> {
+            unreachable!()
+        }
+        fn add_worktree(&self, _: &str, _: &str, _: &str) -> Result<(), String> {
+            unreachable!()
+        }
+        fn remove_worktree(&self, _: &str, _: &str) -> Result<(), String> {
+            unreachable!()
+        }
+        fn set_sparse_dirs(&self, _: &[String], _: &str) -> Result<(), String> {
+            unreachable!()
+        }
+        fn merge_base(&self, _: &str, _: &str, _: &str) -> Result<String, String> {
+            unreachable!()
+        }
+        fn parents(&self, _: &str, _: &str) -> Result<Vec<String>, String> {
+            unreachable!()
+        }
+        fn commit_metadata(&self, _: &str, _: &str) -> Result<CommitMetadata, String> {
+            unreachable!()
+        }
+        fn blame(&self, _: &str, _: &str, _: &str) -> Result<Vec<BlameHunk>, String> {
+            unreachable!()
+        }
+        fn first_parent_commits(&self, _: &str, _: &str, _: &str) -> Result<Vec<String>, String> {
+            unreachable!()
+        }
+        fn blob_ids(&self, _: &str, _: &[String], _: &str) -> Result<HashMap<String, String>, String> {
+            unreachable!()
+        }
+        fn missing_blobs(&self, _: &str, _: &[String], _: &str) -> Result<Vec<String>, String> {
+            unreachable!()
+        }
+        fn fetch_objects(&self, _: &[String], _: &str, _: Option<&str>, _: &str) -> Result<(), String> {
+            unreachable!()
+        }
+    }
+
+    // Resolve a ref on fake libgit2 and CLI backends under a backend kind
+    fn resolve(kind: BackendKind, libgit2: &FakeBackend, cli: &FakeBackend) -> Result<String, GitError> {
+        run_on_backend(kind, libgit2, cli, "rev-parse", |backend| backend.resolve_commit("main", "repo"))
+    }
+
+    #[test]
+    fn auto_uses_libgit2_when_it_succeeds() {
+        let libgit2 = FakeBackend::new("libgit2", Ok("abc"));
+        let cli = FakeBackend::new("cli", Ok("def"));
+        assert_eq!(resolve(BackendKind::Auto, &libgit2, &cli), Ok("abc".to_string()));
+        assert_eq!(cli.calls.get(), 0);
+    }
+
+    #[test]
+    fn auto_falls_back_to_cli_when_libgit2_fails() {
+        let libgit2 = FakeBackend::new("libgit2", Err("unsupported extension"));
+        let cli = FakeBackend::new("cli", Ok("def"));
+        assert_eq!(resolve(BackendKind::Auto, &libgit2, &cli), Ok("def".to_string()));
+        assert_eq!((libgit2.calls.get(), cli.calls.get()), (1, 1));
+    }
+
+    #[test]
+    fn auto_classifies_the_cli_error_after_falling_back() {
+        let libgit2 = FakeBackend::new("libgit2", Err("unsupported extension"));
+        let cli = FakeBackend::new("cli", Err("fatal: could not resolve host: example.com"));
+        assert!(matches!(resolve(BackendKind::Auto, &libgit2, &cli), Err(GitError::Network(_))));
+    }
+
+    #[test]
+    fn auto_does_not_fall_back_on_missing_ref() {
+        let libgit2 = FakeBackend::new("libgit2", Err("revspec 'main' not found"));
+        let cli = FakeBackend::new("cli", Ok("def"));
+        assert!(matches!(resolve(BackendKind::Auto, &libgit2, &cli), Err(GitError::RefNotFound(_))));
+        assert_eq!(cli.calls.get(), 0);
+    }
+
+    #[test]
+    fn explicit_backends_never_fall_back() {
+        let libgit2 = FakeBackend::new("libgit2", Err("unsupported extension"));
+        let cli = FakeBackend::new("cli", Ok("def"));
+        assert!(matches!(resolve(BackendKind::Libgit2, &libgit2, &cli), Err(GitError::Command(_))));
+        assert_eq!(cli.calls.get(), 0);
+
+        assert_eq!(resolve(BackendKind::Cli, &libgit2, &cli), Ok("def".to_string()));
+        assert_eq!(libgit2.calls.get(), 1);
+    }
+
+    // Options allowing the given number of retries, starting at a one second delay
+    fn retry_options(retries: u32) -> CloneOptions {
+        CloneOptions {
+            retries,
+            retry_delay: Duration::from_secs(1),
+            ..CloneOptions::default()
+        }
+    }
+
+    #[test]
+    fn retries_transient_errors_with_doubling_delays() {
+        let attempts = Cell::new(0);
+        let delays = RefCell::new(Vec::new());
+        let op = || {
+            attempts.set(attempts.get() + 1);
+            match attempts.get() {
+                1..=3 => Err(GitError::Network("connection reset".to_string())),
+                _ => Ok("fetched"),
+            }
+        };
+        let result = retry_with_backoff("fetch", &retry_options(5), op, |delay| delays.borrow_mut().push(delay));
+
+        assert_eq!(result, Ok("fetched"));
+        assert_eq!(attempts.get(), 4);
+        assert_eq!(*delays.borrow(), [1, 2, 4].map(Duration::from_secs));
+    }
+
+    #[test]
+    fn gives_up_after_the_last_retry() {
+        let attempts = Cell::new(0);
+        let delays = RefCell::new(Vec::new());
+        let op = || {
+            attempts.set(attempts.get() + 1);
+            Err::<(), _>(GitError::Command(format!("attempt {}", attempts.get())))
+        };
+        let result = retry_with_backoff("fetch", &retry_options(2), op, |delay| delays.borrow_mut().push(delay));
+
+        assert_eq!(result, Err(GitError::Command("attempt 3".to_string())));
+        assert_eq!(*delays.borrow(), [1, 2].map(Duration::from_secs));
+    }
+
+    #[test]
+    fn does_not_retry_permanent_errors() {
+        let attempts = Cell::new(0);
+        let op = || {
+            attempts.set(attempts.get() + 1);
+            Err::<(), _>(GitError::Auth("authentication failed".to_string()))
+        };
+        let result = retry_with_backoff("fetch", &retry_options(3), op, |_| panic!("slept before a retry"));
+
+        assert!(matches!(result, Err(GitError::Auth(_))));
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[test]
+    fn classifies_errors_by_message() {
+        let classify = |message: &str| GitError::classify(message.to_string());
+        assert!(matches!(classify("fatal: Authentication failed for 'https://x'"), GitError::Auth(_)));
+        assert!(matches!(classify("fatal: could not read Username for 'https://x'"), GitError::Auth(_)));
+        assert!(matches!(classify("fatal: Could not resolve host: example.com"), GitError::Network(_)));
+        assert!(matches!(classify("fetch-pack: unexpected disconnect, early EOF"), GitError::Network(_)));
+        assert!(matches!(classify("fatal: bad revision 'nope'"), GitError::RefNotFound(_)));
+        assert!(matches!(classify("revspec 'nope' not found; class=Reference (4)"), GitError::RefNotFound(_)));
+        assert!(matches!(classify("fatal: not a git repository"), GitError::Command(_)));
+    }
+
+    #[test]
+    fn classifies_auth_before_network() {
+        // An HTTP 403 also mentions that the URL couldn't be accessed
+        let message = "fatal: unable to access 'https://x/': The requested URL returned error: 403";
+        assert!(matches!(GitError::classify(message.to_string()), GitError::Auth(_)));
+    }
+
+    #[test]
+    fn only_network_and_command_errors_are_transient() {
+        assert!(GitError::Network(String::new()).is_transient());
+        assert!(GitError::Command(String::new()).is_transient());
+        assert!(!GitError::Auth(String::new()).is_transient());
+        assert!(!GitError::RefNotFound(String::new()).is_transient());
+        assert!(!GitError::WrongRepository(String::new()).is_transient());
+    }
+
+    #[test]
+    fn context_keeps_the_kind() {
+        let error = GitError::RefNotFound("unknown revision".to_string()).context("Error resolving main");
+        assert_eq!(error, GitError::RefNotFound("Error resolving main: unknown revision".to_string()));
+
+        let dirty = GitError::DirtyTree {
+            path: "repo".to_string(),
+            files: vec!["src/lib.rs".to_string()],
+        };
+        assert_eq!(dirty.clone().context("Error checking out main"), dirty);
+    }
+}
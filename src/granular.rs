@@ -1,276 +1,276 @@
 // src/granular.rs
+use rayon::prelude::*;
 use serde_json::json;
 use std::collections::HashMap;
-use std::env;
-use std::fs;
 use std::path::Path;
 use syn::spanned::Spanned;
 use syn::{ImplItemFn, ItemFn};
 
 use crate::ast_parser::{
-    extract_file_ast, extract_function_calls, extract_literals, format_node, get_source_location,
-    remove_duplicates,
+    extract_function_calls, extract_literals, extract_panic_points, extract_propagation_points, extract_unsafe_regions,
+    format_node, get_source_location, remove_duplicates,
+};
+use crate::differ::{load_both_sides, parse_or_empty};
+use crate::modules::{resolve_module_paths, short_name};
+use crate::types::{
+    AnalysisContext, ArityChange, CalledFunctionChanges, CallSite, FileASTData, GranularChanges, PanicPoint,
+    PanicReportEntry, SourceLocation, TypedLiteral, UnsafeRegion,
 };
-use crate::git_ops::{checkout_branch, checkout_commit};
-use crate::types::{CalledFunctionChanges, FileASTData, SourceLocation, TypedLiteral};
-
-pub fn get_granular_change_for_functions(
-    rust_files: &[String],
-    local_repo_path: &str,
-    output_path: &str,
-) {
-    // Map to store file => function => changes
-    let mut granular_changes = HashMap::new();
-
-    // Get arguments from command line
-    let args: Vec<String> = env::args().collect();
-    let branch_name = &args[3];
-    let current_commit = &args[4];
-
-    println!("Using previous HI commit: {}", branch_name);
-    println!("Current commit: {}", current_commit);
-
-    // Step 1: Checkout the previous commit and extract all ASTs
-    if let Err(e) = checkout_branch(branch_name, local_repo_path) {
-        println!("Error checking out previous commit {}: {}", branch_name, e);
-        return;
-    }
-    println!("Checked out previous commit: {}", branch_name);
 
-    // Map to store all ASTs from previous commit
-    let mut old_asts = HashMap::new();
+pub fn get_granular_change_for_functions(rust_files: &[String], ctx: &AnalysisContext) -> GranularChanges {
+    eprintln!("Using previous HI commit: {}", ctx.branch_name);
+    eprintln!("Current commit: {}", ctx.current_commit);
 
-    // Process all files in the previous commit
-    for rust_file in rust_files {
-        let full_path = Path::new(local_repo_path).join(rust_file);
-        println!("Processing old AST for: {}", full_path.display());
+    // Step 1: Read all files at the previous and current commits
+    let Some((old_contents, new_contents)) = load_both_sides(rust_files, ctx) else {
+        return GranularChanges::new();
+    };
 
-        match extract_file_ast(full_path.to_str().unwrap_or("")) {
-            Ok(ast) => {
-                old_asts.insert(rust_file.clone(), ast);
-            }
-            Err(e) => {
-                println!(
-                    "Error extracting old AST for {}: {} (file might not exist in old commit)",
-                    rust_file, e
-                );
-                // Create an empty AST for files that don't exist in the old commit
-                old_asts.insert(rust_file.clone(), FileASTData::empty(rust_file.clone()));
+    // Step 2: Resolve the module path each file has in its crate, which qualifies every key
+    let module_paths = resolve_module_paths(rust_files, ctx);
+
+    // Step 3: Analyze functions and methods for all files in parallel
+    let granular_changes: GranularChanges = rust_files
+        .par_iter()
+        .filter_map(|rust_file| {
+            let full_path = Path::new(ctx.local_repo_path).join(rust_file);
+            let full_path = full_path.to_str().unwrap_or("");
+
+            // Files that don't exist in one commit are compared against an empty AST
+            let module_path = module_paths.get(rust_file).map(|location| location.path.as_str());
+            let (old_content, new_content) = (old_contents.get(rust_file), new_contents.get(rust_file));
+            let old_ast = parse_or_empty(rust_file, full_path, module_path, old_content, ctx.branch_name, ctx.errors);
+            let new_ast = parse_or_empty(rust_file, full_path, module_path, new_content, ctx.current_commit, ctx.errors);
+
+            let file_changes = analyze_file(&old_ast, &new_ast);
+            if file_changes.is_empty() {
+                return None;
             }
-        }
-    }
-
-    // Step 2: Checkout the current commit and extract all ASTs
-    if let Err(e) = checkout_commit(current_commit, local_repo_path) {
-        println!(
-            "Error checking out current commit {}: {}",
-            current_commit, e
-        );
-        return;
-    }
-    println!("Checked out current commit: {}", current_commit);
-
-    // Map to store all ASTs from current commit
-    let mut new_asts = HashMap::new();
 
-    // Process all files in the current commit
-    for rust_file in rust_files {
-        let full_path = Path::new(local_repo_path).join(rust_file);
-        println!("Processing new AST for: {}", full_path.display());
+            eprintln!(
+                "Added {} changes for file {}",
+                file_changes.len(),
+                rust_file
+            );
+            Some((rust_file.clone(), file_changes))
+        })
+        .collect();
 
-        match extract_file_ast(full_path.to_str().unwrap_or("")) {
-            Ok(ast) => {
-                new_asts.insert(rust_file.clone(), ast);
-            }
-            Err(e) => {
-                println!(
-                    "Error extracting new AST for {}: {} (file might not exist in new commit)",
-                    rust_file, e
-                );
-                // Create an empty AST for files that don't exist in the new commit
-                new_asts.insert(rust_file.clone(), FileASTData::empty(rust_file.clone()));
-            }
-        }
+    if granular_changes.is_empty() {
+        eprintln!("No granular changes found in any files");
+    } else {
+        eprintln!("Found granular changes in {} files", granular_changes.len());
     }
 
-    // Step 3: Analyze functions and methods for all files
-    for rust_file in rust_files {
-        let old_ast = old_asts.get(rust_file);
-        let new_ast = new_asts.get(rust_file);
-
-        // Skip files that don't exist in either commit
-        if old_ast.is_none() && new_ast.is_none() {
-            continue;
-        }
-
-        // Handle cases where the file exists only in one commit
-        let old_ast = old_ast
-            .cloned()
-            .unwrap_or_else(|| FileASTData::empty(rust_file.clone()));
-        let new_ast = new_ast
-            .cloned()
-            .unwrap_or_else(|| FileASTData::empty(rust_file.clone()));
-
-        let mut file_changes = HashMap::new();
-
-        // Check regular functions
-        for (name, old_func) in &old_ast.functions {
-            if let Some(new_func) = new_ast.functions.get(name) {
-                // Function exists in both commits, compare them
-                let old_code = format_node(old_func);
-                let new_code = format_node(new_func);
-
-                if old_code != new_code {
-                    // Function has changed, analyze in detail
-                    let changes = compare_called_functions(old_func, new_func, &old_ast, &new_ast);
-                    file_changes.insert(name.clone(), changes);
-                    println!("Added modified function: {}", name);
-                }
-            }
-        }
+    granular_changes
+}
 
-        // Check methods too
-        for (name, (_, old_method)) in &old_ast.methods {
-            if let Some((_, new_method)) = new_ast.methods.get(name) {
-                // Method exists in both commits, compare them
-                let old_code = format_node(old_method);
-                let new_code = format_node(new_method);
-
-                if old_code != new_code {
-                    // Method has changed, analyze in detail
-                    let changes =
-                        compare_called_functions(old_method, new_method, &old_ast, &new_ast);
-                    file_changes.insert(name.clone(), changes);
-                    println!("Added modified method: {}", name);
-                }
+// Analyze the functions and methods present in both versions of a file, keyed by their
+// qualified names
+fn analyze_file(old_ast: &FileASTData, new_ast: &FileASTData) -> HashMap<String, CalledFunctionChanges> {
+    let mut file_changes = HashMap::new();
+
+    // Check regular functions
+    for (name, old_func) in &old_ast.functions {
+        if let Some(new_func) = new_ast.functions.get(name) {
+            // Function exists in both commits, compare them
+            let old_code = format_node(old_func);
+            let new_code = format_node(new_func);
+
+            if old_code != new_code {
+                // Function has changed, analyze in detail
+                let changes = compare_called_functions(name, old_func, new_func, old_ast, new_ast);
+                file_changes.insert(name.clone(), changes);
+                eprintln!("Added modified function: {}", name);
             }
         }
-
-        if !file_changes.is_empty() {
-            println!(
-                "Added {} changes for file {}",
-                file_changes.len(),
-                rust_file
-            );
-            granular_changes.insert(rust_file.clone(), file_changes);
-        }
     }
 
-    // Write the results to a JSON file
-    if !granular_changes.is_empty() {
-        println!("Found granular changes in {} files", granular_changes.len());
-
-        match serde_json::to_string_pretty(&granular_changes) {
-            Ok(granular_json) => {
-                let granular_path = Path::new(output_path).join("function_changes_granular.json");
-                if let Err(e) = fs::write(&granular_path, granular_json) {
-                    println!("Error writing granular changes file: {}", e);
-                } else {
-                    println!(
-                        "Wrote granular function changes to {}",
-                        granular_path.display()
-                    );
-                }
+    // Check methods too
+    for (name, (_, old_method)) in &old_ast.methods {
+        if let Some((_, new_method)) = new_ast.methods.get(name) {
+            // Method exists in both commits, compare them
+            let old_code = format_node(old_method);
+            let new_code = format_node(new_method);
+
+            if old_code != new_code {
+                // Method has changed, analyze in detail
+                let changes = compare_called_functions(name, old_method, new_method, old_ast, new_ast);
+                file_changes.insert(name.clone(), changes);
+                eprintln!("Added modified method: {}", name);
             }
-            Err(e) => {
-                println!("Error marshaling granular changes: {}", e);
-            }
-        }
-    } else {
-        println!("No granular changes found in any files");
-
-        // Write an empty JSON object to the file
-        let empty_json = "{}";
-        let granular_path = Path::new(output_path).join("function_changes_granular.json");
-        if let Err(e) = fs::write(&granular_path, empty_json) {
-            println!("Error writing empty granular changes file: {}", e);
-        } else {
-            println!(
-                "Wrote empty granular changes file to {}",
-                granular_path.display()
-            );
         }
     }
+
+    file_changes
 }
 
 // Compare called functions between two function declarations
 fn compare_called_functions(
+    name: &str,
     old_func: &ItemFn,
     new_func: &ItemFn,
     old_ast: &FileASTData,
     new_ast: &FileASTData,
 ) -> CalledFunctionChanges {
-    let old_calls = extract_function_calls(old_func);
-    let new_calls = extract_function_calls(new_func);
+    let old_visitor = extract_function_calls(old_func);
+    let new_visitor = extract_function_calls(new_func);
+    let (old_calls, new_calls) = (&old_visitor.calls, &new_visitor.calls);
 
     // Find added and removed function calls
     let mut added_functions = Vec::new();
-    for call in &new_calls {
+    for call in new_calls {
         if !old_calls.contains(call) {
             added_functions.push(call.clone());
         }
     }
 
     let mut removed_functions = Vec::new();
-    for call in &old_calls {
+    for call in old_calls {
         if !new_calls.contains(call) {
             removed_functions.push(call.clone());
         }
     }
 
     // Extract literals
-    let old_literals = extract_literals(old_func);
-    let new_literals = extract_literals(new_func);
+    let old_literals = extract_literals(old_func, &old_ast.file_path);
+    let new_literals = extract_literals(new_func, &new_ast.file_path);
 
     // Find added and removed literals
     let mut added_literals = Vec::new();
     for lit in &new_literals {
-        if !old_literals
-            .iter()
-            .any(|old_lit| old_lit.type_name == lit.type_name && old_lit.value == lit.value)
-        {
+        if !old_literals.iter().any(|old_lit| old_lit.same_as(lit)) {
             added_literals.push(lit.clone());
         }
     }
 
     let mut removed_literals = Vec::new();
     for lit in &old_literals {
-        if !new_literals
-            .iter()
-            .any(|new_lit| new_lit.type_name == lit.type_name && new_lit.value == lit.value)
-        {
+        if !new_literals.iter().any(|new_lit| new_lit.same_as(lit)) {
             removed_literals.push(lit.clone());
         }
     }
 
+    // Await points and error propagations, which unlike calls and literals aren't swapped
+    let old_points = extract_propagation_points(old_func);
+    let new_points = extract_propagation_points(new_func);
+    let only_in = |points: &[String], other: &[String]| {
+        remove_duplicates(points.iter().filter(|point| !other.contains(point)).cloned().collect())
+    };
+
+    // Unsafe blocks are matched by their content hash, wherever they moved
+    let old_unsafe = extract_unsafe_regions(old_func, old_ast);
+    let new_unsafe = extract_unsafe_regions(new_func, new_ast);
+    let blocks_only_in = |blocks: &[UnsafeRegion], other: &[UnsafeRegion]| -> Vec<UnsafeRegion> {
+        blocks.iter().filter(|block| !other.iter().any(|o| o.hash == block.hash)).cloned().collect()
+    };
+
+    // Panic points are matched by their kind and code, wherever they moved
+    let old_panics = extract_panic_points(old_func, old_ast);
+    let new_panics = extract_panic_points(new_func, new_ast);
+    let panics_only_in = |points: &[PanicPoint], other: &[PanicPoint]| -> Vec<PanicPoint> {
+        points
+            .iter()
+            .filter(|point| !other.iter().any(|o| o.kind == point.kind && o.code == point.code))
+            .cloned()
+            .collect()
+    };
+
     // Get source locations
     let old_function_src_loc = get_source_location(old_func.span(), &old_ast.file_path);
     let new_function_src_loc = get_source_location(new_func.span(), &new_ast.file_path);
 
     // Create the result - note the swapped values for compatibility with the original Go code
     let result = CalledFunctionChanges {
+        name: short_name(new_ast.module_path.as_deref(), name).to_string(),
         added_functions: remove_duplicates(removed_functions),
         removed_functions: remove_duplicates(added_functions),
         added_literals: removed_literals,
         removed_literals: added_literals,
+        added_await_points: only_in(&new_points.awaits, &old_points.awaits),
+        removed_await_points: only_in(&old_points.awaits, &new_points.awaits),
+        added_error_propagations: only_in(&new_points.error_propagations, &old_points.error_propagations),
+        removed_error_propagations: only_in(&old_points.error_propagations, &new_points.error_propagations),
+        arity_changes: find_arity_changes(&old_visitor.call_sites, &new_visitor.call_sites),
+        added_unsafe_blocks: blocks_only_in(&new_unsafe.blocks, &old_unsafe.blocks),
+        removed_unsafe_blocks: blocks_only_in(&old_unsafe.blocks, &new_unsafe.blocks),
+        added_unsafe_calls: only_in(&new_unsafe.calls, &old_unsafe.calls),
+        removed_unsafe_calls: only_in(&old_unsafe.calls, &new_unsafe.calls),
+        added_panic_points: panics_only_in(&new_panics, &old_panics),
+        removed_panic_points: panics_only_in(&old_panics, &new_panics),
         old_function_src_loc,
         new_function_src_loc,
     };
 
     // Log the changes found for debugging
-    if !result.added_functions.is_empty()
-        || !result.removed_functions.is_empty()
-        || !result.added_literals.is_empty()
-        || !result.removed_literals.is_empty()
-    {
-        println!(
-            "  - Function changes: +{} calls, -{} calls, +{} literals, -{} literals",
+    if result.has_changes() {
+        eprintln!(
+            "  - Function changes: +{} calls, -{} calls, +{} literals, -{} literals, \
+             +{} awaits, -{} awaits, +{} ?, -{} ?",
             result.added_functions.len(),
             result.removed_functions.len(),
             result.added_literals.len(),
-            result.removed_literals.len()
+            result.removed_literals.len(),
+            result.added_await_points.len(),
+            result.removed_await_points.len(),
+            result.added_error_propagations.len(),
+            result.removed_error_propagations.len()
         );
     }
 
     result
 }
+
+// Find the calls made in both versions whose sets of argument counts differ
+fn find_arity_changes(old_sites: &[CallSite], new_sites: &[CallSite]) -> Vec<ArityChange> {
+    let arg_counts = |sites: &[CallSite], name: &str| {
+        let mut counts: Vec<usize> = sites.iter().filter(|site| site.name == name).map(|site| site.arg_count).collect();
+        counts.sort_unstable();
+        counts.dedup();
+        counts
+    };
+
+    let mut arity_changes: Vec<ArityChange> = Vec::new();
+    for site in new_sites {
+        if arity_changes.iter().any(|change| change.name == site.name) {
+            continue;
+        }
+        let old_arg_counts = arg_counts(old_sites, &site.name);
+        let new_arg_counts = arg_counts(new_sites, &site.name);
+        if old_arg_counts.is_empty() || old_arg_counts == new_arg_counts {
+            continue;
+        }
+
+        let receiver_type = new_sites
+            .iter()
+            .chain(old_sites)
+            .filter(|other| other.name == site.name)
+            .find_map(|other| other.receiver_type.clone());
+        arity_changes.push(ArityChange {
+            name: site.name.clone(),
+            receiver_type,
+            old_arg_counts,
+            new_arg_counts,
+        });
+    }
+    arity_changes
+}
+
+// Gather the modified functions that introduce new panic points, ordered by file and name
+pub fn build_panic_report(granular_changes: &GranularChanges) -> Vec<PanicReportEntry> {
+    let mut report: Vec<PanicReportEntry> = granular_changes
+        .iter()
+        .flat_map(|(file, functions)| {
+            functions
+                .iter()
+                .filter(|(_, changes)| !changes.added_panic_points.is_empty())
+                .map(move |(name, changes)| PanicReportEntry {
+                    module: file.clone(),
+                    qualified_name: name.clone(),
+                    added_panic_points: changes.added_panic_points.clone(),
+                })
+        })
+        .collect();
+    report.sort_by(|a, b| (&a.module, &a.qualified_name).cmp(&(&b.module, &b.qualified_name)));
+    report
+}
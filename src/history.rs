@@ -0,0 +1,76 @@
+// src/history.rs
+use crate::changeset::detect_changes;
+use crate::cli::DiffArgs;
+use crate::differ::process_rust_files;
+use crate::git_ops::{get_range_commits, resolve_commit};
+use crate::types::{CommitChanges, ErrorLog};
+
+// Analyze each commit between the two refs against its first parent, oldest first
+pub fn analyze_commits(old_ref: &str, new_ref: &str, args: &DiffArgs, errors: &ErrorLog) -> Vec<CommitChanges> {
+    let local_repo_path = &args.local_repo_path;
+
+    let commits = match get_range_commits(old_ref, new_ref, local_repo_path) {
+        Ok(commits) => commits,
+        Err(e) => {
+            eprintln!("Error listing commits: {}", e);
+            errors.record("git_diff", None, Some(new_ref), &e);
+            return Vec::new();
+        }
+    };
+    eprintln!("Analyzing {} commits between {} and {}", commits.len(), old_ref, new_ref);
+
+    let mut commit_changes = Vec::new();
+    for commit in commits {
+        // Root commits and commits cut off by a shallow clone have no parent to diff against
+        let parent = match resolve_commit(&format!("{}^", commit), local_repo_path) {
+            Ok(parent) => parent,
+            Err(e) => {
+                eprintln!("Error resolving parent of commit {}: {}", commit, e);
+                errors.record("git_diff", None, Some(&commit), &e);
+                continue;
+            }
+        };
+
+        commit_changes.extend(analyze_commit(&parent, &commit, args, errors));
+    }
+
+    commit_changes
+}
+
+// Analyze a commit against each of its parents, in parent order
+pub fn analyze_parents(commit: &str, parents: &[String], args: &DiffArgs, errors: &ErrorLog) -> Vec<CommitChanges> {
+    eprintln!("Analyzing {} against each of its {} parents", commit, parents.len());
+
+    parents
+        .iter()
+        .filter_map(|parent| analyze_commit(parent, commit, args, errors))
+        .collect()
+}
+
+// Analyze the changes a commit made relative to one parent
+fn analyze_commit(parent: &str, commit: &str, args: &DiffArgs, errors: &ErrorLog) -> Option<CommitChanges> {
+    let local_repo_path = &args.local_repo_path;
+
+    let change_set = match detect_changes(parent, commit, local_repo_path, &args.detect_options(), errors) {
+        Ok(change_set) => change_set,
+        Err(e) => {
+            eprintln!("Error getting changed files for commit {}: {}", commit, e);
+            errors.record("git_diff", None, Some(commit), &e);
+            return None;
+        }
+    };
+
+    let changes = if change_set.rust_files.is_empty() {
+        Vec::new()
+    } else {
+        let options = &args.options;
+        let ctx = change_set.context(local_repo_path, parent, commit, options.content_source, options.lfs, errors);
+        process_rust_files(&change_set.rust_files, &ctx)
+    };
+
+    Some(CommitChanges {
+        commit: commit.to_string(),
+        parent: parent.to_string(),
+        changes,
+    })
+}
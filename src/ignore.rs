@@ -0,0 +1,183 @@
+// src/ignore.rs
+use proc_macro2::{Delimiter, Group, Punct, Spacing, TokenStream, TokenTree};
+use quote::ToTokens;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use syn::File;
+
+use crate::filters::glob_match;
+use crate::modules::short_name;
+use crate::types::FileASTData;
+
+// IgnoreRules is the `[ignore]` table of `cada.toml`: what both sides of every file are stripped
+// of before they're compared, so mechanical churn doesn't show up as changes
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IgnoreRules {
+    // Attributes by path, e.g. `instrument`, which also matches `tracing::instrument`
+    pub attributes: Vec<String>,
+    // Derived traits by path, e.g. `Debug`, taken out of `#[derive(..)]` lists
+    pub derives: Vec<String>,
+    // Globs over entity names, e.g. `generated::*` or `*.fmt`
+    pub names: Vec<String>,
+}
+
+static RULES: OnceLock<IgnoreRules> = OnceLock::new();
+
+// Ignore what these rules match for the rest of the process
+pub fn configure(rules: IgnoreRules) {
+    if RULES.set(rules).is_err() {
+        eprintln!("Warning: Ignore rules already configured");
+    }
+}
+
+// The configured rules, if any
+pub fn configured_rules() -> Option<&'static IgnoreRules> {
+    RULES.get()
+}
+
+// The parsed file without the attributes and derives the rules ignore, at any depth. The file is
+// parsed again from its stripped tokens, which keep their spans, so source locations still hold.
+pub fn strip_attributes(file: File) -> File {
+    let Some(rules) = RULES.get().filter(|rules| !rules.attributes.is_empty() || !rules.derives.is_empty()) else {
+        return file;
+    };
+    match syn::parse2(strip(file.to_token_stream(), rules)) {
+        Ok(stripped) => stripped,
+        Err(e) => {
+            eprintln!("Warning: Failed to strip ignored attributes: {}", e);
+            file
+        }
+    }
+}
+
+// Drop the entities whose names the rules ignore, before they're hashed and located. Patterns
+// match the name within the file or the name qualified with the module path.
+pub fn drop_ignored_names(ast_data: &mut FileASTData) {
+    let Some(rules) = RULES.get().filter(|rules| !rules.names.is_empty()) else {
+        return;
+    };
+    let module_path = ast_data.module_path.clone();
+    let keep = |name: &String| {
+        let short = short_name(module_path.as_deref(), name);
+        !rules.names.iter().any(|pattern| glob_match(pattern, name) || glob_match(pattern, short))
+    };
+
+    fn retain<V>(entities: &mut HashMap<String, V>, keep: impl Fn(&String) -> bool) {
+        entities.retain(|name, _| keep(name));
+    }
+    retain(&mut ast_data.functions, keep);
+    retain(&mut ast_data.types, keep);
+    retain(&mut ast_data.struct_fields, keep);
+    retain(&mut ast_data.enum_variants, keep);
+    retain(&mut ast_data.globals, keep);
+    retain(&mut ast_data.interfaces, keep);
+    retain(&mut ast_data.methods, keep);
+    retain(&mut ast_data.trait_methods, keep);
+    retain(&mut ast_data.trait_impls, keep);
+    retain(&mut ast_data.constants, keep);
+    retain(&mut ast_data.macros, keep);
+    retain(&mut ast_data.associated_consts, keep);
+    retain(&mut ast_data.associated_types, keep);
+}
+
+// Remove ignored `#[..]` and `#![..]` attributes, and ignored traits from `#[derive(..)]` lists
+fn strip(tokens: TokenStream, rules: &IgnoreRules) -> TokenStream {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let mut stripped = Vec::with_capacity(tokens.len());
+    let mut index = 0;
+    while index < tokens.len() {
+        let attribute = match &tokens[index..] {
+            [TokenTree::Punct(pound), TokenTree::Group(group), ..] if pound.as_char() == '#' => Some((1, group)),
+            [TokenTree::Punct(pound), TokenTree::Punct(bang), TokenTree::Group(group), ..]
+                if pound.as_char() == '#' && bang.as_char() == '!' =>
+            {
+                Some((2, group))
+            }
+            _ => None,
+        };
+        if let Some((prefix_len, group)) = attribute.filter(|(_, group)| group.delimiter() == Delimiter::Bracket) {
+            let path = attribute_path(group.stream());
+            if rules.attributes.iter().any(|rule| path_matches(&path, rule)) {
+                index += prefix_len + 1;
+                continue;
+            }
+            if path_matches(&path, "derive") && !rules.derives.is_empty() {
+                // A derive with nothing left to derive is dropped whole
+                if let Some(derive) = strip_derives(group, rules) {
+                    stripped.extend(tokens[index..index + prefix_len].iter().cloned());
+                    stripped.push(TokenTree::Group(derive));
+                }
+                index += prefix_len + 1;
+                continue;
+            }
+        }
+
+        stripped.push(match &tokens[index] {
+            TokenTree::Group(group) => {
+                let mut inner = Group::new(group.delimiter(), strip(group.stream(), rules));
+                inner.set_span(group.span());
+                TokenTree::Group(inner)
+            }
+            token => token.clone(),
+        });
+        index += 1;
+    }
+    stripped.into_iter().collect()
+}
+
+// A `derive(..)` attribute body without the ignored traits, or None when none are left
+fn strip_derives(attribute: &Group, rules: &IgnoreRules) -> Option<Group> {
+    let mut body = attribute.stream().into_iter();
+    let name = body.next()?;
+    let Some(TokenTree::Group(list)) = body.next() else {
+        return Some(attribute.clone());
+    };
+
+    let mut derives: Vec<Vec<TokenTree>> = vec![Vec::new()];
+    for token in list.stream() {
+        match token {
+            TokenTree::Punct(comma) if comma.as_char() == ',' => derives.push(Vec::new()),
+            token => derives.last_mut().unwrap().push(token),
+        }
+    }
+    derives.retain(|derive| {
+        let path = attribute_path(derive.iter().cloned().collect());
+        !derive.is_empty() && !rules.derives.iter().any(|rule| path_matches(&path, rule))
+    });
+    if derives.is_empty() {
+        return None;
+    }
+
+    let mut kept = Vec::new();
+    for (position, derive) in derives.into_iter().enumerate() {
+        if position > 0 {
+            kept.push(TokenTree::Punct(Punct::new(',', Spacing::Alone)));
+        }
+        kept.extend(derive);
+    }
+    let mut kept_list = Group::new(list.delimiter(), kept.into_iter().collect());
+    kept_list.set_span(list.span());
+    let mut stripped = Group::new(Delimiter::Bracket, [name, TokenTree::Group(kept_list)].into_iter().collect());
+    stripped.set_span(attribute.span());
+    Some(stripped)
+}
+
+// Path an attribute body or derive starts with, e.g. `tracing::instrument`
+fn attribute_path(tokens: TokenStream) -> String {
+    let mut path = String::new();
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => path.push_str(&ident.to_string()),
+            TokenTree::Punct(colon) if colon.as_char() == ':' => path.push(':'),
+            _ => break,
+        }
+    }
+    path
+}
+
+// Whether a path is the rule's path or ends with it, so `instrument` matches `tracing::instrument`
+fn path_matches(path: &str, rule: &str) -> bool {
+    path == rule || path.strip_suffix(rule).is_some_and(|prefix| prefix.ends_with("::"))
+}
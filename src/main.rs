@@ -1,120 +1,419 @@
 // src/main.rs
-use std::env;
+use clap::{CommandFactory, Parser};
+use std::io;
 use std::process;
 
+use crate::output::{ReportHeader, RunDetails};
+use crate::types::{ErrorLog, GranularChanges, PathChanges};
+
 mod ast_parser;
+mod attributes;
+mod blame;
+mod cfg;
+mod changeset;
+mod cli;
+mod config;
+mod content;
+mod deprecation;
 mod differ;
+mod error_surface;
+mod filters;
+mod git_cli;
+mod git_libgit2;
 mod git_ops;
 mod granular;
+mod history;
+mod ignore;
+mod manifest;
+mod merge;
+mod modules;
+mod normalize;
 mod output;
+mod policy;
+mod query;
+mod scoring;
+mod semver;
+mod state;
+mod statements;
 mod types;
 
 
 fn main() {
-    // Check arguments
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 5 {
-        println!("Usage: rust-ast-differ <repoUrl> <localRepoPath> <branchName> <currentCommit> [outputPath]");
+    let cli = cli::Cli::parse();
+
+    match (cli.command, cli.diff) {
+        (Some(cli::Command::Query(args)), _) => {
+            configure(&args.repo.options);
+            query::run_query(&args)
+        }
+        (Some(cli::Command::MergeDiff(args)), _) => {
+            configure(&args.repo.options);
+            merge::run_merge_diff(&args)
+        }
+        (Some(cli::Command::Completions { shell }), _) => {
+            let mut command = cli::Cli::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(shell, &mut command, name, &mut io::stdout());
+        }
+        (None, Some(args)) => {
+            configure(&args.options);
+            run_diff(&args)
+        }
+        // clap requires the diff arguments whenever no subcommand is given
+        (None, None) => unreachable!("missing diff arguments"),
+    }
+}
+
+// Set up git, cfg evaluation, normalization, scoring and the configuration file from the options
+// a diff run and the subcommands share, so every subcommand analyzes the same way
+fn configure(options: &cli::RepoOptions) {
+    git_ops::set_backend(options.git_backend);
+    git_ops::set_git_command(options.git_command());
+    if let Some(features) = &options.features {
+        cfg::set_features(features.clone());
+    }
+    normalize::configure(options.normalize.clone(), options.cosmetic);
+    scoring::configure(options.risk_weights.clone());
+    if let Err(e) = config::configure(options.config.as_deref()) {
+        eprintln!("Error: {}", e);
         process::exit(1);
     }
-    let repo_url = &args[1];
-    let local_repo_path = &args[2];
-    let branch_name = &args[3];
-    let current_commit = &args[4];
+}
 
-    // Set default output path if not provided
-    let output_path = if args.len() >= 6 {
-        args[5].clone()
-    } else {
-        "./".to_string()
-    };
+// Run the full diff pipeline and write the reports
+fn run_diff(args: &cli::DiffArgs) {
+    let repo_url = &args.repo_url;
+    let local_repo_path = &args.local_repo_path;
+    let branch_name = &args.branch_name;
+    let current_commit = &args.current_commit;
+    let output_path = args.output_path.clone();
+
+    // Bound the parallelism used for parsing, diffing and granular analysis
+    let jobs = args
+        .jobs
+        .or_else(|| std::thread::available_parallelism().ok())
+        .map(|n| n.get())
+        .unwrap_or(1);
+    if let Err(e) = rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global() {
+        eprintln!("Warning: Failed to configure {} worker threads: {}", jobs, e);
+    }
+
+    let errors = ErrorLog::new();
 
     // Clone repository if it doesn't exist
-    git_ops::clone_repo(repo_url, branch_name, local_repo_path);
+    let required_refs = [branch_name.as_str(), current_commit.as_str()];
+    if let Err(e) = git_ops::clone_repo(repo_url, &required_refs, local_repo_path, &args.options.clone_options()) {
+        eprintln!("Error: {}", e);
+        errors.record("clone", None, None, &e);
+        exit_with_errors(args, &errors);
+    }
+
+    // Bare repositories can only be read through the object database
+    if let Err(e) = content::ensure_supported(args.options.content_source, local_repo_path) {
+        eprintln!("Error: {}", e);
+        errors.record("checkout", None, None, &e);
+        exit_with_errors(args, &errors);
+    }
+
+    // Resolve both refs to commits up front so every later step sees the same revisions
+    let branch_name = resolve_or_exit(branch_name, args, &errors);
+    let current_commit = resolve_or_exit(current_commit, args, &errors);
+    eprintln!("Comparing {} ({}) with {} ({})", args.branch_name, branch_name, args.current_commit, current_commit);
 
-    // Get changed files between commits
-    let changed_files = match git_ops::get_changed_files(branch_name, local_repo_path) {
-        Ok(files) => files,
+    // Diffing a merge commit against the base ref mixes the changes of all its parents
+    let parents = match git_ops::get_parents(&current_commit, local_repo_path) {
+        Ok(parents) => parents,
         Err(e) => {
-            println!("Error getting changed files: {}", e);
-            process::exit(1);
+            eprintln!("Warning: {}", e);
+            errors.record("git_diff", None, Some(&current_commit), &e);
+            Vec::new()
         }
     };
-
-    // Get lists of new and deleted files
-    let new_files = match git_ops::get_new_files(branch_name, current_commit, local_repo_path) {
-        Ok(files) => files,
+    let branch_name = match comparison_parent(&parents, args) {
+        Ok(Some(parent)) => {
+            eprintln!("Comparing {} against its parent {}", current_commit, parent);
+            parent.clone()
+        }
+        Ok(None) => branch_name,
         Err(e) => {
-            println!("Warning: Error getting new files: {}", e);
-            vec![]
+            eprintln!("Error: {}", e);
+            errors.record("git_diff", None, Some(&current_commit), &e);
+            exit_with_errors(args, &errors);
         }
     };
-    
-    let deleted_files = match git_ops::get_deleted_files(branch_name, current_commit, local_repo_path) {
-        Ok(files) => files,
-        Err(e) => {
-            println!("Warning: Error getting    deleted files: {}", e);
-            vec![]
+
+    // Diff against the common ancestor so changes that landed on the branch meanwhile are left out
+    let branch_name = if args.merge_base {
+        match git_ops::get_merge_base(&branch_name, &current_commit, local_repo_path) {
+            Ok(base) => base,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                errors.record("git_diff", None, Some(&branch_name), &e);
+                exit_with_errors(args, &errors);
+            }
         }
+    } else {
+        branch_name
     };
+    let branch_name = &branch_name;
+    let current_commit = &current_commit;
+    let mut header = report_header(args, Some((branch_name, current_commit)), &errors);
 
-    // Create maps for quick lookup
-    let mut new_file_map = std::collections::HashMap::new();
-    let mut deleted_file_map = std::collections::HashMap::new();
+    let change_set = match changeset::detect_changes(
+        branch_name,
+        current_commit,
+        local_repo_path,
+        &args.detect_options(),
+        &errors,
+    ) {
+        Ok(change_set) => change_set,
+        Err(e) => {
+            eprintln!("Error getting changed files: {}", e);
+            errors.record("git_diff", None, Some(current_commit), &e);
+            exit_with_errors(args, &errors);
+        }
+    };
+    let rust_files = &change_set.rust_files;
 
-    for file in &new_files {
-        new_file_map.insert(file.clone(), true);
-        println!("New file detected: {}", file);
+    // Build scripts are flagged up front since their effects don't show in the code diff
+    header.build_script_changes = change_set.build_script_changes();
+    for change in &header.build_script_changes {
+        let file = &change.file;
+        eprintln!("Warning: Build script {} was {}; generated code and cfgs may have changed", file, change.status);
     }
 
-    for file in &deleted_files {
-        deleted_file_map.insert(file.clone(), true);
-        println!("Deleted file detected: {}", file);
+    // List the candidate files without parsing or checking anything out
+    if args.dry_run {
+        for file in rust_files {
+            println!("{}", change_set.describe(file));
+        }
+        for skipped in &change_set.skipped_files {
+            println!("skipped\t{}\t{}", skipped.file, skipped.reason);
+        }
+        eprintln!("Dry run: {} Rust files would be analyzed", rust_files.len());
+        process::exit(0);
     }
 
-    println!("Modified files: {:?}", changed_files);
+    // Manifests are read straight from the object database, whatever the content source
+    let manifest_changes =
+        manifest::find_manifest_changes(&change_set, branch_name, current_commit, local_repo_path, &errors);
 
-    // Filter only Rust files
-    let rust_files: Vec<String> = changed_files
-        .iter()
-        .filter(|file| file.ends_with(".rs"))
-        .cloned()
-        .collect();
+    // Partial clones fetch the blobs that will be read in one batch rather than on each read
+    git_ops::prefetch_blobs(
+        &[branch_name, current_commit],
+        &change_set.paths_to_read(),
+        local_repo_path,
+        &args.options.clone_options(),
+    );
+
+    // Checked out refs and stashed modifications are undone once the analysis finishes, fails or panics
+    let options = &args.options;
+    let checkout_guard = match content::prepare_checkout(options.content_source, options.dirty_tree_policy(), local_repo_path) {
+        Ok(guard) => guard,
+        Err(e @ git_ops::GitError::DirtyTree { .. }) => {
+            eprintln!("Error: {}; rerun with --stash or --force, or use --content-source objects", e);
+            errors.record("dirty_tree", None, None, &e);
+            exit_with_errors(args, &errors);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            errors.record("checkout", None, None, &e);
+            exit_with_errors(args, &errors);
+        }
+    };
+
+    // Analyze each commit of the range on its own for a per-commit timeline
+    let commit_changes = if args.per_commit {
+        Some(history::analyze_commits(branch_name, current_commit, args, &errors))
+    } else {
+        None
+    };
+
+    // Analyze the commit against every parent so each side of a merge can be reviewed
+    let parent_changes = if args.all_parents {
+        Some(history::analyze_parents(current_commit, &parents, args, &errors))
+    } else {
+        None
+    };
+
+    let details = RunDetails {
+        commit_changes: commit_changes.as_deref(),
+        parent_changes: parent_changes.as_deref(),
+        skipped_files: &change_set.skipped_files,
+        path_changes: &change_set.path_changes,
+        manifest_changes: &manifest_changes,
+    };
 
     if rust_files.is_empty() {
-        println!("No Rust files were modified between the specified commits");
+        drop(checkout_guard);
+        eprintln!("No Rust files were modified between the specified commits");
+        if args.stdout {
+            output::write_stdout_report(&[], &GranularChanges::new(), &details, &header, &errors.snapshot());
+        } else {
+            output::create_run_detail_files(&details, &header, &output_path);
+            output::create_errors_file(&errors.snapshot(), &header, &output_path);
+        }
         process::exit(0);
     }
 
-    // First checkout the branch to ensure we're starting from the right point
-    if let Err(e) = git_ops::checkout_branch(branch_name, local_repo_path) {
-        println!("Error checking out branch {}: {}", branch_name, e);
-        println!("Trying alternative checkout approaches...");
-        
-        // Try to checkout the commit directly
-        if let Err(e) = git_ops::checkout_commit(&format!("{}^{{commit}}", branch_name), local_repo_path) {
-            println!("Error checking out commit directly: {}", e);
-            process::exit(1);
+    // Reuse results of a previous run for files whose blobs haven't changed
+    let mut run_state = None;
+    let mut pending_files = rust_files.clone();
+    if let Some(state_dir) = &args.state_dir {
+        let settings = state::settings_fingerprint(args);
+        match state::RunState::load(state_dir, branch_name, current_commit, rust_files, &settings, local_repo_path) {
+            Ok(loaded) => {
+                pending_files = loaded.pending_files(rust_files);
+                eprintln!(
+                    "Resuming from {}: {} of {} files already analyzed",
+                    state_dir,
+                    rust_files.len() - pending_files.len(),
+                    rust_files.len()
+                );
+                run_state = Some(loaded);
+            }
+            Err(e) => {
+                eprintln!("Warning: Failed to load run state: {}", e);
+                errors.record("state", None, None, &e);
+            }
         }
-        
-        println!("Successfully checked out commit directly.");
     }
 
-    // Process all Rust files to find changes
-    let all_changes = differ::process_rust_files(
-        &rust_files,
-        local_repo_path,
-        branch_name,
-        current_commit,
-        &new_file_map,
-        &deleted_file_map,
-    );
+    let mut all_changes = Vec::new();
+    let mut granular_changes = GranularChanges::new();
+
+    if !pending_files.is_empty() {
+        let ctx = change_set.context(
+            local_repo_path,
+            branch_name,
+            current_commit,
+            args.options.content_source,
+            args.options.lfs,
+            &errors,
+        );
+        content::prepare_sparse_checkout(&pending_files, args.options.sparse, &ctx);
+
+        // Process all Rust files to find changes
+        all_changes = differ::process_rust_files(&pending_files, &ctx);
+        if args.blame {
+            blame::annotate_changes(&mut all_changes, &ctx);
+        }
+
+        // Get granular changes for functions
+        granular_changes = granular::get_granular_change_for_functions(&pending_files, &ctx);
+    }
+    drop(checkout_guard);
+
+    let errors = errors.snapshot();
+    if !errors.is_empty() {
+        eprintln!("Analysis finished with {} errors", errors.len());
+    }
+
+    if let Some(run_state) = &mut run_state {
+        run_state.record_results(&pending_files, &all_changes, &granular_changes, &errors);
+        if let Err(e) = run_state.save() {
+            eprintln!("Warning: {}", e);
+        }
+        (all_changes, granular_changes) =
+            run_state.merge_results(rust_files, all_changes, granular_changes);
+    }
+
+    // Added dependencies are linked to the code that starts using them once all files are diffed
+    let linked_manifest_changes = manifest::link_new_dependencies(&manifest_changes, &all_changes);
+    let details = RunDetails {
+        manifest_changes: &linked_manifest_changes,
+        ..details
+    };
+
+    if args.stdout {
+        output::write_stdout_report(&all_changes, &granular_changes, &details, &header, &errors);
+        eprintln!("AST diff complete.");
+    } else {
+        // Create output files with the changes
+        output::create_output_files(&all_changes, &header, &output_path);
+        output::create_granular_file(&granular_changes, &header, &output_path);
+        output::create_run_detail_files(&details, &header, &output_path);
+        output::create_errors_file(&errors, &header, &output_path);
+        eprintln!("AST diff complete. Check output files for details.");
+    }
+
+    // Gate the exit code on the requested change categories
+    let triggered = policy::triggered_categories(&all_changes, &args.fail_on);
+    if !triggered.is_empty() {
+        let names: Vec<String> = triggered.iter().map(|c| c.name()).collect();
+        eprintln!("Failing due to change categories: {}", names.join(", "));
+        process::exit(policy::POLICY_FAILURE_EXIT_CODE);
+    }
+}
+
+// Pick the parent of the current commit to compare against: the one chosen with --parent, or
+// the first parent of a merge commit unless --merge-base was given. Other commits keep the base ref.
+fn comparison_parent<'p>(parents: &'p [String], args: &cli::DiffArgs) -> Result<Option<&'p String>, String> {
+    match args.parent {
+        Some(n) => parents.get(n.get() - 1).map(Some).ok_or_else(|| {
+            format!(
+                "{} has {} parents, so there is no parent {}",
+                args.current_commit,
+                parents.len(),
+                n
+            )
+        }),
+        None if parents.len() > 1 && !args.merge_base => Ok(parents.first()),
+        None => Ok(None),
+    }
+}
 
-    // Create output files with the changes
-    output::create_output_files(&all_changes, &output_path);
+// Resolve a ref to a commit, stopping the run if it names nothing
+fn resolve_or_exit(reference: &str, args: &cli::DiffArgs, errors: &ErrorLog) -> String {
+    match git_ops::resolve_ref(reference, &args.local_repo_path) {
+        Ok(commit) => commit,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            errors.record("git_diff", None, Some(reference), &e);
+            exit_with_errors(args, errors);
+        }
+    }
+}
+
+// Describe the repository and the compared commits for the header of every report.
+// Metadata that can't be read is recorded and left out rather than failing the run.
+fn report_header(args: &cli::DiffArgs, commits: Option<(&str, &str)>, errors: &ErrorLog) -> ReportHeader {
+    let metadata = |commit: &str| match git_ops::get_commit_metadata(commit, &args.local_repo_path) {
+        Ok(metadata) => Some(metadata),
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            errors.record("metadata", None, Some(commit), &e);
+            None
+        }
+    };
 
-    // Get granular changes for functions
-    granular::get_granular_change_for_functions(&rust_files, local_repo_path, &output_path);
+    ReportHeader {
+        repo_url: args.repo_url.clone(),
+        base_ref: args.branch_name.clone(),
+        target_ref: args.current_commit.clone(),
+        base: commits.and_then(|(base, _)| metadata(base)),
+        target: commits.and_then(|(_, target)| metadata(target)),
+        build_script_changes: Vec::new(),
+    }
+}
 
-    println!("AST diff complete. Check output files for details.");
-}
\ No newline at end of file
+// Emit the errors collected so far and stop with a failure status
+fn exit_with_errors(args: &cli::DiffArgs, errors: &ErrorLog) -> ! {
+    // Runs that stop early only report the requested refs
+    let header = report_header(args, None, errors);
+    let errors = errors.snapshot();
+    if args.stdout {
+        let path_changes = PathChanges::default();
+        let details = RunDetails {
+            commit_changes: None,
+            parent_changes: None,
+            skipped_files: &[],
+            path_changes: &path_changes,
+            manifest_changes: &[],
+        };
+        output::write_stdout_report(&[], &GranularChanges::new(), &details, &header, &errors);
+    } else {
+        output::create_errors_file(&errors, &header, &args.output_path);
+    }
+    process::exit(1);
+}
@@ -0,0 +1,247 @@
+// src/manifest.rs
+use std::collections::BTreeSet;
+use toml::{Table, Value};
+
+use crate::ast_parser::token_sequence;
+use crate::changeset::ChangeSet;
+use crate::git_ops;
+use crate::types::{DependencyChange, DependencyUse, DetailedChanges, ErrorLog, FeatureChange, ManifestChanges};
+
+// Tables dependencies are listed in, at the top level and under each `target.<cfg>`
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+// Compare the changed Cargo.toml manifests of a change set at both refs. A manifest missing on
+// one side is compared against an empty one; one that doesn't parse is recorded and skipped.
+pub fn find_manifest_changes(
+    change_set: &ChangeSet,
+    old_ref: &str,
+    new_ref: &str,
+    local_repo_path: &str,
+    errors: &ErrorLog,
+) -> Vec<ManifestChanges> {
+    let read = |file: &str, reference: &str, missing: bool| -> Option<Table> {
+        if missing {
+            return Some(Table::new());
+        }
+        let parsed = git_ops::read_file_at(reference, file, local_repo_path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| content.parse::<Table>().map_err(|e| format!("Invalid manifest {}: {}", file, e)));
+        match parsed {
+            Ok(table) => Some(table),
+            Err(e) => {
+                eprintln!("Warning: {}", e);
+                errors.record("manifest", Some(file), Some(reference), &e);
+                None
+            }
+        }
+    };
+
+    let mut all_changes = Vec::new();
+    for file in &change_set.manifest_files {
+        let Some(old) = read(file, old_ref, change_set.new_file_map.contains_key(file)) else {
+            continue;
+        };
+        let Some(new) = read(file, new_ref, change_set.deleted_file_map.contains_key(file)) else {
+            continue;
+        };
+
+        let changes = ManifestChanges {
+            file: file.clone(),
+            dependency_changes: dependency_changes(&old, &new),
+            feature_changes: feature_changes(&old, &new),
+        };
+        if !changes.dependency_changes.is_empty() || !changes.feature_changes.is_empty() {
+            eprintln!(
+                "Manifest change detected: {} ({} dependencies, {} features)",
+                file,
+                changes.dependency_changes.len(),
+                changes.feature_changes.len()
+            );
+            all_changes.push(changes);
+        }
+    }
+    all_changes
+}
+
+// Every dependency table of a manifest by its name, including the workspace's shared dependencies
+fn dependency_tables(manifest: &Table) -> Vec<(String, &Table)> {
+    let mut tables = Vec::new();
+    for name in DEPENDENCY_TABLES {
+        // Cargo still accepts the old underscored spelling
+        let table = manifest.get(name).or_else(|| manifest.get(&name.replace('-', "_")));
+        if let Some(Value::Table(table)) = table {
+            tables.push((name.to_string(), table));
+        }
+    }
+    if let Some(Value::Table(targets)) = manifest.get("target") {
+        for (target, target_tables) in targets {
+            let Value::Table(target_tables) = target_tables else {
+                continue;
+            };
+            for name in DEPENDENCY_TABLES {
+                let table = target_tables.get(name).or_else(|| target_tables.get(&name.replace('-', "_")));
+                if let Some(Value::Table(table)) = table {
+                    tables.push((format!("target.{}.{}", target, name), table));
+                }
+            }
+        }
+    }
+    if let Some(Value::Table(table)) = manifest.get("workspace").and_then(|workspace| workspace.get("dependencies")) {
+        tables.push(("workspace.dependencies".to_string(), table));
+    }
+    tables
+}
+
+// Added, removed and changed dependencies, table by table
+fn dependency_changes(old: &Table, new: &Table) -> Vec<DependencyChange> {
+    let old_tables = dependency_tables(old);
+    let new_tables = dependency_tables(new);
+    let empty = Table::new();
+    fn find<'a>(tables: &[(String, &'a Table)], name: &str) -> Option<&'a Table> {
+        tables.iter().find(|(table, _)| table == name).map(|(_, table)| *table)
+    }
+
+    let table_names: BTreeSet<&String> = old_tables.iter().chain(&new_tables).map(|(name, _)| name).collect();
+    let mut changes = Vec::new();
+    for table in table_names {
+        let old_deps = find(&old_tables, table).unwrap_or(&empty);
+        let new_deps = find(&new_tables, table).unwrap_or(&empty);
+        let names: BTreeSet<&String> = old_deps.keys().chain(new_deps.keys()).collect();
+
+        for name in names {
+            let (old_spec, new_spec) = (old_deps.get(name), new_deps.get(name));
+            let change = match (old_spec, new_spec) {
+                (None, Some(_)) => "added",
+                (Some(_), None) => "removed",
+                (Some(old_spec), Some(new_spec)) if old_spec == new_spec => continue,
+                (Some(old_spec), Some(new_spec)) if version(old_spec) != version(new_spec) => "version_changed",
+                (Some(_), Some(_)) => "modified",
+                (None, None) => continue,
+            };
+            changes.push(DependencyChange {
+                table: table.clone(),
+                name: name.clone(),
+                change: change.to_string(),
+                old_version: old_spec.and_then(version),
+                new_version: new_spec.and_then(version),
+                old_spec: old_spec.map(|spec| spec.to_string()),
+                new_spec: new_spec.map(|spec| spec.to_string()),
+                used_by: Vec::new(),
+            });
+        }
+    }
+    changes
+}
+
+// Version requirement of a dependency spec, given as a string or as the `version` key of a table
+fn version(spec: &Value) -> Option<String> {
+    match spec {
+        Value::String(version) => Some(version.clone()),
+        Value::Table(table) => table.get("version").and_then(Value::as_str).map(|version| version.to_string()),
+        _ => None,
+    }
+}
+
+// Added, removed and changed features, with the members each one gained and lost
+fn feature_changes(old: &Table, new: &Table) -> Vec<FeatureChange> {
+    let features = |manifest: &Table| manifest.get("features").and_then(Value::as_table).cloned().unwrap_or_default();
+    let (old_features, new_features) = (features(old), features(new));
+    let members = |features: &Table, name: &str| -> Vec<String> {
+        features
+            .get(name)
+            .and_then(Value::as_array)
+            .map(|members| members.iter().filter_map(Value::as_str).map(|member| member.to_string()).collect())
+            .unwrap_or_default()
+    };
+
+    let names: BTreeSet<&String> = old_features.keys().chain(new_features.keys()).collect();
+    let mut changes = Vec::new();
+    for name in names {
+        let change = match (old_features.get(name), new_features.get(name)) {
+            (None, Some(_)) => "added",
+            (Some(_), None) => "removed",
+            (Some(old_members), Some(new_members)) if old_members != new_members => "modified",
+            _ => continue,
+        };
+        let (old_members, new_members) = (members(&old_features, name), members(&new_features, name));
+        changes.push(FeatureChange {
+            name: name.clone(),
+            change: change.to_string(),
+            added_members: new_members.iter().filter(|member| !old_members.contains(member)).cloned().collect(),
+            removed_members: old_members.iter().filter(|member| !new_members.contains(member)).cloned().collect(),
+        });
+    }
+    changes
+}
+
+// Link each added dependency to the changed functions, methods and trait methods of its crate
+// that start using it, through a path starting with the dependency or a name imported from it
+pub fn link_new_dependencies(
+    manifest_changes: &[ManifestChanges],
+    all_changes: &[DetailedChanges],
+) -> Vec<ManifestChanges> {
+    let mut manifest_changes = manifest_changes.to_vec();
+    for manifest in &mut manifest_changes {
+        // Dependencies of a manifest are used by the files next to and below it
+        let crate_dir = manifest.file.strip_suffix("Cargo.toml").unwrap_or_default().to_string();
+        for dependency in &mut manifest.dependency_changes {
+            if dependency.change != "added" {
+                continue;
+            }
+            // Code refers to a dependency by its key, with dashes as underscores
+            let crate_ident = dependency.name.replace('-', "_");
+
+            for c in all_changes.iter().filter(|c| c.module_name.starts_with(&crate_dir)) {
+                let imported = imported_names(&c.added_imports, &crate_ident);
+                let categories = [
+                    ("function", &c.added_functions, &c.modified_functions),
+                    ("method", &c.added_methods, &c.modified_methods),
+                    ("trait_method", &c.added_trait_methods, &c.modified_trait_methods),
+                ];
+                for (kind, added, modified) in categories {
+                    let starts_using = added
+                        .iter()
+                        .filter(|entry| uses(&entry[1], &crate_ident, &imported))
+                        .chain(modified.iter().filter(|entry| {
+                            uses(&entry[2], &crate_ident, &imported) && !uses(&entry[1], &crate_ident, &imported)
+                        }));
+                    for entry in starts_using {
+                        dependency.used_by.push(DependencyUse {
+                            file: c.module_name.clone(),
+                            kind: kind.to_string(),
+                            name: entry[0].clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    manifest_changes
+}
+
+// Names that newly added imports bring in from a crate, e.g. `to_string` for `serde_json::to_string`
+fn imported_names(added_imports: &[String], crate_ident: &str) -> Vec<String> {
+    added_imports
+        .iter()
+        .filter_map(|import| import.trim_start_matches("::").strip_prefix(crate_ident)?.strip_prefix("::"))
+        .filter_map(|path| {
+            let name = match path.split_once(" as ") {
+                Some((_, alias)) => alias,
+                None => path.rsplit("::").next()?,
+            };
+            (name != "*" && name != "_").then(|| name.to_string())
+        })
+        .collect()
+}
+
+// Whether code refers to a crate through a path starting with it, or to a name imported from it
+fn uses(code: &str, crate_ident: &str, imported: &[String]) -> bool {
+    let Ok(tokens) = code.parse() else {
+        return false;
+    };
+    let sequence = token_sequence(tokens);
+    sequence.iter().enumerate().any(|(index, token)| {
+        (token == crate_ident && sequence.get(index + 1..index + 3).is_some_and(|next| next == [":", ":"]))
+            || imported.contains(token)
+    })
+}
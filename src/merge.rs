@@ -0,0 +1,172 @@
+// src/merge.rs
+use proc_macro2::TokenStream;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::process;
+
+use crate::cli::MergeDiffArgs;
+use crate::query::{diff_refs, prepare_repo, resolve};
+use crate::types::{AnalysisError, DetailedChanges, ErrorLog};
+
+// SideChange is how one side of a merge changed an entity relative to the base
+#[derive(Debug, Clone, Serialize)]
+pub struct SideChange {
+    pub verdict: String,
+    pub code: Option<String>,
+    // `signature`, `body` or `both` for modified functions, methods and trait methods
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_kind: Option<String>,
+}
+
+// MergeConflict is an entity both sides changed differently, a semantic conflict candidate even
+// when the textual edits don't overlap
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeConflict {
+    pub file: String,
+    pub kind: String,
+    pub name: String,
+    pub base_code: Option<String>,
+    pub ours: SideChange,
+    pub theirs: SideChange,
+}
+
+// MergeDiffResult lists the conflict candidates of a three-way comparison
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeDiffResult {
+    pub base: String,
+    pub ours: String,
+    pub theirs: String,
+    // Number of entities changed on each side
+    pub ours_changed: usize,
+    pub theirs_changed: usize,
+    pub conflicts: Vec<MergeConflict>,
+    pub errors: Vec<AnalysisError>,
+}
+
+// Run the merge-diff subcommand and print the conflict candidates as JSON to stdout
+pub fn run_merge_diff(args: &MergeDiffArgs) {
+    let repo = &args.repo;
+    prepare_repo(repo, &[&args.base, &args.ours, &args.theirs]);
+
+    // Resolve all refs to commits up front so every later step sees the same revisions
+    let base = resolve(repo, &args.base);
+    let ours = resolve(repo, &args.ours);
+    let theirs = resolve(repo, &args.theirs);
+
+    let errors = ErrorLog::new();
+    let ours_changes = diff_refs(repo, &base, &ours, &args.files, &errors);
+    let theirs_changes = diff_refs(repo, &base, &theirs, &args.files, &errors);
+
+    let mut result = find_conflicts(&ours_changes, &theirs_changes, &base, &ours, &theirs);
+    result.errors = errors.snapshot();
+
+    match serde_json::to_string_pretty(&result) {
+        Ok(result_json) => println!("{}", result_json),
+        Err(e) => {
+            eprintln!("Error marshaling merge diff result: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// Pair the entities changed on both sides, keeping those the sides changed differently
+pub fn find_conflicts(
+    ours_changes: &[DetailedChanges],
+    theirs_changes: &[DetailedChanges],
+    base: &str,
+    ours: &str,
+    theirs: &str,
+) -> MergeDiffResult {
+    let ours_entities = changed_entities(ours_changes);
+    let theirs_entities = changed_entities(theirs_changes);
+
+    let mut conflicts = Vec::new();
+    for (key, (base_code, ours_change)) in &ours_entities {
+        let Some((_, theirs_change)) = theirs_entities.get(key) else {
+            continue;
+        };
+        // Both sides making the same change merges cleanly
+        if ours_change.verdict == theirs_change.verdict && same_code(&ours_change.code, &theirs_change.code) {
+            continue;
+        }
+        let (file, kind, name) = key.clone();
+        conflicts.push(MergeConflict {
+            file,
+            kind,
+            name,
+            base_code: base_code.clone(),
+            ours: ours_change.clone(),
+            theirs: theirs_change.clone(),
+        });
+    }
+
+    MergeDiffResult {
+        base: base.to_string(),
+        ours: ours.to_string(),
+        theirs: theirs.to_string(),
+        ours_changed: ours_entities.len(),
+        theirs_changed: theirs_entities.len(),
+        conflicts,
+        errors: Vec::new(),
+    }
+}
+
+// Every added, modified and deleted entity by file, kind and name, with its base code and change
+type ChangedEntities = BTreeMap<(String, String, String), (Option<String>, SideChange)>;
+
+fn changed_entities(all_changes: &[DetailedChanges]) -> ChangedEntities {
+    let mut entities = ChangedEntities::new();
+    for c in all_changes {
+        let categories = [
+            ("function", &c.added_functions, &c.modified_functions, &c.deleted_functions),
+            ("type", &c.added_types, &c.modified_types, &c.deleted_types),
+            ("interface", &c.added_interfaces, &c.modified_interfaces, &c.deleted_interfaces),
+            ("method", &c.added_methods, &c.modified_methods, &c.deleted_methods),
+            ("trait_method", &c.added_trait_methods, &c.modified_trait_methods, &c.deleted_trait_methods),
+            ("constant", &c.added_constants, &c.modified_constants, &c.deleted_constants),
+            ("macro", &c.added_macros, &c.modified_macros, &c.deleted_macros),
+            (
+                "associated_const",
+                &c.added_associated_consts,
+                &c.modified_associated_consts,
+                &c.deleted_associated_consts,
+            ),
+            ("associated_type", &c.added_associated_types, &c.modified_associated_types, &c.deleted_associated_types),
+            ("trait_impl", &c.added_trait_impls, &Vec::new(), &c.deleted_trait_impls),
+        ];
+
+        let key = |kind: &str, name: &str| (c.module_name.clone(), kind.to_string(), name.to_string());
+        for (kind, added, modified, deleted) in categories {
+            for item in added {
+                let change = side_change("added", Some(&item[1]), None);
+                entities.insert(key(kind, &item[0]), (None, change));
+            }
+            for item in modified {
+                let change = side_change("modified", Some(&item[2]), item.get(3));
+                entities.insert(key(kind, &item[0]), (Some(item[1].clone()), change));
+            }
+            for item in deleted {
+                let change = side_change("deleted", None, None);
+                entities.insert(key(kind, &item[0]), (Some(item[1].clone()), change));
+            }
+        }
+    }
+    entities
+}
+
+fn side_change(verdict: &str, code: Option<&String>, change_kind: Option<&String>) -> SideChange {
+    SideChange {
+        verdict: verdict.to_string(),
+        code: code.cloned(),
+        change_kind: change_kind.cloned(),
+    }
+}
+
+// Whether two versions of code have the same tokens, so formatting alone doesn't set them apart
+fn same_code(ours: &Option<String>, theirs: &Option<String>) -> bool {
+    let tokens = |code: &Option<String>| {
+        code.as_ref()
+            .map(|code| code.parse::<TokenStream>().map(|tokens| tokens.to_string()).unwrap_or_else(|_| code.clone()))
+    };
+    tokens(ours) == tokens(theirs)
+}
@@ -0,0 +1,361 @@
+// src/modules.rs
+use std::collections::{HashMap, HashSet};
+use syn::{Expr, Item, Lit};
+
+use crate::git_ops::read_file_at;
+use crate::types::AnalysisContext;
+
+// Directories whose top-level files are crate roots of their own, next to src/lib.rs and src/main.rs
+const TARGET_DIRS: &[&str] = &["src/bin", "tests", "examples", "benches"];
+
+// ModulePath locates a file within the crates of the repository: the name other crates know its
+// crate by, when its manifest gives one, and its module path within the crate
+#[derive(Debug, Clone)]
+pub struct ModulePath {
+    pub crate_name: Option<String>,
+    pub path: String,
+}
+
+// Resolve the crate and crate-relative module path of each file, at the ref it's read from
+pub fn resolve_module_paths(rust_files: &[String], ctx: &AnalysisContext) -> HashMap<String, ModulePath> {
+    let (deleted_files, current_files): (Vec<String>, Vec<String>) = rust_files
+        .iter()
+        .cloned()
+        .partition(|file| ctx.deleted_file_map.contains_key(file));
+
+    let mut module_paths = ModuleResolver::new(ctx.current_commit, ctx.local_repo_path).resolve(&current_files);
+    module_paths.extend(ModuleResolver::new(ctx.branch_name, ctx.local_repo_path).resolve(&deleted_files));
+    module_paths
+}
+
+// ModuleResolver finds the module paths of Rust files at one ref by following `mod`
+// declarations down from the roots of the crate each file belongs to
+pub struct ModuleResolver<'a> {
+    reference: &'a str,
+    local_repo_path: &'a str,
+    // Parsed files, or None when a file is missing or doesn't parse
+    sources: HashMap<String, Option<syn::File>>,
+    // The Cargo.toml each directory holds, if any
+    manifests: HashMap<String, Option<String>>,
+}
+
+impl<'a> ModuleResolver<'a> {
+    pub fn new(reference: &'a str, local_repo_path: &'a str) -> Self {
+        ModuleResolver {
+            reference,
+            local_repo_path,
+            sources: HashMap::new(),
+            manifests: HashMap::new(),
+        }
+    }
+
+    // Resolve the given files to paths such as `crate::net::tcp`, leaving out files
+    // that no crate root declares
+    pub fn resolve(&mut self, files: &[String]) -> HashMap<String, ModulePath> {
+        let mut module_paths = HashMap::new();
+
+        for file in files {
+            if module_paths.contains_key(file) {
+                continue;
+            }
+            let Some(crate_dir) = self.crate_dir(file) else {
+                continue;
+            };
+
+            // One walk resolves every requested file it passes, not just this one
+            for root in crate_roots(&crate_dir, file) {
+                let mut walk = Walk {
+                    targets: files,
+                    crate_name: self.crate_name(&crate_dir, &root),
+                    module_paths: &mut module_paths,
+                    visited: HashSet::new(),
+                };
+                self.walk_file(&root, "crate", &mut walk);
+                if module_paths.contains_key(file) {
+                    break;
+                }
+            }
+            if !module_paths.contains_key(file) {
+                eprintln!("No crate root at {} declares module file {}", self.reference, file);
+            }
+        }
+
+        module_paths
+    }
+
+    // Find the directory of the nearest Cargo.toml above a file
+    fn crate_dir(&mut self, file: &str) -> Option<String> {
+        let mut dir = parent_dir(file);
+        loop {
+            if self.manifest(dir).is_some() {
+                return Some(dir.to_string());
+            }
+            if dir.is_empty() {
+                return None;
+            }
+            dir = parent_dir(dir);
+        }
+    }
+
+    // Read the Cargo.toml of a directory once
+    fn manifest(&mut self, dir: &str) -> Option<&String> {
+        if !self.manifests.contains_key(dir) {
+            let manifest = read_file_at(self.reference, &join(dir, "Cargo.toml"), self.local_repo_path).ok();
+            self.manifests.insert(dir.to_string(), manifest);
+        }
+        self.manifests.get(dir)?.as_ref()
+    }
+
+    // Name a crate root is known by, as Cargo names it: the package name for the library and
+    // the main binary, the file or directory name for the other targets, with dashes as
+    // underscores. None when the manifest doesn't name the package.
+    fn crate_name(&mut self, crate_dir: &str, root: &str) -> Option<String> {
+        let relative = root.strip_prefix(crate_dir).map(|rest| rest.trim_start_matches('/')).unwrap_or(root);
+        let name = match relative {
+            "build.rs" => "build_script_build".to_string(),
+            "src/lib.rs" | "src/main.rs" => package_name(self.manifest(crate_dir)?)?,
+            target => {
+                let target = target.trim_end_matches("/main.rs");
+                target.rsplit('/').next()?.trim_end_matches(".rs").to_string()
+            }
+        };
+        Some(name.replace('-', "_"))
+    }
+
+    // Record a requested module file and walk the modules it declares
+    fn walk_file(&mut self, file: &str, module_path: &str, walk: &mut Walk) {
+        if !walk.visited.insert(file.to_string()) || !self.load(file) {
+            return;
+        }
+        if walk.targets.iter().any(|target| target == file) {
+            let location = ModulePath {
+                crate_name: walk.crate_name.clone(),
+                path: module_path.to_string(),
+            };
+            walk.module_paths.insert(file.to_string(), location);
+        }
+
+        // Take the items out of the cache while walking them, since walking loads more files
+        let Some(Some(source)) = self.sources.remove(file) else {
+            return;
+        };
+        let dir = parent_dir(file).to_string();
+        self.walk_items(&source.items, module_path, &module_dir(file), &dir, walk);
+        self.sources.insert(file.to_string(), Some(source));
+    }
+
+    // Walk the `mod` items of a file or inline module. `child_dir` holds the files of
+    // child modules, and `attr_dir` is what `#[path]` attributes are relative to.
+    fn walk_items(&mut self, items: &[Item], module_path: &str, child_dir: &str, attr_dir: &str, walk: &mut Walk) {
+        for item in items {
+            let Item::Mod(module) = item else {
+                continue;
+            };
+            let name = module.ident.to_string();
+            let child_path = format!("{}::{}", module_path, name);
+            let path_attr = path_attribute(&module.attrs);
+
+            if let Some((_, module_items)) = &module.content {
+                // Inline modules nest their children's files one directory deeper
+                let nested_dir = join(child_dir, path_attr.as_deref().unwrap_or(&name));
+                self.walk_items(module_items, &child_path, &nested_dir, &nested_dir, walk);
+                continue;
+            }
+
+            let candidates = match &path_attr {
+                Some(path) => vec![normalize(&join(attr_dir, path))],
+                None => vec![join(child_dir, &format!("{}.rs", name)), join(child_dir, &format!("{}/mod.rs", name))],
+            };
+            for candidate in candidates {
+                // Only descend where a requested file can still be found; #[path] can point anywhere
+                if path_attr.is_none() && !walk.leads_to_target(&candidate) {
+                    break;
+                }
+                if self.load(&candidate) {
+                    self.walk_file(&candidate, &child_path, walk);
+                    break;
+                }
+            }
+        }
+    }
+
+    // Read and parse a file once, returning whether it exists and parses
+    fn load(&mut self, file: &str) -> bool {
+        if !self.sources.contains_key(file) {
+            let source = read_file_at(self.reference, file, self.local_repo_path)
+                .ok()
+                .and_then(|content| syn::parse_file(&content).ok());
+            self.sources.insert(file.to_string(), source);
+        }
+        matches!(self.sources.get(file), Some(Some(_)))
+    }
+}
+
+// Walk carries the state shared by one walk down a crate's module tree
+struct Walk<'w> {
+    targets: &'w [String],
+    crate_name: Option<String>,
+    module_paths: &'w mut HashMap<String, ModulePath>,
+    // Files already walked, so #[path] cycles end
+    visited: HashSet<String>,
+}
+
+impl Walk<'_> {
+    // Whether an unresolved requested file is this module file or lies below its directory
+    fn leads_to_target(&self, file: &str) -> bool {
+        let below = format!("{}/", module_dir(file));
+        self.targets
+            .iter()
+            .filter(|target| !self.module_paths.contains_key(*target))
+            .any(|target| target == file || target.starts_with(&below) || below == "/")
+    }
+}
+
+// Qualify an entity key with its file's module path, e.g. `tcp::connect` in `crate::net`
+// becomes `crate::net::tcp::connect`, so items with the same name in different files stay apart.
+// Keys of files with no known module path are left as they are.
+pub fn qualify(module_path: Option<&str>, name: &str) -> String {
+    match module_path {
+        Some(module_path) => format!("{}::{}", module_path, name),
+        None => name.to_string(),
+    }
+}
+
+// Name of an entity within its file, the qualified key without the file's module path
+pub fn short_name<'a>(module_path: Option<&str>, name: &'a str) -> &'a str {
+    module_path
+        .and_then(|module_path| name.strip_prefix(module_path)?.strip_prefix("::"))
+        .unwrap_or(name)
+}
+
+// Whether a file holds integration tests, lying in the `tests` directory of a crate rather than
+// anywhere under its `src`
+pub fn is_test_file(file: &str) -> bool {
+    file.split('/').take_while(|part| *part != "src").any(|part| part == "tests")
+}
+
+// Whether a file is a crate's build script, a `build.rs` beside its manifest rather than a module
+// somewhere under `src`
+pub fn is_build_script(file: &str) -> bool {
+    let mut parts = file.rsplit('/');
+    parts.next() == Some("build.rs") && parts.all(|part| part != "src")
+}
+
+// Path of an entity as other crates name it, e.g. `crate::net::tcp::connect` in crate `net_utils`
+// is `net_utils::net::tcp::connect`, so same-named items of different crates in one repository
+// stay apart. None unless the crate is known and the name is qualified with a module path.
+pub fn full_name(crate_name: Option<&str>, qualified_name: &str) -> Option<String> {
+    let within_crate = qualified_name.strip_prefix("crate::")?;
+    Some(format!("{}::{}", crate_name?, within_crate))
+}
+
+// Name of the package in a Cargo.toml, from the `name` key of its `[package]` table
+fn package_name(manifest: &str) -> Option<String> {
+    let mut in_package = false;
+    for line in manifest.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_package = line == "[package]";
+        } else if let Some(value) = line.strip_prefix("name").and_then(|rest| rest.trim_start().strip_prefix('=')) {
+            if in_package {
+                return value.split('"').nth(1).map(str::to_string);
+            }
+        }
+    }
+    None
+}
+
+// Candidate crate roots for a file: the target it sits under, if any, then the library and binary
+fn crate_roots(crate_dir: &str, file: &str) -> Vec<String> {
+    let relative = if crate_dir.is_empty() {
+        file
+    } else {
+        file.strip_prefix(crate_dir).and_then(|rest| rest.strip_prefix('/')).unwrap_or(file)
+    };
+
+    let mut roots = Vec::new();
+    for target_dir in TARGET_DIRS {
+        if let Some(rest) = relative.strip_prefix(target_dir).and_then(|rest| rest.strip_prefix('/')) {
+            match rest.split_once('/') {
+                Some((target, _)) => roots.push(join(crate_dir, &format!("{}/{}/main.rs", target_dir, target))),
+                None => roots.push(join(crate_dir, &format!("{}/{}", target_dir, rest))),
+            }
+        }
+    }
+    roots.push(join(crate_dir, "src/lib.rs"));
+    roots.push(join(crate_dir, "src/main.rs"));
+    roots.push(join(crate_dir, "build.rs"));
+    roots
+}
+
+// Directory holding the files of a module's children: mod.rs files and crate roots share
+// their own directory, other files use a directory named after the module
+fn module_dir(file: &str) -> String {
+    let is_mod_rs = file == "mod.rs" || file.ends_with("/mod.rs");
+    let is_root = ["src/lib.rs", "src/main.rs", "build.rs"]
+        .iter()
+        .any(|root| file == *root || file.ends_with(&format!("/{}", root)))
+        || TARGET_DIRS.iter().any(|dir| is_target_root(file, dir));
+
+    if is_mod_rs || is_root {
+        parent_dir(file).to_string()
+    } else {
+        file.trim_end_matches(".rs").to_string()
+    }
+}
+
+// Whether a file is a top-level file of a target directory, like tests/api.rs or src/bin/cli/main.rs
+fn is_target_root(file: &str, target_dir: &str) -> bool {
+    let Some(index) = file.find(target_dir) else {
+        return false;
+    };
+    if index > 0 && !file[..index].ends_with('/') {
+        return false;
+    }
+    match file[index + target_dir.len()..].strip_prefix('/') {
+        Some(rest) => !rest.contains('/') || (rest.split('/').count() == 2 && rest.ends_with("/main.rs")),
+        None => false,
+    }
+}
+
+// Value of a `#[path = "..."]` attribute
+fn path_attribute(attrs: &[syn::Attribute]) -> Option<String> {
+    attrs.iter().find(|attr| attr.path().is_ident("path")).and_then(|attr| match &attr.meta {
+        syn::Meta::NameValue(name_value) => match &name_value.value {
+            Expr::Lit(expr) => match &expr.lit {
+                Lit::Str(path) => Some(path.value()),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+// Parent directory of a repository-relative path, empty at the root
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+// Join repository-relative path segments
+fn join(dir: &str, path: &str) -> String {
+    if dir.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}/{}", dir, path)
+    }
+}
+
+// Resolve `.` and `..` components left by #[path] attributes
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
@@ -0,0 +1,138 @@
+// src/normalize.rs
+use clap::ValueEnum;
+use proc_macro2::{Delimiter, Group, TokenStream, TokenTree};
+use std::sync::OnceLock;
+
+use crate::types::{CosmeticChange, DetailedChanges};
+
+// NormalizeStep is one normalization applied to both sides of a modified entity before deciding
+// whether the change is cosmetic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NormalizeStep {
+    // Drop doc comments, including those of fields, variants and nested items
+    Docs,
+    // Print the code with prettyplease, so only what the formatter keeps is compared
+    Pretty,
+}
+
+// CosmeticMode selects what happens to changes the normalization finds cosmetic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum CosmeticMode {
+    // Keep them and list them under `cosmetic_only`
+    #[default]
+    Label,
+    // Leave them out of the report
+    Exclude,
+}
+
+static SETTINGS: OnceLock<(Vec<NormalizeStep>, CosmeticMode)> = OnceLock::new();
+
+// Normalize modified entities with these steps for the rest of the process
+pub fn configure(steps: Vec<NormalizeStep>, mode: CosmeticMode) {
+    if SETTINGS.set((steps, mode)).is_err() {
+        eprintln!("Warning: Normalization already configured");
+    }
+}
+
+// Find the modified entities whose sides are the same once normalized, and label or drop them.
+// Runs once the attribute classification has taken out attribute- and doc-only changes, which
+// count as cosmetic too when doc comments are normalized away.
+pub fn classify_cosmetic_changes(changes: &mut DetailedChanges) {
+    let Some((steps, mode)) = SETTINGS.get().filter(|(steps, _)| !steps.is_empty()) else {
+        return;
+    };
+
+    let mut cosmetic = Vec::new();
+    let categories = [
+        ("function", &mut changes.modified_functions),
+        ("type", &mut changes.modified_types),
+        ("interface", &mut changes.modified_interfaces),
+        ("method", &mut changes.modified_methods),
+        ("trait_method", &mut changes.modified_trait_methods),
+        ("constant", &mut changes.modified_constants),
+        ("macro", &mut changes.modified_macros),
+        ("associated_const", &mut changes.modified_associated_consts),
+        ("associated_type", &mut changes.modified_associated_types),
+    ];
+    for (kind, modified) in categories {
+        modified.retain(|entry| {
+            if normalized(&entry[1], steps) != normalized(&entry[2], steps) {
+                return true;
+            }
+            cosmetic.push(CosmeticChange {
+                kind: kind.to_string(),
+                name: entry[0].clone(),
+            });
+            *mode == CosmeticMode::Label
+        });
+    }
+
+    if steps.contains(&NormalizeStep::Docs) {
+        cosmetic.extend(changes.doc_only_changes.iter().map(|change| CosmeticChange {
+            kind: change.kind.clone(),
+            name: change.name.clone(),
+        }));
+        if *mode == CosmeticMode::Exclude {
+            changes.doc_only_changes.clear();
+        }
+    }
+
+    if *mode == CosmeticMode::Label {
+        changes.cosmetic_only = cosmetic;
+    }
+}
+
+// Normalized form of an entity's code. Code that doesn't parse as items is compared by its tokens.
+fn normalized(code: &str, steps: &[NormalizeStep]) -> String {
+    let Ok(mut tokens) = code.parse::<TokenStream>() else {
+        return code.to_string();
+    };
+    if steps.contains(&NormalizeStep::Docs) {
+        tokens = strip_docs(tokens);
+    }
+    if steps.contains(&NormalizeStep::Pretty) {
+        if let Ok(file) = syn::parse2::<syn::File>(tokens.clone()) {
+            return prettyplease::unparse(&file);
+        }
+    }
+    tokens.to_string()
+}
+
+// Remove the `#[doc = ".."]` and `#![doc = ".."]` attributes that doc comments become, at any depth
+fn strip_docs(tokens: TokenStream) -> TokenStream {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let mut stripped = Vec::with_capacity(tokens.len());
+    let mut index = 0;
+    while index < tokens.len() {
+        let attribute_len = match &tokens[index..] {
+            [TokenTree::Punct(pound), TokenTree::Group(group), ..] if pound.as_char() == '#' && is_doc(group) => 2,
+            [TokenTree::Punct(pound), TokenTree::Punct(bang), TokenTree::Group(group), ..]
+                if pound.as_char() == '#' && bang.as_char() == '!' && is_doc(group) =>
+            {
+                3
+            }
+            _ => 0,
+        };
+        if attribute_len > 0 {
+            index += attribute_len;
+            continue;
+        }
+
+        stripped.push(match &tokens[index] {
+            TokenTree::Group(group) => {
+                let mut inner = Group::new(group.delimiter(), strip_docs(group.stream()));
+                inner.set_span(group.span());
+                TokenTree::Group(inner)
+            }
+            token => token.clone(),
+        });
+        index += 1;
+    }
+    stripped.into_iter().collect()
+}
+
+// Whether a bracketed attribute body is a `doc` attribute
+fn is_doc(group: &Group) -> bool {
+    group.delimiter() == Delimiter::Bracket
+        && matches!(group.stream().into_iter().next(), Some(TokenTree::Ident(ident)) if ident == "doc")
+}
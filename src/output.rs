@@ -2,60 +2,68 @@
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
-use serde::{Deserialize, Serialize};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
 use serde_json::json;
 
-use crate::types::DetailedChanges;
+use crate::git_ops::CommitMetadata;
+use crate::ast_parser::format_compact;
+use crate::modules::{full_name, short_name};
+use crate::deprecation::build_deprecation_report;
+use crate::error_surface::build_error_surface_report;
+use crate::granular::build_panic_report;
+use crate::semver::build_semver_report;
+use crate::types::{
+    AnalysisError, BuildScriptChange, CommitChanges, CrateAttributeChanges, DeprecationReportEntry, DetailedChanges,
+    ErrorSurfaceReportEntry, GranularChanges, ManifestChanges, PanicReportEntry, PathChanges, SemverReport,
+    SkippedFile, TraitImplChange,
+};
 
-// Create all the output JSON files
-pub fn create_output_files(all_changes: &[DetailedChanges], output_path: &str) {
-    // Create output directory if it doesn't exist
-    if let Err(e) = fs::create_dir_all(output_path) {
-        println!("Error creating output directory: {}", e);
-        return;
-    }
+// ReportHeader records what a report was built from, so archived reports describe themselves
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportHeader {
+    pub repo_url: String,
+    // Refs as requested on the command line
+    pub base_ref: String,
+    pub target_ref: String,
+    // The commits actually compared; missing when the run stopped before resolving them
+    pub base: Option<CommitMetadata>,
+    pub target: Option<CommitMetadata>,
+    // Changed build scripts, which can alter generated code and cfgs invisibly
+    pub build_script_changes: Vec<BuildScriptChange>,
+}
 
+// Create all the output JSON files
+pub fn create_output_files(all_changes: &[DetailedChanges], header: &ReportHeader, output_path: &str) {
     // Write detailed changes to a single file
-    match serde_json::to_string_pretty(all_changes) {
-        Ok(all_changes_json) => {
-            let all_changes_path = Path::new(output_path).join("all_code_changes.json");
-            if let Err(e) = fs::write(&all_changes_path, all_changes_json) {
-                println!("Error writing all changes file: {}", e);
-            }
-        },
-        Err(e) => {
-            println!("Error marshaling all changes: {}", e);
-        }
-    }
+    write_json_file(all_changes, "all_code_changes.json", header, output_path);
 
     // Create separate files for each type of change
-    create_type_specific_file(
-        all_changes,
-        "function_changes.json",
-        |c| (&c.added_functions, &c.modified_functions, &c.deleted_functions),
-        output_path,
-    );
+    for (kind, extractor) in ENTITY_KINDS {
+        let changes = build_type_specific_changes(all_changes, kind, extractor);
+        write_json_file(&changes, &format!("{}_changes.json", kind), header, output_path);
+    }
 
-    create_type_specific_file(
-        all_changes,
-        "type_changes.json",
-        |c| (&c.added_types, &c.modified_types, &c.deleted_types),
-        output_path,
-    );
+    // Test code gets a file of its own, so the type-specific files only hold production code
+    write_json_file(&build_test_changes(all_changes), "test_changes.json", header, output_path);
 
-    create_type_specific_file(
-        all_changes,
-        "interface_changes.json",
-        |c| (&c.added_interfaces, &c.modified_interfaces, &c.deleted_interfaces),
+    // Crate-level attributes get a file of their own, since toggling one affects the whole crate
+    write_json_file(
+        &build_crate_attribute_changes(all_changes),
+        "crate_attribute_changes.json",
+        header,
         output_path,
     );
 
-    create_type_specific_file(
-        all_changes,
-        "method_changes.json",
-        |c| (&c.added_methods, &c.modified_methods, &c.deleted_methods),
-        output_path,
-    );
+    // Trait impls added or removed as a whole, such as a type that stops implementing `Display`
+    write_json_file(&build_trait_impl_changes(all_changes), "trait_impl_changes.json", header, output_path);
+
+    // Public API changes classified by what they mean for downstream crates
+    write_json_file(&build_semver_report(all_changes), "semver_report.json", header, output_path);
+    write_json_file(&build_deprecation_report(all_changes), "deprecations.json", header, output_path);
+
+    // Error enums whose variants changed, which exhaustive matches downstream have to follow
+    write_json_file(&build_error_surface_report(all_changes), "error_surface.json", header, output_path);
 }
 
 // Structure for type-specific changes
@@ -66,57 +74,355 @@ struct TypeSpecificChanges {
     deleted: Vec<HashMap<String, serde_json::Value>>,
 }
 
-// Create a file for a specific type of change
-fn create_type_specific_file(
+// Entity lists of one kind of entity: added, modified and deleted
+type EntityLists = fn(&DetailedChanges) -> (&Vec<Vec<String>>, &Vec<Vec<String>>, &Vec<Vec<String>>);
+
+// The kinds of entities with type-specific files, and their lists
+const ENTITY_KINDS: [(&str, EntityLists); 9] = [
+    ("function", |c| (&c.added_functions, &c.modified_functions, &c.deleted_functions)),
+    ("type", |c| (&c.added_types, &c.modified_types, &c.deleted_types)),
+    ("interface", |c| (&c.added_interfaces, &c.modified_interfaces, &c.deleted_interfaces)),
+    ("method", |c| (&c.added_methods, &c.modified_methods, &c.deleted_methods)),
+    ("trait_method", |c| (&c.added_trait_methods, &c.modified_trait_methods, &c.deleted_trait_methods)),
+    ("constant", |c| (&c.added_constants, &c.modified_constants, &c.deleted_constants)),
+    ("macro", |c| (&c.added_macros, &c.modified_macros, &c.deleted_macros)),
+    ("associated_const", |c| {
+        (&c.added_associated_consts, &c.modified_associated_consts, &c.deleted_associated_consts)
+    }),
+    ("associated_type", |c| {
+        (&c.added_associated_types, &c.modified_associated_types, &c.deleted_associated_types)
+    }),
+];
+
+// Collect the added/modified/deleted entries of one kind of entity, leaving test code to
+// build_test_changes
+fn build_type_specific_changes(
     all_changes: &[DetailedChanges],
-    filename: &str,
+    kind: &str,
     extractor: impl Fn(&DetailedChanges) -> (&Vec<Vec<String>>, &Vec<Vec<String>>, &Vec<Vec<String>>),
-    output_path: &str,
-) {
+) -> TypeSpecificChanges {
+    let mut changes = TypeSpecificChanges {
+        added: Vec::new(),
+        modified: Vec::new(),
+        deleted: Vec::new(),
+    };
+    collect_entries(&mut changes, all_changes, kind, extractor, false);
+    changes
+}
+
+// Collect the added, modified and deleted entities of every kind that are test code, each
+// entry naming its kind, so they can be reviewed apart from production code
+fn build_test_changes(all_changes: &[DetailedChanges]) -> TypeSpecificChanges {
     let mut changes = TypeSpecificChanges {
         added: Vec::new(),
         modified: Vec::new(),
         deleted: Vec::new(),
     };
+    for (kind, extractor) in ENTITY_KINDS {
+        collect_entries(&mut changes, all_changes, kind, extractor, true);
+    }
+    changes
+}
 
+// Add the entries of one kind of entity that are test code, or those that aren't
+fn collect_entries(
+    changes: &mut TypeSpecificChanges,
+    all_changes: &[DetailedChanges],
+    kind: &str,
+    extractor: impl Fn(&DetailedChanges) -> (&Vec<Vec<String>>, &Vec<Vec<String>>, &Vec<Vec<String>>),
+    test_code: bool,
+) {
     for c in all_changes {
         let (added, modified, deleted) = extractor(c);
-
-        for item in added {
+        let selected = |item: &&Vec<String>| {
+            c.test_changes.iter().any(|t| t.kind == kind && t.name == item[0]) == test_code
+        };
+        let new_map = |item: &Vec<String>| {
             let mut map = HashMap::new();
+            if test_code {
+                map.insert("kind".to_string(), json!(kind));
+            }
             map.insert("module".to_string(), json!(c.module_name));
-            map.insert("name".to_string(), json!(item[0]));
+            map.insert("name".to_string(), json!(short_name(c.module_path.as_deref(), &item[0])));
+            map.insert("qualifiedName".to_string(), json!(item[0]));
+            insert_full_name(&mut map, c, &item[0]);
+            map
+        };
+
+        for item in added.iter().filter(selected) {
+            let mut map = new_map(item);
             map.insert("code".to_string(), json!(item[1]));
+            insert_risk_score(&mut map, c, kind, &item[0]);
             changes.added.push(map);
         }
 
-        for item in modified {
-            let mut map = HashMap::new();
-            map.insert("module".to_string(), json!(c.module_name));
-            map.insert("name".to_string(), json!(item[0]));
+        for item in modified.iter().filter(selected) {
+            let mut map = new_map(item);
             map.insert("oldCode".to_string(), json!(item[1]));
             map.insert("newCode".to_string(), json!(item[2]));
+            // Modified functions, methods and trait methods say whether the signature, body or both changed
+            if let Some(change_kind) = item.get(3) {
+                map.insert("changeKind".to_string(), json!(change_kind));
+            }
+            if let Some(similarity) = c.similarities.iter().find(|s| s.kind == kind && s.name == item[0]) {
+                map.insert("similarity".to_string(), json!(similarity.similarity));
+            }
+            insert_risk_score(&mut map, c, kind, &item[0]);
             changes.modified.push(map);
         }
 
-        for item in deleted {
-            let mut map = HashMap::new();
-            map.insert("module".to_string(), json!(c.module_name));
-            map.insert("name".to_string(), json!(item[0]));
+        for item in deleted.iter().filter(selected) {
+            let mut map = new_map(item);
             map.insert("code".to_string(), json!(item[1]));
+            insert_risk_score(&mut map, c, kind, &item[0]);
             changes.deleted.push(map);
         }
     }
+}
+
+// Name the crate of an entry and its path under the crate's name, when both are known
+fn insert_full_name(map: &mut HashMap<String, serde_json::Value>, c: &DetailedChanges, name: &str) {
+    if let Some(full_name) = full_name(c.crate_name.as_deref(), name) {
+        map.insert("crateName".to_string(), json!(c.crate_name));
+        map.insert("fullName".to_string(), json!(full_name));
+    }
+}
+
+// Add the risk score of an entry and the kind of change it was scored as
+fn insert_risk_score(map: &mut HashMap<String, serde_json::Value>, c: &DetailedChanges, kind: &str, name: &str) {
+    if let Some(score) = c.risk_scores.iter().find(|s| s.kind == kind && s.name == name) {
+        map.insert("riskScore".to_string(), json!(score.score));
+        map.insert("riskKind".to_string(), json!(score.change));
+    }
+}
+
+// Collect the crate-level attribute changes of each crate root
+fn build_crate_attribute_changes(all_changes: &[DetailedChanges]) -> Vec<CrateAttributeChanges> {
+    all_changes
+        .iter()
+        .filter(|c| !c.added_crate_attributes.is_empty() || !c.removed_crate_attributes.is_empty())
+        .map(|c| CrateAttributeChanges {
+            module: c.module_name.clone(),
+            added_attributes: c.added_crate_attributes.clone(),
+            removed_attributes: c.removed_crate_attributes.clone(),
+            added_features: features(&c.added_crate_attributes),
+            removed_features: features(&c.removed_crate_attributes),
+            no_std: if c.added_crate_attributes.iter().any(|attr| attr == "#![no_std]") {
+                Some(true)
+            } else if c.removed_crate_attributes.iter().any(|attr| attr == "#![no_std]") {
+                Some(false)
+            } else {
+                None
+            },
+        })
+        .collect()
+}
+
+// Collect the trait impls added or removed as a whole, with the trait and type of each
+fn build_trait_impl_changes(all_changes: &[DetailedChanges]) -> Vec<TraitImplChange> {
+    let mut trait_impl_changes = Vec::new();
+    for c in all_changes {
+        let entries = c
+            .added_trait_impls
+            .iter()
+            .map(|entry| ("added", entry))
+            .chain(c.deleted_trait_impls.iter().map(|entry| ("removed", entry)));
+        for (change, entry) in entries {
+            let Ok(impl_block) = syn::parse_str::<syn::ItemImpl>(&entry[1]) else {
+                eprintln!("Warning: Could not parse trait impl {} in {}", entry[0], c.module_name);
+                continue;
+            };
+            let Some((negation, trait_path, _)) = &impl_block.trait_ else {
+                continue;
+            };
+            trait_impl_changes.push(TraitImplChange {
+                module: c.module_name.clone(),
+                name: entry[0].clone(),
+                change: change.to_string(),
+                trait_name: format_compact(trait_path),
+                self_type: format_compact(&impl_block.self_ty),
+                generics: impl_block.generics.params.iter().map(format_compact).collect(),
+                negative: negation.is_some(),
+                is_unsafe: impl_block.unsafety.is_some(),
+            });
+        }
+    }
+    trait_impl_changes
+}
+
+// Names of the nightly features in `#![feature(..)]` entries
+fn features(attributes: &[String]) -> Vec<String> {
+    attributes
+        .iter()
+        .filter_map(|attr| attr.strip_prefix("#![feature(")?.strip_suffix(")]"))
+        .map(str::to_string)
+        .collect()
+}
+
+// Write the granular function changes file
+pub fn create_granular_file(granular_changes: &GranularChanges, header: &ReportHeader, output_path: &str) {
+    if write_json_file(granular_changes, "function_changes_granular.json", header, output_path) {
+        eprintln!(
+            "Wrote granular function changes to {}",
+            Path::new(output_path).join("function_changes_granular.json").display()
+        );
+    }
+    // Newly introduced panics get their own file so reviewers can go through them first
+    write_json_file(&build_panic_report(granular_changes), "panic_changes.json", header, output_path);
+}
+
+// Write the errors that occurred during analysis, so an empty diff can be
+// told apart from a failed one
+pub fn create_errors_file(errors: &[AnalysisError], header: &ReportHeader, output_path: &str) {
+    write_json_file(errors, "errors.json", header, output_path);
+}
+
+// OutputFile is the document written to each output file: the report header, then the
+// contents under a key named after the file
+struct OutputFile<'a, T: ?Sized> {
+    header: &'a ReportHeader,
+    key: &'a str,
+    contents: &'a T,
+}
+
+impl<T: Serialize + ?Sized> Serialize for OutputFile<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("header", self.header)?;
+        map.serialize_entry(self.key, self.contents)?;
+        map.end()
+    }
+}
+
+// Write a value with the report header as pretty-printed JSON to a file in the output
+// directory, returning whether the file was written
+fn write_json_file<T: Serialize + ?Sized>(
+    value: &T,
+    file_name: &str,
+    header: &ReportHeader,
+    output_path: &str,
+) -> bool {
+    if let Err(e) = fs::create_dir_all(output_path) {
+        eprintln!("Error creating output directory: {}", e);
+        return false;
+    }
 
-    match serde_json::to_string_pretty(&changes) {
-        Ok(changes_json) => {
-            let file_path = Path::new(output_path).join(filename);
-            if let Err(e) = fs::write(&file_path, changes_json) {
-                println!("Error writing {}: {}", filename, e);
+    let document = OutputFile {
+        header,
+        key: file_name.trim_end_matches(".json"),
+        contents: value,
+    };
+    match serde_json::to_string_pretty(&document) {
+        Ok(json) => match fs::write(Path::new(output_path).join(file_name), json) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Error writing {}: {}", file_name, e);
+                false
             }
         },
         Err(e) => {
-            println!("Error marshaling {}: {}", filename, e);
+            eprintln!("Error marshaling {}: {}", file_name, e);
+            false
+        }
+    }
+}
+
+// RunDetails holds the report sections that accompany the code changes
+#[derive(Serialize)]
+pub struct RunDetails<'a> {
+    // Reports of each commit in the range, with --per-commit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_changes: Option<&'a [CommitChanges]>,
+    // Reports against each parent of the current commit, with --all-parents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_changes: Option<&'a [CommitChanges]>,
+    // Changed files left out of the analysis, with the reason for each
+    pub skipped_files: &'a [SkippedFile],
+    // Mode changes and case-only renames, which don't show up as code changes
+    pub path_changes: &'a PathChanges,
+    // Dependency and feature changes of the changed Cargo.toml manifests
+    pub manifest_changes: &'a [ManifestChanges],
+}
+
+// Write each report section that accompanies the code changes to its own file
+pub fn create_run_detail_files(details: &RunDetails, header: &ReportHeader, output_path: &str) {
+    if let Some(commit_changes) = details.commit_changes {
+        write_json_file(commit_changes, "commit_changes.json", header, output_path);
+    }
+    if let Some(parent_changes) = details.parent_changes {
+        write_json_file(parent_changes, "parent_changes.json", header, output_path);
+    }
+    write_json_file(details.skipped_files, "skipped_files.json", header, output_path);
+    write_json_file(details.path_changes, "path_changes.json", header, output_path);
+    write_json_file(details.manifest_changes, "manifest_changes.json", header, output_path);
+}
+
+// Type-specific changes of every entity kind, one `<kind>_changes` key each, in the order of ENTITY_KINDS
+struct TypeSpecificReports(Vec<(String, TypeSpecificChanges)>);
+
+impl Serialize for TypeSpecificReports {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, changes) in &self.0 {
+            map.serialize_entry(key, changes)?;
         }
+        map.end()
+    }
+}
+
+// Consolidated report written to stdout, one key per output file
+#[derive(Serialize)]
+struct ConsolidatedReport<'a> {
+    header: &'a ReportHeader,
+    all_code_changes: &'a [DetailedChanges],
+    #[serde(flatten)]
+    type_specific: TypeSpecificReports,
+    test_changes: TypeSpecificChanges,
+    crate_attribute_changes: Vec<CrateAttributeChanges>,
+    trait_impl_changes: Vec<TraitImplChange>,
+    semver_report: SemverReport,
+    deprecations: Vec<DeprecationReportEntry>,
+    error_surface: Vec<ErrorSurfaceReportEntry>,
+    function_changes_granular: &'a GranularChanges,
+    panic_changes: Vec<PanicReportEntry>,
+    #[serde(flatten)]
+    details: &'a RunDetails<'a>,
+    errors: &'a [AnalysisError],
+}
+
+// Write all changes as a single JSON document to stdout
+pub fn write_stdout_report(
+    all_changes: &[DetailedChanges],
+    granular_changes: &GranularChanges,
+    details: &RunDetails,
+    header: &ReportHeader,
+    errors: &[AnalysisError],
+) {
+    let report = ConsolidatedReport {
+        header,
+        all_code_changes: all_changes,
+        type_specific: TypeSpecificReports(
+            ENTITY_KINDS
+                .iter()
+                .map(|(kind, extractor)| {
+                    (format!("{}_changes", kind), build_type_specific_changes(all_changes, kind, extractor))
+                })
+                .collect(),
+        ),
+        test_changes: build_test_changes(all_changes),
+        crate_attribute_changes: build_crate_attribute_changes(all_changes),
+        trait_impl_changes: build_trait_impl_changes(all_changes),
+        semver_report: build_semver_report(all_changes),
+        deprecations: build_deprecation_report(all_changes),
+        error_surface: build_error_surface_report(all_changes),
+        function_changes_granular: granular_changes,
+        panic_changes: build_panic_report(granular_changes),
+        details,
+        errors,
+    };
+
+    match serde_json::to_string_pretty(&report) {
+        Ok(report_json) => println!("{}", report_json),
+        Err(e) => eprintln!("Error marshaling consolidated report: {}", e),
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,143 @@
+// src/policy.rs
+use clap::ValueEnum;
+
+use crate::semver;
+use crate::types::DetailedChanges;
+
+// Exit code used when a --fail-on category is present in the diff
+pub const POLICY_FAILURE_EXIT_CODE: i32 = 2;
+
+// ChangeCategory names a class of changes that can gate the exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ChangeCategory {
+    Any,
+    AddedFunctions,
+    ModifiedFunctions,
+    DeletedFunctions,
+    AddedTypes,
+    ModifiedTypes,
+    DeletedTypes,
+    AddedInterfaces,
+    ModifiedInterfaces,
+    DeletedInterfaces,
+    AddedMethods,
+    ModifiedMethods,
+    DeletedMethods,
+    AddedTraitMethods,
+    ModifiedTraitMethods,
+    DeletedTraitMethods,
+    AddedConstants,
+    ModifiedConstants,
+    DeletedConstants,
+    AddedMacros,
+    ModifiedMacros,
+    DeletedMacros,
+    AddedAssociatedConsts,
+    ModifiedAssociatedConsts,
+    DeletedAssociatedConsts,
+    AddedAssociatedTypes,
+    ModifiedAssociatedTypes,
+    DeletedAssociatedTypes,
+    AddedTraitImpls,
+    DeletedTraitImpls,
+    ChangedAttributes,
+    ChangedDocs,
+    ChangedVisibility,
+    ChangedQualifiers,
+    ChangedSignatures,
+    ChangedLifetimes,
+    ChangedGlobals,
+    AddedImports,
+    RemovedImports,
+    AddedReexports,
+    RemovedReexports,
+    ChangedCrateAttributes,
+    Renamed,
+    Moved,
+    Reordered,
+    BreakingChanges,
+}
+
+impl ChangeCategory {
+    // Check whether a file's changes contain entries of this category
+    pub fn is_present(&self, changes: &DetailedChanges) -> bool {
+        match self {
+            ChangeCategory::Any => changes.has_changes(),
+            ChangeCategory::AddedFunctions => !changes.added_functions.is_empty(),
+            ChangeCategory::ModifiedFunctions => !changes.modified_functions.is_empty(),
+            ChangeCategory::DeletedFunctions => !changes.deleted_functions.is_empty(),
+            ChangeCategory::AddedTypes => !changes.added_types.is_empty(),
+            ChangeCategory::ModifiedTypes => !changes.modified_types.is_empty(),
+            ChangeCategory::DeletedTypes => !changes.deleted_types.is_empty(),
+            ChangeCategory::AddedInterfaces => !changes.added_interfaces.is_empty(),
+            ChangeCategory::ModifiedInterfaces => !changes.modified_interfaces.is_empty(),
+            ChangeCategory::DeletedInterfaces => !changes.deleted_interfaces.is_empty(),
+            ChangeCategory::AddedMethods => !changes.added_methods.is_empty(),
+            ChangeCategory::ModifiedMethods => !changes.modified_methods.is_empty(),
+            ChangeCategory::DeletedMethods => !changes.deleted_methods.is_empty(),
+            ChangeCategory::AddedTraitMethods => !changes.added_trait_methods.is_empty(),
+            ChangeCategory::ModifiedTraitMethods => !changes.modified_trait_methods.is_empty(),
+            ChangeCategory::DeletedTraitMethods => !changes.deleted_trait_methods.is_empty(),
+            ChangeCategory::AddedConstants => !changes.added_constants.is_empty(),
+            ChangeCategory::ModifiedConstants => !changes.modified_constants.is_empty(),
+            ChangeCategory::DeletedConstants => !changes.deleted_constants.is_empty(),
+            ChangeCategory::AddedMacros => !changes.added_macros.is_empty(),
+            ChangeCategory::ModifiedMacros => !changes.modified_macros.is_empty(),
+            ChangeCategory::DeletedMacros => !changes.deleted_macros.is_empty(),
+            ChangeCategory::AddedAssociatedConsts => !changes.added_associated_consts.is_empty(),
+            ChangeCategory::ModifiedAssociatedConsts => !changes.modified_associated_consts.is_empty(),
+            ChangeCategory::DeletedAssociatedConsts => !changes.deleted_associated_consts.is_empty(),
+            ChangeCategory::AddedAssociatedTypes => !changes.added_associated_types.is_empty(),
+            ChangeCategory::ModifiedAssociatedTypes => !changes.modified_associated_types.is_empty(),
+            ChangeCategory::DeletedAssociatedTypes => !changes.deleted_associated_types.is_empty(),
+            ChangeCategory::AddedTraitImpls => !changes.added_trait_impls.is_empty(),
+            ChangeCategory::DeletedTraitImpls => !changes.deleted_trait_impls.is_empty(),
+            ChangeCategory::ChangedAttributes => !changes.attribute_changes.is_empty(),
+            ChangeCategory::ChangedDocs => !changes.doc_only_changes.is_empty(),
+            ChangeCategory::ChangedVisibility => !changes.visibility_changes.is_empty(),
+            ChangeCategory::ChangedQualifiers => !changes.qualifier_changes.is_empty(),
+            ChangeCategory::ChangedSignatures => !changes.signature_changes.is_empty(),
+            ChangeCategory::ChangedLifetimes => !changes.lifetime_changes.is_empty(),
+            ChangeCategory::ChangedGlobals => !changes.global_changes.is_empty(),
+            ChangeCategory::AddedImports => !changes.added_imports.is_empty(),
+            ChangeCategory::RemovedImports => !changes.removed_imports.is_empty(),
+            ChangeCategory::AddedReexports => !changes.added_reexports.is_empty(),
+            ChangeCategory::RemovedReexports => !changes.removed_reexports.is_empty(),
+            ChangeCategory::ChangedCrateAttributes => {
+                !changes.added_crate_attributes.is_empty() || !changes.removed_crate_attributes.is_empty()
+            }
+            ChangeCategory::Renamed => !changes.renamed_entities.is_empty(),
+            ChangeCategory::Moved => !changes.moved_in.is_empty() || !changes.moved_out.is_empty(),
+            ChangeCategory::Reordered => !changes.moved_entities.is_empty(),
+            ChangeCategory::BreakingChanges => {
+                changes.semver_changes.iter().any(|change| change.level == semver::BREAKING)
+            }
+        }
+    }
+
+    // Name of the category as accepted on the command line
+    pub fn name(&self) -> String {
+        self.to_possible_value()
+            .map(|v| v.get_name().to_string())
+            .unwrap_or_default()
+    }
+}
+
+// Return the categories from the policy that are present in any of the changes
+pub fn triggered_categories(
+    all_changes: &[DetailedChanges],
+    fail_on: &[ChangeCategory],
+) -> Vec<ChangeCategory> {
+    let mut triggered = Vec::new();
+
+    for category in fail_on {
+        if triggered.contains(category) {
+            continue;
+        }
+        if all_changes.iter().any(|c| category.is_present(c)) {
+            triggered.push(*category);
+        }
+    }
+
+    triggered
+}
@@ -0,0 +1,316 @@
+// src/query.rs
+use serde::Serialize;
+use std::process;
+
+use crate::cli::{QueryArgs, RepoArgs};
+use crate::ast_parser::parse_file_ast;
+use crate::cfg;
+use crate::content;
+use crate::changeset::detect_changes;
+use crate::differ::process_rust_files;
+use crate::git_ops;
+use crate::modules::{short_name, ModuleResolver};
+use crate::types::{AnalysisError, DetailedChanges, ErrorLog};
+
+// QueryMatch describes one changed entity matching the queried symbol
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryMatch {
+    pub file: String,
+    pub kind: String,
+    pub verdict: String,
+    pub old_code: Option<String>,
+    pub new_code: Option<String>,
+    // `signature`, `body` or `both` for modified functions, methods and trait methods
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_kind: Option<String>,
+    // Percentage of the code modified and renamed entries keep, token by token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<u32>,
+    // Names on both sides of renamed and moved entries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_name: Option<String>,
+    // File a moved entry left, with `file` the one it moved to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_file: Option<String>,
+}
+
+// QueryResult is the machine-readable answer to "did symbol X change?"
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryResult {
+    pub symbol: String,
+    pub from: String,
+    pub to: String,
+    pub verdict: String,
+    pub matches: Vec<QueryMatch>,
+    pub errors: Vec<AnalysisError>,
+}
+
+// Run the query subcommand and print the verdict as JSON to stdout
+pub fn run_query(args: &QueryArgs) {
+    let repo = &args.repo;
+    prepare_repo(repo, &[&args.from, &args.to]);
+
+    // Resolve both refs to commits up front so every later step sees the same revisions
+    let from = resolve(repo, &args.from);
+    let to = resolve(repo, &args.to);
+
+    let from = if args.merge_base {
+        git_ops::get_merge_base(&from, &to, &repo.local_repo_path).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        })
+    } else {
+        from
+    };
+
+    let errors = ErrorLog::new();
+    let all_changes = diff_refs(repo, &from, &to, &args.files, &errors);
+
+    let mut result = find_symbol(&all_changes, &args.symbol, &from, &to);
+    result.errors = errors.snapshot();
+    // Without a match, a failed analysis can't claim the symbol is unchanged
+    if result.matches.is_empty() && !result.errors.is_empty() {
+        result.verdict = "unknown".to_string();
+    } else if result.matches.is_empty() && !symbol_exists(repo, &args.symbol, &[&from, &to], &args.files) {
+        result.verdict = "not_found".to_string();
+    }
+
+    match serde_json::to_string_pretty(&result) {
+        Ok(result_json) => println!("{}", result_json),
+        Err(e) => {
+            eprintln!("Error marshaling query result: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// Clone or reuse the repository with the given refs, exiting when it can't be used
+pub fn prepare_repo(repo: &RepoArgs, required_refs: &[&str]) {
+    let (local_repo_path, options) = (&repo.local_repo_path, &repo.options);
+    if let Err(e) = git_ops::clone_repo(&repo.repo_url, required_refs, local_repo_path, &options.clone_options()) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+
+    if let Err(e) = content::ensure_supported(options.content_source, local_repo_path) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+// Resolve a ref to a commit, exiting when it doesn't exist
+pub fn resolve(repo: &RepoArgs, reference: &str) -> String {
+    git_ops::resolve_ref(reference, &repo.local_repo_path).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    })
+}
+
+// Diff the requested files, or every changed Rust file, between two commits
+pub fn diff_refs(repo: &RepoArgs, from: &str, to: &str, files: &[String], errors: &ErrorLog) -> Vec<DetailedChanges> {
+    let (local_repo_path, options) = (&repo.local_repo_path, &repo.options);
+    let change_set = match detect_changes(from, to, local_repo_path, &options.detect_options(files), errors) {
+        Ok(change_set) => change_set,
+        Err(e) => {
+            eprintln!("Error getting changed files: {}", e);
+            process::exit(1);
+        }
+    };
+    if change_set.rust_files.is_empty() {
+        return Vec::new();
+    }
+
+    git_ops::prefetch_blobs(&[from, to], &change_set.paths_to_read(), local_repo_path, &options.clone_options());
+    let ctx = change_set.context(local_repo_path, from, to, options.content_source, options.lfs, errors);
+    content::prepare_sparse_checkout(&change_set.rust_files, options.sparse, &ctx);
+    let _checkout_guard =
+        match content::prepare_checkout(options.content_source, options.dirty_tree_policy(), local_repo_path) {
+            Ok(guard) => guard,
+            Err(e @ git_ops::GitError::DirtyTree { .. }) => {
+                eprintln!("Error: {}; rerun with --stash or --force, or use --content-source objects", e);
+                process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        };
+    process_rust_files(&change_set.rust_files, &ctx)
+}
+
+// Whether an entity key names the symbol, given by its name within the file, e.g. `add`, or by
+// its qualified name, e.g. `crate::math::add`
+fn names_symbol(key: &str, module_path: Option<&str>, symbol: &str) -> bool {
+    cfg::names(key, symbol) || cfg::names(short_name(module_path, key), symbol)
+}
+
+// Whether any Rust file, or any of the given files, has an entity named after the symbol at one of
+// the refs. Only the files mentioning the symbol's last identifier are parsed.
+fn symbol_exists(repo: &RepoArgs, symbol: &str, refs: &[&str], files: &[String]) -> bool {
+    let name = symbol.split(" #[cfg(").next().unwrap_or(symbol);
+    let Some(word) = name.split(|c: char| !c.is_alphanumeric() && c != '_').rfind(|part| !part.is_empty()) else {
+        return false;
+    };
+
+    let local_repo_path = &repo.local_repo_path;
+    refs.iter().any(|reference| {
+        let candidates = match git_ops::grep_rust_files(reference, word, local_repo_path) {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                // Without the search, the symbol can't be ruled out
+                eprintln!("Warning: {}", e);
+                return true;
+            }
+        };
+        let candidates: Vec<String> =
+            candidates.into_iter().filter(|file| files.is_empty() || files.contains(file)).collect();
+        let module_paths = ModuleResolver::new(reference, local_repo_path).resolve(&candidates);
+        candidates.iter().any(|file| {
+            let module_path = module_paths.get(file).map(|location| location.path.as_str());
+            git_ops::read_file_at(reference, file, local_repo_path)
+                .ok()
+                .and_then(|content| parse_file_ast(file, content, module_path).ok())
+                .is_some_and(|ast| {
+                    ast.content_hashes
+                        .values()
+                        .flat_map(|hashes| hashes.keys())
+                        .any(|key| names_symbol(key, module_path, symbol))
+                })
+        })
+    })
+}
+
+// Search all changes for entries named after the symbol
+pub fn find_symbol(all_changes: &[DetailedChanges], symbol: &str, from: &str, to: &str) -> QueryResult {
+    let mut matches = Vec::new();
+
+    for c in all_changes {
+        let module_path = c.module_path.as_deref();
+        let categories = [
+            ("function", &c.added_functions, &c.modified_functions, &c.deleted_functions),
+            ("type", &c.added_types, &c.modified_types, &c.deleted_types),
+            ("interface", &c.added_interfaces, &c.modified_interfaces, &c.deleted_interfaces),
+            ("method", &c.added_methods, &c.modified_methods, &c.deleted_methods),
+            ("trait_method", &c.added_trait_methods, &c.modified_trait_methods, &c.deleted_trait_methods),
+            ("constant", &c.added_constants, &c.modified_constants, &c.deleted_constants),
+            ("macro", &c.added_macros, &c.modified_macros, &c.deleted_macros),
+            (
+                "associated_const",
+                &c.added_associated_consts,
+                &c.modified_associated_consts,
+                &c.deleted_associated_consts,
+            ),
+            ("associated_type", &c.added_associated_types, &c.modified_associated_types, &c.deleted_associated_types),
+        ];
+
+        for (kind, added, modified, deleted) in categories {
+            for item in added.iter().filter(|item| names_symbol(&item[0], module_path, symbol)) {
+                matches.push(QueryMatch {
+                    file: c.module_name.clone(),
+                    kind: kind.to_string(),
+                    verdict: "added".to_string(),
+                    old_code: None,
+                    new_code: Some(item[1].clone()),
+                    change_kind: None,
+                    similarity: None,
+                    old_name: None,
+                    new_name: None,
+                    old_file: None,
+                });
+            }
+            for item in modified.iter().filter(|item| names_symbol(&item[0], module_path, symbol)) {
+                matches.push(QueryMatch {
+                    file: c.module_name.clone(),
+                    kind: kind.to_string(),
+                    verdict: "modified".to_string(),
+                    old_code: Some(item[1].clone()),
+                    new_code: Some(item[2].clone()),
+                    change_kind: item.get(3).cloned(),
+                    similarity: c
+                        .similarities
+                        .iter()
+                        .find(|similarity| similarity.kind == kind && similarity.name == item[0])
+                        .map(|similarity| similarity.similarity),
+                    old_name: None,
+                    new_name: None,
+                    old_file: None,
+                });
+            }
+            for item in deleted.iter().filter(|item| names_symbol(&item[0], module_path, symbol)) {
+                matches.push(QueryMatch {
+                    file: c.module_name.clone(),
+                    kind: kind.to_string(),
+                    verdict: "deleted".to_string(),
+                    old_code: Some(item[1].clone()),
+                    new_code: None,
+                    change_kind: None,
+                    similarity: None,
+                    old_name: None,
+                    new_name: None,
+                    old_file: None,
+                });
+            }
+        }
+
+        // Renamed entities are found under either name
+        let renamed = c.renamed_entities.iter().filter(|entity| {
+            names_symbol(&entity.old_name, module_path, symbol) || names_symbol(&entity.new_name, module_path, symbol)
+        });
+        for entity in renamed {
+            matches.push(QueryMatch {
+                file: c.module_name.clone(),
+                kind: entity.kind.clone(),
+                verdict: "renamed".to_string(),
+                old_code: Some(entity.old_code.clone()),
+                new_code: Some(entity.new_code.clone()),
+                change_kind: None,
+                similarity: Some(entity.similarity),
+                old_name: Some(entity.old_name.clone()),
+                new_name: Some(entity.new_name.clone()),
+                old_file: None,
+            });
+        }
+
+        // Each move is listed by both files, and taken from the one it went to
+        let moved = c.moved_in.iter().filter(|entity| {
+            let old_module_path = all_changes
+                .iter()
+                .find(|other| other.module_name == entity.old_module)
+                .and_then(|other| other.module_path.as_deref());
+            names_symbol(&entity.old_name, old_module_path, symbol)
+                || names_symbol(&entity.new_name, module_path, symbol)
+        });
+        for entity in moved {
+            matches.push(QueryMatch {
+                file: c.module_name.clone(),
+                kind: entity.kind.clone(),
+                verdict: "moved".to_string(),
+                old_code: None,
+                new_code: None,
+                change_kind: None,
+                similarity: Some(entity.similarity),
+                old_name: Some(entity.old_name.clone()),
+                new_name: Some(entity.new_name.clone()),
+                old_file: Some(entity.old_module.clone()),
+            });
+        }
+    }
+
+    // A symbol matched in several places is reported as modified overall
+    let verdict = match matches.as_slice() {
+        [] => "unchanged".to_string(),
+        [single] => single.verdict.clone(),
+        _ => "modified".to_string(),
+    };
+
+    QueryResult {
+        symbol: symbol.to_string(),
+        from: from.to_string(),
+        to: to.to_string(),
+        verdict,
+        matches,
+        errors: Vec::new(),
+    }
+}
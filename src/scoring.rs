@@ -0,0 +1,186 @@
+// src/scoring.rs
+use clap::ValueEnum;
+use proc_macro2::{TokenStream, TokenTree};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::semver::is_public;
+use crate::types::{DetailedChanges, FileASTData, RiskScore};
+
+// RiskKind is a kind of change the risk score weighs, from the most to the least risky by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+pub enum RiskKind {
+    // A public entity was deleted
+    DeletedPublic,
+    // A public entity was modified
+    ModifiedPublic,
+    // A private entity was deleted
+    Deleted,
+    // A private entity was modified
+    Modified,
+    // A public entity was added
+    AddedPublic,
+    // A private entity was added
+    Added,
+    // Only literals of a modified entity changed, such as a number or string
+    Literal,
+    // The changed entity is test code
+    Test,
+    // The change is cosmetic under --normalize
+    Cosmetic,
+}
+
+impl RiskKind {
+    // Weight of a change of this kind unless configured otherwise
+    fn default_weight(self) -> u32 {
+        match self {
+            RiskKind::DeletedPublic => 10,
+            RiskKind::ModifiedPublic => 6,
+            RiskKind::Deleted => 4,
+            RiskKind::Modified => 3,
+            RiskKind::AddedPublic => 2,
+            RiskKind::Added => 1,
+            RiskKind::Literal => 1,
+            RiskKind::Test => 1,
+            RiskKind::Cosmetic => 0,
+        }
+    }
+
+    // Name of the kind in reports, e.g. `deleted_public`
+    fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().replace('-', "_"))
+            .unwrap_or_default()
+    }
+}
+
+static WEIGHTS: OnceLock<HashMap<RiskKind, u32>> = OnceLock::new();
+
+// Weigh changes of these kinds differently from the defaults for the rest of the process
+pub fn configure(weights: Vec<(RiskKind, u32)>) {
+    if WEIGHTS.set(weights.into_iter().collect()).is_err() {
+        eprintln!("Warning: Risk weights already configured");
+    }
+}
+
+fn weight(kind: RiskKind) -> u32 {
+    WEIGHTS
+        .get()
+        .and_then(|weights| weights.get(&kind).copied())
+        .unwrap_or_else(|| kind.default_weight())
+}
+
+// Score every added, modified and deleted entity of a file by the weight of its kind of change,
+// and the file by the sum of its changes
+pub fn score_changes(changes: &mut DetailedChanges, old_ast: &FileASTData, new_ast: &FileASTData) {
+    let categories = [
+        ("function", &changes.added_functions, &changes.modified_functions, &changes.deleted_functions),
+        ("type", &changes.added_types, &changes.modified_types, &changes.deleted_types),
+        ("interface", &changes.added_interfaces, &changes.modified_interfaces, &changes.deleted_interfaces),
+        ("method", &changes.added_methods, &changes.modified_methods, &changes.deleted_methods),
+        (
+            "trait_method",
+            &changes.added_trait_methods,
+            &changes.modified_trait_methods,
+            &changes.deleted_trait_methods,
+        ),
+        ("constant", &changes.added_constants, &changes.modified_constants, &changes.deleted_constants),
+        ("macro", &changes.added_macros, &changes.modified_macros, &changes.deleted_macros),
+        (
+            "associated_const",
+            &changes.added_associated_consts,
+            &changes.modified_associated_consts,
+            &changes.deleted_associated_consts,
+        ),
+        (
+            "associated_type",
+            &changes.added_associated_types,
+            &changes.modified_associated_types,
+            &changes.deleted_associated_types,
+        ),
+        ("trait_impl", &changes.added_trait_impls, &Vec::new(), &changes.deleted_trait_impls),
+    ];
+
+    let mut scores = Vec::new();
+    for (kind, added, modified, deleted) in categories {
+        // Whether trait impls matter depends on their trait and type, so they count as public
+        let public = |ast: &FileASTData, name: &str| kind == "trait_impl" || is_public(ast, kind, name);
+        let is_test = |name: &str| changes.test_changes.iter().any(|c| c.kind == kind && c.name == name);
+
+        for entry in added {
+            let risk = if is_test(&entry[0]) {
+                RiskKind::Test
+            } else if public(new_ast, &entry[0]) {
+                RiskKind::AddedPublic
+            } else {
+                RiskKind::Added
+            };
+            scores.push(risk_score(kind, &entry[0], risk));
+        }
+        for entry in modified {
+            let is_cosmetic = changes.cosmetic_only.iter().any(|c| c.kind == kind && c.name == entry[0]);
+            let risk = if is_cosmetic {
+                RiskKind::Cosmetic
+            } else if is_test(&entry[0]) {
+                RiskKind::Test
+            } else if literals_only(&entry[1], &entry[2]) {
+                RiskKind::Literal
+            } else if public(old_ast, &entry[0]) || public(new_ast, &entry[0]) {
+                RiskKind::ModifiedPublic
+            } else {
+                RiskKind::Modified
+            };
+            scores.push(risk_score(kind, &entry[0], risk));
+        }
+        for entry in deleted {
+            let risk = if is_test(&entry[0]) {
+                RiskKind::Test
+            } else if public(old_ast, &entry[0]) {
+                RiskKind::DeletedPublic
+            } else {
+                RiskKind::Deleted
+            };
+            scores.push(risk_score(kind, &entry[0], risk));
+        }
+    }
+
+    changes.risk_score = scores.iter().map(|score| score.score).sum();
+    changes.risk_scores = scores;
+}
+
+fn risk_score(kind: &str, name: &str, risk: RiskKind) -> RiskScore {
+    RiskScore {
+        kind: kind.to_string(),
+        name: name.to_string(),
+        change: risk.name(),
+        score: weight(risk),
+    }
+}
+
+// Whether two versions of code differ only in their literals
+fn literals_only(old_code: &str, new_code: &str) -> bool {
+    match (old_code.parse::<TokenStream>(), new_code.parse::<TokenStream>()) {
+        (Ok(old), Ok(new)) => old.to_string() != new.to_string() && without_literals(old) == without_literals(new),
+        _ => false,
+    }
+}
+
+// Tokens as a string with every literal replaced by the same placeholder
+fn without_literals(tokens: TokenStream) -> String {
+    let mut stripped = String::new();
+    for token in tokens {
+        match token {
+            TokenTree::Group(group) => {
+                stripped.push_str(&format!("{:?}(", group.delimiter()));
+                stripped.push_str(&without_literals(group.stream()));
+                stripped.push(')');
+            }
+            TokenTree::Literal(_) => stripped.push_str("<lit> "),
+            token => {
+                stripped.push_str(&token.to_string());
+                stripped.push(' ');
+            }
+        }
+    }
+    stripped
+}
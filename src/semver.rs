@@ -0,0 +1,477 @@
+// src/semver.rs
+use syn::{ItemTrait, TraitItem, Visibility};
+
+use crate::ast_parser::split_outer_attributes;
+use crate::cfg;
+use crate::differ::entity_source_code;
+use crate::modules::{full_name, is_test_file, short_name};
+use crate::types::{DetailedChanges, FileASTData, GenericsChange, SemverChange, SemverReport, SemverReportEntry};
+
+// Levels of a semver change, from least to most severe
+pub const NON_BREAKING: &str = "non_breaking";
+pub const POTENTIALLY_BREAKING: &str = "potentially_breaking";
+pub const BREAKING: &str = "breaking";
+
+// Classify the public API changes of one file by combining its entity, visibility, signature,
+// generics, field and variant changes. An entity counts as public when its own declaration
+// says so: declared `pub`, a `#[macro_export]` macro, or an item of a public trait. Whether
+// the module it sits in is reachable from outside the crate is not checked.
+pub fn classify_semver_changes(
+    changes: &DetailedChanges,
+    old_ast: &FileASTData,
+    new_ast: &FileASTData,
+) -> Vec<SemverChange> {
+    if is_test_file(&changes.module_name) {
+        return Vec::new();
+    }
+    let mut semver = SemverChanges::default();
+
+    let entity_lists = [
+        ("function", &changes.added_functions, &changes.modified_functions, &changes.deleted_functions),
+        ("type", &changes.added_types, &changes.modified_types, &changes.deleted_types),
+        ("interface", &changes.added_interfaces, &changes.modified_interfaces, &changes.deleted_interfaces),
+        ("method", &changes.added_methods, &changes.modified_methods, &changes.deleted_methods),
+        (
+            "trait_method",
+            &changes.added_trait_methods,
+            &changes.modified_trait_methods,
+            &changes.deleted_trait_methods,
+        ),
+        ("constant", &changes.added_constants, &changes.modified_constants, &changes.deleted_constants),
+        ("macro", &changes.added_macros, &changes.modified_macros, &changes.deleted_macros),
+        (
+            "associated_const",
+            &changes.added_associated_consts,
+            &changes.modified_associated_consts,
+            &changes.deleted_associated_consts,
+        ),
+        (
+            "associated_type",
+            &changes.added_associated_types,
+            &changes.modified_associated_types,
+            &changes.deleted_associated_types,
+        ),
+    ];
+
+    // Removed entities break every use of them, added ones only break implementors of traits.
+    // Items of traits added or removed as a whole go with their trait.
+    let with_trait = |ast: &FileASTData, other: &FileASTData, name: &str| {
+        owning_trait(ast, name).is_none() || owning_trait(other, name).is_some()
+    };
+    for (kind, added, _, deleted) in entity_lists {
+        for entry in deleted.iter().filter(|entry| is_public(old_ast, kind, &entry[0])) {
+            if !with_trait(old_ast, new_ast, &entry[0]) {
+                continue;
+            }
+            semver.push(kind, &entry[0], BREAKING, format!("removed public {}", label(kind)));
+        }
+        for entry in added.iter().filter(|entry| is_public(new_ast, kind, &entry[0])) {
+            if !with_trait(new_ast, old_ast, &entry[0]) {
+                continue;
+            }
+            let (level, reason) = match trait_item_default(new_ast, kind, &entry[0]) {
+                Some(false) => (BREAKING, format!("added a {} without a default to a public trait", label(kind))),
+                Some(true) => (
+                    POTENTIALLY_BREAKING,
+                    format!("added a defaulted {} to a public trait, which can make calls ambiguous", label(kind)),
+                ),
+                None => (NON_BREAKING, format!("added public {}", label(kind))),
+            };
+            semver.push(kind, &entry[0], level, reason);
+        }
+    }
+
+    // Whether the trait and the type of an impl are public isn't known from the impl alone
+    for entry in &changes.deleted_trait_impls {
+        let reason = "removed a trait impl, which breaks its users if the trait and type are public";
+        semver.push("trait_impl", &entry[0], POTENTIALLY_BREAKING, reason.to_string());
+    }
+    for entry in &changes.added_trait_impls {
+        semver.push("trait_impl", &entry[0], NON_BREAKING, "added a trait impl".to_string());
+    }
+
+    for renamed in changes.renamed_entities.iter().filter(|r| is_public(old_ast, &r.kind, &r.old_name)) {
+        let reason = format!("renamed public {} to {}", label(&renamed.kind), renamed.new_name);
+        semver.push(&renamed.kind, &renamed.old_name, BREAKING, reason);
+    }
+
+    for change in &changes.visibility_changes {
+        if change.old_visibility == "pub" {
+            let reason = format!("narrowed visibility from pub to {}", change.new_visibility);
+            semver.push(&change.kind, &change.name, BREAKING, reason);
+        } else if change.new_visibility == "pub" {
+            semver.push(&change.kind, &change.name, NON_BREAKING, "became public".to_string());
+        }
+    }
+
+    for change in changes.signature_changes.iter().filter(|c| is_public(old_ast, &c.kind, &c.name)) {
+        // Renaming a parameter doesn't change how the function is called
+        for param_change in change.param_changes.iter().filter(|c| c.change != "renamed") {
+            let reason = match (param_change.change.as_str(), &param_change.old_name, &param_change.new_name) {
+                ("return_type", _, _) => "changed return type".to_string(),
+                ("added", _, Some(name)) => format!("added parameter {}", name),
+                ("removed", Some(name), _) => format!("removed parameter {}", name),
+                ("retyped", _, Some(name)) => format!("changed type of parameter {}", name),
+                (_, _, name) => format!("reordered parameter {}", name.as_deref().unwrap_or_default()),
+            };
+            semver.push(&change.kind, &change.name, BREAKING, reason);
+        }
+        if change.old_signature.variadic != change.new_signature.variadic {
+            semver.push(&change.kind, &change.name, BREAKING, "changed parameters".to_string());
+        }
+    }
+
+    for change in changes.qualifier_changes.iter().filter(|c| is_public(old_ast, &c.kind, &c.name)) {
+        for qualifier_change in &change.changes {
+            // Implementors of a trait or trait method have to match its qualifiers either way
+            let level = match qualifier_change.as_str() {
+                "became_const" => NON_BREAKING,
+                "no_longer_unsafe" if !matches!(change.kind.as_str(), "trait_method" | "interface") => NON_BREAKING,
+                _ => BREAKING,
+            };
+            semver.push(&change.kind, &change.name, level, qualifier_change.replace('_', " "));
+        }
+    }
+
+    // Trait impls count like their addition and removal; inherent impls are as public as their type
+    let generics_public = |change: &&GenericsChange| match change.kind.as_str() {
+        "trait_impl" => true,
+        "impl" => is_public(old_ast, "type", &impl_type(&change.name)),
+        kind => is_public(old_ast, kind, &change.name),
+    };
+    for change in changes.generics_changes.iter().filter(generics_public) {
+        let is_fn = matches!(change.kind.as_str(), "function" | "method" | "trait_method");
+        for param in &change.removed_params {
+            semver.push(&change.kind, &change.name, BREAKING, format!("removed generic parameter {}", param.name));
+        }
+        for param in &change.added_params {
+            // Callers naming the parameters with a turbofish break when a function gains one
+            let level = match (is_fn, &param.default) {
+                (true, _) => POTENTIALLY_BREAKING,
+                (false, Some(_)) => NON_BREAKING,
+                (false, None) => BREAKING,
+            };
+            semver.push(&change.kind, &change.name, level, format!("added generic parameter {}", param.name));
+        }
+        // A new bound only breaks the uses that don't meet it
+        for bound in &change.added_bounds {
+            semver.push(&change.kind, &change.name, POTENTIALLY_BREAKING, format!("added bound {}", bound));
+        }
+        for bound in &change.removed_bounds {
+            // Code generic over a trait may rely on its supertraits and bounds
+            let level = if change.kind == "interface" { POTENTIALLY_BREAKING } else { NON_BREAKING };
+            semver.push(&change.kind, &change.name, level, format!("removed bound {}", bound));
+        }
+    }
+
+    for change in changes.lifetime_changes.iter().filter(|c| is_public(old_ast, &c.kind, &c.name)) {
+        let reason = format!("changed lifetimes: {}", change.changes.join(", "));
+        semver.push(&change.kind, &change.name, POTENTIALLY_BREAKING, reason);
+    }
+
+    for fields in changes.field_changes.iter().filter(|c| is_public(old_ast, "type", &c.name)) {
+        // Struct literals and patterns without `..` need every field to be public and listed
+        let old_fields = old_ast.struct_fields.get(&fields.name);
+        let has_private_fields = old_fields.is_some_and(|old| old.iter().any(|field| field.visibility != "pub"));
+        let exhaustive = !has_private_fields && !is_non_exhaustive(old_ast, "type", &fields.name);
+
+        for field in fields.removed_fields.iter().filter(|field| field.visibility == "pub") {
+            semver.push("type", &fields.name, BREAKING, format!("removed public field {}", field.name));
+        }
+        for field in &fields.modified_fields {
+            let (old, new) = (&field.old_field, &field.new_field);
+            if old.visibility == "pub" && new.visibility != "pub" {
+                let reason = format!("field {} is no longer public", field.name);
+                semver.push("type", &fields.name, BREAKING, reason);
+            } else if old.visibility == "pub" && old.ty != new.ty {
+                let reason = format!("changed type of public field {}", field.name);
+                semver.push("type", &fields.name, BREAKING, reason);
+            } else if old.visibility != "pub" && new.visibility == "pub" {
+                semver.push("type", &fields.name, NON_BREAKING, format!("field {} became public", field.name));
+            }
+        }
+        for field in &fields.added_fields {
+            if exhaustive {
+                let reason = format!("added field {} to a struct that can be built with a literal", field.name);
+                semver.push("type", &fields.name, BREAKING, reason);
+            } else {
+                semver.push("type", &fields.name, NON_BREAKING, format!("added field {}", field.name));
+            }
+        }
+    }
+
+    for variants in changes.variant_changes.iter().filter(|c| is_public(old_ast, "type", &c.name)) {
+        let exhaustive = !is_non_exhaustive(old_ast, "type", &variants.name);
+        for variant in &variants.removed_variants {
+            semver.push("type", &variants.name, BREAKING, format!("removed variant {}", variant.name));
+        }
+        for variant in &variants.modified_variants {
+            let (old, new) = (&variant.old_variant, &variant.new_variant);
+            if old.shape != new.shape || old.fields != new.fields {
+                let reason = format!("changed payload of variant {}", variant.name);
+                semver.push("type", &variants.name, BREAKING, reason);
+            } else if old.discriminant != new.discriminant {
+                let reason = format!("changed discriminant of variant {}", variant.name);
+                semver.push("type", &variants.name, POTENTIALLY_BREAKING, reason);
+            }
+        }
+        for variant in &variants.added_variants {
+            if exhaustive {
+                let reason = format!("added variant {} to an enum that can be matched exhaustively", variant.name);
+                semver.push("type", &variants.name, BREAKING, reason);
+            } else {
+                semver.push("type", &variants.name, NON_BREAKING, format!("added variant {}", variant.name));
+            }
+        }
+    }
+
+    for change in changes.attribute_changes.iter().filter(|c| is_public(old_ast, &c.kind, &c.name)) {
+        for (derive, level, verb) in derive_changes(&change.removed_attributes, &change.added_attributes)
+            .into_iter()
+            .map(|derive| (derive, BREAKING, "no longer derives"))
+            .chain(
+                derive_changes(&change.added_attributes, &change.removed_attributes)
+                    .into_iter()
+                    .map(|derive| (derive, NON_BREAKING, "now derives")),
+            )
+        {
+            semver.push(&change.kind, &change.name, level, format!("{} {}", verb, derive));
+        }
+        if change.added_attributes.iter().any(|attr| attr == "#[non_exhaustive]") {
+            semver.push(&change.kind, &change.name, BREAKING, "became #[non_exhaustive]".to_string());
+        }
+        if change.removed_attributes.iter().any(|attr| attr == "#[non_exhaustive]") {
+            let reason = "is no longer #[non_exhaustive]".to_string();
+            semver.push(&change.kind, &change.name, NON_BREAKING, reason);
+        }
+        let repr = |attrs: &[String]| attrs.iter().any(|attr| attr.starts_with("#[repr("));
+        if repr(&change.added_attributes) || repr(&change.removed_attributes) {
+            semver.push(&change.kind, &change.name, POTENTIALLY_BREAKING, "changed #[repr]".to_string());
+        }
+    }
+
+    // Trait items that lose their default have to be provided by every implementor
+    for (kind, _, modified, _) in entity_lists {
+        for entry in modified {
+            let defaults = (
+                trait_item_default(old_ast, kind, &entry[0]),
+                trait_item_default(new_ast, kind, &entry[0]),
+            );
+            match defaults {
+                (Some(true), Some(false)) => {
+                    let reason = format!("removed the default of a {} of a public trait", label(kind));
+                    semver.push(kind, &entry[0], BREAKING, reason);
+                }
+                (Some(false), Some(true)) => {
+                    let reason = format!("added a default to a {} of a public trait", label(kind));
+                    semver.push(kind, &entry[0], NON_BREAKING, reason);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Modified public entities with nothing more specific to say about them. Traits whose items
+    // changed are covered by the changes to those items.
+    for (kind, _, modified, _) in entity_lists {
+        for entry in modified.iter().filter(|entry| is_public(old_ast, kind, &entry[0])) {
+            let has_item_changes = kind == "interface" && changes.trait_item_changes.iter().any(|c| c.name == entry[0]);
+            if has_item_changes || semver.covers(kind, &entry[0]) {
+                continue;
+            }
+            let (level, reason) = match kind {
+                "function" | "method" | "trait_method" => (NON_BREAKING, "changed the body only"),
+                _ => (POTENTIALLY_BREAKING, "changed its definition"),
+            };
+            semver.push(kind, &entry[0], level, reason.to_string());
+        }
+    }
+
+    for reexport in &changes.removed_reexports {
+        semver.push("reexport", reexport, BREAKING, "removed public re-export".to_string());
+    }
+    for reexport in &changes.added_reexports {
+        semver.push("reexport", reexport, NON_BREAKING, "added public re-export".to_string());
+    }
+
+    // Test code isn't part of the API, even when declared `pub`
+    let mut semver_changes = semver.changes;
+    semver_changes.retain(|change| {
+        !changes.test_changes.iter().any(|test| test.kind == change.kind && test.name == change.name)
+    });
+    semver_changes
+}
+
+// Sum up the semver changes of all files, most severe level first within each file
+pub fn build_semver_report(all_changes: &[DetailedChanges]) -> SemverReport {
+    let mut changes = Vec::new();
+    for c in all_changes {
+        let mut file_changes: Vec<&SemverChange> = c.semver_changes.iter().collect();
+        file_changes.sort_by_key(|change| std::cmp::Reverse(severity(&change.level)));
+        changes.extend(file_changes.into_iter().map(|change| SemverReportEntry {
+            module: c.module_name.clone(),
+            qualified_name: change.name.clone(),
+            full_name: full_name(c.crate_name.as_deref(), &change.name),
+            change: SemverChange {
+                name: short_name(c.module_path.as_deref(), &change.name).to_string(),
+                ..change.clone()
+            },
+        }));
+    }
+
+    let count = |level: &str| changes.iter().filter(|entry| entry.change.level == level).count();
+    SemverReport {
+        level: changes
+            .iter()
+            .map(|entry| entry.change.level.as_str())
+            .max_by_key(|level| severity(level))
+            .unwrap_or(NON_BREAKING)
+            .to_string(),
+        breaking: count(BREAKING),
+        potentially_breaking: count(POTENTIALLY_BREAKING),
+        non_breaking: count(NON_BREAKING),
+        changes,
+    }
+}
+
+// SemverChanges collects the classified changes of one file
+#[derive(Default)]
+struct SemverChanges {
+    changes: Vec<SemverChange>,
+}
+
+impl SemverChanges {
+    fn push(&mut self, kind: &str, name: &str, level: &str, reason: String) {
+        self.changes.push(SemverChange {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            level: level.to_string(),
+            reason,
+        });
+    }
+
+    // Whether an entity already has a classified change
+    fn covers(&self, kind: &str, name: &str) -> bool {
+        self.changes.iter().any(|change| change.kind == kind && change.name == name)
+    }
+}
+
+// Rank of a level, higher being more severe
+fn severity(level: &str) -> u8 {
+    match level {
+        BREAKING => 2,
+        POTENTIALLY_BREAKING => 1,
+        _ => 0,
+    }
+}
+
+// Human-readable name of an entity kind
+fn label(kind: &str) -> String {
+    match kind {
+        "interface" => "trait".to_string(),
+        "associated_const" => "associated const".to_string(),
+        "associated_type" => "associated type".to_string(),
+        "trait_method" => "trait method".to_string(),
+        kind => kind.to_string(),
+    }
+}
+
+// Whether an entity is public on one side. Items of trait impls are left to the impl itself.
+pub fn is_public(ast: &FileASTData, kind: &str, name: &str) -> bool {
+    if matches!(kind, "method" | "trait_method" | "associated_const" | "associated_type") {
+        if is_trait_impl_item(ast, name) {
+            return false;
+        }
+        if let Some(trait_def) = owning_trait(ast, name) {
+            return matches!(trait_def.vis, Visibility::Public(_));
+        }
+    }
+
+    match kind {
+        "macro" => ast
+            .macros
+            .get(name)
+            .is_some_and(|macro_def| macro_def.attrs.iter().any(|attr| attr.path().is_ident("macro_export"))),
+        _ => entity_source_code(ast, kind, name)
+            .and_then(|code| split_outer_attributes(&code))
+            .is_some_and(|code| code.visibility == "pub"),
+    }
+}
+
+// The type an inherent impl is on, e.g. `Store` for `Store<T>` and `<Store<T> where T: Send>`
+fn impl_type(owner: &str) -> String {
+    let unwrapped = match owner.strip_prefix('<') {
+        Some(rest) => rest.to_string(),
+        None => owner.replacen("::<", "::", 1),
+    };
+    unwrapped.split(['<', ' ']).next().unwrap_or_default().to_string()
+}
+
+// Owners an item key may have: before the last `::` for methods, before the last `.` for
+// associated items, e.g. `Store` for `Store::get` and `Store.Key`
+fn owners(name: &str) -> impl Iterator<Item = &str> {
+    let key = name.split(" #[cfg(").next().unwrap_or(name);
+    [key.rsplit_once("::"), key.rsplit_once('.')]
+        .into_iter()
+        .flatten()
+        .map(|(owner, _)| owner)
+}
+
+// Whether an item belongs to a trait impl, as `<Trait for Type>::name` does
+fn is_trait_impl_item(ast: &FileASTData, name: &str) -> bool {
+    owners(name).any(|owner| ast.trait_impls.keys().any(|key| cfg::names(key, owner)))
+}
+
+// The trait declaring a trait method or associated item
+fn owning_trait<'a>(ast: &'a FileASTData, name: &str) -> Option<&'a ItemTrait> {
+    owners(name).find_map(|owner| {
+        ast.interfaces
+            .iter()
+            .find(|(key, _)| cfg::names(key, owner))
+            .map(|(_, trait_def)| trait_def)
+    })
+}
+
+// Whether an item of a public trait has a default, or None for anything else
+fn trait_item_default(ast: &FileASTData, kind: &str, name: &str) -> Option<bool> {
+    if !owning_trait(ast, name).is_some_and(|trait_def| matches!(trait_def.vis, Visibility::Public(_))) {
+        return None;
+    }
+    match kind {
+        "trait_method" => ast.trait_methods.get(name).map(|method| method.default.is_some()),
+        "associated_const" | "associated_type" => {
+            let items = if kind == "associated_const" { &ast.associated_consts } else { &ast.associated_types };
+            match syn::parse2::<TraitItem>(items.get(name)?.clone()).ok()? {
+                TraitItem::Const(item) => Some(item.default.is_some()),
+                TraitItem::Type(item) => Some(item.default.is_some()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// Whether a type is declared `#[non_exhaustive]`
+pub fn is_non_exhaustive(ast: &FileASTData, kind: &str, name: &str) -> bool {
+    entity_source_code(ast, kind, name)
+        .and_then(|code| split_outer_attributes(&code))
+        .is_some_and(|code| code.attributes.iter().any(|attr| attr == "#[non_exhaustive]"))
+}
+
+// Traits derived by the `#[derive(..)]` attributes of one list that the other list doesn't derive
+fn derive_changes(attributes: &[String], others: &[String]) -> Vec<String> {
+    let derives = |attributes: &[String]| -> Vec<String> {
+        attributes
+            .iter()
+            .filter_map(|attr| attr.strip_prefix("#[derive(")?.strip_suffix(")]"))
+            .flat_map(|list| list.split(','))
+            .map(|derive| derive.trim().to_string())
+            .filter(|derive| !derive.is_empty())
+            .collect()
+    };
+    let others = derives(others);
+    derives(attributes)
+        .into_iter()
+        .filter(|derive| !others.contains(derive))
+        .collect()
+}
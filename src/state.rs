@@ -0,0 +1,199 @@
+// src/state.rs
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast_parser::text_hash;
+use crate::cli::DiffArgs;
+use crate::git_ops::{get_blob_ids, resolve_commit};
+use crate::ignore;
+use crate::types::{AnalysisError, CalledFunctionChanges, DetailedChanges, GranularChanges};
+
+// FileState stores the results of one analyzed file, keyed by the blobs it was computed from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileState {
+    pub old_blob: Option<String>,
+    pub new_blob: Option<String>,
+    pub changes: Option<DetailedChanges>,
+    pub granular: HashMap<String, CalledFunctionChanges>,
+}
+
+// RunState records what has already been analyzed for a commit pair
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunState {
+    pub old_commit: String,
+    pub new_commit: String,
+    // Fingerprint of the settings the results were computed under, see settings_fingerprint
+    #[serde(default)]
+    pub settings: String,
+    pub files: HashMap<String, FileState>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    current_blobs: HashMap<String, (Option<String>, Option<String>)>,
+}
+
+impl RunState {
+    // Load the state for the resolved commit pair, starting fresh if none was recorded or it
+    // was recorded under other settings
+    pub fn load(
+        state_dir: &str,
+        old_ref: &str,
+        new_ref: &str,
+        rust_files: &[String],
+        settings: &str,
+        local_repo_path: &str,
+    ) -> Result<Self, String> {
+        let old_commit = resolve_commit(old_ref, local_repo_path)?;
+        let new_commit = resolve_commit(new_ref, local_repo_path)?;
+        let path = Path::new(state_dir).join(format!("state-{}-{}.json", old_commit, new_commit));
+
+        let mut state = match fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str::<RunState>(&content) {
+                Ok(state) if state.settings == settings => state,
+                Ok(_) => {
+                    eprintln!("Ignoring state file {} recorded with other settings", path.display());
+                    RunState::empty(&old_commit, &new_commit, settings)
+                }
+                Err(e) => {
+                    eprintln!("Warning: Ignoring unreadable state file {}: {}", path.display(), e);
+                    RunState::empty(&old_commit, &new_commit, settings)
+                }
+            },
+            Err(_) => RunState::empty(&old_commit, &new_commit, settings),
+        };
+        state.path = path;
+
+        // Record the blobs the files currently resolve to on each side
+        let old_blobs = get_blob_ids(&old_commit, rust_files, local_repo_path)?;
+        let new_blobs = get_blob_ids(&new_commit, rust_files, local_repo_path)?;
+        for file in rust_files {
+            state.current_blobs.insert(
+                file.clone(),
+                (old_blobs.get(file).cloned(), new_blobs.get(file).cloned()),
+            );
+        }
+
+        Ok(state)
+    }
+
+    fn empty(old_commit: &str, new_commit: &str, settings: &str) -> Self {
+        RunState {
+            old_commit: old_commit.to_string(),
+            new_commit: new_commit.to_string(),
+            settings: settings.to_string(),
+            files: HashMap::new(),
+            path: PathBuf::new(),
+            current_blobs: HashMap::new(),
+        }
+    }
+
+    // Check whether a file was analyzed before with the same blobs
+    fn is_complete(&self, file: &str) -> bool {
+        match (self.files.get(file), self.current_blobs.get(file)) {
+            (Some(saved), Some((old_blob, new_blob))) => {
+                &saved.old_blob == old_blob && &saved.new_blob == new_blob
+            }
+            _ => false,
+        }
+    }
+
+    // Files that still need to be analyzed
+    pub fn pending_files(&self, rust_files: &[String]) -> Vec<String> {
+        rust_files
+            .iter()
+            .filter(|file| !self.is_complete(file))
+            .cloned()
+            .collect()
+    }
+
+    // Record the results of freshly analyzed files, skipping files that hit errors
+    pub fn record_results(
+        &mut self,
+        analyzed_files: &[String],
+        all_changes: &[DetailedChanges],
+        granular_changes: &GranularChanges,
+        errors: &[AnalysisError],
+    ) {
+        // A failure not tied to a file (e.g. a checkout) invalidates the whole pass
+        if errors.iter().any(|e| e.file.is_none()) {
+            return;
+        }
+
+        for file in analyzed_files {
+            if errors.iter().any(|e| e.file.as_deref() == Some(file.as_str())) {
+                continue;
+            }
+
+            let (old_blob, new_blob) = self.current_blobs.get(file).cloned().unwrap_or_default();
+            self.files.insert(
+                file.clone(),
+                FileState {
+                    old_blob,
+                    new_blob,
+                    changes: all_changes.iter().find(|c| &c.module_name == file).cloned(),
+                    granular: granular_changes.get(file).cloned().unwrap_or_default(),
+                },
+            );
+        }
+    }
+
+    // Combine saved and fresh results, keeping the order of the input files
+    pub fn merge_results(
+        &self,
+        rust_files: &[String],
+        all_changes: Vec<DetailedChanges>,
+        mut granular_changes: GranularChanges,
+    ) -> (Vec<DetailedChanges>, GranularChanges) {
+        let mut fresh: HashMap<String, DetailedChanges> = all_changes
+            .into_iter()
+            .map(|c| (c.module_name.clone(), c))
+            .collect();
+        let mut merged = Vec::new();
+
+        for file in rust_files {
+            if let Some(changes) = fresh.remove(file) {
+                merged.push(changes);
+            } else if let Some(saved) = self.files.get(file).filter(|_| self.is_complete(file)) {
+                if let Some(changes) = &saved.changes {
+                    merged.push(changes.clone());
+                }
+                if !saved.granular.is_empty() && !granular_changes.contains_key(file) {
+                    granular_changes.insert(file.clone(), saved.granular.clone());
+                }
+            }
+        }
+
+        (merged, granular_changes)
+    }
+
+    // Persist the state to the state directory
+    pub fn save(&self) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Error creating state directory: {}", e))?;
+        }
+
+        let state_json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Error marshaling run state: {}", e))?;
+        fs::write(&self.path, state_json)
+            .map_err(|e| format!("Error writing state file {}: {}", self.path.display(), e))
+    }
+}
+
+// Fingerprint of the settings that shape a file's results: cfg features, normalization, risk
+// weights, ignore rules, copy detection and blame
+pub fn settings_fingerprint(args: &DiffArgs) -> String {
+    let options = &args.options;
+    let settings = format!(
+        "{:?} {:?} {:?} {:?} {:?} {} {}",
+        options.features,
+        options.normalize,
+        options.cosmetic,
+        options.risk_weights,
+        ignore::configured_rules(),
+        options.find_copies,
+        args.blame
+    );
+    text_hash(&settings)
+}
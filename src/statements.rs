@@ -0,0 +1,204 @@
+// src/statements.rs
+use quote::ToTokens;
+use syn::spanned::Spanned;
+use syn::{Arm, Block, Expr, Stmt};
+
+use crate::ast_parser::get_source_code;
+use crate::types::{DetailedChanges, FileASTData, StatementChanges, StatementHunk};
+
+// Node is one statement of a block, or one arm of a match, with what it nests
+struct Node {
+    // Tokens the node is compared by
+    tokens: String,
+    // Source text as written
+    code: String,
+    line: usize,
+    // What surrounds the nested statements, e.g. the condition of a `while`, and the statements
+    nested: Option<(String, Vec<Node>)>,
+}
+
+// Diff the bodies of modified functions, methods and trait methods statement by statement,
+// keeping the statements both sides share in order. A statement that only changed inside its
+// nested block, such as the body of a loop, `if` or match arm, is diffed down to the statements
+// of that block.
+pub fn find_statement_changes(
+    changes: &DetailedChanges,
+    old_ast: &FileASTData,
+    new_ast: &FileASTData,
+) -> Vec<StatementChanges> {
+    let entries = changes
+        .modified_functions
+        .iter()
+        .map(|entry| ("function", entry))
+        .chain(changes.modified_methods.iter().map(|entry| ("method", entry)))
+        .chain(changes.modified_trait_methods.iter().map(|entry| ("trait_method", entry)));
+
+    entries
+        .filter_map(|(kind, entry)| {
+            let old_block = fn_block(old_ast, kind, &entry[0])?;
+            let new_block = fn_block(new_ast, kind, &entry[0])?;
+            let old_nodes = block_nodes(old_block, &old_ast.file_content);
+            let new_nodes = block_nodes(new_block, &new_ast.file_content);
+
+            let mut hunks = Vec::new();
+            diff_nodes(&old_nodes, &new_nodes, &mut hunks);
+            (!hunks.is_empty()).then(|| StatementChanges {
+                kind: kind.to_string(),
+                name: entry[0].clone(),
+                hunks,
+            })
+        })
+        .collect()
+}
+
+// Body of a function, method or trait method with a default
+fn fn_block<'a>(ast: &'a FileASTData, kind: &str, name: &str) -> Option<&'a Block> {
+    match kind {
+        "function" => ast.functions.get(name).map(|func| &*func.block),
+        "method" => ast.methods.get(name).map(|(_, method)| &*method.block),
+        "trait_method" => ast.trait_methods.get(name)?.default.as_ref(),
+        _ => None,
+    }
+}
+
+// Nodes of the statements of a block
+fn block_nodes(block: &Block, content: &str) -> Vec<Node> {
+    block
+        .stmts
+        .iter()
+        .map(|stmt| Node {
+            tokens: stmt.to_token_stream().to_string(),
+            code: get_source_code(stmt, content),
+            line: stmt.span().start().line,
+            nested: match stmt {
+                Stmt::Expr(expr, _) => nested_nodes(expr, content),
+                _ => None,
+            },
+        })
+        .collect()
+}
+
+// Nodes of the arms of a match, nesting the statements of block bodies
+fn arm_nodes(arms: &[Arm], content: &str) -> Vec<Node> {
+    arms.iter()
+        .map(|arm| Node {
+            tokens: arm.to_token_stream().to_string(),
+            code: get_source_code(arm, content),
+            line: arm.span().start().line,
+            nested: match &*arm.body {
+                Expr::Block(body) => {
+                    let guard = arm.guard.as_ref().map(|(_, guard)| tokens(guard)).unwrap_or_default();
+                    let surrounding = format!("{} if {}", tokens(&arm.pat), guard);
+                    Some((surrounding, block_nodes(&body.block, content)))
+                }
+                _ => None,
+            },
+        })
+        .collect()
+}
+
+// The statements or arms an expression nests, with everything around them
+fn nested_nodes(expr: &Expr, content: &str) -> Option<(String, Vec<Node>)> {
+    let (surrounding, block) = match expr {
+        Expr::Block(expr) => (format!("block {}", tokens(&expr.label)), &expr.block),
+        Expr::Unsafe(expr) => ("unsafe".to_string(), &expr.block),
+        Expr::Loop(expr) => (format!("loop {}", tokens(&expr.label)), &expr.body),
+        Expr::While(expr) => (format!("while {} {}", tokens(&expr.label), tokens(&expr.cond)), &expr.body),
+        Expr::ForLoop(expr) => {
+            let surrounding = format!("for {} {} in {}", tokens(&expr.label), tokens(&expr.pat), tokens(&expr.expr));
+            (surrounding, &expr.body)
+        }
+        Expr::If(expr) => {
+            let else_branch = expr.else_branch.as_ref().map(|(_, branch)| tokens(branch)).unwrap_or_default();
+            (format!("if {} else {}", tokens(&expr.cond), else_branch), &expr.then_branch)
+        }
+        Expr::Match(expr) => {
+            return Some((format!("match {}", tokens(&expr.expr)), arm_nodes(&expr.arms, content)));
+        }
+        _ => return None,
+    };
+    Some((surrounding, block_nodes(block, content)))
+}
+
+// Tokens of a node as one string, for comparing what surrounds nested statements
+fn tokens(node: &impl ToTokens) -> String {
+    node.to_token_stream().to_string()
+}
+
+// Diff two lists of nodes through their longest common subsequence, collecting a hunk for each
+// run of removed and added nodes between the ones both sides keep
+fn diff_nodes(old: &[Node], new: &[Node], hunks: &mut Vec<StatementHunk>) {
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i].tokens == new[j].tokens {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i].tokens == new[j].tokens {
+            push_hunk(std::mem::take(&mut removed), std::mem::take(&mut added), hunks);
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            added.push(&new[j]);
+            j += 1;
+        } else {
+            removed.push(&old[i]);
+            i += 1;
+        }
+    }
+    push_hunk(removed, added, hunks);
+}
+
+// Record a run of removed and added nodes. A removed and an added node nesting statements in
+// the same surroundings, like the same loop, are diffed down to those statements, and the nodes
+// before and after them recorded on their own.
+fn push_hunk(mut removed: Vec<&Node>, mut added: Vec<&Node>, hunks: &mut Vec<StatementHunk>) {
+    if removed.is_empty() && added.is_empty() {
+        return;
+    }
+
+    let pair = removed.iter().enumerate().find_map(|(old_index, old)| {
+        let (surrounding, _) = old.nested.as_ref()?;
+        added
+            .iter()
+            .position(|new| new.nested.as_ref().is_some_and(|(other, _)| other == surrounding))
+            .map(|new_index| (old_index, new_index))
+    });
+    let Some((old_index, new_index)) = pair else {
+        hunks.push(hunk(&removed, &added));
+        return;
+    };
+
+    let (removed_after, added_after) = (removed.split_off(old_index + 1), added.split_off(new_index + 1));
+    let (old, new) = (removed.pop(), added.pop());
+    push_hunk(removed, added, hunks);
+    if let (Some(old), Some(new)) = (old, new) {
+        let found = hunks.len();
+        if let (Some((_, old_nodes)), Some((_, new_nodes))) = (&old.nested, &new.nested) {
+            diff_nodes(old_nodes, new_nodes, hunks);
+        }
+        // Nodes differing somewhere else, like in their attributes, are reported whole
+        if hunks.len() == found {
+            hunks.push(hunk(&[old], &[new]));
+        }
+    }
+    push_hunk(removed_after, added_after, hunks);
+}
+
+// Hunk of removed and added nodes, located by the first one on each side
+fn hunk(removed: &[&Node], added: &[&Node]) -> StatementHunk {
+    StatementHunk {
+        old_line: removed.first().map(|node| node.line),
+        new_line: added.first().map(|node| node.line),
+        removed: removed.iter().map(|node| node.code.clone()).collect(),
+        added: added.iter().map(|node| node.code.clone()).collect(),
+    }
+}
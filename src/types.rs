@@ -1,7 +1,13 @@
 // src/types.rs
+use proc_macro2::TokenStream;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use syn::{Item, ItemFn, ItemImpl, ItemTrait};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::sync::Mutex;
+use syn::{ExprClosure, Ident, Item, ItemFn, ItemImpl, ItemMacro, ItemTrait, TraitItemFn};
+
+use crate::content::{ContentSource, LfsMode};
+use crate::git_ops::{CaseRename, ModeChange};
 
 // SourceLocation captures position information of a declaration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,15 +24,49 @@ pub struct SourceLocation {
 pub struct TypedLiteral {
     pub type_name: String,
     pub value: String,
+    // Type suffix as written, e.g. `u8` in `1u8`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub suffix: String,
+    // Whether a string literal is raw, e.g. `r#"..."#`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub raw: bool,
+    pub location: SourceLocation,
+}
+
+impl TypedLiteral {
+    // Whether two literals are the same wherever they sit, including suffix and raw form
+    pub fn same_as(&self, other: &TypedLiteral) -> bool {
+        self.type_name == other.type_name
+            && self.value == other.value
+            && self.suffix == other.suffix
+            && self.raw == other.raw
+    }
 }
 
 // CalledFunctionChanges captures the granular changes in function calls
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CalledFunctionChanges {
+    // Name of the function or method within its file; the map key qualifies it with the module path
+    pub name: String,
     pub added_functions: Vec<String>,
     pub removed_functions: Vec<String>,
     pub added_literals: Vec<TypedLiteral>,
     pub removed_literals: Vec<TypedLiteral>,
+    // Awaited expressions and expressions propagated with `?`, added and removed in the new version
+    pub added_await_points: Vec<String>,
+    pub removed_await_points: Vec<String>,
+    pub added_error_propagations: Vec<String>,
+    pub removed_error_propagations: Vec<String>,
+    // Calls made in both versions with different numbers of arguments
+    pub arity_changes: Vec<ArityChange>,
+    // Unsafe blocks, and calls made in unsafe context, added and removed in the new version
+    pub added_unsafe_blocks: Vec<UnsafeRegion>,
+    pub removed_unsafe_blocks: Vec<UnsafeRegion>,
+    pub added_unsafe_calls: Vec<String>,
+    pub removed_unsafe_calls: Vec<String>,
+    // Expressions that can panic, added and removed in the new version
+    pub added_panic_points: Vec<PanicPoint>,
+    pub removed_panic_points: Vec<PanicPoint>,
     pub old_function_src_loc: SourceLocation,
     pub new_function_src_loc: SourceLocation,
 }
@@ -36,10 +76,61 @@ impl CalledFunctionChanges {
         !self.added_functions.is_empty() ||
         !self.removed_functions.is_empty() ||
         !self.added_literals.is_empty() ||
-        !self.removed_literals.is_empty()
+        !self.removed_literals.is_empty() ||
+        !self.added_await_points.is_empty() ||
+        !self.removed_await_points.is_empty() ||
+        !self.added_error_propagations.is_empty() ||
+        !self.removed_error_propagations.is_empty() ||
+        !self.arity_changes.is_empty() ||
+        !self.added_unsafe_blocks.is_empty() ||
+        !self.removed_unsafe_blocks.is_empty() ||
+        !self.added_unsafe_calls.is_empty() ||
+        !self.removed_unsafe_calls.is_empty() ||
+        !self.added_panic_points.is_empty() ||
+        !self.removed_panic_points.is_empty()
     }
 }
 
+// UnsafeRegion is an `unsafe { ... }` block of a function as written, its content hash and where it sits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsafeRegion {
+    pub code: String,
+    pub hash: String,
+    pub location: SourceLocation,
+}
+
+// PanicPoint is an expression of a function that can panic: a `panic!`, `todo!`, `unimplemented!`
+// or `unreachable!`, an `unwrap()` or `expect()`, or indexing, with where it sits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PanicPoint {
+    // `panic`, `todo`, `unimplemented`, `unreachable`, `unwrap`, `expect` or `index`
+    pub kind: String,
+    pub code: String,
+    pub location: SourceLocation,
+}
+
+// PanicReportEntry is a modified function that newly introduces panic points, with its file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PanicReportEntry {
+    pub module: String,
+    pub qualified_name: String,
+    pub added_panic_points: Vec<PanicPoint>,
+}
+
+// ArityChange records a call whose argument counts differ between versions, e.g. `foo(a)`
+// becoming `foo(a, b)`, with the receiver's declared type for method calls where known
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArityChange {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receiver_type: Option<String>,
+    pub old_arg_counts: Vec<usize>,
+    pub new_arg_counts: Vec<usize>,
+}
+
+// GranularChanges maps file => function => call/literal changes
+pub type GranularChanges = HashMap<String, HashMap<String, CalledFunctionChanges>>;
+
 // NamedCode represents a named code entity with its source
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NamedCode {
@@ -55,10 +146,498 @@ pub struct ModifiedCode {
     pub new_code: String,
 }
 
+// MacroArmChanges captures the rules added, removed or rewritten in a modified macro.
+// Arms are matched by their matcher, so a rewritten arm keeps its matcher as its name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacroArmChanges {
+    pub name: String,
+    pub added_arms: Vec<String>,
+    pub modified_arms: Vec<ModifiedCode>,
+    pub removed_arms: Vec<String>,
+    // Arms whose matcher changed while what they expand to stayed the same
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modified_matchers: Vec<MatcherChange>,
+    // Whether the arms both sides have are tried in a different order, which changes which
+    // arm an invocation matching several of them expands
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reordered: bool,
+}
+
+// MatcherChange records the old and new matcher of a macro arm with the same transcriber
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatcherChange {
+    pub old_matcher: String,
+    pub new_matcher: String,
+    pub transcriber: String,
+}
+
+// StructField is one field of a struct or union, with tuple struct fields named by position
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructField {
+    pub name: String,
+    pub ty: String,
+    pub visibility: String,
+    // Outer attributes other than doc comments, in compact form
+    pub attributes: Vec<String>,
+}
+
+// ModifiedField records a field whose type, visibility or attributes changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModifiedField {
+    pub name: String,
+    pub old_field: StructField,
+    pub new_field: StructField,
+}
+
+// FieldChanges captures the fields added, removed or changed in a modified struct or union.
+// Fields are matched by name, so a renamed field is one removed and one added.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldChanges {
+    pub name: String,
+    pub added_fields: Vec<StructField>,
+    pub modified_fields: Vec<ModifiedField>,
+    pub removed_fields: Vec<StructField>,
+}
+
+// EnumVariant is one variant of an enum, with the fields of its payload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnumVariant {
+    pub name: String,
+    // `unit`, `tuple` or `struct`
+    pub shape: String,
+    pub fields: Vec<StructField>,
+    // Explicit discriminant expression, as in `A = 1`
+    pub discriminant: Option<String>,
+    // Outer attributes other than doc comments, in compact form
+    pub attributes: Vec<String>,
+}
+
+// ModifiedVariant records a variant whose payload, discriminant or attributes changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModifiedVariant {
+    pub name: String,
+    pub old_variant: EnumVariant,
+    pub new_variant: EnumVariant,
+}
+
+// VariantChanges captures the variants added, removed or changed in a modified enum
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VariantChanges {
+    pub name: String,
+    pub added_variants: Vec<EnumVariant>,
+    pub modified_variants: Vec<ModifiedVariant>,
+    pub removed_variants: Vec<EnumVariant>,
+}
+
+// TraitItemChanges breaks a modified trait down into the methods, associated types and
+// associated consts that changed, named as they are within the trait
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraitItemChanges {
+    pub name: String,
+    pub added_methods: Vec<String>,
+    pub modified_methods: Vec<String>,
+    pub removed_methods: Vec<String>,
+    pub added_associated_types: Vec<String>,
+    pub modified_associated_types: Vec<String>,
+    pub removed_associated_types: Vec<String>,
+    pub added_associated_consts: Vec<String>,
+    pub modified_associated_consts: Vec<String>,
+    pub removed_associated_consts: Vec<String>,
+}
+
+// GlobalItem is a piece of global state: a static of a lazily initialized or interior-mutable
+// type, a `static mut`, or a static declared through `thread_local!` or `lazy_static!`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GlobalItem {
+    // `lazy`, `once`, `interior_mutable`, `static_mut`, `thread_local` or `lazy_static`
+    pub kind: String,
+    pub ty: String,
+    pub initializer: String,
+}
+
+// GlobalChange records a global that was added, removed, or changed its type or initializer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GlobalChange {
+    pub name: String,
+    pub old_global: Option<GlobalItem>,
+    pub new_global: Option<GlobalItem>,
+}
+
+// OuterAttributes splits an entity's formatted code into its doc comments, its other
+// outer attributes and the code after them
+#[derive(Debug, Clone)]
+pub struct OuterAttributes {
+    pub docs: String,
+    pub attributes: Vec<String>,
+    // Visibility the code starts with, e.g. `pub(crate)`, or `private` without one
+    pub visibility: String,
+    pub code: String,
+}
+
+// DocChange records a modified entity whose doc comments are all that changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocChange {
+    pub kind: String,
+    pub name: String,
+    pub old_docs: String,
+    pub new_docs: String,
+}
+
+// VisibilityChange records a modified entity whose visibility changed, e.g. from `pub` to
+// `pub(crate)`, which can break callers even when nothing else changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VisibilityChange {
+    pub kind: String,
+    pub name: String,
+    pub old_visibility: String,
+    pub new_visibility: String,
+}
+
+// FnQualifiers describes the qualifiers of a function or method signature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FnQualifiers {
+    pub is_async: bool,
+    pub is_const: bool,
+    pub is_unsafe: bool,
+    // ABI of an `extern` function, e.g. `C` (also for a bare `extern`) or `system`
+    pub abi: Option<String>,
+}
+
+// FnParam is one parameter of a function signature, with `self` receivers as pattern `self`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FnParam {
+    pub pattern: String,
+    pub ty: String,
+}
+
+// FnSignature is the structured form of a function or method signature, with generic
+// parameters and where-clause predicates rendered one per entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FnSignature {
+    pub name: String,
+    pub generics: Vec<String>,
+    pub where_predicates: Vec<String>,
+    pub params: Vec<FnParam>,
+    pub variadic: bool,
+    // None when the function returns `()` implicitly
+    pub return_type: Option<String>,
+    pub qualifiers: FnQualifiers,
+}
+
+// ParamChange is one change to the parameters or return type of a signature: a parameter
+// `added`, `removed`, `renamed`, `retyped` or `reordered`, or the `return_type` changed. Each
+// side the parameter exists on has its name, type and position; an implicit `()` return type
+// has no type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParamChange {
+    pub change: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_position: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_position: Option<usize>,
+}
+
+// SignatureChange records a modified function or method whose signature changed; modified
+// ones without an entry only changed in their body, attributes or docs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureChange {
+    pub kind: String,
+    pub name: String,
+    pub old_signature: FnSignature,
+    pub new_signature: FnSignature,
+    // The parameter and return type changes between the two signatures, one per entry
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub param_changes: Vec<ParamChange>,
+    pub body_changed: bool,
+}
+
+// GenericParameter is one generic parameter of an item, without its bounds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenericParameter {
+    // `lifetime`, `type` or `const`
+    pub kind: String,
+    pub name: String,
+    // Type of a const parameter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ty: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+// ItemGenerics is the structured form of an item's generics, with bounds split one per entry
+// as `T: Send`, whether written inline, in the where-clause or as supertraits of a trait
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemGenerics {
+    pub params: Vec<GenericParameter>,
+    pub bounds: Vec<String>,
+}
+
+// GenericsChange records how the generic parameters and bounds of a modified item changed; a
+// parameter whose type or default changed is both removed and added
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenericsChange {
+    pub kind: String,
+    pub name: String,
+    pub added_params: Vec<GenericParameter>,
+    pub removed_params: Vec<GenericParameter>,
+    pub added_bounds: Vec<String>,
+    pub removed_bounds: Vec<String>,
+    pub old_generics: ItemGenerics,
+    pub new_generics: ItemGenerics,
+}
+
+// LifetimeUsage describes the lifetimes of a function signature or type: its lifetime parameters
+// and the lifetime of every reference and lifetime argument in order, with elided ones as `'_`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LifetimeUsage {
+    pub params: Vec<String>,
+    pub positions: Vec<String>,
+}
+
+// LifetimeChange records how the lifetimes of a modified function, method, trait method or type
+// changed, as change kinds such as `added_param`, `elided_to_explicit` or `became_static`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LifetimeChange {
+    pub kind: String,
+    pub name: String,
+    pub changes: Vec<String>,
+    pub old_lifetimes: LifetimeUsage,
+    pub new_lifetimes: LifetimeUsage,
+}
+
+// QualifierChange records how the qualifiers of a modified function or method changed, as
+// change kinds such as `became_async`, `no_longer_unsafe` or `changed_abi`. Traits and trait
+// impls that became or stopped being `unsafe` are recorded too, with only `is_unsafe` set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QualifierChange {
+    pub kind: String,
+    pub name: String,
+    pub changes: Vec<String>,
+    pub old_qualifiers: FnQualifiers,
+    pub new_qualifiers: FnQualifiers,
+}
+
+// EntityHash identifies the content of a changed entity on each side it exists on, so tools can
+// recognize the same change across reports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityHash {
+    pub kind: String,
+    pub name: String,
+    pub old_hash: Option<String>,
+    pub new_hash: Option<String>,
+}
+
+// StatementHunk is one run of statements or match arms removed from and added to a function
+// body, with the lines of the first removed and first added one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatementHunk {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_line: Option<usize>,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+// StatementChanges lists the hunks of a modified function, method or trait method body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatementChanges {
+    pub kind: String,
+    pub name: String,
+    pub hunks: Vec<StatementHunk>,
+}
+
+// EntitySimilarity scores how much of a modified entity's code survived the change, as the
+// percentage of tokens both sides keep in order, so tweaks can be told apart from rewrites
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntitySimilarity {
+    pub kind: String,
+    pub name: String,
+    pub similarity: u32,
+}
+
+// ValueChange records the initializer of a const, static or associated const, or the
+// discriminant of an enum variant, changing from one value to another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValueChange {
+    pub kind: String,
+    pub name: String,
+    // None for a discriminant left implicit on that side
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+// DeprecationChange is a public entity, field or variant that gained or lost `#[deprecated]`, or
+// whose deprecation changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeprecationChange {
+    // An entity kind, or `field` or `variant`, named `Type.field` or `Enum::Variant`
+    pub kind: String,
+    pub name: String,
+    // `deprecated`, `undeprecated` or `changed`
+    pub change: String,
+    // The `since` and `note` of the deprecation, the removed one for `undeprecated`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+// DeprecationReportEntry is a deprecation change together with the file it was found in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeprecationReportEntry {
+    pub module: String,
+    pub qualified_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    #[serde(flatten)]
+    pub change: DeprecationChange,
+}
+
+// ErrorSurfaceChange is an error enum whose variants changed, which downstream code matching on
+// it exhaustively has to follow
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorSurfaceChange {
+    pub name: String,
+    // What marks the enum as an error type: its `name`, a `derive` of `Error` or an `error_impl`
+    pub detected_by: Vec<String>,
+    pub public: bool,
+    // Whether the old enum was `#[non_exhaustive]`, so matches on it already had a wildcard arm
+    pub non_exhaustive: bool,
+    pub added_variants: Vec<String>,
+    pub removed_variants: Vec<String>,
+    // Variants whose payload changed shape or fields
+    pub changed_variants: Vec<String>,
+    // Whether downstream matches can stop compiling
+    pub breaking: bool,
+}
+
+// ErrorSurfaceReportEntry is an error surface change together with the file it was found in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorSurfaceReportEntry {
+    pub module: String,
+    pub qualified_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    #[serde(flatten)]
+    pub change: ErrorSurfaceChange,
+}
+
+// RiskScore weighs one added, modified or deleted entity by its kind of change, such as
+// `deleted_public` or `literal`, for ordering review
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskScore {
+    pub kind: String,
+    pub name: String,
+    pub change: String,
+    pub score: u32,
+}
+
+// RenamedEntity records an entity deleted under one name and added under another with nearly the
+// same code, reported instead of as one deleted and one added entity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenamedEntity {
+    pub kind: String,
+    pub old_name: String,
+    pub new_name: String,
+    // Percentage of tokens the two sides share
+    pub similarity: u32,
+    pub old_code: String,
+    pub new_code: String,
+}
+
+// CrossFileMove records an entity deleted from one file and added to another with nearly the
+// same code, reported instead of as one deleted and one added entity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossFileMove {
+    pub kind: String,
+    pub old_module: String,
+    pub old_name: String,
+    pub new_module: String,
+    pub new_name: String,
+    // Percentage of tokens the two sides share
+    pub similarity: u32,
+}
+
+// EntityPosition places an entity in its file: its index among all extracted entities in source
+// order, and the line it starts on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntityPosition {
+    pub index: usize,
+    pub line: usize,
+}
+
+// MovedEntity records an entity on both sides that moved relative to the entities around it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MovedEntity {
+    pub kind: String,
+    pub name: String,
+    pub old_position: EntityPosition,
+    pub new_position: EntityPosition,
+}
+
+// CosmeticChange names a modified entity whose sides are the same once normalized
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CosmeticChange {
+    pub kind: String,
+    pub name: String,
+}
+
+// TestChange names a changed entity that is test code: a test function, or an item of a
+// `#[cfg(test)]` module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestChange {
+    pub kind: String,
+    pub name: String,
+}
+
+// AttributeChange records how the outer attributes, other than doc comments, of a modified entity changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttributeChange {
+    pub kind: String,
+    pub name: String,
+    pub added_attributes: Vec<String>,
+    pub removed_attributes: Vec<String>,
+    // Nothing but the attributes (and maybe docs) changed, so the entity isn't listed among the modified ones
+    pub attributes_only: bool,
+}
+
+// EntityBlame names the commit that last touched an added or modified function or method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityBlame {
+    pub name: String,
+    // Line range of the entity at the current commit
+    pub start_line: usize,
+    pub end_line: usize,
+    pub commit: String,
+    pub author: String,
+    pub author_email: String,
+}
+
 // DetailedChanges captures all types of declarations that can change
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DetailedChanges {
     pub module_name: String,
+    // Crate-relative module path of the file, e.g. `crate::net::tcp`, when a crate root declares it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module_path: Option<String>,
+    // Name of the file's crate as other crates know it, e.g. `net_utils`, when its manifest gives one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crate_name: Option<String>,
+    // Path of the file at the old ref when it was renamed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_from: Option<String>,
+    // Path of the old-ref file this file was copied from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copied_from: Option<String>,
     pub added_functions: Vec<Vec<String>>,
     pub modified_functions: Vec<Vec<String>>,
     pub deleted_functions: Vec<Vec<String>>,
@@ -71,12 +650,124 @@ pub struct DetailedChanges {
     pub added_methods: Vec<Vec<String>>,
     pub modified_methods: Vec<Vec<String>>,
     pub deleted_methods: Vec<Vec<String>>,
+    pub added_trait_methods: Vec<Vec<String>>,
+    pub modified_trait_methods: Vec<Vec<String>>,
+    pub deleted_trait_methods: Vec<Vec<String>>,
+    pub added_constants: Vec<Vec<String>>,
+    pub modified_constants: Vec<Vec<String>>,
+    pub deleted_constants: Vec<Vec<String>>,
+    pub added_macros: Vec<Vec<String>>,
+    pub modified_macros: Vec<Vec<String>>,
+    pub deleted_macros: Vec<Vec<String>>,
+    pub added_associated_consts: Vec<Vec<String>>,
+    pub modified_associated_consts: Vec<Vec<String>>,
+    pub deleted_associated_consts: Vec<Vec<String>>,
+    pub added_associated_types: Vec<Vec<String>>,
+    pub modified_associated_types: Vec<Vec<String>>,
+    pub deleted_associated_types: Vec<Vec<String>>,
+    // Modified entities whose attributes changed, including those where nothing else did
+    pub attribute_changes: Vec<AttributeChange>,
+    // Modified entities where only the doc comments changed, left out of the modified lists
+    pub doc_only_changes: Vec<DocChange>,
+    // Modified entities whose changes are cosmetic under --normalize
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cosmetic_only: Vec<CosmeticChange>,
+    // Modified entities whose visibility changed; they stay in the modified lists
+    pub visibility_changes: Vec<VisibilityChange>,
+    // Modified functions, methods and trait methods that became or stopped being async, const, unsafe or extern
+    pub qualifier_changes: Vec<QualifierChange>,
+    // Modified functions, methods and trait methods whose signature changed, with both signatures
+    pub signature_changes: Vec<SignatureChange>,
+    // Modified functions, methods, trait methods, types and traits whose generic parameters or bounds changed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub generics_changes: Vec<GenericsChange>,
+    // Modified functions, methods, trait methods and types whose lifetimes changed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lifetime_changes: Vec<LifetimeChange>,
+    // Trait impls, as `<Trait for Type>`, that only one side has
+    pub added_trait_impls: Vec<Vec<String>>,
+    pub deleted_trait_impls: Vec<Vec<String>>,
+    // Imported paths, one per name, that only one side has
+    pub added_imports: Vec<String>,
+    pub removed_imports: Vec<String>,
+    // Paths re-exported with `pub use` that only one side has, i.e. changes to the public API surface
+    pub added_reexports: Vec<String>,
+    pub removed_reexports: Vec<String>,
+    // Crate-level `#![..]` attributes that only one side of a crate root has
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added_crate_attributes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_crate_attributes: Vec<String>,
+    // Arm-level changes of modified macros whose rules could be split apart
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub macro_arm_changes: Vec<MacroArmChanges>,
+    // Field-level changes of modified structs and unions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub field_changes: Vec<FieldChanges>,
+    // Variant-level changes of modified enums
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variant_changes: Vec<VariantChanges>,
+    // Item-level changes of modified traits
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trait_item_changes: Vec<TraitItemChanges>,
+    // Changes to the public API, classified by what they mean for downstream crates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub semver_changes: Vec<SemverChange>,
+    // Global state that was added, removed or re-initialized
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub global_changes: Vec<GlobalChange>,
+    // Changed entities that are test code, and whether the file's changes are all test code
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub test_changes: Vec<TestChange>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub test_only: bool,
+    // Content hashes of the added, modified and deleted entities
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entity_hashes: Vec<EntityHash>,
+    // Similarity of the old and new code of each modified entity
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub similarities: Vec<EntitySimilarity>,
+    // Risk score of each changed entity, and of the file as their sum
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub risk_scores: Vec<RiskScore>,
+    #[serde(default)]
+    pub risk_score: u32,
+    // Old and new values of modified constants and enum discriminants
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub value_changes: Vec<ValueChange>,
+    // Public entities, fields and variants whose deprecation changed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deprecations: Vec<DeprecationChange>,
+    // Error enums whose variants changed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub error_surface_changes: Vec<ErrorSurfaceChange>,
+    // Statement-level hunks of the modified function bodies
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub statement_changes: Vec<StatementChanges>,
+    // Entities deleted and added again under a new name with nearly the same code
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub renamed_entities: Vec<RenamedEntity>,
+    // Entities that moved to this file from another one, and from this file to another one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moved_in: Vec<CrossFileMove>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moved_out: Vec<CrossFileMove>,
+    // Entities that moved within the file, whether or not they changed otherwise
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moved_entities: Vec<MovedEntity>,
+    // Last-touching commit of each added or modified function and method, with --blame
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blame: Vec<EntityBlame>,
 }
 
 impl DetailedChanges {
     pub fn new(module_name: String) -> Self {
         DetailedChanges {
             module_name,
+            module_path: None,
+            crate_name: None,
+            renamed_from: None,
+            copied_from: None,
             added_functions: Vec::new(),
             modified_functions: Vec::new(),
             deleted_functions: Vec::new(),
@@ -89,9 +780,63 @@ impl DetailedChanges {
             added_methods: Vec::new(),
             modified_methods: Vec::new(),
             deleted_methods: Vec::new(),
+            added_trait_methods: Vec::new(),
+            modified_trait_methods: Vec::new(),
+            deleted_trait_methods: Vec::new(),
+            added_constants: Vec::new(),
+            modified_constants: Vec::new(),
+            deleted_constants: Vec::new(),
+            added_macros: Vec::new(),
+            modified_macros: Vec::new(),
+            deleted_macros: Vec::new(),
+            added_associated_consts: Vec::new(),
+            modified_associated_consts: Vec::new(),
+            deleted_associated_consts: Vec::new(),
+            added_associated_types: Vec::new(),
+            modified_associated_types: Vec::new(),
+            deleted_associated_types: Vec::new(),
+            attribute_changes: Vec::new(),
+            doc_only_changes: Vec::new(),
+            cosmetic_only: Vec::new(),
+            visibility_changes: Vec::new(),
+            qualifier_changes: Vec::new(),
+            signature_changes: Vec::new(),
+            generics_changes: Vec::new(),
+            lifetime_changes: Vec::new(),
+            added_trait_impls: Vec::new(),
+            deleted_trait_impls: Vec::new(),
+            added_imports: Vec::new(),
+            removed_imports: Vec::new(),
+            added_reexports: Vec::new(),
+            removed_reexports: Vec::new(),
+            added_crate_attributes: Vec::new(),
+            removed_crate_attributes: Vec::new(),
+            macro_arm_changes: Vec::new(),
+            field_changes: Vec::new(),
+            variant_changes: Vec::new(),
+            trait_item_changes: Vec::new(),
+            semver_changes: Vec::new(),
+            global_changes: Vec::new(),
+            test_changes: Vec::new(),
+            test_only: false,
+            entity_hashes: Vec::new(),
+            similarities: Vec::new(),
+            risk_scores: Vec::new(),
+            risk_score: 0,
+            value_changes: Vec::new(),
+            deprecations: Vec::new(),
+            error_surface_changes: Vec::new(),
+            statement_changes: Vec::new(),
+            renamed_entities: Vec::new(),
+            moved_in: Vec::new(),
+            moved_out: Vec::new(),
+            moved_entities: Vec::new(),
+            blame: Vec::new(),
         }
     }
 
+    // Whether any list holds an entry, so a file whose only change is a re-export, a value or a
+    // deprecation is still reported
     pub fn has_changes(&self) -> bool {
         !self.added_functions.is_empty() ||
         !self.modified_functions.is_empty() ||
@@ -104,7 +849,269 @@ impl DetailedChanges {
         !self.deleted_interfaces.is_empty() ||
         !self.added_methods.is_empty() ||
         !self.modified_methods.is_empty() ||
-        !self.deleted_methods.is_empty()
+        !self.deleted_methods.is_empty() ||
+        !self.added_trait_methods.is_empty() ||
+        !self.modified_trait_methods.is_empty() ||
+        !self.deleted_trait_methods.is_empty() ||
+        !self.added_constants.is_empty() ||
+        !self.modified_constants.is_empty() ||
+        !self.deleted_constants.is_empty() ||
+        !self.added_macros.is_empty() ||
+        !self.modified_macros.is_empty() ||
+        !self.deleted_macros.is_empty() ||
+        !self.added_associated_consts.is_empty() ||
+        !self.modified_associated_consts.is_empty() ||
+        !self.deleted_associated_consts.is_empty() ||
+        !self.added_associated_types.is_empty() ||
+        !self.modified_associated_types.is_empty() ||
+        !self.deleted_associated_types.is_empty() ||
+        !self.attribute_changes.is_empty() ||
+        !self.doc_only_changes.is_empty() ||
+        !self.cosmetic_only.is_empty() ||
+        !self.visibility_changes.is_empty() ||
+        !self.qualifier_changes.is_empty() ||
+        !self.signature_changes.is_empty() ||
+        !self.generics_changes.is_empty() ||
+        !self.lifetime_changes.is_empty() ||
+        !self.added_trait_impls.is_empty() ||
+        !self.deleted_trait_impls.is_empty() ||
+        !self.added_imports.is_empty() ||
+        !self.removed_imports.is_empty() ||
+        !self.added_reexports.is_empty() ||
+        !self.removed_reexports.is_empty() ||
+        !self.added_crate_attributes.is_empty() ||
+        !self.removed_crate_attributes.is_empty() ||
+        !self.macro_arm_changes.is_empty() ||
+        !self.field_changes.is_empty() ||
+        !self.variant_changes.is_empty() ||
+        !self.trait_item_changes.is_empty() ||
+        !self.semver_changes.is_empty() ||
+        !self.global_changes.is_empty() ||
+        !self.test_changes.is_empty() ||
+        !self.entity_hashes.is_empty() ||
+        !self.similarities.is_empty() ||
+        !self.risk_scores.is_empty() ||
+        !self.value_changes.is_empty() ||
+        !self.deprecations.is_empty() ||
+        !self.error_surface_changes.is_empty() ||
+        !self.statement_changes.is_empty() ||
+        !self.renamed_entities.is_empty() ||
+        !self.moved_in.is_empty() ||
+        !self.moved_out.is_empty() ||
+        !self.moved_entities.is_empty() ||
+        !self.blame.is_empty()
+    }
+}
+
+// CrateAttributeChanges records the crate-level attributes of one crate root that only one side
+// has, with the nightly features enabled and disabled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateAttributeChanges {
+    pub module: String,
+    pub added_attributes: Vec<String>,
+    pub removed_attributes: Vec<String>,
+    pub added_features: Vec<String>,
+    pub removed_features: Vec<String>,
+    // The new `no_std` setting, when it was toggled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_std: Option<bool>,
+}
+
+// TraitImplChange records a whole `impl Trait for Type` block that was added or removed, apart
+// from the changes to the items inside it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraitImplChange {
+    pub module: String,
+    pub name: String,
+    // `added` or `removed`
+    pub change: String,
+    pub trait_name: String,
+    pub self_type: String,
+    // Generic parameters of the impl, e.g. `T: Clone` in `impl<T: Clone> Trait for Wrapper<T>`
+    pub generics: Vec<String>,
+    // `impl !Trait for Type`
+    pub negative: bool,
+    pub is_unsafe: bool,
+}
+
+// SemverChange classifies one change to the public API as `breaking`, `potentially_breaking`
+// or `non_breaking` for downstream crates, with the reason
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SemverChange {
+    pub kind: String,
+    pub name: String,
+    pub level: String,
+    pub reason: String,
+}
+
+// SemverReportEntry is a classified change together with the file it was found in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SemverReportEntry {
+    pub module: String,
+    pub qualified_name: String,
+    // The qualified name under the crate's own name, unique across the crates of a repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    #[serde(flatten)]
+    pub change: SemverChange,
+}
+
+// SemverReport sums up the public API changes of a diff: the most severe level found, how many
+// changes there are of each level, and the changes themselves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SemverReport {
+    pub level: String,
+    pub breaking: usize,
+    pub potentially_breaking: usize,
+    pub non_breaking: usize,
+    pub changes: Vec<SemverReportEntry>,
+}
+
+// CommitChanges holds the changes a single commit made relative to its first parent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitChanges {
+    pub commit: String,
+    pub parent: String,
+    pub changes: Vec<DetailedChanges>,
+}
+
+// SkippedFile records a changed file left out of the analysis and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedFile {
+    pub file: String,
+    pub reason: String,
+}
+
+// BuildScriptChange is a changed `build.rs`, flagged in the report header
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildScriptChange {
+    pub file: String,
+    // `added`, `modified`, `deleted`, `renamed` or `copied`
+    pub status: String,
+}
+
+// PathChanges holds changes to file modes and path case that leave the code itself untouched
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PathChanges {
+    pub mode_changes: Vec<ModeChange>,
+    pub case_renames: Vec<CaseRename>,
+}
+
+// ManifestChanges holds the dependency and feature changes of one Cargo.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ManifestChanges {
+    pub file: String,
+    pub dependency_changes: Vec<DependencyChange>,
+    pub feature_changes: Vec<FeatureChange>,
+}
+
+// DependencyChange is a dependency added, removed or changed in one dependency table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DependencyChange {
+    // Table the dependency is listed in, e.g. `dependencies` or `target.cfg(unix).dev-dependencies`
+    pub table: String,
+    pub name: String,
+    // `added`, `removed`, `version_changed`, or `modified` when only the rest of the spec changed
+    pub change: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_version: Option<String>,
+    // The whole spec as written inline, e.g. `{ version = "1", features = ["derive"] }`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_spec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_spec: Option<String>,
+    // Changed functions and methods that start using an added dependency
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub used_by: Vec<DependencyUse>,
+}
+
+// DependencyUse is an entity that starts using a dependency
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DependencyUse {
+    pub file: String,
+    pub kind: String,
+    pub name: String,
+}
+
+// FeatureChange is a feature added to, removed from or changed in the `[features]` table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeatureChange {
+    pub name: String,
+    // `added`, `removed` or `modified`
+    pub change: String,
+    // Features and dependencies the feature enables, e.g. `dep:serde` or `tokio/rt`
+    pub added_members: Vec<String>,
+    pub removed_members: Vec<String>,
+}
+
+// AnalysisError records a failure that prevented part of the analysis
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnalysisError {
+    pub stage: String,
+    pub file: Option<String>,
+    pub reference: Option<String>,
+    pub message: String,
+}
+
+// ErrorLog collects analysis errors from concurrent workers
+#[derive(Debug, Default)]
+pub struct ErrorLog {
+    errors: Mutex<Vec<AnalysisError>>,
+}
+
+impl ErrorLog {
+    pub fn new() -> Self {
+        ErrorLog::default()
+    }
+
+    pub fn record(&self, stage: &str, file: Option<&str>, reference: Option<&str>, message: impl Display) {
+        let error = AnalysisError {
+            stage: stage.to_string(),
+            file: file.map(|f| f.to_string()),
+            reference: reference.map(|r| r.to_string()),
+            message: message.to_string(),
+        };
+
+        let mut errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        // The differ and granular passes can hit the same failure
+        if !errors.contains(&error) {
+            errors.push(error);
+        }
+    }
+
+    // Return the recorded errors in a stable order
+    pub fn snapshot(&self) -> Vec<AnalysisError> {
+        let mut errors = self.errors.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        errors.sort_by(|a, b| (&a.file, &a.stage).cmp(&(&b.file, &b.stage)));
+        errors
+    }
+}
+
+// AnalysisContext carries the inputs shared by the differ and granular passes
+pub struct AnalysisContext<'a> {
+    pub local_repo_path: &'a str,
+    pub branch_name: &'a str,
+    pub current_commit: &'a str,
+    pub new_file_map: &'a HashMap<String, bool>,
+    pub deleted_file_map: &'a HashMap<String, bool>,
+    // Old path of each renamed file, keyed by its new path
+    pub renamed_file_map: &'a HashMap<String, String>,
+    // Source path of each copied file, keyed by its new path
+    pub copied_file_map: &'a HashMap<String, String>,
+    pub content_source: ContentSource,
+    pub lfs: LfsMode,
+    pub errors: &'a ErrorLog,
+}
+
+impl AnalysisContext<'_> {
+    // Path a file had at the old ref, following renames and copies
+    pub fn old_path<'f>(&'f self, file: &'f String) -> &'f String {
+        self.renamed_file_map
+            .get(file)
+            .or_else(|| self.copied_file_map.get(file))
+            .unwrap_or(file)
     }
 }
 
@@ -113,8 +1120,25 @@ impl DetailedChanges {
 pub struct FileASTData {
     pub functions: HashMap<String, ItemFn>,
     pub types: HashMap<String, Item>,         // Struct, Enum, Type Alias
+    pub struct_fields: HashMap<String, Vec<StructField>>, // Fields of structs and unions, keyed like types
+    pub enum_variants: HashMap<String, Vec<EnumVariant>>, // Variants of enums, keyed like types
+    pub globals: HashMap<String, GlobalItem>, // Global state, keyed like constants
     pub interfaces: HashMap<String, ItemTrait>, // Traits in Rust
     pub methods: HashMap<String, (ItemImpl, ItemFn)>, // impl methods
+    pub trait_methods: HashMap<String, TraitItemFn>, // Trait methods, default body or not, as `Trait::method`
+    pub trait_impls: HashMap<String, ItemImpl>, // Trait impls, as `<Trait for Type>`
+    pub constants: HashMap<String, Item>,     // Const and Static
+    pub macros: HashMap<String, ItemMacro>,   // macro_rules! definitions
+    pub associated_consts: HashMap<String, TokenStream>, // Consts of impls and traits, as `Owner.NAME`
+    pub associated_types: HashMap<String, TokenStream>,  // Types of impls and traits, as `Owner.Name`
+    pub imports: Vec<String>,                 // Imported paths, in source order
+    pub reexports: Vec<String>,               // Paths of `pub use` imports, in source order
+    pub inner_attributes: Vec<String>,        // File-level `#![..]` attributes, one per feature or lint listed
+    pub content_hashes: HashMap<&'static str, HashMap<String, String>>, // Content hash of each entity, by kind and name
+    pub positions: HashMap<&'static str, HashMap<String, EntityPosition>>, // Position of each entity, by kind and name
+    pub test_entities: HashMap<&'static str, HashSet<String>>, // Test functions and items of test modules, by kind
+    pub parse_error: Option<String>,          // Syntax error the items were recovered around, if any
+    pub module_path: Option<String>,          // Module path of the file in its crate, which qualifies every key
     pub file_content: String,
     pub file_path: String,
 }
@@ -124,8 +1148,25 @@ impl FileASTData {
         FileASTData {
             functions: HashMap::new(),
             types: HashMap::new(),
+            struct_fields: HashMap::new(),
+            enum_variants: HashMap::new(),
+            globals: HashMap::new(),
             interfaces: HashMap::new(),
             methods: HashMap::new(),
+            trait_methods: HashMap::new(),
+            trait_impls: HashMap::new(),
+            constants: HashMap::new(),
+            macros: HashMap::new(),
+            associated_consts: HashMap::new(),
+            associated_types: HashMap::new(),
+            imports: Vec::new(),
+            reexports: Vec::new(),
+            inner_attributes: Vec::new(),
+            content_hashes: HashMap::new(),
+            positions: HashMap::new(),
+            test_entities: HashMap::new(),
+            parse_error: None,
+            module_path: None,
             file_content,
             file_path,
         }
@@ -135,20 +1176,95 @@ impl FileASTData {
         FileASTData {
             functions: HashMap::new(),
             types: HashMap::new(),
+            struct_fields: HashMap::new(),
+            enum_variants: HashMap::new(),
+            globals: HashMap::new(),
             interfaces: HashMap::new(),
             methods: HashMap::new(),
+            trait_methods: HashMap::new(),
+            trait_impls: HashMap::new(),
+            constants: HashMap::new(),
+            macros: HashMap::new(),
+            associated_consts: HashMap::new(),
+            associated_types: HashMap::new(),
+            imports: Vec::new(),
+            reexports: Vec::new(),
+            inner_attributes: Vec::new(),
+            content_hashes: HashMap::new(),
+            positions: HashMap::new(),
+            test_entities: HashMap::new(),
+            parse_error: None,
+            module_path: None,
             file_content: String::new(),
             file_path,
         }
     }
+
+    // Position of an extracted entity of one kind
+    pub fn position(&self, kind: &str, name: &str) -> Option<EntityPosition> {
+        self.positions.get(kind)?.get(name).copied()
+    }
+
+    // Content hash of an extracted entity of one kind
+    pub fn content_hash(&self, kind: &str, name: &str) -> Option<&String> {
+        self.content_hashes.get(kind)?.get(name)
+    }
+
+    // Whether an extracted entity of one kind is test code
+    pub fn is_test(&self, kind: &str, name: &str) -> bool {
+        self.test_entities.get(kind).is_some_and(|names| names.contains(name))
+    }
 }
 
 // Structure for holding function call visitor data
 pub struct FunctionCallVisitor {
     pub calls: Vec<String>,
+    pub call_sites: Vec<CallSite>,
+    pub bindings: HashMap<String, String>, // Declared types of the parameters and typed lets seen so far
+}
+
+// CallSite records one path or method call with its argument count and, when the receiver
+// is a binding with a declared type, that type
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSite {
+    pub name: String,
+    pub arg_count: usize,
+    pub receiver_type: Option<String>,
+}
+
+// Structure for holding the awaited expressions and the expressions propagated with `?` in a function
+pub struct PropagationVisitor {
+    pub awaits: Vec<String>,
+    pub error_propagations: Vec<String>,
+}
+
+// Structure for holding the lifetimes written in a signature or type, with elided ones as `'_`
+pub struct LifetimeVisitor {
+    pub lifetimes: Vec<String>,
+}
+
+// Structure for holding the expressions of a function that can panic
+pub struct PanicVisitor<'a> {
+    pub points: Vec<PanicPoint>,
+    pub file_path: &'a str,
+}
+
+// Structure for holding the unsafe blocks of a function and the calls made in unsafe context
+pub struct UnsafeVisitor<'a> {
+    pub blocks: Vec<UnsafeRegion>,
+    pub calls: Vec<String>,
+    pub file_path: &'a str,
+    pub file_content: &'a str,
 }
 
 // Structure for holding literal visitor data
 pub struct LiteralVisitor {
     pub literals: Vec<TypedLiteral>,
+    pub file_path: String,
+}
+
+// Structure for holding the functions and let-bound closures declared in a function body
+pub struct NestedFnVisitor {
+    pub functions: Vec<ItemFn>,
+    pub closures: Vec<(Ident, ExprClosure)>,
 }
\ No newline at end of file